@@ -1,33 +1,46 @@
+use super::bytecode::Chunk;
+use crate::error::Span;
+use crate::intern::Name;
 use crate::lexer::token::TokenType;
+use std::cell::OnceCell;
+use std::rc::Rc;
 
 #[derive(Debug, Clone)]
 pub enum ASTNode {
-    Program(Box<Vec<ASTNode>>),
+    Program(BlockStatement),
 
     IfStatement(IfStatement),
     BlockStatement(BlockStatement),
     ReturnStatement(Box<ASTNode>),
     ForStatement(ForStatement),
+    WithStatement(WithStatement),
 
     MemberExpression(MemberExpression),
     IndexExpression(IndexExpression),
     FunctionStatement(FunctionStatement),
+    AliasStatement(String),
+    TrapStatement(TrapStatement),
     CallExpression(CallExpression),
 
     VariableExpression(VariableExpression),
     BinaryExpression(BinaryExpression),
     UnaryExpression(Box<ASTNode>),
     RangeExpression(RangeExpression),
+    TimeExpression(Box<ASTNode>),
 
     Number(f64),
     Boolean(bool),
     String(String),
     TemplateString(TemplateString),
-    Identifier(String),
+    Identifier(Name),
     None,
     List(Box<Vec<ASTNode>>),
+    Object(Box<Vec<(String, ASTNode)>>),
 
-    Command(Box<Vec<ASTNode>>),
+    Command(Box<Command>),
+    Capture(Box<Command>),
+    Spawn(Box<Command>),
+    Interactive(Box<Command>),
 }
 
 #[derive(Debug, Clone)]
@@ -37,17 +50,38 @@ pub struct TemplateString {
 
 #[derive(Debug, Clone)]
 pub enum TemplateToken {
-    Expression(String),
-    Literal(String),
+    Expression(Name),
+    Literal(Rc<str>),
 }
 
 #[derive(Debug, Clone)]
 pub struct ForStatement {
-    pub variable: String,
+    pub binding: ForBinding,
     pub iterable: Box<Iterable>,
     pub body: Box<ASTNode>,
 }
 
+// for x in list = ForBinding::Identifier(x)
+// for (x, y) in list = ForBinding::Destructure([x, y]), each item is a list
+#[derive(Debug, Clone)]
+pub enum ForBinding {
+    Identifier(Name),
+    Destructure(Vec<Name>),
+}
+
+#[derive(Debug, Clone)]
+pub struct WithStatement {
+    pub kind: WithKind,
+    pub argument: Box<ASTNode>,
+    pub body: Box<ASTNode>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum WithKind {
+    Env,
+    Dir,
+}
+
 #[derive(Debug, Clone)]
 pub enum Iterable {
     RangeExpression(RangeExpression),
@@ -77,13 +111,13 @@ pub struct IndexExpression {
 pub struct MemberExpression {
     //  pub identifier: String, // this needs to be abstract
     pub base: Box<ASTNode>,
-    pub property: String,
+    pub property: Name,
 }
 
 #[derive(Debug, Clone)]
 pub struct BinaryExpression {
     pub left: Box<ASTNode>,
-    pub operator: TokenType,
+    pub operator: TokenType<'static>,
     pub right: Box<ASTNode>,
 }
 
@@ -93,11 +127,22 @@ pub struct VariableExpression {
     pub rhs: Box<ASTNode>,
 }
 
+/// `trap "handler" SIGNAL...`, where the handler is sod code run when one of
+/// the signals arrives or, for `EXIT`, when the script ends.
+#[derive(Debug, Clone)]
+pub struct TrapStatement {
+    pub handler: Box<ASTNode>,
+    pub signals: Vec<String>,
+}
+
 #[derive(Debug, Clone)]
 pub struct FunctionStatement {
     pub name: String,
     pub body: Box<ASTNode>,
-    pub args: Vec<String>,
+    pub args: Vec<Name>,
+    /// The body compiled to bytecode when the function is declared with the
+    /// VM on, run by each call in place of walking the body.
+    pub compiled: OnceCell<Chunk>,
 }
 
 impl PartialEq for FunctionStatement {
@@ -112,6 +157,30 @@ pub struct CallExpression {
     pub args: Vec<ASTNode>,
 }
 
+/// A shell command line, with any `NAME=value` variables set for it alone.
+#[derive(Debug, Clone)]
+pub struct Command {
+    pub envs: Vec<(String, Vec<ASTNode>)>,
+    pub tokens: Vec<ASTNode>,
+    pub redirect: Option<Redirect>,
+    /// The stages after the first when the command is piped through sod
+    /// functions, as in `tail -f log | parse_line`.
+    pub stages: Vec<PipeStage>,
+}
+
+#[derive(Debug, Clone)]
+pub enum PipeStage {
+    Function(String),
+    Command(Vec<ASTNode>),
+}
+
+/// `> file` or `>> file` after a command, writing its output to a file.
+#[derive(Debug, Clone)]
+pub struct Redirect {
+    pub append: bool,
+    pub target: Vec<ASTNode>,
+}
+
 #[derive(Debug, Clone)]
 pub struct IfStatement {
     pub condition: Box<ASTNode>,
@@ -119,7 +188,9 @@ pub struct IfStatement {
     pub alternative: Option<Box<ASTNode>>,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Default)]
 pub struct BlockStatement {
     pub body: Box<Vec<ASTNode>>,
+    /// Where each statement of `body` is in the source it was parsed from.
+    pub spans: Vec<Span>,
 }
@@ -0,0 +1,592 @@
+use super::ast::{
+    self, ASTNode, BinaryExpression, CallExpression, Command, ForStatement, FunctionStatement,
+    IfStatement, IndexExpression, PipeStage, TemplateString, TemplateToken, TrapStatement,
+    VariableExpression, WithKind, WithStatement,
+};
+use crate::commands::Signal;
+use crate::lexer::token::TokenType;
+
+/// Translates a parsed program into an equivalent bash script. This is best
+/// effort, constructs without a bash equivalent (objects, member functions,
+/// with_env, ...) are reported as errors rather than guessed at.
+pub fn export(program: &ASTNode) -> Result<String, String> {
+    let mut exporter = BashExporter {
+        output: "#!/usr/bin/env bash\n".to_string(),
+        indent: 0,
+        in_function: false,
+        traps: 0,
+    };
+
+    match program {
+        ASTNode::Program(root) => {
+            for node in root.body.iter() {
+                exporter.statement(node)?;
+            }
+            Ok(exporter.output)
+        }
+        _ => Err("expected program".to_string()),
+    }
+}
+
+fn unsupported(what: &str) -> String {
+    format!("unable to export {} to bash", what)
+}
+
+fn quote(s: &str) -> String {
+    format!("'{}'", s.replace('\'', r"'\''"))
+}
+
+fn number(n: f64) -> String {
+    n.to_string()
+}
+
+fn is_argv(node: &ASTNode) -> bool {
+    match node {
+        ASTNode::MemberExpression(me) => {
+            &*me.property == "argv"
+                && matches!(&*me.base, ASTNode::Identifier(i) if &**i == "process")
+        }
+        _ => false,
+    }
+}
+
+fn is_string(node: &ASTNode) -> bool {
+    match node {
+        ASTNode::String(_) | ASTNode::TemplateString(_) => true,
+        ASTNode::BinaryExpression(be) => {
+            be.operator == TokenType::Plus && (is_string(&be.left) || is_string(&be.right))
+        }
+        _ => false,
+    }
+}
+
+struct BashExporter {
+    output: String,
+    indent: usize,
+    in_function: bool,
+    /// The number of trap handlers written, used to name them.
+    traps: usize,
+}
+
+impl BashExporter {
+    fn line(&mut self, line: &str) {
+        self.output.push_str(&"    ".repeat(self.indent));
+        self.output.push_str(line);
+        self.output.push('\n');
+    }
+
+    fn block(&mut self, node: &ASTNode) -> Result<(), String> {
+        self.indent += 1;
+        let result = match node {
+            ASTNode::BlockStatement(bs) => bs.body.iter().try_for_each(|n| self.statement(n)),
+            _ => self.statement(node),
+        };
+        self.indent -= 1;
+        result
+    }
+
+    fn statement(&mut self, node: &ASTNode) -> Result<(), String> {
+        match node {
+            ASTNode::VariableExpression(ve) => self.variable_expression(ve),
+            ASTNode::FunctionStatement(fs) => self.function_statement(fs),
+            // commands using the alias have already been expanded
+            ASTNode::AliasStatement(_) => Ok(()),
+            ASTNode::TrapStatement(ts) => self.trap_statement(ts),
+            ASTNode::ReturnStatement(expr) => self.return_statement(expr),
+            ASTNode::IfStatement(is) => {
+                self.if_statement(is, "if")?;
+                self.line("fi");
+                Ok(())
+            }
+            ASTNode::ForStatement(fs) => self.for_statement(fs),
+            ASTNode::WithStatement(ws) => self.with_statement(ws),
+            ASTNode::BlockStatement(_) => self.block(node),
+            ASTNode::CallExpression(ce) => {
+                let call = self.call_expression(ce)?;
+                self.line(&call);
+                Ok(())
+            }
+            ASTNode::Command(tokens) | ASTNode::Interactive(tokens) => {
+                let cmd = self.command(tokens)?;
+                self.line(&cmd);
+                Ok(())
+            }
+            ASTNode::Capture(tokens) => {
+                let cmd = self.command(tokens)?;
+                self.line(&format!("{} > /dev/null", cmd));
+                Ok(())
+            }
+            ASTNode::Spawn(tokens) => {
+                let cmd = self.command(tokens)?;
+                self.line(&format!("{} &", cmd));
+                Ok(())
+            }
+            ASTNode::BinaryExpression(be)
+                if be.operator == TokenType::And || be.operator == TokenType::Or =>
+            {
+                let chain = self.chain(node)?;
+                self.line(&chain);
+                Ok(())
+            }
+            // bare values are only echoed by the REPL, a script discards them
+            ASTNode::Number(_)
+            | ASTNode::Boolean(_)
+            | ASTNode::String(_)
+            | ASTNode::TemplateString(_)
+            | ASTNode::Identifier(_)
+            | ASTNode::None => Ok(()),
+            _ => Err(unsupported("statement")),
+        }
+    }
+
+    fn variable_expression(&mut self, ve: &VariableExpression) -> Result<(), String> {
+        let value = match &*ve.rhs {
+            ASTNode::List(items) => {
+                let words = items
+                    .iter()
+                    .map(|item| self.expression(item))
+                    .collect::<Result<Vec<_>, _>>()?;
+                format!("({})", words.join(" "))
+            }
+            rhs => self.expression(rhs)?,
+        };
+
+        let lhs = match &*ve.lhs {
+            ASTNode::Identifier(name) => name.to_string(),
+            ASTNode::IndexExpression(IndexExpression { base, index }) => match &**base {
+                ASTNode::Identifier(name) => format!("{}[{}]", name, self.arithmetic(index)?),
+                _ => return Err(unsupported("assignment")),
+            },
+            _ => return Err(unsupported("assignment")),
+        };
+
+        self.line(&format!("{}={}", lhs, value));
+        Ok(())
+    }
+
+    fn function_statement(&mut self, fs: &FunctionStatement) -> Result<(), String> {
+        self.line(&format!("{}() {{", fs.name));
+        self.indent += 1;
+        for (i, arg) in fs.args.iter().enumerate() {
+            self.line(&format!("local {}=\"${}\"", arg, i + 1));
+        }
+        self.indent -= 1;
+
+        let in_function = self.in_function;
+        self.in_function = true;
+        let result = self.block(&fs.body);
+        self.in_function = in_function;
+        result?;
+
+        self.line("}");
+        Ok(())
+    }
+
+    /// Writes the handler as a function. As in sod, a trapped signal stops
+    /// the script once the handler has run.
+    fn trap_statement(&mut self, ts: &TrapStatement) -> Result<(), String> {
+        let statements = match &*ts.handler {
+            ASTNode::Program(program) => &program.body,
+            _ => return Err(unsupported("trap handler")),
+        };
+
+        if statements.is_empty() {
+            self.line(&format!("trap - {}", ts.signals.join(" ")));
+            return Ok(());
+        }
+
+        self.traps += 1;
+        let name = format!("_sod_trap_{}", self.traps);
+        self.line(&format!("{}() {{", name));
+        self.indent += 1;
+        let result = statements.iter().try_for_each(|n| self.statement(n));
+        self.indent -= 1;
+        result?;
+        self.line("}");
+
+        for signal in &ts.signals {
+            match Signal::from_name(signal) {
+                Some(signal) => self.line(&format!(
+                    "trap '{}; exit {}' {}",
+                    name,
+                    128 + signal.number(),
+                    signal.name().trim_start_matches("SIG")
+                )),
+                None => self.line(&format!("trap {} EXIT", name)),
+            }
+        }
+        Ok(())
+    }
+
+    fn return_statement(&mut self, expr: &ASTNode) -> Result<(), String> {
+        if !self.in_function {
+            return Err(unsupported("return outside of a function"));
+        }
+
+        // bash functions can only return a status, and echoing the value
+        // instead would mix it with the output of the function's commands
+        if !matches!(expr, ASTNode::None) {
+            return Err(unsupported("a returned value"));
+        }
+        self.line("return");
+        Ok(())
+    }
+
+    fn if_statement(&mut self, is: &IfStatement, keyword: &str) -> Result<(), String> {
+        let condition = self.condition(&is.condition)?;
+        self.line(&format!("{} {}; then", keyword, condition));
+        self.block(&is.consequence)?;
+
+        match is.alternative.as_deref() {
+            Some(ASTNode::IfStatement(alternative)) => self.if_statement(alternative, "elif"),
+            Some(alternative) => {
+                self.line("else");
+                self.block(alternative)
+            }
+            None => Ok(()),
+        }
+    }
+
+    fn for_statement(&mut self, fs: &ForStatement) -> Result<(), String> {
+        let variable = match &fs.binding {
+            ast::ForBinding::Identifier(name) => name,
+            ast::ForBinding::Destructure(_) => return Err(unsupported("for loop destructuring")),
+        };
+
+        let header = match &*fs.iterable {
+            ast::Iterable::RangeExpression(re)
+            | ast::Iterable::Collection(ASTNode::RangeExpression(re)) => {
+                let start = self.arithmetic(&re.start)?;
+                let end = self.arithmetic(&re.end)?;
+                let (comparison, increment) = match re.increment.as_deref() {
+                    None => ("<", "1".to_string()),
+                    Some(ASTNode::UnaryExpression(inc)) => {
+                        (">", format!("-{}", self.arithmetic(inc)?))
+                    }
+                    Some(inc) => ("<", self.arithmetic(inc)?),
+                };
+                format!(
+                    "for (( {v} = {}; {v} {} {}; {v} += {} )); do",
+                    start,
+                    comparison,
+                    end,
+                    increment,
+                    v = variable
+                )
+            }
+            ast::Iterable::Collection(ASTNode::List(items)) => {
+                let words = items
+                    .iter()
+                    .map(|item| self.expression(item))
+                    .collect::<Result<Vec<_>, _>>()?;
+                format!("for {} in {}; do", variable, words.join(" "))
+            }
+            ast::Iterable::Collection(node) if is_argv(node) => {
+                format!("for {} in \"$@\"; do", variable)
+            }
+            ast::Iterable::Collection(ASTNode::Identifier(name)) => {
+                format!("for {} in \"${{{}[@]}}\"; do", variable, name)
+            }
+            _ => return Err(unsupported("for loop iterable")),
+        };
+
+        self.line(&header);
+        self.block(&fs.body)?;
+        self.line("done");
+        Ok(())
+    }
+
+    fn with_statement(&mut self, ws: &WithStatement) -> Result<(), String> {
+        if ws.kind != WithKind::Dir {
+            return Err(unsupported("with_env"));
+        }
+
+        let dir = self.expression(&ws.argument)?;
+        self.line(&format!("pushd {} > /dev/null", dir));
+        self.block(&ws.body)?;
+        self.line("popd > /dev/null");
+        Ok(())
+    }
+
+    fn call_expression(&self, ce: &CallExpression) -> Result<String, String> {
+        let name = match &*ce.base {
+            ASTNode::Identifier(name) => name,
+            _ => return Err(unsupported("member function call")),
+        };
+
+        let mut words = vec![name.to_string()];
+        for arg in &ce.args {
+            words.push(self.expression(arg)?);
+        }
+
+        Ok(words.join(" "))
+    }
+
+    fn command(&self, command: &Command) -> Result<String, String> {
+        let mut cmd = String::new();
+        for (name, value) in &command.envs {
+            cmd.push_str(&format!("{}={} ", name, self.env_value(value)?));
+        }
+
+        cmd.push_str(&self.command_words(&command.tokens)?);
+        for stage in &command.stages {
+            match stage {
+                PipeStage::Function(name) => cmd.push_str(&format!(
+                    " | while IFS= read -r line; do {} \"$line\"; done",
+                    name
+                )),
+                PipeStage::Command(tokens) => {
+                    cmd.push_str(&format!(" | {}", self.command_words(tokens)?))
+                }
+            }
+        }
+
+        if let Some(redirect) = &command.redirect {
+            let op = if redirect.append { ">>" } else { ">" };
+            cmd.push_str(&format!(" {} {}", op, self.env_value(&redirect.target)?));
+        }
+
+        Ok(cmd)
+    }
+
+    fn command_words(&self, tokens: &[ASTNode]) -> Result<String, String> {
+        let mut cmd = String::new();
+        for token in tokens {
+            match token {
+                ASTNode::String(s) => cmd.push_str(s),
+                ASTNode::Identifier(name) => cmd.push_str(&format!("\"${{{}}}\"", name)),
+                ASTNode::TemplateString(ts) => cmd.push_str(&self.template_string(ts)),
+                ASTNode::CallExpression(ce) => match (&*ce.base, ce.args.as_slice()) {
+                    (ASTNode::Identifier(f), [ASTNode::Identifier(name)]) if &**f == "raw" => {
+                        cmd.push_str(&format!("${{{}}}", name))
+                    }
+                    _ => cmd.push_str(&self.expression(token)?),
+                },
+                _ => cmd.push_str(&self.expression(token)?),
+            }
+        }
+
+        Ok(cmd)
+    }
+
+    /// A word that isn't split by the shell, an env value or redirect target.
+    fn env_value(&self, value: &[ASTNode]) -> Result<String, String> {
+        let mut words = String::new();
+        let mut literal = String::new();
+        for token in value {
+            match token {
+                ASTNode::String(s) => literal.push_str(s),
+                _ => {
+                    if !literal.is_empty() {
+                        words.push_str(&quote(&std::mem::take(&mut literal)));
+                    }
+                    words.push_str(&self.expression(token)?);
+                }
+            }
+        }
+
+        if !literal.is_empty() {
+            words.push_str(&quote(&literal));
+        }
+
+        Ok(words)
+    }
+
+    fn template_string(&self, ts: &TemplateString) -> String {
+        let mut s = "\"".to_string();
+        for token in &ts.tokens {
+            match token {
+                TemplateToken::Literal(literal) => {
+                    for c in literal.chars() {
+                        if matches!(c, '"' | '\\' | '$' | '`') {
+                            s.push('\\');
+                        }
+                        s.push(c);
+                    }
+                }
+                TemplateToken::Expression(name) => s.push_str(&format!("${{{}}}", name)),
+            }
+        }
+        s.push('"');
+        s
+    }
+
+    fn index_expression(&self, ie: &IndexExpression) -> Result<String, String> {
+        if is_argv(&ie.base) {
+            return match &*ie.index {
+                ASTNode::Number(n) => Ok(format!("\"${{{}}}\"", number(n + 1.0))),
+                _ => Ok(format!(
+                    "\"${{@:$(( {} + 1 )):1}}\"",
+                    self.arithmetic(&ie.index)?
+                )),
+            };
+        }
+
+        match &*ie.base {
+            ASTNode::Identifier(name) => Ok(format!(
+                "\"${{{}[{}]}}\"",
+                name,
+                self.arithmetic(&ie.index)?
+            )),
+            _ => Err(unsupported("index expression")),
+        }
+    }
+
+    /// Translates a node into a single bash word, quoted where needed.
+    fn expression(&self, node: &ASTNode) -> Result<String, String> {
+        let word = match node {
+            ASTNode::Number(n) => number(*n),
+            ASTNode::Boolean(b) => b.to_string(),
+            ASTNode::String(s) => quote(s),
+            ASTNode::TemplateString(ts) => self.template_string(ts),
+            ASTNode::None => "''".to_string(),
+            ASTNode::Identifier(name) => format!("\"${{{}}}\"", name),
+            ASTNode::Command(tokens) | ASTNode::Capture(tokens) => {
+                format!("\"$({})\"", self.command(tokens)?)
+            }
+            ASTNode::CallExpression(ce) => match &*ce.base {
+                ASTNode::MemberExpression(me)
+                    if &*me.property == "len" && ce.args.is_empty() && is_argv(&me.base) =>
+                {
+                    "$#".to_string()
+                }
+                _ => format!("\"$({})\"", self.call_expression(ce)?),
+            },
+            ASTNode::IndexExpression(ie) => self.index_expression(ie)?,
+            ASTNode::MemberExpression(me) => {
+                return Err(unsupported(&format!("member '{}'", me.property)))
+            }
+            ASTNode::BinaryExpression(be) if be.operator == TokenType::Plus && is_string(node) => {
+                format!(
+                    "{}{}",
+                    self.expression(&be.left)?,
+                    self.expression(&be.right)?
+                )
+            }
+            ASTNode::BinaryExpression(_) | ASTNode::UnaryExpression(_) => {
+                format!("$(( {} ))", self.arithmetic(node)?)
+            }
+            ASTNode::List(_) => return Err(unsupported("nested list")),
+            ASTNode::Object(_) => return Err(unsupported("object")),
+            ASTNode::RangeExpression(_) => return Err(unsupported("range outside of a for loop")),
+            _ => return Err(unsupported("expression")),
+        };
+
+        Ok(word)
+    }
+
+    /// Translates a node for use inside `$(( ))` or `(( ))`.
+    fn arithmetic(&self, node: &ASTNode) -> Result<String, String> {
+        let expression = match node {
+            // bash arithmetic only has whole numbers
+            ASTNode::Number(n) if n.fract() != 0.0 => return Err(unsupported("a fraction")),
+            ASTNode::Number(n) => number(*n),
+            ASTNode::Identifier(name) => name.to_string(),
+            ASTNode::UnaryExpression(inner) => format!("-{}", self.arithmetic(inner)?),
+            ASTNode::BinaryExpression(BinaryExpression {
+                left,
+                operator,
+                right,
+            }) => {
+                let operator = match operator {
+                    TokenType::Plus => "+",
+                    TokenType::Minus => "-",
+                    TokenType::Asterisk => "*",
+                    // would drop the remainder
+                    TokenType::ForwardSlash => return Err(unsupported("division")),
+                    TokenType::Carat => "**",
+                    _ => return Err(unsupported(&format!("operator {}", operator))),
+                };
+                format!(
+                    "({} {} {})",
+                    self.arithmetic(left)?,
+                    operator,
+                    self.arithmetic(right)?
+                )
+            }
+            ASTNode::CallExpression(_)
+            | ASTNode::IndexExpression(_)
+            | ASTNode::Command(_)
+            | ASTNode::Capture(_) => self.expression(node)?,
+            _ => return Err(unsupported("arithmetic expression")),
+        };
+
+        Ok(expression)
+    }
+
+    /// Translates `a && cmd` style statements, where commands are run for
+    /// their side effects rather than tested for output.
+    fn chain(&self, node: &ASTNode) -> Result<String, String> {
+        match node {
+            ASTNode::BinaryExpression(be)
+                if be.operator == TokenType::And || be.operator == TokenType::Or =>
+            {
+                let operator = if be.operator == TokenType::And {
+                    "&&"
+                } else {
+                    "||"
+                };
+                Ok(format!(
+                    "{} {} {}",
+                    self.chain(&be.left)?,
+                    operator,
+                    self.chain(&be.right)?
+                ))
+            }
+            ASTNode::Command(tokens) => self.command(tokens),
+            ASTNode::Capture(tokens) => Ok(format!("{} > /dev/null", self.command(tokens)?)),
+            ASTNode::CallExpression(ce) => self.call_expression(ce),
+            _ => self.condition(node),
+        }
+    }
+
+    fn condition(&self, node: &ASTNode) -> Result<String, String> {
+        let condition = match node {
+            ASTNode::Boolean(b) => b.to_string(),
+            ASTNode::BinaryExpression(BinaryExpression {
+                left,
+                operator,
+                right,
+            }) => match operator {
+                TokenType::And | TokenType::Or => {
+                    let operator = if operator == &TokenType::And {
+                        "&&"
+                    } else {
+                        "||"
+                    };
+                    format!(
+                        "{} {} {}",
+                        self.condition(left)?,
+                        operator,
+                        self.condition(right)?
+                    )
+                }
+                TokenType::DoubleEquals | TokenType::NotEquals => format!(
+                    "[[ {} {} {} ]]",
+                    self.expression(left)?,
+                    operator,
+                    self.expression(right)?
+                ),
+                TokenType::GreaterThan | TokenType::LessThan | TokenType::Ge | TokenType::Le => {
+                    format!(
+                        "(( {} {} {} ))",
+                        self.arithmetic(left)?,
+                        operator,
+                        self.arithmetic(right)?
+                    )
+                }
+                _ if is_string(node) => format!("[[ -n {} ]]", self.expression(node)?),
+                _ => return Err(unsupported("a condition that isn't a comparison")),
+            },
+            ASTNode::Command(tokens) | ASTNode::Capture(tokens) => {
+                format!("[[ -n \"$({})\" ]]", self.command(tokens)?)
+            }
+            // only strings are false exactly when empty, whereas false, 0 and
+            // none are not
+            _ if is_string(node) => format!("[[ -n {} ]]", self.expression(node)?),
+            _ => return Err(unsupported("a condition that isn't a comparison")),
+        };
+
+        Ok(condition)
+    }
+}
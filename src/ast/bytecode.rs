@@ -0,0 +1,314 @@
+use super::ast::{
+    ASTNode, BinaryExpression, CallExpression, ForBinding, ForStatement, IfStatement, Iterable,
+    RangeExpression,
+};
+use crate::intern::Name;
+use crate::lexer::token::TokenType;
+use crate::new_string_symbol;
+use crate::symbol::scope::ScopeKind;
+use crate::symbol::symbol::Symbol;
+
+/// An instruction for the evaluator's stack machine. Values are kept on a
+/// stack as `Option<Symbol>`, none being what a statement leaves behind.
+#[derive(Debug, Clone)]
+pub enum Instruction {
+    Constant(Option<Symbol>),
+    /// Pushes the value of a variable.
+    Load(Name),
+    /// Pushes `name[index]`, popping the index, without copying the rest of
+    /// the variable.
+    LoadIndex(Name),
+    /// Pushes `name.property` without copying the rest of the variable.
+    LoadProperty(Name, Name),
+    /// Pops a value into a variable.
+    Store(Name),
+    /// Pops the right then the left operand and pushes the result.
+    Binary(TokenType<'static>),
+    Negate,
+    /// Pops an index then a value and pushes the value at the index.
+    Index,
+    /// Pops a value and pushes one of its properties.
+    Property(Name),
+    /// Pops the arguments and calls a function or builtin by name.
+    Call(Name, usize),
+    /// Pops the arguments and calls a member function on a variable, which
+    /// it may change.
+    CallMethod(Name, Name, usize),
+    /// Pops a value, then the arguments, and calls a member function on it.
+    CallValueMethod(Name, usize),
+    List(usize),
+    Object(Vec<String>),
+    /// Pops the end and start of a range, and the increment first if set.
+    Range(bool),
+    /// Turns a none left by a statement into the value none, as returned by
+    /// a block.
+    OrNone,
+    Pop,
+    Jump(usize),
+    /// Pops a value and jumps if it isn't truthy.
+    JumpUnless(usize),
+    /// Jumps, keeping the value, if it isn't truthy, otherwise pops it. For
+    /// the left of `&&`.
+    JumpUnlessKeep(usize),
+    /// Jumps, keeping the value, if it is truthy, otherwise pops it. For the
+    /// left of `||`.
+    JumpIfKeep(usize),
+    PushScope(ScopeKind),
+    PopScope,
+    /// Pops a collection and starts iterating over it.
+    Iterate,
+    /// Pushes the next item of the innermost iteration or, once there are no
+    /// more, ends it and jumps.
+    Next(usize),
+    /// Pops an item into the variables of a for loop.
+    Bind(ForBinding),
+    /// Evaluates a node by walking it, for what isn't compiled.
+    Eval(ASTNode),
+}
+
+/// Compiled code, which leaves the value of the node it came from on the
+/// stack.
+#[derive(Debug, Clone, Default)]
+pub struct Chunk {
+    pub instructions: Vec<Instruction>,
+}
+
+/// Compiles a node to run on the evaluator's stack machine. Loops,
+/// conditionals and expressions are compiled; everything else, such as
+/// commands and function declarations, is left to the tree walking
+/// evaluator with `Instruction::Eval`.
+pub fn compile(node: &ASTNode) -> Chunk {
+    let mut chunk = Chunk::default();
+    chunk.node(node);
+    chunk
+}
+
+impl Chunk {
+    fn emit(&mut self, instruction: Instruction) -> usize {
+        self.instructions.push(instruction);
+        self.instructions.len() - 1
+    }
+
+    /// Points the jump at `at` to the next instruction.
+    fn patch(&mut self, at: usize) {
+        let target = self.instructions.len();
+        match &mut self.instructions[at] {
+            Instruction::Jump(to)
+            | Instruction::JumpUnless(to)
+            | Instruction::JumpUnlessKeep(to)
+            | Instruction::JumpIfKeep(to)
+            | Instruction::Next(to) => *to = target,
+            _ => (),
+        }
+    }
+
+    fn node(&mut self, node: &ASTNode) {
+        match node {
+            ASTNode::Number(n) => {
+                self.emit(Instruction::Constant(Some(Symbol::Number(*n))));
+            }
+            ASTNode::Boolean(b) => {
+                self.emit(Instruction::Constant(Some(Symbol::Boolean(*b))));
+            }
+            ASTNode::String(s) => {
+                self.emit(Instruction::Constant(Some(new_string_symbol!(s.clone()))));
+            }
+            ASTNode::None => {
+                self.emit(Instruction::Constant(Some(Symbol::None)));
+            }
+            ASTNode::Identifier(name) => {
+                self.emit(Instruction::Load(name.clone()));
+            }
+            ASTNode::VariableExpression(ve) => match &*ve.lhs {
+                ASTNode::Identifier(name) => {
+                    self.node(&ve.rhs);
+                    self.emit(Instruction::Store(name.clone()));
+                    self.emit(Instruction::Constant(None));
+                }
+                _ => self.eval(node),
+            },
+            ASTNode::BinaryExpression(be) => self.binary_expression(be),
+            ASTNode::UnaryExpression(expr) => {
+                self.node(expr);
+                self.emit(Instruction::Negate);
+            }
+            ASTNode::IndexExpression(ie) => {
+                self.node(&ie.index);
+                match &*ie.base {
+                    ASTNode::Identifier(name) => {
+                        self.emit(Instruction::LoadIndex(name.clone()));
+                    }
+                    base => {
+                        self.node(base);
+                        self.emit(Instruction::Index);
+                    }
+                }
+            }
+            ASTNode::MemberExpression(me) => match &*me.base {
+                ASTNode::Identifier(name) => {
+                    self.emit(Instruction::LoadProperty(name.clone(), me.property.clone()));
+                }
+                base => {
+                    self.node(base);
+                    self.emit(Instruction::Property(me.property.clone()));
+                }
+            },
+            ASTNode::CallExpression(ce) => self.call_expression(node, ce),
+            ASTNode::List(items) => {
+                for item in items.iter() {
+                    self.node(item);
+                }
+                self.emit(Instruction::List(items.len()));
+            }
+            ASTNode::Object(entries) => {
+                for (_, value) in entries.iter() {
+                    self.node(value);
+                }
+                let keys = entries.iter().map(|(key, _)| key.clone()).collect();
+                self.emit(Instruction::Object(keys));
+            }
+            ASTNode::RangeExpression(range) => self.range(range),
+            ASTNode::BlockStatement(bs) => {
+                let mut returns = vec![];
+                for statement in bs.body.iter() {
+                    match statement {
+                        // a return ends the block it is in, with its value, and
+                        // is left to the evaluator to end a function too
+                        ASTNode::ReturnStatement(_) => {
+                            self.eval(statement);
+                            self.emit(Instruction::OrNone);
+                            returns.push(self.emit(Instruction::Jump(0)));
+                        }
+                        statement => {
+                            self.node(statement);
+                            self.emit(Instruction::Pop);
+                        }
+                    }
+                }
+                self.emit(Instruction::Constant(Some(Symbol::None)));
+                for at in returns {
+                    self.patch(at);
+                }
+            }
+            ASTNode::IfStatement(is) => self.if_statement(is),
+            ASTNode::ForStatement(fs) => self.for_statement(fs),
+            node => self.eval(node),
+        }
+    }
+
+    fn eval(&mut self, node: &ASTNode) {
+        self.emit(Instruction::Eval(node.clone()));
+    }
+
+    fn binary_expression(&mut self, be: &BinaryExpression) {
+        self.node(&be.left);
+        let jump = match be.operator {
+            TokenType::And => Some(self.emit(Instruction::JumpUnlessKeep(0))),
+            TokenType::Or => Some(self.emit(Instruction::JumpIfKeep(0))),
+            _ => None,
+        };
+        self.node(&be.right);
+        match jump {
+            // the right of `&&` and `||` is the result once the left is
+            // popped
+            Some(at) => self.patch(at),
+            None => {
+                self.emit(Instruction::Binary(be.operator.clone()));
+            }
+        }
+    }
+
+    fn call_expression(&mut self, node: &ASTNode, ce: &CallExpression) {
+        let method = match &*ce.base {
+            ASTNode::Identifier(name) => {
+                for arg in &ce.args {
+                    self.node(arg);
+                }
+                self.emit(Instruction::Call(name.clone(), ce.args.len()));
+                return;
+            }
+            ASTNode::MemberExpression(me) => me,
+            _ => return self.eval(node),
+        };
+
+        // higher order functions call back into the evaluator, and members
+        // of members are changed in place, so both are left to it
+        let call = &method.property;
+        if crate::symbol::symbol::is_higher_order(call) {
+            return self.eval(node);
+        }
+        match &*method.base {
+            ASTNode::Identifier(_) | ASTNode::CallExpression(_) => (),
+            _ => return self.eval(node),
+        }
+
+        for arg in &ce.args {
+            self.node(arg);
+        }
+        match &*method.base {
+            ASTNode::Identifier(name) => {
+                self.emit(Instruction::CallMethod(
+                    name.clone(),
+                    call.clone(),
+                    ce.args.len(),
+                ));
+            }
+            base => {
+                self.node(base);
+                self.emit(Instruction::CallValueMethod(call.clone(), ce.args.len()));
+            }
+        }
+    }
+
+    fn range(&mut self, range: &RangeExpression) {
+        self.node(&range.start);
+        self.node(&range.end);
+        if let Some(increment) = &range.increment {
+            self.node(increment);
+        }
+        self.emit(Instruction::Range(range.increment.is_some()));
+    }
+
+    fn if_statement(&mut self, is: &IfStatement) {
+        self.node(&is.condition);
+        let otherwise = self.emit(Instruction::JumpUnless(0));
+        self.emit(Instruction::PushScope(ScopeKind::ConditionalBlock));
+        self.node(&is.consequence);
+        self.emit(Instruction::Pop);
+        self.emit(Instruction::PopScope);
+
+        match &is.alternative {
+            Some(alternative) => {
+                let end = self.emit(Instruction::Jump(0));
+                self.patch(otherwise);
+                self.emit(Instruction::PushScope(ScopeKind::ConditionalBlock));
+                self.node(alternative);
+                self.emit(Instruction::Pop);
+                self.emit(Instruction::PopScope);
+                self.patch(end);
+            }
+            None => self.patch(otherwise),
+        }
+        self.emit(Instruction::Constant(None));
+    }
+
+    fn for_statement(&mut self, fs: &ForStatement) {
+        match &*fs.iterable {
+            Iterable::RangeExpression(range) => self.range(range),
+            Iterable::Collection(node) => self.node(node),
+        }
+        self.emit(Instruction::Iterate);
+        self.emit(Instruction::PushScope(ScopeKind::ForBlock));
+
+        let start = self.instructions.len();
+        let next = self.emit(Instruction::Next(0));
+        self.emit(Instruction::Bind(fs.binding.clone()));
+        self.node(&fs.body);
+        self.emit(Instruction::Pop);
+        self.emit(Instruction::Jump(start));
+        self.patch(next);
+
+        self.emit(Instruction::PopScope);
+        self.emit(Instruction::Constant(None));
+    }
+}
@@ -1,270 +1,1996 @@
 use super::ast::{
     self, ASTNode, BinaryExpression, BlockStatement, CallExpression, ForStatement,
     FunctionStatement, IfStatement, IndexExpression, MemberExpression, RangeExpression,
-    TemplateString, VariableExpression,
+    TemplateString, TrapStatement, VariableExpression, WithKind, WithStatement,
 };
-use crate::commands;
+use super::bytecode::{self, Chunk, Instruction};
+use super::source;
+use crate::builtins;
+use crate::commands::{
+    self, CommandExecutor, CommandLine, CommandResult, Echo, EnvSnapshot, Environment, Job, Output,
+    PipeInput, Program, Signal,
+};
+use crate::error::{RuntimeError, RuntimeErrorKind};
 use crate::lexer::token::TokenType;
+use crate::limits::Limits;
 use crate::new_string_symbol;
+use crate::platform;
 use crate::symbol::scope::ScopeKind;
-use crate::symbol::symbol::{self, List, Range, Symbol};
+use crate::symbol::symbol::{self, JobRef, List, Object, Range, StringSymbol, Symbol};
 use crate::symbol::table::SymbolTable;
+use std::collections::HashMap;
+use std::io::Write;
+use std::path::PathBuf;
+use std::rc::Rc;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+/// How many calls deep functions may go by default, not counting tail
+/// calls, which run in place of their caller. Each call recurses into the
+/// evaluator, so this stops runaway recursion before it uses up memory.
+pub const MAX_CALL_DEPTH: usize = 1000;
+/// How much stack a call needs left, enough for one call's worth of
+/// evaluation in a debug build, before more is allocated.
+const STACK_RED_ZONE: usize = 256 * 1024;
+/// How much stack is allocated at a time once it runs low.
+const STACK_SEGMENT: usize = 4 * 1024 * 1024;
+
+/// How a `return` inside a function ends it.
+enum Return {
+    Value(Symbol),
+    /// `return f(...)`, which calls `f` in place of the function once its
+    /// scope is gone, so recursion in tail position doesn't grow the stack.
+    TailCall(Rc<FunctionStatement>, Vec<Symbol>),
+}
+
+/// State captured on entering a `with_*` block, restored when the block exits.
+enum WithGuard {
+    Env(EnvSnapshot),
+    Dir(Option<PathBuf>),
+}
+
+impl WithGuard {
+    fn enter(
+        kind: &WithKind,
+        argument: Symbol,
+        environment: &mut Environment,
+    ) -> Result<Self, RuntimeError> {
+        match (kind, argument) {
+            (WithKind::Env, Symbol::Object(vars)) => {
+                let vars = vars
+                    .iter()
+                    .map(|(key, value)| (key.clone(), value.raw_str()))
+                    .collect();
+                Ok(WithGuard::Env(environment.set_vars(vars)))
+            }
+            (WithKind::Dir, dir @ Symbol::String(_)) => {
+                let previous = environment
+                    .set_current_dir(&dir.raw_str())
+                    .map_err(|e| RuntimeError::new(RuntimeErrorKind::Io, e))?;
+                Ok(WithGuard::Dir(previous))
+            }
+            (WithKind::Env, symbol) => Err(RuntimeError::new(
+                RuntimeErrorKind::Type,
+                format!("with_env expected an object, found {}", symbol.kind()),
+            )),
+            (WithKind::Dir, symbol) => Err(RuntimeError::new(
+                RuntimeErrorKind::Type,
+                format!("with_dir expected a string, found {}", symbol.kind()),
+            )),
+        }
+    }
+
+    fn restore(self, environment: &mut Environment) {
+        match self {
+            WithGuard::Env(snapshot) => environment.restore(snapshot),
+            WithGuard::Dir(previous) => environment.restore_dir(previous),
+        }
+    }
+}
 
 enum SymbolRef<'a> {
     MutRef(&'a mut Symbol),
     Value(Symbol),
 }
 
+/// Builtins that need the evaluator's state, see `call_builtin`.
+const EVALUATOR_BUILTINS: [&str; 8] = [
+    "run",
+    "exec",
+    "parallel",
+    "capture_stderr",
+    "pipefail",
+    "args_parse",
+    "measure",
+    "process.exit",
+];
+
+/// A function of the program sod is embedded in, registered with
+/// `ASTEvaluator::register_fn` and called from sod like a builtin.
+pub type HostFunction = dyn FnMut(Vec<Symbol>) -> Result<Symbol, String>;
+
+/// `raw(value)`, or `$name!`, inside a command is passed to the shell
+/// unquoted.
+fn is_raw_call(ce: &CallExpression) -> bool {
+    matches!(&*ce.base, ASTNode::Identifier(name) if &**name == "raw")
+}
+
+/// A command's words without the whitespace around them.
+fn trim_whitespace(mut tokens: &[ASTNode]) -> &[ASTNode] {
+    let blank = |node: &ASTNode| matches!(node, ASTNode::String(word) if word.trim().is_empty());
+    while tokens.first().is_some_and(blank) {
+        tokens = &tokens[1..];
+    }
+    while tokens.last().is_some_and(blank) {
+        tokens = &tokens[..tokens.len() - 1];
+    }
+    tokens
+}
+
+/// Quotes a value interpolated into a command. Each item of a list becomes
+/// an argument of its own.
+fn command_words(symbol: &Symbol) -> String {
+    match symbol {
+        Symbol::List(list) => list
+            .items()
+            .iter()
+            .map(|item| platform::current().quote(&item.raw_str()))
+            .collect::<Vec<String>>()
+            .join(" "),
+        symbol => platform::current().quote(&symbol.raw_str()),
+    }
+}
+
+/// `command_words` as the command is displayed, with secrets as `*****`.
+fn masked_words(symbol: &Symbol) -> String {
+    match symbol {
+        Symbol::List(list) => list
+            .items()
+            .iter()
+            .map(masked_words)
+            .collect::<Vec<String>>()
+            .join(" "),
+        Symbol::Secret(_) => symbol.to_string(),
+        symbol => platform::current().quote(&symbol.raw_str()),
+    }
+}
+
+/// A value's text as a command is displayed, with a secret as `*****`.
+fn masked_str(symbol: &Symbol) -> String {
+    match symbol {
+        Symbol::Secret(_) => symbol.to_string(),
+        symbol => symbol.raw_str(),
+    }
+}
+
+/// Text put together for a command, along with how it is displayed.
+#[derive(Default)]
+struct Interpolated {
+    text: String,
+    masked: String,
+}
+
+impl Interpolated {
+    fn push(&mut self, text: &str, masked: &str) {
+        self.text.push_str(text);
+        self.masked.push_str(masked);
+    }
+}
+
+/// The number in an index expression such as `x[i]`.
+fn index_of(symbol: Option<Symbol>) -> Result<usize, RuntimeError> {
+    // TODO: later use u64 instead for [-1] list access?
+    match symbol {
+        Some(Symbol::Number(index)) => Ok(index as usize),
+        _ => Err(RuntimeError::new(
+            RuntimeErrorKind::Type,
+            "indices must be numbers".to_string(),
+        )),
+    }
+}
+
+fn get_index(symbol: &Symbol, index: usize) -> Result<Symbol, RuntimeError> {
+    match symbol {
+        Symbol::List(list) => Ok(list.get(index)?.clone()),
+        Symbol::String(ss) => Ok(ss.get(index)?),
+        Symbol::Bytes(bytes) => match bytes.get(index) {
+            Some(byte) => Ok(Symbol::Number(*byte as f64)),
+            None => Err(RuntimeError::new(
+                RuntimeErrorKind::Argument,
+                "bytes index out of range".to_string(),
+            )),
+        },
+        _ => Err(RuntimeError::new(
+            RuntimeErrorKind::Type,
+            format!("{} is not indexable", symbol.kind()),
+        )),
+    }
+}
+
+fn get_property(symbol: &Symbol, property: &str) -> Result<Symbol, RuntimeError> {
+    match symbol {
+        Symbol::Object(obj) => match obj.get(property) {
+            Some(s) => Ok(s.clone()),
+            None => Err(RuntimeError::new(
+                RuntimeErrorKind::Undefined,
+                format!("object has no property {}", property),
+            )),
+        },
+        _ => Err(RuntimeError::new(
+            RuntimeErrorKind::Undefined,
+            format!("{} has no property {}", symbol.kind(), property),
+        )),
+    }
+}
+
+/// The start, end or increment of a range.
+fn range_bound(symbol: Option<Symbol>, label: &str) -> Result<i32, RuntimeError> {
+    match symbol {
+        Some(Symbol::Number(num)) => Ok(num as i32),
+        Some(symbol) => Err(RuntimeError::new(
+            RuntimeErrorKind::Type,
+            format!("range {} must be a number, found {}", label, symbol.kind()),
+        )),
+        None => Err(RuntimeError::new(
+            RuntimeErrorKind::NoValue,
+            "invalid range",
+        )),
+    }
+}
+
+/// Fails when a value holds more items or bytes than `Limits::value_size`
+/// allows.
+fn check_size(max: Option<usize>, symbol: &Symbol) -> Result<(), RuntimeError> {
+    match max {
+        Some(max) if symbol.size() > max => {
+            let unit = match symbol {
+                Symbol::List(_) | Symbol::Object(_) => "items",
+                _ => "bytes",
+            };
+            Err(RuntimeError::new(
+                RuntimeErrorKind::Limit,
+                format!("limit of {} {} in a {} exceeded", max, unit, symbol.kind()),
+            ))
+        }
+        _ => Ok(()),
+    }
+}
+
+/// The items a for loop goes over.
+fn iterate(symbol: Option<Symbol>) -> Result<Box<dyn Iterator<Item = Symbol>>, RuntimeError> {
+    match symbol {
+        Some(Symbol::List(list)) => Ok(Box::new(list.into_items().into_iter())),
+        Some(Symbol::String(ss)) => Ok(Box::new(ss.into_iter())),
+        Some(Symbol::Range(r)) => Ok(Box::new(r.into_iter())),
+        Some(symbol) => Err(RuntimeError::new(
+            RuntimeErrorKind::Type,
+            format!("{} is not iterable", symbol.kind()),
+        )),
+        None => Err(RuntimeError::new(
+            RuntimeErrorKind::NoValue,
+            "iterator not found".to_string(),
+        )),
+    }
+}
+
+/// Pops the last `n` values, in the order they were pushed.
+fn pop_n(stack: &mut Vec<Option<Symbol>>, n: usize) -> Vec<Option<Symbol>> {
+    stack.split_off(stack.len().saturating_sub(n))
+}
+
+/// Arguments for a call, which must all have values.
+fn call_args(values: Vec<Option<Symbol>>) -> Result<Vec<Symbol>, RuntimeError> {
+    values
+        .into_iter()
+        .map(|value| {
+            value.ok_or_else(|| {
+                RuntimeError::new(RuntimeErrorKind::NoValue, "argument has no value")
+            })
+        })
+        .collect()
+}
+
 pub struct ASTEvaluator {
     symbol_table: SymbolTable,
+    interactive: bool,
+    exit_status: Option<i32>,
+    /// Whether commands keep their stderr in `last_result` instead of
+    /// printing it.
+    capture_stderr: bool,
+    /// Whether a pipeline fails when any of its commands does, rather than
+    /// only the last.
+    pipefail: bool,
+    /// Whether commands are printed instead of run.
+    dry_run: bool,
+    /// The exit status of the last command run, 128+n if signal n ended it.
+    last_status: Option<i32>,
+    /// Whether the last statement run was a command, counting the statements
+    /// run inside blocks and functions.
+    ended_with_command: bool,
+    /// Whether each statement and command is printed to stderr as it runs.
+    trace: bool,
+    /// How many blocks deep the running statement is, to indent the trace.
+    depth: usize,
+    /// Whether loops are compiled to bytecode before they run.
+    vm: bool,
+    /// How many function calls deep the running statement is.
+    calls: usize,
+    limits: Limits,
+    /// How many loop passes the program has run, for `limits`.
+    iterations: u64,
+    /// When the program started, for `limits`.
+    started: Instant,
+    /// Set by a `return` inside a function, until the blocks and loops
+    /// around it have ended and the function can return.
+    returning: Option<Return>,
+    /// The arguments after the script name.
+    script_args: Vec<String>,
+    /// Handlers set with `trap`, run when a signal arrives.
+    signal_traps: HashMap<Signal, ASTNode>,
+    /// The handler set with `trap "..." EXIT`, run when the script ends.
+    exit_trap: Option<ASTNode>,
+    /// Functions registered by the program sod is embedded in.
+    host_functions: HashMap<String, Box<HostFunction>>,
+    /// What runs commands in place of this machine, if anything.
+    executor: Option<Box<dyn CommandExecutor>>,
+    /// Where the output of commands, dry runs and the trace is written.
+    output: Output,
+    /// The environment variables and directory the script has set for its
+    /// commands.
+    environment: Environment,
 }
 
 impl ASTEvaluator {
     pub fn new(argv: Vec<String>) -> Self {
+        let script_args = argv.iter().skip(1).cloned().collect();
         let global_vars = symbol::get_global_vars(argv);
         Self {
             symbol_table: SymbolTable::from(global_vars),
+            interactive: false,
+            exit_status: None,
+            capture_stderr: false,
+            pipefail: false,
+            dry_run: false,
+            last_status: Some(0),
+            ended_with_command: false,
+            trace: false,
+            depth: 0,
+            vm: false,
+            calls: 0,
+            limits: Limits::default(),
+            iterations: 0,
+            started: Instant::now(),
+            returning: None,
+            script_args,
+            signal_traps: HashMap::new(),
+            exit_trap: None,
+            host_functions: HashMap::new(),
+            executor: None,
+            output: Output::terminal(),
+            environment: Environment::new(),
+        }
+    }
+
+    /// Interactive evaluators run commands so they can be timed and cancelled
+    /// from the REPL.
+    pub fn set_interactive(&mut self, interactive: bool) {
+        self.interactive = interactive;
+    }
+
+    /// In a dry run each command is printed once interpolated, and treated as
+    /// if it succeeded without output.
+    pub fn set_dry_run(&mut self, dry_run: bool) {
+        self.dry_run = dry_run;
+    }
+
+    /// Compiles each for loop to bytecode, which runs faster than walking
+    /// its body on every pass. What can't be compiled is still walked.
+    /// Loops are walked as before while tracing.
+    pub fn set_vm(&mut self, vm: bool) {
+        self.vm = vm;
+    }
+
+    /// When tracing, each statement is printed to stderr before it runs, as
+    /// is each command once interpolated, indented by how deeply nested it
+    /// is. Secrets are hidden.
+    pub fn set_trace(&mut self, trace: bool) {
+        self.trace = trace;
+    }
+
+    /// Makes a Rust function callable from sod by name, like a builtin. Sod
+    /// functions of the same name are called instead, and it is called in
+    /// place of any builtin of the same name. Names such as `app.config`
+    /// are called as `app.config()`. An error it returns ends the program
+    /// like any other.
+    pub fn register_fn(
+        &mut self,
+        name: impl Into<String>,
+        function: impl FnMut(Vec<Symbol>) -> Result<Symbol, String> + 'static,
+    ) {
+        self.host_functions.insert(name.into(), Box::new(function));
+    }
+
+    /// Runs every command with `executor` rather than on this machine. The
+    /// sandbox and audit log still apply. Background jobs and `parallel` are
+    /// left to the executor, while pipelines through sod functions can't run
+    /// with an executor.
+    pub fn set_command_executor(&mut self, executor: impl CommandExecutor + 'static) {
+        self.executor = Some(Box::new(executor));
+    }
+
+    /// Writes the output of commands, and the commands printed by a dry run,
+    /// to `stdout` rather than the terminal. Commands run with
+    /// `interactive`, and those run by a command executor, write their own.
+    pub fn set_stdout(&mut self, stdout: impl Write + Send + 'static) {
+        self.output.stdout = Arc::new(Mutex::new(stdout));
+    }
+
+    /// Writes the errors of commands, the trace and errors in trap handlers
+    /// to `stderr` rather than the terminal.
+    pub fn set_stderr(&mut self, stderr: impl Write + Send + 'static) {
+        self.output.stderr = Arc::new(Mutex::new(stderr));
+    }
+
+    fn is_builtin(&self, name: &str) -> bool {
+        self.host_functions.contains_key(name)
+            || EVALUATOR_BUILTINS.contains(&name)
+            || builtins::get_env_builtin(name).is_some()
+            || builtins::get(name).is_some()
+    }
+
+    /// Limits how long a program may run and how much it may do, stopping
+    /// it with an error once it goes over.
+    pub fn set_limits(&mut self, limits: Limits) {
+        self.limits = limits;
+    }
+
+    /// The environment variables and directory the script's commands run
+    /// with, such as the PATH to parse later source with.
+    pub fn environment(&self) -> &Environment {
+        &self.environment
+    }
+
+    /// Sets up the environment the script's commands start with, such as
+    /// the variables imported from the login shell.
+    pub fn environment_mut(&mut self) -> &mut Environment {
+        &mut self.environment
+    }
+
+    pub fn symbol_table(&self) -> &SymbolTable {
+        &self.symbol_table
+    }
+
+    pub fn symbol_table_mut(&mut self) -> &mut SymbolTable {
+        &mut self.symbol_table
+    }
+
+    /// The status passed to `process.exit`, once the script has called it.
+    pub fn exit_status(&self) -> Option<i32> {
+        self.exit_status
+    }
+
+    /// The exit status of the last command run as a shell reports it, which
+    /// is 0 before any have run and 128+n if signal n ended it.
+    pub fn last_status(&self) -> Option<i32> {
+        self.last_status
+    }
+
+    /// Whether the last statement run was a command, wherever it ran, such as
+    /// at the end of an `if` block or a function. A script that ends this way
+    /// exits with `last_status`.
+    pub fn ended_with_command(&self) -> bool {
+        self.ended_with_command
+    }
+
+    /// Evaluates a program. If it calls `process.exit`, evaluation stops and
+    /// the results so far are returned; check `exit_status` afterwards.
+    pub fn eval(&mut self, program: ASTNode) -> Result<Vec<Option<Symbol>>, RuntimeError> {
+        let mut prog_results = vec![];
+        self.start_limits();
+        match program {
+            ASTNode::Program(root) => {
+                for (i, line) in root.body.iter().enumerate() {
+                    self.trace_statement(line);
+                    let result = self
+                        .check_limits()
+                        .and_then(|_| self.eval_node(line))
+                        .map_err(|e| e.at(root.spans.get(i)));
+                    // a signal may arrive during the last statement, or stop
+                    // the command it was running with an error
+                    if let Some(signal) = commands::take_signal() {
+                        self.on_signal(signal);
+                    }
+
+                    match result {
+                        Ok(_) if self.exit_status.is_some() => break,
+                        Ok(result) => prog_results.push(result),
+                        Err(_) if self.exit_status.is_some() => break,
+                        Err(e) => return Err(e),
+                    }
+                }
+                Ok(prog_results)
+            }
+            _ => Err(RuntimeError::new(
+                RuntimeErrorKind::Argument,
+                "expected program".to_string(),
+            )),
+        }
+    }
+
+    /// Calls a sod function, or a builtin, by name, as the program would.
+    /// The limits apply to the call as they would to a program.
+    pub fn call(&mut self, name: &str, args: Vec<Symbol>) -> Result<Symbol, RuntimeError> {
+        match self.symbol_table.get(name) {
+            Some(Symbol::Function(_)) | None => (),
+            Some(symbol) => {
+                return Err(RuntimeError::new(
+                    RuntimeErrorKind::Type,
+                    format!("'{}' is a {}, not a function", name, symbol.kind()),
+                ))
+            }
+        }
+
+        self.start_limits();
+        self.call_named(name, args)
+    }
+
+    /// Starts counting towards `limits` from nothing.
+    fn start_limits(&mut self) {
+        self.iterations = 0;
+        self.started = Instant::now();
+    }
+
+    /// Fails once the program has run for longer, or holds more symbols,
+    /// than `limits` allows.
+    fn check_limits(&self) -> Result<(), RuntimeError> {
+        if let Some(timeout) = self.limits.timeout {
+            if self.started.elapsed() > timeout {
+                return Err(RuntimeError::new(
+                    RuntimeErrorKind::Limit,
+                    format!("time limit of {:?} exceeded", timeout),
+                ));
+            }
+        }
+        if let Some(max) = self.limits.symbols {
+            if self.symbol_table.len() > max {
+                return Err(RuntimeError::new(
+                    RuntimeErrorKind::Limit,
+                    format!("limit of {} symbols exceeded", max),
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// Counts a pass of a loop, failing once there have been more than
+    /// `limits` allows.
+    fn count_iteration(&mut self) -> Result<(), RuntimeError> {
+        self.iterations += 1;
+        if let Some(max) = self.limits.loop_iterations {
+            if self.iterations > max {
+                return Err(RuntimeError::new(
+                    RuntimeErrorKind::Limit,
+                    format!("limit of {} loop iterations exceeded", max),
+                ));
+            }
+        }
+        self.check_limits()
+    }
+
+    /// Prints a line of the trace. Commands are displayed with the values of
+    /// secrets hidden.
+    fn trace(&self, line: &str) {
+        let line = format!("+ {}{}", "  ".repeat(self.depth), line);
+        commands::write_line(&self.output.stderr, &line);
+    }
+
+    /// Traces a statement. Commands are traced once interpolated instead.
+    fn trace_statement(&self, node: &ASTNode) {
+        let is_command = matches!(
+            node,
+            ASTNode::Command(_) | ASTNode::Capture(_) | ASTNode::Spawn(_) | ASTNode::Interactive(_)
+        );
+        if self.trace && !is_command {
+            self.trace(&source::statement(node));
+        }
+    }
+
+    fn trace_command(&self, cmd: &CommandLine) {
+        if self.trace {
+            self.trace(&cmd.to_string());
+        }
+    }
+
+    /// Runs the `EXIT` trap, if the script set one. Call this once the script
+    /// has ended, however it ended.
+    pub fn run_exit_trap(&mut self) {
+        if let Some(handler) = self.exit_trap.take() {
+            let status = self.exit_status;
+            self.run_trap(handler, status);
+        }
+    }
+
+    /// Runs the trap for a signal, then stops the script with the status a
+    /// shell reports for a process ended by the signal. Without a trap, Ctrl-C
+    /// in the REPL only abandons the line being evaluated.
+    fn on_signal(&mut self, signal: Signal) -> String {
+        let status = Some(128 + signal.number());
+        match self.signal_traps.remove(&signal) {
+            Some(handler) => self.run_trap(handler, status),
+            None if self.interactive && signal == Signal::Int => return "interrupted".to_string(),
+            None => self.exit_status = status,
+        }
+        format!("received {}", signal.name())
+    }
+
+    /// Runs a trap's handler, which may call `process.exit` to replace the
+    /// status the script ends with.
+    fn run_trap(&mut self, handler: ASTNode, status: Option<i32>) {
+        self.exit_status = None;
+        if let ASTNode::Program(program) = handler {
+            for statement in program.body.iter() {
+                if let Err(e) = self.eval_node(statement) {
+                    if self.exit_status.is_none() {
+                        commands::write_line(&self.output.stderr, &e.to_string());
+                    }
+                    break;
+                }
+            }
+        }
+
+        if self.exit_status.is_none() {
+            self.exit_status = status;
+        }
+    }
+
+    /// Sets or, with an empty handler, clears a trap. A trap on `EXIT` also
+    /// catches signals, so the handler runs when a signal ends the script.
+    fn eval_trap_statement(&mut self, trap: &TrapStatement) -> Result<(), RuntimeError> {
+        let handler = match &*trap.handler {
+            ASTNode::Program(program) if program.body.is_empty() => None,
+            handler => Some(handler.clone()),
+        };
+
+        for name in &trap.signals {
+            match Signal::from_name(name) {
+                Some(signal) => {
+                    commands::handle_signal(signal)?;
+                    match &handler {
+                        Some(handler) => self.signal_traps.insert(signal, handler.clone()),
+                        None => self.signal_traps.remove(&signal),
+                    };
+                }
+                None => {
+                    // signals that can't be caught on this platform still end
+                    // the script without it
+                    for signal in Signal::ALL {
+                        let _ = commands::handle_signal(signal);
+                    }
+                    self.exit_trap = handler.clone();
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn eval_node(&mut self, node: &ASTNode) -> Result<Option<Symbol>, RuntimeError> {
+        if let Some(signal) = commands::take_signal() {
+            return Err(RuntimeError::new(
+                RuntimeErrorKind::Signal,
+                self.on_signal(signal),
+            ));
+        }
+
+        // blocks and calls end with whatever statement they ran last
+        let nested = matches!(
+            node,
+            ASTNode::IfStatement(_)
+                | ASTNode::BlockStatement(_)
+                | ASTNode::ForStatement(_)
+                | ASTNode::WithStatement(_)
+                | ASTNode::CallExpression(_)
+                | ASTNode::TimeExpression(_)
+        );
+        if nested {
+            self.ended_with_command = false;
+        }
+
+        let option = match node {
+            ASTNode::BinaryExpression(be) => self.eval_binary_expression(be)?,
+            ASTNode::UnaryExpression(n) => self.eval_unary_expression(n)?,
+            ASTNode::VariableExpression(ve) => {
+                self.eval_variable_expression(ve)?;
+                None
+            }
+            ASTNode::MemberExpression(me) => Some(self.visit_member_expression(me)?),
+            ASTNode::IndexExpression(ie) => Some(self.visit_index_expression(ie)?),
+            // shared from here on, so calling it or copying it copies nothing
+            ASTNode::FunctionStatement(fs) => {
+                let function = Rc::new(fs.clone());
+                if self.vm {
+                    function
+                        .compiled
+                        .get_or_init(|| bytecode::compile(&function.body));
+                }
+                self.symbol_table
+                    .set(fs.name.clone(), Symbol::Function(function));
+                None
+            }
+            // aliases are expanded by the parser
+            ASTNode::AliasStatement(_) => None,
+            ASTNode::TrapStatement(trap) => {
+                self.eval_trap_statement(trap)?;
+                None
+            }
+            ASTNode::CallExpression(fc) => Some(self.eval_call_expression(fc)?),
+            ASTNode::IfStatement(is) => {
+                self.eval_if_statement(is)?;
+                None
+            }
+
+            ASTNode::BlockStatement(bs) => Some(self.eval_block_statement(bs)?),
+            ASTNode::ReturnStatement(expr) => self.eval_return(expr)?,
+            ASTNode::ForStatement(_) if self.vm && !self.trace => {
+                let chunk = bytecode::compile(node);
+                self.run_chunk(&chunk)?
+            }
+            ASTNode::ForStatement(fs) => {
+                self.eval_for_statement(fs)?;
+                None
+            }
+            ASTNode::WithStatement(ws) => {
+                self.eval_with_statement(ws)?;
+                None
+            }
+            ASTNode::TimeExpression(body) => Some(self.eval_time_expression(body)?),
+
+            ASTNode::Number(value) => Some(Symbol::Number(*value)),
+            ASTNode::Boolean(value) => Some(Symbol::Boolean(*value)),
+            ASTNode::String(value) => Some(new_string_symbol!(value.clone())),
+            ASTNode::TemplateString(ts) => Some(self.visit_template_string(ts)?),
+            ASTNode::List(nodes) => Some(self.eval_list(nodes)?),
+            ASTNode::Object(entries) => Some(self.eval_object(entries)?),
+            ASTNode::None => Some(Symbol::None),
+            ASTNode::RangeExpression(range_expr) => {
+                Some(Symbol::Range(self.visit_range_expression(range_expr)?))
+            }
+
+            ASTNode::Command(cmd) => Some(self.eval_command(cmd, true)?),
+            ASTNode::Capture(cmd) => Some(self.eval_command(cmd, false)?),
+            ASTNode::Spawn(cmd) => Some(self.eval_spawn(cmd)?),
+            ASTNode::Interactive(cmd) => {
+                self.eval_interactive(cmd)?;
+                None
+            }
+            // TODO: allow returning reference to a symbol in the future.
+            ASTNode::Identifier(ident) => Some(self.get_symbol(ident)?.clone()),
+            ASTNode::Program(_) => None,
+        };
+
+        if !nested {
+            self.ended_with_command = matches!(
+                node,
+                ASTNode::Command(_) | ASTNode::Capture(_) | ASTNode::Interactive(_)
+            );
+        }
+        Ok(option)
+    }
+
+    fn visit_node_mut(&mut self, node: &ASTNode) -> Result<SymbolRef<'_>, RuntimeError> {
+        let res = match node {
+            ASTNode::MemberExpression(me) => {
+                SymbolRef::MutRef(self.visit_member_expression_mut(me)?)
+            }
+            ASTNode::Identifier(ident) => SymbolRef::MutRef(self.get_symbol_mut(ident)?),
+            ASTNode::IndexExpression(ie) => SymbolRef::MutRef(self.visit_index_expression_mut(ie)?),
+            ASTNode::CallExpression(ce) => SymbolRef::Value(self.eval_call_expression(ce)?),
+            _ => return Err(RuntimeError::new(RuntimeErrorKind::Type, "not mutable")),
+        };
+
+        Ok(res)
+    }
+
+    fn get_symbol(&self, name: &str) -> Result<&Symbol, RuntimeError> {
+        match self.symbol_table.get(&name) {
+            Some(symbol) => Ok(symbol),
+            None => Err(RuntimeError::new(
+                RuntimeErrorKind::Undefined,
+                format!("'{}' is not defined", name),
+            )),
+        }
+    }
+
+    fn get_symbol_mut(&mut self, name: &str) -> Result<&mut Symbol, RuntimeError> {
+        match self.symbol_table.get_mut(&name) {
+            Some(symbol) => Ok(symbol),
+            None => Err(RuntimeError::new(
+                RuntimeErrorKind::Undefined,
+                format!("'{}' is not defined", name),
+            )),
+        }
+    }
+
+    /// Looks up a template expression, following any properties such as
+    /// `$process.env.HOME`.
+    fn get_template_symbol(&self, expr: &str) -> Result<&Symbol, RuntimeError> {
+        let mut path = expr.split('.');
+        let mut symbol = self.get_symbol(path.next().unwrap_or_default())?;
+        for property in path {
+            symbol = match symbol {
+                Symbol::Object(obj) => obj.get(property).ok_or_else(|| {
+                    RuntimeError::new(
+                        RuntimeErrorKind::Undefined,
+                        format!("object has no property {}", property),
+                    )
+                })?,
+                _ => {
+                    return Err(RuntimeError::new(
+                        RuntimeErrorKind::Undefined,
+                        format!("{} has no property {}", symbol.kind(), property),
+                    ))
+                }
+            };
+        }
+
+        Ok(symbol)
+    }
+
+    fn visit_template_string(
+        &self,
+        template_string: &TemplateString,
+    ) -> Result<Symbol, RuntimeError> {
+        let mut res = "".to_string();
+        let mut secret = false;
+        for token in &template_string.tokens {
+            match token {
+                ast::TemplateToken::Expression(expr) => {
+                    let symbol = self.get_template_symbol(expr)?;
+                    secret |= matches!(symbol, Symbol::Secret(_));
+                    res.push_str(&symbol.raw_str());
+                }
+                ast::TemplateToken::Literal(s) => res.push_str(s),
+            }
+        }
+
+        if secret {
+            return Ok(Symbol::Secret(StringSymbol::new(res)));
+        }
+
+        Ok(new_string_symbol!(res))
+    }
+
+    fn visit_range_expression(
+        &mut self,
+        range_expr: &RangeExpression,
+    ) -> Result<Range, RuntimeError> {
+        let mut visit_range_prop = |node: &ASTNode, label: &str| -> Result<i32, RuntimeError> {
+            range_bound(self.eval_node(node)?, label)
+        };
+
+        let start = visit_range_prop(&range_expr.start, "start")?;
+        let end = visit_range_prop(&range_expr.end, "end")?;
+        let increment = if let Some(inc) = &range_expr.increment {
+            Some(visit_range_prop(inc, "increment")?)
+        } else {
+            None
+        };
+
+        Ok(Range::new(start, end, increment))
+    }
+
+    fn visit_iterable(
+        &mut self,
+        iterable: &ast::Iterable,
+    ) -> Result<Box<dyn Iterator<Item = Symbol>>, RuntimeError> {
+        match iterable {
+            ast::Iterable::RangeExpression(re) => {
+                let iterator = self.visit_range_expression(re)?;
+                Ok(Box::new(iterator))
+            }
+            ast::Iterable::Collection(node) => iterate(self.eval_node(node)?),
+        }
+    }
+
+    fn bind_for_variables(
+        &mut self,
+        binding: &ast::ForBinding,
+        symbol: Symbol,
+    ) -> Result<(), RuntimeError> {
+        let names = match binding {
+            ast::ForBinding::Identifier(name) => {
+                self.symbol_table.set(name.clone(), symbol);
+                return Ok(());
+            }
+            ast::ForBinding::Destructure(names) => names,
+        };
+
+        let items = match symbol {
+            Symbol::List(list) => list.into_items(),
+            _ => {
+                return Err(RuntimeError::new(
+                    RuntimeErrorKind::Type,
+                    format!("unable to destructure {}", symbol.kind()),
+                ))
+            }
+        };
+
+        if items.len() < names.len() {
+            return Err(RuntimeError::new(
+                RuntimeErrorKind::Type,
+                format!(
+                    "unable to destructure {} values from a list of {}",
+                    names.len(),
+                    items.len()
+                ),
+            ));
+        }
+
+        for (name, item) in names.iter().zip(items) {
+            self.symbol_table.set(name.clone(), item);
+        }
+
+        Ok(())
+    }
+
+    /// Runs code compiled by `bytecode::compile`, returning the value it
+    /// leaves.
+    fn run_chunk(&mut self, chunk: &Chunk) -> Result<Option<Symbol>, RuntimeError> {
+        let mut stack: Vec<Option<Symbol>> = vec![];
+        let mut iterators: Vec<Box<dyn Iterator<Item = Symbol>>> = vec![];
+        let mut scopes = 0;
+        let mut pc = 0;
+
+        while let Some(instruction) = chunk.instructions.get(pc) {
+            pc += 1;
+            match instruction {
+                Instruction::Constant(value) => stack.push(value.clone()),
+                Instruction::Load(name) => stack.push(Some(self.get_symbol(name)?.clone())),
+                Instruction::LoadIndex(name) => {
+                    let index = index_of(stack.pop().flatten())?;
+                    stack.push(Some(get_index(self.get_symbol(name)?, index)?));
+                }
+                Instruction::LoadProperty(name, property) => {
+                    stack.push(Some(get_property(self.get_symbol(name)?, property)?));
+                }
+                Instruction::Store(name) => match stack.pop().flatten() {
+                    Some(symbol) => self.symbol_table.set(name.clone(), symbol),
+                    None => {
+                        return Err(RuntimeError::new(
+                            RuntimeErrorKind::NoValue,
+                            "right hand side not found",
+                        ))
+                    }
+                },
+                Instruction::Binary(operator) => {
+                    let right = stack.pop().flatten();
+                    let left = stack.pop().flatten();
+                    let result = match (left, right) {
+                        (Some(left), Some(right)) => {
+                            let result = symbol::eval_binary_expression(&left, operator, &right)?;
+                            check_size(self.limits.value_size, &result)?;
+                            Some(result)
+                        }
+                        _ => None,
+                    };
+                    stack.push(result);
+                }
+                Instruction::Negate => {
+                    let result = match stack.pop().flatten() {
+                        Some(Symbol::Number(num)) => Some(Symbol::Number(-num)),
+                        _ => None,
+                    };
+                    stack.push(result);
+                }
+                Instruction::Index => {
+                    let symbol = stack.pop().flatten().unwrap_or(Symbol::None);
+                    let index = index_of(stack.pop().flatten())?;
+                    stack.push(Some(get_index(&symbol, index)?));
+                }
+                Instruction::Property(property) => match stack.pop().flatten() {
+                    Some(symbol) => stack.push(Some(get_property(&symbol, property)?)),
+                    None => {
+                        return Err(RuntimeError::new(
+                            RuntimeErrorKind::Undefined,
+                            format!("none has no property {}", property),
+                        ))
+                    }
+                },
+                Instruction::Call(name, count) => {
+                    let args = call_args(pop_n(&mut stack, *count))?;
+                    stack.push(Some(self.call_named(name, args)?));
+                }
+                Instruction::CallMethod(name, call, count) => {
+                    let args = call_args(pop_n(&mut stack, *count))?;
+                    let builtin = format!("{}.{}", name, call);
+                    let max = self.limits.value_size;
+                    let result = match self.is_builtin(&builtin) {
+                        true => self.call_builtin(&builtin, args)?,
+                        false => {
+                            let symbol = self.get_symbol_mut(name)?;
+                            let result = symbol.call(call, args)?;
+                            check_size(max, symbol)?;
+                            result
+                        }
+                    };
+                    check_size(max, &result)?;
+                    stack.push(Some(result));
+                }
+                Instruction::CallValueMethod(call, count) => {
+                    let mut symbol = stack.pop().flatten().unwrap_or(Symbol::None);
+                    let args = call_args(pop_n(&mut stack, *count))?;
+                    let result = symbol.call(call, args)?;
+                    check_size(self.limits.value_size, &result)?;
+                    stack.push(Some(result));
+                }
+                Instruction::List(count) => {
+                    let mut items = vec![];
+                    for value in pop_n(&mut stack, *count) {
+                        match value {
+                            Some(symbol) => items.push(symbol),
+                            None => {
+                                return Err(RuntimeError::new(
+                                    RuntimeErrorKind::NoValue,
+                                    "invalid expression in list",
+                                ))
+                            }
+                        }
+                    }
+                    let list = Symbol::List(List::from(items));
+                    check_size(self.limits.value_size, &list)?;
+                    stack.push(Some(list));
+                }
+                Instruction::Object(keys) => {
+                    let mut object = Object::from(vec![]);
+                    for (key, value) in keys.iter().zip(pop_n(&mut stack, keys.len())) {
+                        match value {
+                            Some(symbol) => object.insert(key.clone(), symbol),
+                            None => {
+                                return Err(RuntimeError::new(
+                                    RuntimeErrorKind::NoValue,
+                                    format!("invalid expression for key '{}'", key),
+                                ))
+                            }
+                        }
+                    }
+                    let object = Symbol::Object(object);
+                    check_size(self.limits.value_size, &object)?;
+                    stack.push(Some(object));
+                }
+                Instruction::Range(has_increment) => {
+                    let increment = match has_increment {
+                        true => Some(range_bound(stack.pop().flatten(), "increment")?),
+                        false => None,
+                    };
+                    let end = range_bound(stack.pop().flatten(), "end")?;
+                    let start = range_bound(stack.pop().flatten(), "start")?;
+                    stack.push(Some(Symbol::Range(Range::new(start, end, increment))));
+                }
+                Instruction::OrNone => {
+                    let value = stack.pop().flatten().unwrap_or(Symbol::None);
+                    stack.push(Some(value));
+                }
+                Instruction::Pop => {
+                    stack.pop();
+                }
+                Instruction::Jump(to) => pc = *to,
+                Instruction::JumpUnless(to) => {
+                    if !stack.pop().flatten().is_some_and(|s| s.is_truthy()) {
+                        pc = *to;
+                    }
+                }
+                Instruction::JumpUnlessKeep(to) => match stack.last() {
+                    Some(Some(symbol)) if symbol.is_truthy() => {
+                        stack.pop();
+                    }
+                    _ => pc = *to,
+                },
+                Instruction::JumpIfKeep(to) => match stack.last() {
+                    Some(Some(symbol)) if !symbol.is_truthy() => {
+                        stack.pop();
+                    }
+                    _ => pc = *to,
+                },
+                Instruction::PushScope(kind) => {
+                    self.symbol_table.push_scope(*kind);
+                    scopes += 1;
+                }
+                Instruction::PopScope => {
+                    self.symbol_table.pop_scope();
+                    scopes -= 1;
+                }
+                Instruction::Iterate => iterators.push(iterate(stack.pop().flatten())?),
+                Instruction::Next(to) => {
+                    if let Some(signal) = commands::take_signal() {
+                        return Err(RuntimeError::new(
+                            RuntimeErrorKind::Signal,
+                            self.on_signal(signal),
+                        ));
+                    }
+                    match iterators.last_mut().and_then(|iterator| iterator.next()) {
+                        Some(item) => {
+                            self.count_iteration()?;
+                            stack.push(Some(item));
+                        }
+                        None => {
+                            iterators.pop();
+                            pc = *to;
+                        }
+                    }
+                }
+                Instruction::Bind(binding) => {
+                    let item = stack.pop().flatten().unwrap_or(Symbol::None);
+                    self.bind_for_variables(binding, item)?;
+                }
+                Instruction::Eval(node) => {
+                    let value = self.eval_node(node)?;
+                    // a return leaves the function, and every loop in it
+                    if self.returning.is_some() {
+                        for _ in 0..scopes {
+                            self.symbol_table.pop_scope();
+                        }
+                        return Ok(None);
+                    }
+                    stack.push(value);
+                }
+            }
+        }
+
+        Ok(stack.pop().flatten())
+    }
+
+    fn eval_for_statement(&mut self, for_statement: &ForStatement) -> Result<(), RuntimeError> {
+        let iterable = self.visit_iterable(&for_statement.iterable)?;
+        self.symbol_table.push_scope(ScopeKind::ForBlock);
+
+        for symbol in iterable {
+            self.count_iteration()?;
+            self.bind_for_variables(&for_statement.binding, symbol)?;
+            self.eval_node(&for_statement.body)?;
+            if self.returning.is_some() {
+                break;
+            }
+        }
+
+        self.symbol_table.pop_scope();
+        Ok(())
+    }
+
+    fn eval_with_statement(&mut self, with_statement: &WithStatement) -> Result<(), RuntimeError> {
+        let argument = match self.eval_node(&with_statement.argument)? {
+            Some(symbol) => symbol,
+            None => Symbol::None,
+        };
+        let guard = WithGuard::enter(&with_statement.kind, argument, &mut self.environment)?;
+
+        self.symbol_table.push_scope(ScopeKind::WithBlock);
+        let result = self.eval_node(&with_statement.body);
+        self.symbol_table.pop_scope();
+
+        guard.restore(&mut self.environment);
+        result.map(|_| ())
+    }
+
+    /// Runs a block and returns how long it took in seconds.
+    fn eval_time_expression(&mut self, body: &ASTNode) -> Result<Symbol, RuntimeError> {
+        let start = Instant::now();
+        self.symbol_table.push_scope(ScopeKind::TimeBlock);
+        let result = self.eval_node(body);
+        self.symbol_table.pop_scope();
+
+        result.map(|_| Symbol::Number(start.elapsed().as_secs_f64()))
+    }
+
+    fn visit_function_args(&mut self, args: &[ASTNode]) -> Result<Vec<Symbol>, RuntimeError> {
+        let mut result = vec![];
+        for node in args {
+            match self.eval_node(node)? {
+                Some(symbol) => result.push(symbol),
+                None => {
+                    return Err(RuntimeError::new(
+                        RuntimeErrorKind::NoValue,
+                        "argument has no value",
+                    ))
+                }
+            };
+        }
+
+        Ok(result)
+    }
+
+    fn eval_list(&mut self, nodes: &[ASTNode]) -> Result<Symbol, RuntimeError> {
+        let mut items = vec![];
+        for node in nodes {
+            match self.eval_node(node)? {
+                Some(symbol) => items.push(symbol),
+                None => {
+                    return Err(RuntimeError::new(
+                        RuntimeErrorKind::NoValue,
+                        "invalid expression in list",
+                    ))
+                }
+            }
+        }
+
+        let list = Symbol::List(List::from(items));
+        check_size(self.limits.value_size, &list)?;
+        Ok(list)
+    }
+
+    fn eval_object(&mut self, entries: &[(String, ASTNode)]) -> Result<Symbol, RuntimeError> {
+        let mut object = Object::from(vec![]);
+        for (key, node) in entries {
+            match self.eval_node(node)? {
+                Some(symbol) => object.insert(key.clone(), symbol),
+                None => {
+                    return Err(RuntimeError::new(
+                        RuntimeErrorKind::NoValue,
+                        format!("invalid expression for key '{}'", key),
+                    ))
+                }
+            }
+        }
+
+        let object = Symbol::Object(object);
+        check_size(self.limits.value_size, &object)?;
+        Ok(object)
+    }
+
+    /// Interpolates a command into the line passed to the shell, along with
+    /// the values of its environment variables.
+    /// The value of an environment variable or redirect target, which is
+    /// passed on as is rather than quoted for the shell.
+    fn word_value(&mut self, word: &[ASTNode]) -> Result<Interpolated, RuntimeError> {
+        let mut s = Interpolated::default();
+        for node in word {
+            match node {
+                ASTNode::String(word) => s.push(word, word),
+                ASTNode::TemplateString(ts) => {
+                    let symbol = self.visit_template_string(ts)?;
+                    s.push(&symbol.raw_str(), &masked_str(&symbol))
+                }
+                _ => {
+                    if let Some(symbol) = self.eval_node(node)? {
+                        s.push(&symbol.raw_str(), &masked_str(&symbol));
+                    }
+                }
+            }
+        }
+        Ok(s)
+    }
+
+    fn command_line(&mut self, command: &ast::Command) -> Result<CommandLine, RuntimeError> {
+        self.command_line_of(command, &command.tokens)
+    }
+
+    /// Builds a command line from a command's variables and redirect, with
+    /// `tokens` as its words.
+    fn command_line_of(
+        &mut self,
+        command: &ast::Command,
+        tokens: &[ASTNode],
+    ) -> Result<CommandLine, RuntimeError> {
+        let mut envs = vec![];
+        let mut masked_envs = vec![];
+        for (name, value) in &command.envs {
+            let value = self.word_value(value)?;
+            envs.push((name.clone(), value.text));
+            masked_envs.push((name.clone(), value.masked));
+        }
+
+        let redirect = match &command.redirect {
+            Some(redirect) => {
+                // relative to the directory the command runs in
+                let target = self.word_value(&redirect.target)?.text;
+                Some(commands::Redirect {
+                    path: self.environment.resolve(target),
+                    append: redirect.append,
+                })
+            }
+            None => None,
+        };
+
+        let cmd = self.command_string(tokens)?;
+        Ok(CommandLine::shell(cmd.text)
+            .with_environment(self.environment.clone())
+            .with_envs(envs)
+            .with_masked(Program::Shell(cmd.masked), masked_envs)
+            .with_redirect(redirect))
+    }
+
+    /// Builds the shell command line from a command's words, quoting the
+    /// values of variables and expressions.
+    fn command_string(&mut self, tokens: &[ASTNode]) -> Result<Interpolated, RuntimeError> {
+        let mut cmd_string = Interpolated::default();
+        for node in tokens {
+            match node {
+                ASTNode::TemplateString(ts) => {
+                    let s = self.visit_template_string(ts)?;
+                    cmd_string.push(&platform::current().quote(&s.raw_str()), &masked_words(&s));
+                }
+                ASTNode::String(word) => cmd_string.push(word, word),
+                ASTNode::CallExpression(ce) if is_raw_call(ce) => {
+                    if let Some(s) = self.eval_node(node)? {
+                        cmd_string.push(&s.raw_str(), &masked_str(&s));
+                    }
+                }
+                _ => match self.eval_node(node)? {
+                    Some(s) => {
+                        cmd_string.push(&command_words(&s), &masked_words(&s));
+                    }
+                    None => {
+                        let empty = platform::current().quote("");
+                        cmd_string.push(&empty, &empty);
+                    }
+                },
+            }
+        }
+
+        Ok(cmd_string)
+    }
+
+    fn eval_command(&mut self, command: &ast::Command, echo: bool) -> Result<Symbol, RuntimeError> {
+        // with pipefail, sod runs each command of a pipeline itself to see
+        // every status
+        if !command.stages.is_empty()
+            || (self.executor.is_none() && self.pipe_segments(&command.tokens).len() > 1)
+        {
+            return self.eval_pipeline(command, echo);
+        }
+
+        let cmd = self.command_line(command)?;
+        let echo = Echo {
+            stdout: echo,
+            stderr: !self.capture_stderr,
+        };
+        let result = self.run_command(&cmd, echo)?;
+        Ok(Symbol::from_bytes(result.stdout))
+    }
+
+    fn eval_spawn(&mut self, command: &ast::Command) -> Result<Symbol, RuntimeError> {
+        if !command.stages.is_empty() {
+            return Err(RuntimeError::new(
+                RuntimeErrorKind::Command,
+                "spawn can't be used with a pipeline through functions".to_string(),
+            ));
+        }
+
+        let cmd = self.command_line(command)?;
+        self.trace_command(&cmd);
+        let job = if self.dry_run {
+            commands::write_line(&self.output.stdout, &cmd.to_string());
+            Job::finished(CommandResult::skipped(&cmd))
+        } else if let Some(executor) = self.executor.as_deref_mut() {
+            commands::spawn_with(executor, &cmd)?
+        } else {
+            Job::spawn(&cmd)?
+        };
+
+        Ok(Symbol::Job(JobRef::new(job)))
+    }
+
+    /// Runs a pipeline with sod functions as stages, such as
+    /// `tail -f log | parse_line`. Each line is passed to the function as it
+    /// arrives, and whatever it returns, other than none, is the function's
+    /// output.
+    fn eval_pipeline(
+        &mut self,
+        command: &ast::Command,
+        echo: bool,
+    ) -> Result<Symbol, RuntimeError> {
+        let start = Instant::now();
+        let segments = self.pipe_segments(&command.tokens);
+        let mut first = self.command_line_of(command, segments[0])?;
+        let mut redirect = first.redirect.take();
+
+        let mut plan = vec![PipelineStage::Command(first)];
+        for tokens in &segments[1..] {
+            plan.push(PipelineStage::Command(self.stage_command(tokens)?));
+        }
+        for stage in &command.stages {
+            match stage {
+                ast::PipeStage::Function(name) => plan.push(PipelineStage::Function(name.clone())),
+                ast::PipeStage::Command(tokens) => {
+                    for tokens in self.pipe_segments(tokens) {
+                        plan.push(PipelineStage::Command(self.stage_command(tokens)?));
+                    }
+                }
+            }
+        }
+
+        // a command at the end writes to the file itself
+        if let Some(PipelineStage::Command(last)) = plan.last_mut() {
+            last.redirect = redirect.take();
+        }
+
+        let stages: Vec<String> = plan.iter().map(|stage| stage.to_string()).collect();
+        let mut display = stages.join(" | ");
+        if let Some(redirect) = &redirect {
+            display = CommandLine::shell(display)
+                .with_redirect(Some(redirect.clone()))
+                .to_string();
+        }
+
+        if self.trace {
+            self.trace(&display);
+        }
+        if self.dry_run {
+            commands::write_line(&self.output.stdout, &display);
+            self.record_result(CommandResult::skipped(&CommandLine::shell(display)));
+            return Ok(new_string_symbol!("".to_string()));
+        }
+        if self.executor.is_some() {
+            return Err(RuntimeError::new(
+                RuntimeErrorKind::Command,
+                "a pipeline through functions can't run with a command executor".to_string(),
+            ));
+        }
+
+        let mut file = redirect
+            .as_ref()
+            .map(|redirect| redirect.open())
+            .transpose()?;
+        // commands next to each other are connected directly, so what passes
+        // between them isn't changed
+        let mut stages: Vec<RunningStage> = vec![];
+        let mut plan = plan.into_iter().peekable();
+        while let Some(stage) = plan.next() {
+            let echo_stderr = (!self.capture_stderr).then(|| self.output.stderr.clone());
+            let to_command = matches!(plan.peek(), Some(PipelineStage::Command(_)));
+            let (input, done) = match stages.last_mut() {
+                None => (PipeInput::None, true),
+                Some(RunningStage::Command { command, .. }) => match command.take_stdout() {
+                    Some(stdout) => (PipeInput::Command(stdout), true),
+                    None => (PipeInput::None, true),
+                },
+                Some(RunningStage::Function(_)) => (PipeInput::Lines, false),
+            };
+            stages.push(match stage {
+                PipelineStage::Command(cmd) => RunningStage::Command {
+                    command: commands::PipeCommand::spawn(&cmd, input, to_command, echo_stderr)?,
+                    done,
+                },
+                PipelineStage::Function(name) => RunningStage::Function(name),
+            });
         }
-    }
 
-    pub fn eval(&mut self, program: ASTNode) -> Result<Vec<Option<Symbol>>, String> {
-        let mut prog_results = vec![];
-        match program {
-            ASTNode::Program(root) => {
-                for line in *root {
-                    prog_results.push(self.eval_node(line)?);
-                }
-                Ok(prog_results)
+        let mut output = vec![];
+        while let Some(item) = self.pull_line(&mut stages)? {
+            let bytes = item.into_bytes();
+            if let Some(file) = file.as_mut() {
+                file.write_all(&bytes).map_err(|e| {
+                    RuntimeError::new(RuntimeErrorKind::Io, format!("failed to write: {}", e))
+                })?;
+                continue;
             }
-            _ => Err("expected program".to_string()),
-        }
-    }
 
-    fn eval_node(&mut self, node: ASTNode) -> Result<Option<Symbol>, String> {
-        let option = match node {
-            ASTNode::BinaryExpression(be) => self.eval_binary_expression(be)?,
-            ASTNode::UnaryExpression(n) => self.eval_unary_expression(*n)?,
-            ASTNode::VariableExpression(ve) => {
-                self.eval_variable_expression(ve)?;
-                None
+            if echo {
+                commands::write_bytes(&self.output.stdout, &bytes);
             }
-            ASTNode::MemberExpression(me) => Some(self.visit_member_expression(me)?.clone()),
-            ASTNode::IndexExpression(ie) => Some(self.visit_index_expression(ie)?),
-            ASTNode::FunctionStatement(fs) => {
-                self.symbol_table
-                    .set(&fs.name.clone(), Symbol::Function(Box::new(fs)));
-                None
+            output.extend_from_slice(&bytes);
+        }
+
+        // the last command is waited for before the others are stopped, as
+        // it may still be reading their output
+        let last_command = stages
+            .iter()
+            .rposition(|stage| matches!(stage, RunningStage::Command { .. }));
+        let mut codes = vec![None; stages.len()];
+        if let Some(i) = last_command {
+            if let RunningStage::Command { command, .. } = &mut stages[i] {
+                codes[i] = command.wait()?;
             }
-            ASTNode::CallExpression(fc) => Some(self.eval_call_expression(fc)?),
-            ASTNode::IfStatement(is) => {
-                self.eval_if_statement(is)?;
-                None
+        }
+        let mut status = None;
+        let mut signal = None;
+        let mut statuses = vec![];
+        let mut failed = None;
+        let mut stderr = vec![];
+        for (i, stage) in stages.iter_mut().enumerate() {
+            if let RunningStage::Command { command, .. } = stage {
+                let code = match Some(i) == last_command {
+                    true => codes[i],
+                    false => command.stop()?,
+                };
+                stderr.extend(command.take_stderr());
+                if Some(i) == last_command {
+                    status = code;
+                    signal = command.signal();
+                }
+                // a command stopped because its output was no longer read
+                // hasn't failed
+                if code != Some(0) && !command.stopped() {
+                    failed = Some((code, command.signal()));
+                }
+                statuses.push(code);
             }
+        }
+        if let Some((code, failed_signal)) = failed.filter(|_| self.pipefail) {
+            status = code;
+            signal = failed_signal;
+        }
 
-            ASTNode::BlockStatement(bs) => Some(self.eval_block_statement(bs)?),
-            ASTNode::ReturnStatement(expr) => self.eval_node(*expr)?,
-            ASTNode::ForStatement(fs) => {
-                self.eval_for_statement(fs)?;
-                None
+        if let Some(RunningStage::Command { command, .. }) = stages.first() {
+            if command.interrupted() {
+                return Err(RuntimeError::new(
+                    RuntimeErrorKind::Signal,
+                    "command interrupted".to_string(),
+                ));
             }
+        }
 
-            ASTNode::Number(value) => Some(Symbol::Number(value)),
-            ASTNode::Boolean(value) => Some(Symbol::Boolean(value)),
-            ASTNode::String(value) => Some(new_string_symbol!(value)),
-            ASTNode::TemplateString(ts) => Some(self.visit_template_string(ts)?),
-            ASTNode::List(nodes) => Some(self.eval_list(*nodes)?),
-            ASTNode::None => Some(Symbol::None),
-            ASTNode::RangeExpression(range_expr) => {
-                Some(Symbol::Range(self.visit_range_expression(range_expr)?))
-            }
+        let result = self.record_result(CommandResult {
+            command: display,
+            stdout: output,
+            stderr,
+            status,
+            signal,
+            statuses,
+            duration: start.elapsed(),
+        });
+        Ok(Symbol::from_bytes(result.stdout))
+    }
 
-            ASTNode::Command(cmd) => Some(self.eval_command(*cmd)?),
-            // TODO: allow returning reference to a symbol in the future.
-            ASTNode::Identifier(ident) => Some(self.get_symbol(&ident)?.clone()),
-            ASTNode::Program(_) => None,
+    /// A command of a pipeline after the first.
+    fn stage_command(&mut self, tokens: &[ASTNode]) -> Result<CommandLine, RuntimeError> {
+        let cmd = self.command_string(tokens)?;
+        Ok(CommandLine::shell(cmd.text)
+            .with_environment(self.environment.clone())
+            .with_masked(Program::Shell(cmd.masked), vec![]))
+    }
+
+    /// The commands of a shell pipeline, split at each `|` when pipefail is
+    /// set so each one's status is seen. A line that needs the shell for
+    /// more than a pipeline, such as one with `;`, `&&` or a subshell, is
+    /// kept whole.
+    fn pipe_segments<'t>(&self, tokens: &'t [ASTNode]) -> Vec<&'t [ASTNode]> {
+        let word = |i: usize| match tokens.get(i) {
+            Some(ASTNode::String(word)) => word.as_str(),
+            _ => "",
         };
+        let separates = |i: usize| match word(i) {
+            ";" | "&&" | "||" | "(" | ")" | "{" | "}" => true,
+            // not part of a redirection such as 2>&1 or &>file
+            "&" => !matches!(word(i.wrapping_sub(1)), ">" | "<") && word(i + 1) != ">",
+            _ => false,
+        };
+        if !self.pipefail || (0..tokens.len()).any(separates) {
+            return vec![tokens];
+        }
 
-        Ok(option)
+        let segments: Vec<&[ASTNode]> = tokens
+            .split(|node| matches!(node, ASTNode::String(word) if word == "|"))
+            .map(trim_whitespace)
+            .collect();
+        match segments.iter().any(|segment| segment.is_empty()) {
+            true => vec![tokens],
+            false => segments,
+        }
     }
 
-    fn visit_node_mut(&mut self, node: ASTNode) -> Result<SymbolRef, String> {
-        let res = match node {
-            ASTNode::MemberExpression(me) => {
-                SymbolRef::MutRef(self.visit_member_expression_mut(me)?)
-            }
-            ASTNode::Identifier(ident) => SymbolRef::MutRef(self.get_symbol_mut(&ident)?),
-            ASTNode::CallExpression(ce) => SymbolRef::Value(self.eval_call_expression(ce)?),
-            _ => return Err(format!("not mutable")),
+    /// Reads the next value from the last stage of a pipeline, pulling lines
+    /// through the stages before it as they are needed. Values returned by a
+    /// function are passed to the next function as they are, and as lines of
+    /// text to commands.
+    fn pull_line(&mut self, stages: &mut [RunningStage]) -> Result<Option<PipeItem>, RuntimeError> {
+        let (stage, upstream) = match stages.split_last_mut() {
+            Some(split) => split,
+            None => return Ok(None),
         };
 
-        Ok(res)
-    }
+        match stage {
+            RunningStage::Function(name) => loop {
+                let value = match self.pull_line(upstream)? {
+                    Some(item) => item.into_symbol(),
+                    None => return Ok(None),
+                };
 
-    fn get_symbol(&self, name: &str) -> Result<&Symbol, String> {
-        match self.symbol_table.get(&name) {
-            Some(symbol) => Ok(symbol),
-            None => Err(format!("'{}' is not defined", name)),
+                match self.call_named(name, vec![value])? {
+                    Symbol::None => continue,
+                    value => return Ok(Some(PipeItem::Value(value))),
+                }
+            },
+            RunningStage::Command { command, done } => loop {
+                if let Some(line) = command.try_line() {
+                    return Ok(Some(PipeItem::Line(line)));
+                }
+                if *done {
+                    return Ok(command.next_line().map(PipeItem::Line));
+                }
+
+                let written = match self.pull_line(upstream)? {
+                    Some(item) => command.write(&item.into_bytes()),
+                    None => false,
+                };
+                if !written {
+                    command.close_input();
+                    *done = true;
+                }
+            },
         }
     }
 
-    fn get_symbol_mut(&mut self, name: &str) -> Result<&mut Symbol, String> {
-        match self.symbol_table.get_mut(&name) {
-            Some(symbol) => Ok(symbol),
-            None => Err(format!("'{}' is not defined", name)),
-        }
+    /// Runs a command and records its result.
+    fn run_command(
+        &mut self,
+        cmd: &CommandLine,
+        echo: Echo,
+    ) -> Result<CommandResult, RuntimeError> {
+        self.trace_command(cmd);
+        let result = if self.dry_run {
+            commands::write_line(&self.output.stdout, &cmd.to_string());
+            CommandResult::skipped(cmd)
+        } else if let Some(executor) = self.executor.as_deref_mut() {
+            commands::run_with(executor, cmd, echo)?
+        } else if self.interactive {
+            commands::run_cmd_interactive(cmd, echo, &self.output)?
+        } else {
+            commands::run_cmd_to(cmd, echo, &self.output)?
+        };
+
+        Ok(self.record_result(result))
     }
 
-    fn visit_template_string(&self, template_string: TemplateString) -> Result<Symbol, String> {
-        let mut res = "".to_string();
-        for token in template_string.tokens {
-            let sub_str = match token {
-                ast::TemplateToken::Expression(expr) => {
-                    let symbol = self.get_symbol(expr.as_str())?;
-                    symbol.raw_str()
-                }
-                ast::TemplateToken::Literal(s) => s,
-            };
-            res.push_str(sub_str.as_str());
+    /// Runs a command attached to the terminal, for programs such as editors
+    /// and ssh sessions. Only the exit status is recorded.
+    fn eval_interactive(&mut self, command: &ast::Command) -> Result<(), RuntimeError> {
+        if !command.stages.is_empty() {
+            return Err(RuntimeError::new(
+                RuntimeErrorKind::Command,
+                "interactive can't be used with a pipeline through functions".to_string(),
+            ));
         }
 
-        Ok(new_string_symbol!(res))
+        let cmd = self.command_line(command)?;
+        self.trace_command(&cmd);
+        let result = if self.dry_run {
+            commands::write_line(&self.output.stdout, &cmd.to_string());
+            CommandResult::skipped(&cmd)
+        } else if let Some(executor) = self.executor.as_deref_mut() {
+            let echo = Echo {
+                stdout: true,
+                stderr: true,
+            };
+            commands::run_with(executor, &cmd, echo)?
+        } else {
+            commands::run_cmd_tty(&cmd)?
+        };
+
+        self.record_result(result);
+        Ok(())
     }
 
-    fn visit_range_expression(&mut self, range_expr: RangeExpression) -> Result<Range, String> {
-        let mut visit_range_prop = |node: ASTNode, label: &str| -> Result<i32, String> {
-            match self.eval_node(node)? {
-                Some(symbol) => match symbol {
-                    Symbol::Number(num) => Ok(num as i32),
-                    _ => Err(format!(
-                        "range {} must be a number, found {}",
-                        label,
-                        symbol.kind()
-                    )),
-                },
-                None => Err(format!("invalid range")),
-            }
+    /// Records a command's result in `last_result` and its exit status in
+    /// `process.status`.
+    fn record_result(&mut self, result: CommandResult) -> CommandResult {
+        self.last_status = result.status.or(result.signal.map(|n| 128 + n));
+        let status = match result.status {
+            Some(code) => Symbol::Number(code as f64),
+            None => Symbol::None,
         };
+        if let Some(Symbol::Object(process)) = self.symbol_table.get_global_mut("process") {
+            process.insert("status".to_string(), status);
+        }
+        self.symbol_table
+            .set_global("last_result", Symbol::from(&result));
+        result
+    }
 
-        let start = visit_range_prop(*range_expr.start, "start")?;
-        let end = visit_range_prop(*range_expr.end, "end")?;
-        let increment = if let Some(inc) = range_expr.increment {
-            Some(visit_range_prop(*inc, "increment")?)
-        } else {
-            None
+    /// `process.exit(code?)` records the exit status and unwinds evaluation
+    /// with an error that `eval` recognises.
+    fn eval_exit(&mut self, args: Vec<Symbol>) -> Result<Symbol, RuntimeError> {
+        let code = match args.as_slice() {
+            [] => 0,
+            [Symbol::Number(code)] if code.fract() == 0.0 => *code as i32,
+            [symbol] => {
+                return Err(RuntimeError::new(
+                    RuntimeErrorKind::Type,
+                    format!("exit expected an integer, found {}", symbol.kind()),
+                ))
+            }
+            _ => {
+                return Err(RuntimeError::new(
+                    RuntimeErrorKind::Argument,
+                    format!("expected 1 argument to exit, found {}", args.len()),
+                ))
+            }
         };
 
-        Ok(Range::new(start, end, increment))
+        self.exit_status = Some(code);
+        Err(RuntimeError::new(
+            RuntimeErrorKind::Exit,
+            format!("exit {}", code),
+        ))
     }
 
-    fn visit_iterable(
-        &mut self,
-        iterable: ast::Iterable,
-    ) -> Result<Box<dyn Iterator<Item = Symbol>>, String> {
-        match iterable {
-            ast::Iterable::RangeExpression(re) => {
-                let iterator = self.visit_range_expression(re)?;
-                Ok(Box::new(iterator))
+    /// `args_parse(spec, argv?)` parses the script's arguments, or `argv` if
+    /// given.
+    fn eval_args_parse(&mut self, args: Vec<Symbol>) -> Result<Symbol, RuntimeError> {
+        let argv = match args.get(1) {
+            Some(Symbol::List(list)) => list.items().iter().map(|arg| arg.raw_str()).collect(),
+            Some(symbol) => {
+                return Err(RuntimeError::new(
+                    RuntimeErrorKind::Type,
+                    format!(
+                        "args_parse expected a list of arguments, found {}",
+                        symbol.kind()
+                    ),
+                ))
             }
-            ast::Iterable::Collection(node) => match self.eval_node(node)? {
-                Some(symbol) => match symbol {
-                    Symbol::List(list) => Ok(Box::new(list.items.into_iter())),
-                    Symbol::String(ss) => Ok(Box::new(ss.into_iter())),
-                    Symbol::Range(r) => Ok(Box::new(r.into_iter())),
-                    _ => Err(format!("{} is not iterable", symbol.kind())),
-                },
-                None => Err("iterator not found".to_string()),
-            },
+            None => self.script_args.clone(),
+        };
+
+        match args.as_slice() {
+            [Symbol::Object(spec)] | [Symbol::Object(spec), _] => {
+                Ok(builtins::args::parse(spec, &argv)?)
+            }
+            [symbol] | [symbol, _] => Err(RuntimeError::new(
+                RuntimeErrorKind::Type,
+                format!("args_parse expected an object, found {}", symbol.kind()),
+            )),
+            _ => Err(RuntimeError::new(
+                RuntimeErrorKind::Argument,
+                format!(
+                    "expected 1 or 2 arguments to args_parse, found {}",
+                    args.len()
+                ),
+            )),
         }
     }
 
-    fn eval_for_statement(&mut self, for_statement: ForStatement) -> Result<(), String> {
-        let iterable = self.visit_iterable(*for_statement.iterable)?;
-        self.symbol_table.push_scope(ScopeKind::ForBlock);
-
-        for symbol in iterable {
-            self.symbol_table
-                .set(for_statement.variable.as_str(), symbol);
-            self.eval_node(*for_statement.body.clone())?;
-        }
+    /// `measure(func)` calls a function with no arguments and returns how long
+    /// it took in seconds.
+    fn eval_measure(&mut self, args: Vec<Symbol>) -> Result<Symbol, RuntimeError> {
+        builtins::expect_args("measure", &args, 1)?;
+        let func = symbol::expect_function("measure", args.first())?;
 
-        self.symbol_table.pop_scope();
-        Ok(())
+        let start = Instant::now();
+        self.call_function(func, vec![])?;
+        Ok(Symbol::Number(start.elapsed().as_secs_f64()))
     }
 
-    fn visit_function_args(&mut self, args: Vec<ASTNode>) -> Result<Vec<Symbol>, String> {
-        let mut result = vec![];
-        for node in args {
-            match self.eval_node(node)? {
-                Some(symbol) => result.push(symbol),
-                None => return Err(format!("TODO: handle None type")),
+    /// `parallel(commands, limit?)` runs a list of commands at the same time,
+    /// at most `limit` at once or all of them without one, and returns their
+    /// results in order.
+    /// `parallel(items, func, limit?)` calls `func` with each item to build
+    /// its command first.
+    fn eval_parallel(&mut self, args: Vec<Symbol>) -> Result<Symbol, RuntimeError> {
+        let (items, func, limit) = match args.as_slice() {
+            [Symbol::List(items)] => (items, None, None),
+            [Symbol::List(items), Symbol::Function(func)] => (items, Some(func), None),
+            [Symbol::List(items), limit] => (items, None, Some(limit)),
+            [Symbol::List(items), Symbol::Function(func), limit] => {
+                (items, Some(func), Some(limit))
+            }
+            [symbol, ..] if !matches!(symbol, Symbol::List(_)) => {
+                return Err(RuntimeError::new(
+                    RuntimeErrorKind::Type,
+                    format!("parallel expected a list, found {}", symbol.kind()),
+                ))
+            }
+            _ => {
+                return Err(RuntimeError::new(
+                    RuntimeErrorKind::Argument,
+                    format!(
+                        "expected 1 to 3 arguments to parallel, found {}",
+                        args.len()
+                    ),
+                ))
+            }
+        };
+
+        let limit = match limit {
+            Some(Symbol::Number(n)) if *n >= 1.0 => *n as usize,
+            Some(Symbol::Number(n)) => {
+                return Err(RuntimeError::new(
+                    RuntimeErrorKind::Argument,
+                    format!("parallel limit must be at least 1, found {}", n),
+                ))
+            }
+            Some(symbol) => {
+                return Err(RuntimeError::new(
+                    RuntimeErrorKind::Type,
+                    format!(
+                        "parallel expected a number for the limit, found {}",
+                        symbol.kind()
+                    ),
+                ))
+            }
+            None => items.items().len(),
+        };
+
+        let mut cmds = vec![];
+        for item in items.items() {
+            let command = match func {
+                Some(func) => self.call_function(func, vec![item.clone()])?,
+                None => item.clone(),
             };
+            match command {
+                Symbol::String(_) | Symbol::Secret(_) => {
+                    let cmd = CommandLine::shell(command.raw_str())
+                        .with_masked(Program::Shell(masked_str(&command)), vec![]);
+                    cmds.push(cmd.with_environment(self.environment.clone()))
+                }
+                symbol => {
+                    return Err(RuntimeError::new(
+                        RuntimeErrorKind::Type,
+                        format!(
+                            "parallel expected commands to be strings, found {}",
+                            symbol.kind()
+                        ),
+                    ))
+                }
+            }
         }
 
-        Ok(result)
+        for cmd in &cmds {
+            self.trace_command(cmd);
+        }
+        let results = if self.dry_run {
+            cmds.iter()
+                .map(|cmd| {
+                    commands::write_line(&self.output.stdout, &cmd.to_string());
+                    CommandResult::skipped(cmd)
+                })
+                .collect()
+        } else if let Some(executor) = self.executor.as_deref_mut() {
+            commands::run_parallel_with(executor, &cmds, limit)?
+        } else {
+            commands::run_parallel(&cmds, limit)?
+        };
+
+        let items = results.iter().map(Symbol::from).collect();
+        Ok(Symbol::List(List::from(items)))
     }
 
-    fn eval_list(&mut self, nodes: Vec<ASTNode>) -> Result<Symbol, String> {
-        let mut items = vec![];
-        for node in nodes {
-            match self.eval_node(node)? {
-                Some(symbol) => items.push(symbol),
-                None => return Err(format!("invalid expression in list")),
+    /// Calls a builtin function. Builtins that need the evaluator's state are
+    /// handled here and the rest are looked up in `builtins`.
+    fn call_builtin(&mut self, name: &str, args: Vec<Symbol>) -> Result<Symbol, RuntimeError> {
+        if let Some(function) = self.host_functions.get_mut(name) {
+            return Ok(function(args)?);
+        }
+        if let Some(builtin) = builtins::get_env_builtin(name) {
+            return Ok(builtin(&mut self.environment, args)?);
+        }
+
+        match name {
+            "run" => self.eval_run(args),
+            "exec" => self.eval_exec(args),
+            "capture_stderr" => {
+                builtins::expect_args(name, &args, 1)?;
+                self.capture_stderr = args[0].is_truthy();
+                Ok(Symbol::None)
+            }
+            "pipefail" => {
+                builtins::expect_args(name, &args, 1)?;
+                self.pipefail = args[0].is_truthy();
+                Ok(Symbol::None)
             }
+            "args_parse" => self.eval_args_parse(args),
+            "measure" => self.eval_measure(args),
+            "parallel" => self.eval_parallel(args),
+            "process.exit" => self.eval_exit(args),
+            _ => match builtins::get(name) {
+                Some(builtin) => Ok(builtin(args)?),
+                None => Err(RuntimeError::new(
+                    RuntimeErrorKind::Undefined,
+                    format!("'{}' is not defined", name),
+                )),
+            },
         }
+    }
+
+    /// `run(cmd)` runs a command without printing its output and returns the
+    /// full result.
+    fn eval_run(&mut self, args: Vec<Symbol>) -> Result<Symbol, RuntimeError> {
+        builtins::expect_args("run", &args, 1)?;
+        let result = match &args[0] {
+            Symbol::String(_) | Symbol::Secret(_) => {
+                let cmd = CommandLine::shell(args[0].raw_str())
+                    .with_masked(Program::Shell(masked_str(&args[0])), vec![])
+                    .with_environment(self.environment.clone());
+                self.run_command(&cmd, Echo::NONE)?
+            }
+            symbol => {
+                return Err(RuntimeError::new(
+                    RuntimeErrorKind::Type,
+                    format!("run expected a string, found {}", symbol.kind()),
+                ))
+            }
+        };
 
-        return Ok(Symbol::List(List { items }));
+        Ok(Symbol::from(&result))
     }
 
-    fn eval_command(&mut self, tokens: Vec<ASTNode>) -> Result<Symbol, String> {
-        let mut cmd_string = "".to_owned();
-        for node in tokens {
-            let sub_str = match node {
-                ASTNode::TemplateString(ts) => {
-                    let s = self.visit_template_string(ts)?;
-                    format!(r#""{}""#, s.raw_str())
-                }
-                _ => match self.eval_node(node)? {
-                    Some(s) => s.raw_str(),
-                    None => "".to_string(),
-                },
-            };
-            cmd_string.push_str(sub_str.as_str());
-        }
+    /// `exec(program, args?)` runs a program directly rather than through
+    /// the shell, so arguments are passed exactly as given. Like `run` it
+    /// returns the full result without printing the output.
+    fn eval_exec(&mut self, args: Vec<Symbol>) -> Result<Symbol, RuntimeError> {
+        let (program, program_args, masked_args) = match args.as_slice() {
+            [program] => (program, vec![], vec![]),
+            [program, Symbol::List(list)] => {
+                let words = list.items().iter().map(|arg| arg.raw_str()).collect();
+                let masked = list.items().iter().map(masked_str).collect();
+                (program, words, masked)
+            }
+            [_, symbol] => {
+                return Err(RuntimeError::new(
+                    RuntimeErrorKind::Type,
+                    format!("exec expected a list of arguments, found {}", symbol.kind()),
+                ))
+            }
+            _ => {
+                return Err(RuntimeError::new(
+                    RuntimeErrorKind::Argument,
+                    format!("expected 1 or 2 arguments to exec, found {}", args.len()),
+                ))
+            }
+        };
+
+        let program = match program {
+            Symbol::String(_) => program.raw_str(),
+            symbol => {
+                return Err(RuntimeError::new(
+                    RuntimeErrorKind::Type,
+                    format!("exec expected a string, found {}", symbol.kind()),
+                ))
+            }
+        };
+
+        let cmd = CommandLine::exec(program.clone(), program_args)
+            .with_masked(Program::Exec(program, masked_args), vec![])
+            .with_environment(self.environment.clone());
+        let result = self.run_command(&cmd, Echo::NONE)?;
+        Ok(Symbol::from(&result))
+    }
 
-        let output = commands::run_cmd(&cmd_string);
-        print!("{}", output);
-        Ok(new_string_symbol!(output))
+    fn eval_block_statement(
+        &mut self,
+        block_statement: &BlockStatement,
+    ) -> Result<Symbol, RuntimeError> {
+        self.depth += 1;
+        let result = self.eval_block_body(block_statement);
+        self.depth -= 1;
+        result
     }
 
-    fn eval_block_statement(&mut self, block_statement: BlockStatement) -> Result<Symbol, String> {
-        for node in *block_statement.body {
+    fn eval_block_body(
+        &mut self,
+        block_statement: &BlockStatement,
+    ) -> Result<Symbol, RuntimeError> {
+        for (i, node) in block_statement.body.iter().enumerate() {
+            self.trace_statement(node);
+            let at = |e: RuntimeError| e.at(block_statement.spans.get(i));
             match node {
                 ASTNode::ReturnStatement(expr) => {
-                    return match self.eval_node(*expr)? {
+                    return match self.eval_return(expr).map_err(at)? {
                         Some(s) => Ok(s),
                         None => Ok(Symbol::None),
                     }
                 }
-                _ => self.eval_node(node)?,
+                _ => self.eval_node(node).map_err(at)?,
             };
+            if self.returning.is_some() {
+                break;
+            }
         }
 
         Ok(Symbol::None)
     }
 
-    fn eval_if_statement(&mut self, if_statement: IfStatement) -> Result<(), String> {
-        let passed = match self.eval_node(*if_statement.condition)? {
+    fn eval_if_statement(&mut self, if_statement: &IfStatement) -> Result<(), RuntimeError> {
+        let passed = match self.eval_node(&if_statement.condition)? {
             Some(sym) => sym.is_truthy(),
             None => false,
         };
 
         if passed {
             self.symbol_table.push_scope(ScopeKind::ConditionalBlock);
-            self.eval_node(*if_statement.consequence)?;
+            self.eval_node(&if_statement.consequence)?;
             self.symbol_table.pop_scope();
-        } else if let Some(alternative) = if_statement.alternative {
+        } else if let Some(alternative) = &if_statement.alternative {
             self.symbol_table.push_scope(ScopeKind::ConditionalBlock);
-            self.eval_node(*alternative)?;
+            self.eval_node(alternative)?;
             self.symbol_table.pop_scope();
         }
 
@@ -273,185 +1999,328 @@ impl ASTEvaluator {
 
     fn validate_function_call(
         &self,
-        func_call: &CallExpression,
         func_expr: &FunctionStatement,
-    ) -> Result<(), String> {
-        if func_call.args.len() < func_expr.args.len() {
-            return Err(format!(
-                "{} missing function args expected {} received {}",
-                func_expr.name,
-                func_expr.args.len(),
-                func_call.args.len()
+        arg_count: usize,
+    ) -> Result<(), RuntimeError> {
+        if arg_count < func_expr.args.len() {
+            return Err(RuntimeError::new(
+                RuntimeErrorKind::Argument,
+                format!(
+                    "{} missing function args expected {} received {}",
+                    func_expr.name,
+                    func_expr.args.len(),
+                    arg_count
+                ),
             ));
         }
 
         Ok(())
     }
 
-    fn push_function(
+    /// Starts a function's scope with its arguments, each in the slot for
+    /// it at the start of the scope.
+    fn push_function(&mut self, func_expr: &FunctionStatement, arg_values: Vec<Symbol>) {
+        self.symbol_table.push_scope(ScopeKind::FunctionBlock);
+
+        for (arg_name, arg_value) in func_expr.args.iter().zip(arg_values) {
+            self.symbol_table.declare(arg_name.clone(), arg_value);
+        }
+    }
+
+    fn call_function(
         &mut self,
-        func_call: CallExpression,
-        func_expr: &FunctionStatement,
-    ) -> Result<(), String> {
-        let arg_values = self.visit_function_args(func_call.args)?;
-        let mut args = vec![];
-        for (name, value) in func_expr.args.iter().zip(arg_values.iter()) {
-            args.push((name, value.clone()));
+        func_statement: &FunctionStatement,
+        args: Vec<Symbol>,
+    ) -> Result<Symbol, RuntimeError> {
+        self.validate_function_call(func_statement, args.len())?;
+        if self.calls >= self.limits.call_depth {
+            return Err(RuntimeError::new(
+                RuntimeErrorKind::Limit,
+                format!(
+                    "maximum call depth of {} exceeded in function {}",
+                    self.limits.call_depth, func_statement.name
+                ),
+            ));
         }
 
-        self.symbol_table.push_scope(ScopeKind::FunctionBlock);
+        self.calls += 1;
+        // each call recurses into the evaluator, so the stack grows onto the
+        // heap as needed, whatever thread sod was started on
+        let result = stacker::maybe_grow(STACK_RED_ZONE, STACK_SEGMENT, || {
+            self.run_function(func_statement, args)
+        });
+        self.calls -= 1;
+        result
+    }
+
+    /// Runs a function, then each function called with `return f(...)` in
+    /// turn, in place of the one that called it.
+    fn run_function(
+        &mut self,
+        func_statement: &FunctionStatement,
+        mut args: Vec<Symbol>,
+    ) -> Result<Symbol, RuntimeError> {
+        let mut tail_call: Option<Rc<FunctionStatement>> = None;
+        loop {
+            let func_statement = tail_call.as_deref().unwrap_or(func_statement);
+            self.push_function(func_statement, args);
+            let compiled = func_statement.compiled.get().filter(|_| !self.trace);
+            let res = self.check_limits().and_then(|_| match compiled {
+                Some(chunk) => self.run_chunk(chunk),
+                None => self.eval_node(&func_statement.body),
+            });
+            self.symbol_table.pop_scope();
+
+            let returning = self.returning.take();
+            let res = res?;
+            match returning {
+                Some(Return::TailCall(next, next_args)) => {
+                    self.validate_function_call(&next, next_args.len())?;
+                    tail_call = Some(next);
+                    args = next_args;
+                }
+                Some(Return::Value(symbol)) => return Ok(symbol),
+                None => return Ok(res.unwrap_or(Symbol::None)),
+            }
+        }
+    }
 
-        for (arg_name, arg_value) in args {
-            self.symbol_table.set(arg_name, arg_value);
+    /// Evaluates what a `return` returns. Inside a function it also ends the
+    /// function, from within any blocks and loops, and a call to another
+    /// function is left for `run_function` to make.
+    fn eval_return(&mut self, expr: &ASTNode) -> Result<Option<Symbol>, RuntimeError> {
+        if self.calls == 0 {
+            return self.eval_node(expr);
         }
 
-        Ok(())
+        let tail_call = match expr {
+            ASTNode::CallExpression(ce) => match &*ce.base {
+                ASTNode::Identifier(name) => match self.symbol_table.get(name) {
+                    Some(Symbol::Function(f)) => Some(f.clone()),
+                    _ => None,
+                },
+                _ => None,
+            },
+            _ => None,
+        };
+
+        match (tail_call, expr) {
+            (Some(func_statement), ASTNode::CallExpression(ce)) => {
+                let args = self.visit_function_args(&ce.args)?;
+                self.returning = Some(Return::TailCall(func_statement, args));
+                Ok(None)
+            }
+            (_, expr) => {
+                let symbol = self.eval_node(expr)?.unwrap_or(Symbol::None);
+                self.returning = Some(Return::Value(symbol.clone()));
+                Ok(Some(symbol))
+            }
+        }
     }
 
     fn visit_function(
         &mut self,
         func_name: &str,
-        call_expr: CallExpression,
-    ) -> Result<Symbol, String> {
-        let func_statement = match self.get_symbol(func_name)? {
-            Symbol::Function(f) => f.clone(),
-            _ => return Ok(Symbol::None),
-        };
-
-        self.validate_function_call(&call_expr, &func_statement)?;
+        call_expr: &CallExpression,
+    ) -> Result<Symbol, RuntimeError> {
+        let args = self.visit_function_args(&call_expr.args)?;
+        self.call_named(func_name, args)
+    }
 
-        self.push_function(call_expr, &func_statement)?;
-        let res = self.eval_node(*func_statement.body)?;
-        self.symbol_table.pop_scope();
+    /// Calls a function or builtin by name with arguments that have already
+    /// been evaluated.
+    fn call_named(&mut self, func_name: &str, args: Vec<Symbol>) -> Result<Symbol, RuntimeError> {
+        let func_statement = match self.symbol_table.get(func_name) {
+            Some(Symbol::Function(f)) => f.clone(),
+            Some(_) => return Ok(Symbol::None),
+            None if self.is_builtin(func_name) => return self.call_builtin(func_name, args),
+            None => {
+                return Err(RuntimeError::new(
+                    RuntimeErrorKind::Undefined,
+                    format!("'{}' is not defined", func_name),
+                ))
+            }
+        };
 
-        match res {
-            Some(symbol) => Ok(symbol),
-            None => Ok(Symbol::None),
-        }
+        self.call_function(&func_statement, args)
     }
 
     fn visit_member_expression_call(
         &mut self,
-        member_expr: MemberExpression,
-        ast_args: Vec<ASTNode>,
-    ) -> Result<Symbol, String> {
+        member_expr: &MemberExpression,
+        ast_args: &[ASTNode],
+    ) -> Result<Symbol, RuntimeError> {
         let args = self.visit_function_args(ast_args)?;
-        let call = member_expr.property.as_str();
+        let call = &*member_expr.property;
+
+        if let ASTNode::Identifier(namespace) = member_expr.base.as_ref() {
+            let name = format!("{}.{}", namespace, call);
+            if self.is_builtin(&name) {
+                return self.call_builtin(&name, args);
+            }
+        }
+
+        if symbol::is_higher_order(call) {
+            let symbol = match self.eval_node(&member_expr.base)? {
+                Some(symbol) => symbol,
+                None => {
+                    return Err(RuntimeError::new(
+                        RuntimeErrorKind::Undefined,
+                        format!("none has no member {}", call),
+                    ))
+                }
+            };
+            // the callback's error is kept as is, rather than as the message
+            // passed back through the member function
+            let mut failure = None;
+            let result = symbol.call_with(call, args, &mut |func, func_args| {
+                self.call_function(func, func_args).map_err(|e| {
+                    let message = e.message.clone();
+                    failure = Some(e);
+                    message
+                })
+            });
+            return result.map_err(|e| failure.unwrap_or_else(|| e.into()));
+        }
 
-        let symbol = match self.visit_node_mut(*member_expr.base)? {
-            SymbolRef::MutRef(symbol) => symbol.call(call, args)?,
+        let max = self.limits.value_size;
+        let symbol = match self.visit_node_mut(&member_expr.base)? {
+            SymbolRef::MutRef(symbol) => {
+                let result = symbol.call(call, args)?;
+                check_size(max, symbol)?;
+                result
+            }
             SymbolRef::Value(mut symbol) => symbol.call(call, args)?,
         };
 
+        check_size(max, &symbol)?;
         Ok(symbol)
     }
 
-    fn eval_call_expression(&mut self, call_expr: CallExpression) -> Result<Symbol, String> {
-        match *call_expr.base {
-            ASTNode::Identifier(ref fname) => {
-                self.visit_function(fname.clone().as_str(), call_expr)
-            }
-            ASTNode::MemberExpression(me) => self.visit_member_expression_call(me, call_expr.args),
+    fn eval_call_expression(&mut self, call_expr: &CallExpression) -> Result<Symbol, RuntimeError> {
+        match &*call_expr.base {
+            ASTNode::Identifier(fname) => self.visit_function(fname, call_expr),
+            ASTNode::MemberExpression(me) => self.visit_member_expression_call(me, &call_expr.args),
             _ => unimplemented!("object is not callable"),
         }
     }
 
-    fn eval_index(&mut self, expression: ASTNode) -> Result<usize, String> {
-        let expr_symbol = match self.eval_node(expression)? {
-            Some(s) => s,
-            None => return Err("indices must be numbers".to_string()),
-        };
-
-        // TODO: later use u64 instead for [-1] list access?
-        match expr_symbol {
-            Symbol::Number(index) => Ok(index as usize),
-            _ => Err("indices must be numbers".to_string()),
-        }
+    fn eval_index(&mut self, expression: &ASTNode) -> Result<usize, RuntimeError> {
+        index_of(self.eval_node(expression)?)
     }
 
-    fn visit_index_expression(&mut self, index_expr: IndexExpression) -> Result<Symbol, String> {
-        let index = self.eval_index(*index_expr.index)?;
-        let symbol = self.eval_node(*index_expr.base)?.unwrap();
-
-        match symbol {
-            Symbol::List(list) => Ok(list.get(index)?.clone()),
-            Symbol::String(ss) => Ok(ss.get(index)?),
-            _ => Err(format!("{} is not indexable", symbol.kind())),
-        }
+    fn visit_index_expression(
+        &mut self,
+        index_expr: &IndexExpression,
+    ) -> Result<Symbol, RuntimeError> {
+        let index = self.eval_index(&index_expr.index)?;
+        let symbol = self.eval_node(&index_expr.base)?.unwrap();
+        get_index(&symbol, index)
     }
 
     fn visit_index_expression_mut(
         &mut self,
-        index_expr: IndexExpression,
-    ) -> Result<&mut Symbol, String> {
-        let index = self.eval_index(*index_expr.index)?;
-        match self.visit_node_mut(*index_expr.base)? {
+        index_expr: &IndexExpression,
+    ) -> Result<&mut Symbol, RuntimeError> {
+        let index = self.eval_index(&index_expr.index)?;
+        match self.visit_node_mut(&index_expr.base)? {
             SymbolRef::MutRef(mr) => Ok(mr.get_index_mut(index)?),
             //SymbolRef::Value(mut val) => val.get_index_mut(index),
             _ => unimplemented!("by value index mutation"),
         }
     }
 
-    fn visit_member_expression(&mut self, member_expr: MemberExpression) -> Result<Symbol, String> {
-        let symbol = match *member_expr.base {
-            ASTNode::Identifier(ident) => self.get_symbol(ident.as_str())?,
-            _ => unimplemented!("TODO"),
+    fn visit_member_expression(
+        &mut self,
+        member_expr: &MemberExpression,
+    ) -> Result<Symbol, RuntimeError> {
+        let base;
+        let symbol = match &*member_expr.base {
+            ASTNode::Identifier(ident) => self.get_symbol(ident)?,
+            node => {
+                base = match self.eval_node(node)? {
+                    Some(symbol) => symbol,
+                    None => {
+                        return Err(RuntimeError::new(
+                            RuntimeErrorKind::Undefined,
+                            format!("none has no property {}", member_expr.property),
+                        ))
+                    }
+                };
+                &base
+            }
         };
 
-        match symbol {
-            Symbol::Object(obj) => match obj.get(member_expr.property.as_str()) {
-                Some(s) => Ok(s.clone()),
-                None => Err(format!("object has no property {}", member_expr.property)),
-            },
-            _ => Err(format!(
-                "{} has no property {}",
-                symbol.kind(),
-                member_expr.property
-            )),
-        }
+        get_property(symbol, &member_expr.property)
     }
 
     fn visit_member_expression_mut(
         &mut self,
-        member_expr: MemberExpression,
-    ) -> Result<&mut Symbol, String> {
-        let symbol = match *member_expr.base {
-            ASTNode::Identifier(ident) => self.get_symbol_mut(ident.as_str())?,
-            _ => unimplemented!("object not supported"),
+        member_expr: &MemberExpression,
+    ) -> Result<&mut Symbol, RuntimeError> {
+        let symbol = match self.visit_node_mut(&member_expr.base)? {
+            SymbolRef::MutRef(symbol) => symbol,
+            SymbolRef::Value(_) => {
+                return Err(RuntimeError::new(RuntimeErrorKind::Type, "not mutable"))
+            }
         };
 
         match symbol {
-            Symbol::Object(obj) => match obj.get_mut(member_expr.property.as_str()) {
+            Symbol::Object(obj) => match obj.get_mut(&member_expr.property) {
                 Some(s) => Ok(s),
-                None => Err(format!("object has no property {}", member_expr.property)),
+                None => Err(RuntimeError::new(
+                    RuntimeErrorKind::Undefined,
+                    format!("object has no property {}", member_expr.property),
+                )),
             },
-            _ => Err(format!(
-                "{} has no property {}",
-                symbol.kind(),
-                member_expr.property
+            _ => Err(RuntimeError::new(
+                RuntimeErrorKind::Undefined,
+                format!("{} has no property {}", symbol.kind(), member_expr.property),
             )),
         }
     }
 
-    fn eval_variable_expression(&mut self, node: VariableExpression) -> Result<(), String> {
-        let rhs = match self.eval_node(*node.rhs)? {
+    fn eval_variable_expression(&mut self, node: &VariableExpression) -> Result<(), RuntimeError> {
+        let rhs = match self.eval_node(&node.rhs)? {
             Some(s) => s,
-            None => return Err(format!("right hand side not found")),
+            None => {
+                return Err(RuntimeError::new(
+                    RuntimeErrorKind::NoValue,
+                    "right hand side not found",
+                ))
+            }
         };
 
-        match *node.lhs {
-            ASTNode::Identifier(ident) => self.symbol_table.set(&ident, rhs),
+        match &*node.lhs {
+            ASTNode::Identifier(ident) => self.symbol_table.set(ident.clone(), rhs),
             ASTNode::IndexExpression(ie) => {
                 let lhs_symbol = self.visit_index_expression_mut(ie)?;
                 *lhs_symbol = rhs;
             }
-            _ => unimplemented!("object assignment"),
+            ASTNode::MemberExpression(me) => match self.visit_node_mut(&me.base)? {
+                SymbolRef::MutRef(Symbol::Object(obj)) => obj.insert(me.property.to_string(), rhs),
+                SymbolRef::MutRef(symbol) => {
+                    return Err(RuntimeError::new(
+                        RuntimeErrorKind::Type,
+                        format!("can't set property {} of {}", me.property, symbol.kind()),
+                    ))
+                }
+                SymbolRef::Value(_) => {
+                    return Err(RuntimeError::new(RuntimeErrorKind::Type, "not mutable"))
+                }
+            },
+            _ => {
+                return Err(RuntimeError::new(
+                    RuntimeErrorKind::Type,
+                    "can't assign to this expression",
+                ))
+            }
         };
 
         Ok(())
     }
 
-    fn eval_unary_expression(&mut self, node: ASTNode) -> Result<Option<Symbol>, String> {
+    fn eval_unary_expression(&mut self, node: &ASTNode) -> Result<Option<Symbol>, RuntimeError> {
         let symbol = match self.eval_node(node)? {
             Some(s) => s,
             None => return Ok(None),
@@ -465,8 +2334,11 @@ impl ASTEvaluator {
         Ok(res)
     }
 
-    fn eval_binary_expression(&mut self, be: BinaryExpression) -> Result<Option<Symbol>, String> {
-        let left_symbol = match self.eval_node(*be.left)? {
+    fn eval_binary_expression(
+        &mut self,
+        be: &BinaryExpression,
+    ) -> Result<Option<Symbol>, RuntimeError> {
+        let left_symbol = match self.eval_node(&be.left)? {
             Some(s) => s,
             None => return Ok(None),
         };
@@ -479,13 +2351,75 @@ impl ASTEvaluator {
             return Ok(Some(left_symbol));
         }
 
-        let right_symbol = match self.eval_node(*be.right)? {
+        let right_symbol = match self.eval_node(&be.right)? {
             Some(s) => s,
             None => return Ok(None),
         };
 
         let symbol_result =
             symbol::eval_binary_expression(&left_symbol, &be.operator, &right_symbol)?;
+        check_size(self.limits.value_size, &symbol_result)?;
         Ok(Some(symbol_result))
     }
 }
+
+/// A stage of a pipeline through sod functions, before it is started.
+enum PipelineStage {
+    Command(CommandLine),
+    Function(String),
+}
+
+impl std::fmt::Display for PipelineStage {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PipelineStage::Command(cmd) => write!(f, "{}", cmd),
+            PipelineStage::Function(name) => write!(f, "{}", name),
+        }
+    }
+}
+
+/// What a stage of a pipeline passes on: a line of a command's output as it
+/// was written, with its newline if it had one, or a value a function
+/// returned.
+enum PipeItem {
+    Line(Vec<u8>),
+    Value(Symbol),
+}
+
+impl PipeItem {
+    /// The item as a function's argument, a line being text without its
+    /// newline.
+    fn into_symbol(self) -> Symbol {
+        match self {
+            PipeItem::Line(mut line) => {
+                if line.last() == Some(&b'\n') {
+                    line.pop();
+                }
+                new_string_symbol!(String::from_utf8_lossy(&line).to_string())
+            }
+            PipeItem::Value(value) => value,
+        }
+    }
+
+    /// The item as output, a value being written as a line of text.
+    fn into_bytes(self) -> Vec<u8> {
+        match self {
+            PipeItem::Line(line) => line,
+            PipeItem::Value(value) => {
+                let mut bytes = value.raw_str().into_bytes();
+                bytes.push(b'\n');
+                bytes
+            }
+        }
+    }
+}
+
+/// A started stage of a pipeline. `done` is set once a command's input has
+/// been closed, and from the start when it isn't fed by a function.
+enum RunningStage {
+    Command {
+        command: commands::PipeCommand,
+        done: bool,
+    },
+    Function(String),
+}
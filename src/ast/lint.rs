@@ -0,0 +1,362 @@
+use super::ast::{
+    ASTNode, BlockStatement, Command, ForBinding, FunctionStatement, Iterable, PipeStage,
+    RangeExpression, TemplateToken, WithKind,
+};
+use crate::error::Span;
+use std::fmt;
+
+/// What a lint warning is about.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WarningKind {
+    /// A variable that is assigned but never read.
+    Unused,
+    /// Statements after a `return`, which never run.
+    Unreachable,
+    /// A variable read after the block that defined it has ended, where it
+    /// isn't defined.
+    Undefined,
+}
+
+/// Something in a script that is likely a mistake, though it parses.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Warning {
+    pub kind: WarningKind,
+    pub message: String,
+    /// The statement the warning is about, such as the one assigning an
+    /// unused variable.
+    pub span: Option<Span>,
+}
+
+impl fmt::Display for Warning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+/// Looks over a program for variables that are never read, code after a
+/// `return` and variables read after the block that defined them.
+pub fn lint(program: &ASTNode) -> Vec<Warning> {
+    let mut linter = Linter {
+        scopes: vec![Scope::default()],
+        function: None,
+        span: None,
+        deferred: vec![],
+        warnings: vec![],
+    };
+    linter.node(program);
+
+    // functions read globals when they are called rather than where they are
+    // declared, so their bodies are looked at once every global is known
+    while !linter.deferred.is_empty() {
+        for (function, body) in std::mem::take(&mut linter.deferred) {
+            linter.function = function.map(|f| f.name.clone());
+            let mut scope = Scope::default();
+            for arg in function.iter().flat_map(|f| &f.args) {
+                scope.variables.push(Variable::read(arg));
+            }
+            linter.scopes.push(scope);
+            linter.node(body);
+            linter.pop_scope(None);
+        }
+    }
+
+    linter.function = None;
+    linter.pop_scope(None);
+    linter.warnings
+}
+
+struct Variable {
+    name: String,
+    read: bool,
+    /// Where it is first assigned.
+    span: Option<Span>,
+}
+
+impl Variable {
+    /// A variable that is never reported as unused, such as a loop variable.
+    fn read(name: &str) -> Self {
+        Variable {
+            name: name.to_string(),
+            read: true,
+            span: None,
+        }
+    }
+}
+
+/// A variable whose block has ended.
+struct Ended {
+    name: String,
+    block: &'static str,
+    read: bool,
+    span: Option<Span>,
+}
+
+#[derive(Default)]
+struct Scope {
+    variables: Vec<Variable>,
+    /// Variables defined in blocks inside this scope that have ended.
+    ended: Vec<Ended>,
+}
+
+struct Linter<'a> {
+    /// The global scope, followed by the scopes of the blocks being looked
+    /// at.
+    scopes: Vec<Scope>,
+    /// The function being looked at, if any.
+    function: Option<String>,
+    /// The statement being looked at, the innermost when blocks are nested.
+    span: Option<Span>,
+    /// Function bodies and trap handlers left until the program has been
+    /// looked at.
+    deferred: Vec<(Option<&'a FunctionStatement>, &'a ASTNode)>,
+    warnings: Vec<Warning>,
+}
+
+impl<'a> Linter<'a> {
+    fn warn(&mut self, kind: WarningKind, message: String, span: Option<Span>) {
+        let message = match &self.function {
+            Some(name) => format!("{} in function {}", message, name),
+            None => message,
+        };
+        self.warnings.push(Warning {
+            kind,
+            message,
+            span,
+        });
+    }
+
+    fn find(&mut self, name: &str) -> Option<&mut Variable> {
+        self.scopes
+            .iter_mut()
+            .rev()
+            .find_map(|scope| scope.variables.iter_mut().find(|v| v.name == name))
+    }
+
+    fn assign(&mut self, name: &str) {
+        if self.find(name).is_some() {
+            return;
+        }
+        if let Some(scope) = self.scopes.last_mut() {
+            scope.variables.push(Variable {
+                name: name.to_string(),
+                read: false,
+                span: self.span,
+            });
+        }
+    }
+
+    fn read(&mut self, name: &str) {
+        if let Some(variable) = self.find(name) {
+            variable.read = true;
+            return;
+        }
+
+        for i in (0..self.scopes.len()).rev() {
+            let ended = &mut self.scopes[i].ended;
+            if let Some(pos) = ended.iter().position(|e| e.name == name) {
+                // each branch of an if may have defined it
+                let block = ended[pos].block;
+                ended.retain(|e| e.name != name);
+                self.warn(
+                    WarningKind::Undefined,
+                    format!(
+                        "'{}' is read after the {} that defines it, where it isn't defined",
+                        name, block
+                    ),
+                    self.span,
+                );
+                return;
+            }
+        }
+    }
+
+    fn push_scope(&mut self) {
+        self.scopes.push(Scope::default());
+    }
+
+    /// Ends a scope. Its variables are remembered by the scope around it as
+    /// those of `block`, or reported as unused if the scope isn't a block.
+    fn pop_scope(&mut self, block: Option<&'static str>) {
+        let scope = match self.scopes.pop() {
+            Some(scope) => scope,
+            None => return,
+        };
+
+        let mut unused: Vec<(String, Option<Span>)> = scope
+            .ended
+            .into_iter()
+            .filter(|ended| !ended.read)
+            .map(|ended| (ended.name, ended.span))
+            .collect();
+        match (block, self.scopes.last_mut()) {
+            (Some(block), Some(outer)) => {
+                outer
+                    .ended
+                    .extend(scope.variables.into_iter().map(|variable| Ended {
+                        name: variable.name,
+                        block,
+                        read: variable.read,
+                        span: variable.span,
+                    }))
+            }
+            _ => unused.extend(
+                scope
+                    .variables
+                    .into_iter()
+                    .filter(|variable| !variable.read)
+                    .map(|variable| (variable.name, variable.span)),
+            ),
+        }
+
+        for (name, span) in unused {
+            self.warn(
+                WarningKind::Unused,
+                format!("'{}' is assigned but never read", name),
+                span,
+            );
+        }
+    }
+
+    /// Looks at a block's body in a scope of its own.
+    fn block(&mut self, body: &'a ASTNode, block: &'static str) {
+        self.push_scope();
+        self.node(body);
+        self.pop_scope(Some(block));
+    }
+
+    fn statements(&mut self, block: &'a BlockStatement) {
+        let returns = block
+            .body
+            .iter()
+            .position(|statement| matches!(statement, ASTNode::ReturnStatement(_)));
+        if let Some(i) = returns.filter(|i| i + 1 < block.body.len()) {
+            self.warn(
+                WarningKind::Unreachable,
+                "code after return is never run".to_string(),
+                block.spans.get(i + 1).copied(),
+            );
+        }
+        // what comes after is still looked at for the variables it reads
+        for (i, statement) in block.body.iter().enumerate() {
+            self.span = block.spans.get(i).copied();
+            self.node(statement);
+        }
+    }
+
+    fn nodes(&mut self, nodes: &'a [ASTNode]) {
+        for node in nodes {
+            self.node(node);
+        }
+    }
+
+    fn node(&mut self, node: &'a ASTNode) {
+        match node {
+            ASTNode::Program(bs) | ASTNode::BlockStatement(bs) => self.statements(bs),
+            ASTNode::IfStatement(is) => {
+                self.node(&is.condition);
+                self.block(&is.consequence, "if");
+                if let Some(alternative) = &is.alternative {
+                    self.block(alternative, "if");
+                }
+            }
+            ASTNode::ForStatement(fs) => {
+                match &*fs.iterable {
+                    Iterable::RangeExpression(range) => self.range(range),
+                    Iterable::Collection(node) => self.node(node),
+                }
+                self.push_scope();
+                let names = match &fs.binding {
+                    ForBinding::Identifier(name) => std::slice::from_ref(name),
+                    ForBinding::Destructure(names) => names.as_slice(),
+                };
+                for name in names {
+                    if let Some(scope) = self.scopes.last_mut() {
+                        scope.variables.push(Variable::read(name));
+                    }
+                }
+                self.node(&fs.body);
+                self.pop_scope(Some("for loop"));
+            }
+            ASTNode::WithStatement(ws) => {
+                self.node(&ws.argument);
+                let block = match ws.kind {
+                    WithKind::Env => "with_env block",
+                    WithKind::Dir => "with_dir block",
+                };
+                self.block(&ws.body, block);
+            }
+            ASTNode::TimeExpression(body) => self.block(body, "time block"),
+            ASTNode::FunctionStatement(fs) => self.deferred.push((Some(fs), &fs.body)),
+            ASTNode::TrapStatement(ts) => self.deferred.push((None, &ts.handler)),
+            ASTNode::AliasStatement(_) => (),
+            ASTNode::ReturnStatement(expr) | ASTNode::UnaryExpression(expr) => self.node(expr),
+
+            ASTNode::VariableExpression(ve) => {
+                self.node(&ve.rhs);
+                match &*ve.lhs {
+                    ASTNode::Identifier(name) => self.assign(name),
+                    lhs => self.node(lhs),
+                }
+            }
+            ASTNode::MemberExpression(me) => self.node(&me.base),
+            ASTNode::IndexExpression(ie) => {
+                self.node(&ie.base);
+                self.node(&ie.index);
+            }
+            ASTNode::CallExpression(ce) => {
+                self.node(&ce.base);
+                self.nodes(&ce.args);
+            }
+            ASTNode::BinaryExpression(be) => {
+                self.node(&be.left);
+                self.node(&be.right);
+            }
+            ASTNode::RangeExpression(range) => self.range(range),
+            ASTNode::List(items) => self.nodes(items),
+            ASTNode::Object(entries) => {
+                for (_, value) in entries.iter() {
+                    self.node(value);
+                }
+            }
+            ASTNode::TemplateString(ts) => {
+                for token in &ts.tokens {
+                    if let TemplateToken::Expression(expr) = token {
+                        let name = expr.split('.').next().unwrap_or_default();
+                        self.read(name);
+                    }
+                }
+            }
+            ASTNode::Identifier(name) => self.read(name),
+            ASTNode::Number(_) | ASTNode::Boolean(_) | ASTNode::String(_) | ASTNode::None => (),
+
+            ASTNode::Command(cmd)
+            | ASTNode::Capture(cmd)
+            | ASTNode::Spawn(cmd)
+            | ASTNode::Interactive(cmd) => self.command(cmd),
+        }
+    }
+
+    fn range(&mut self, range: &'a RangeExpression) {
+        self.node(&range.start);
+        self.node(&range.end);
+        if let Some(increment) = &range.increment {
+            self.node(increment);
+        }
+    }
+
+    fn command(&mut self, cmd: &'a Command) {
+        for (_, value) in &cmd.envs {
+            self.nodes(value);
+        }
+        self.nodes(&cmd.tokens);
+        for stage in &cmd.stages {
+            match stage {
+                PipeStage::Function(name) => self.read(name),
+                PipeStage::Command(tokens) => self.nodes(tokens),
+            }
+        }
+        if let Some(redirect) = &cmd.redirect {
+            self.nodes(&redirect.target);
+        }
+    }
+}
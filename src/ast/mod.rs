@@ -1,2 +1,6 @@
 pub mod ast;
+pub mod bash;
+pub mod bytecode;
 pub mod evaluator;
+pub mod lint;
+pub mod source;
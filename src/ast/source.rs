@@ -0,0 +1,183 @@
+use super::ast::{
+    ASTNode, BinaryExpression, Command, ForBinding, Iterable, PipeStage, RangeExpression,
+    TemplateToken, WithKind,
+};
+use crate::parser;
+
+/// Writes a statement back out as a line of sod, as shown when tracing.
+/// Statements with a body show only the line that opens it, such as
+/// `for x in xs {`, as the statements inside are shown as they run.
+pub fn statement(node: &ASTNode) -> String {
+    match node {
+        ASTNode::IfStatement(is) => format!("if {} {{", expression(&is.condition)),
+        ASTNode::BlockStatement(_) => "{".to_string(),
+        ASTNode::ForStatement(fs) => {
+            let binding = match &fs.binding {
+                ForBinding::Identifier(name) => name.to_string(),
+                ForBinding::Destructure(names) => format!("({})", names.join(", ")),
+            };
+            let iterable = match &*fs.iterable {
+                Iterable::RangeExpression(range) => range_expression(range),
+                Iterable::Collection(node) => expression(node),
+            };
+            format!("for {} in {} {{", binding, iterable)
+        }
+        ASTNode::WithStatement(ws) => {
+            let name = match ws.kind {
+                WithKind::Env => "with_env",
+                WithKind::Dir => "with_dir",
+            };
+            format!("{}({}) {{", name, expression(&ws.argument))
+        }
+        ASTNode::FunctionStatement(fs) => format!("func {}({}) {{", fs.name, fs.args.join(", ")),
+        ASTNode::AliasStatement(name) => format!("alias {}", name),
+        ASTNode::TrapStatement(ts) => {
+            let handler = match &*ts.handler {
+                ASTNode::Program(program) => program
+                    .body
+                    .iter()
+                    .map(statement)
+                    .collect::<Vec<String>>()
+                    .join("; "),
+                handler => statement(handler),
+            };
+            format!("trap {} {}", quote(&handler), ts.signals.join(" "))
+        }
+        ASTNode::ReturnStatement(expr) => format!("return {}", expression(expr)),
+        node => expression(node),
+    }
+}
+
+/// Writes an expression back out as sod.
+pub fn expression(node: &ASTNode) -> String {
+    match node {
+        ASTNode::MemberExpression(me) => format!("{}.{}", expression(&me.base), me.property),
+        ASTNode::IndexExpression(ie) => {
+            format!("{}[{}]", expression(&ie.base), expression(&ie.index))
+        }
+        ASTNode::CallExpression(ce) => format!("{}({})", expression(&ce.base), list(&ce.args)),
+        ASTNode::VariableExpression(ve) => {
+            format!("{} = {}", expression(&ve.lhs), expression(&ve.rhs))
+        }
+        ASTNode::BinaryExpression(be) => binary_expression(be),
+        ASTNode::UnaryExpression(expr) => format!("-{}", operand(expr, usize::MAX)),
+        ASTNode::RangeExpression(range) => range_expression(range),
+        ASTNode::TimeExpression(_) => "time {".to_string(),
+
+        ASTNode::Number(n) => n.to_string(),
+        ASTNode::Boolean(b) => b.to_string(),
+        ASTNode::String(s) => quote(s),
+        ASTNode::TemplateString(ts) => {
+            let mut s = String::from("\"");
+            for token in &ts.tokens {
+                match token {
+                    TemplateToken::Literal(literal) => s.push_str(literal),
+                    TemplateToken::Expression(src) => {
+                        s.push('$');
+                        s.push_str(src);
+                    }
+                }
+            }
+            s.push('"');
+            s
+        }
+        ASTNode::Identifier(name) => name.to_string(),
+        ASTNode::None => "none".to_string(),
+        ASTNode::List(items) => format!("[{}]", list(items)),
+        ASTNode::Object(entries) => {
+            let entries: Vec<String> = entries
+                .iter()
+                .map(|(key, value)| format!("{}: {}", quote(key), expression(value)))
+                .collect();
+            format!("{{{}}}", entries.join(", "))
+        }
+
+        ASTNode::Command(cmd) | ASTNode::Capture(cmd) => command(cmd),
+        ASTNode::Spawn(cmd) => format!("spawn {}", command(cmd)),
+        ASTNode::Interactive(cmd) => format!("interactive {}", command(cmd)),
+        node => statement(node),
+    }
+}
+
+fn list(nodes: &[ASTNode]) -> String {
+    nodes
+        .iter()
+        .map(expression)
+        .collect::<Vec<String>>()
+        .join(", ")
+}
+
+fn quote(s: &str) -> String {
+    format!("'{}'", s.replace('\\', "\\\\").replace('\'', "\\'"))
+}
+
+fn range_expression(range: &RangeExpression) -> String {
+    let mut s = format!("{}..{}", expression(&range.start), expression(&range.end));
+    if let Some(increment) = &range.increment {
+        s.push_str("..");
+        s.push_str(&expression(increment));
+    }
+    s
+}
+
+fn binary_expression(be: &BinaryExpression) -> String {
+    let precedence = parser::precedence_of(&be.operator);
+    // operators are left associative, so an operator of the same precedence
+    // on the right came from parentheses
+    format!(
+        "{} {} {}",
+        operand(&be.left, precedence),
+        be.operator,
+        operand(&be.right, precedence + 1)
+    )
+}
+
+/// An operand of an operator, in parentheses if it binds less tightly than
+/// `precedence`.
+fn operand(node: &ASTNode, precedence: usize) -> String {
+    match node {
+        ASTNode::BinaryExpression(be) if parser::precedence_of(&be.operator) < precedence => {
+            format!("({})", binary_expression(be))
+        }
+        node => expression(node),
+    }
+}
+
+/// A command as written, with variables and expressions in it shown before
+/// interpolation.
+fn command(cmd: &Command) -> String {
+    let mut s = String::new();
+    for (name, value) in &cmd.envs {
+        s.push_str(&format!("{}={} ", name, words(value)));
+    }
+    s.push_str(&words(&cmd.tokens));
+    for stage in &cmd.stages {
+        match stage {
+            PipeStage::Function(name) => s.push_str(&format!(" | {}", name)),
+            PipeStage::Command(tokens) => s.push_str(&format!(" | {}", words(tokens))),
+        }
+    }
+    if let Some(redirect) = &cmd.redirect {
+        let operator = if redirect.append { ">>" } else { ">" };
+        s.push_str(&format!(" {} {}", operator, words(&redirect.target)));
+    }
+    s
+}
+
+fn words(tokens: &[ASTNode]) -> String {
+    tokens
+        .iter()
+        .map(|token| match token {
+            ASTNode::String(word) => word.clone(),
+            ASTNode::Identifier(name) => format!("${}", name),
+            ASTNode::TemplateString(_) => expression(token),
+            ASTNode::CallExpression(ce) => match (&*ce.base, ce.args.as_slice()) {
+                (ASTNode::Identifier(raw), [ASTNode::Identifier(name)]) if &**raw == "raw" => {
+                    format!("${}!", name)
+                }
+                _ => format!("${{{}}}", expression(token)),
+            },
+            token => format!("${{{}}}", expression(token)),
+        })
+        .collect()
+}
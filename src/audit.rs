@@ -0,0 +1,126 @@
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use crate::commands::CommandLine;
+
+/// A command that sod ran, as passed to the audit hook once it has finished.
+#[derive(Debug, Clone)]
+pub struct AuditEntry {
+    /// The command string after interpolation, with secrets as `*****`.
+    pub command: String,
+    /// The directory the command ran in.
+    pub cwd: PathBuf,
+    pub started: SystemTime,
+    pub duration: Duration,
+    /// The exit code, or `None` if the command was interrupted or terminated
+    /// by a signal.
+    pub status: Option<i32>,
+}
+
+impl AuditEntry {
+    /// The entry as a single line of json.
+    pub fn to_json(&self) -> String {
+        let started = self
+            .started
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs_f64();
+        let status = match self.status {
+            Some(code) => code.to_string(),
+            None => "null".to_string(),
+        };
+
+        format!(
+            "{{\"started\":{:.3},\"command\":{},\"cwd\":{},\"duration\":{:.3},\"status\":{}}}",
+            started,
+            json_string(&self.command),
+            json_string(&self.cwd.to_string_lossy()),
+            self.duration.as_secs_f64(),
+            status
+        )
+    }
+}
+
+fn json_string(s: &str) -> String {
+    let mut out = String::from("\"");
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+pub type AuditHook = Box<dyn FnMut(&AuditEntry) + Send>;
+
+static HOOK: Mutex<Option<AuditHook>> = Mutex::new(None);
+
+/// Calls `hook` with every command run from now on, or stops auditing when
+/// `hook` is none.
+pub fn set_hook(hook: Option<AuditHook>) {
+    if let Ok(mut current) = HOOK.lock() {
+        *current = hook;
+    }
+}
+
+/// Appends every command run from now on to a file, one json object per
+/// line.
+pub fn log_to_file(path: &Path) -> Result<(), String> {
+    let mut file = fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .map_err(|e| format!("failed to open audit log {}: {}", path.display(), e))?;
+
+    set_hook(Some(Box::new(move |entry| {
+        let _ = writeln!(file, "{}", entry.to_json());
+    })));
+    Ok(())
+}
+
+/// The details of a command noted as it starts, recorded once it finishes.
+#[derive(Debug)]
+pub(crate) struct Started {
+    command: String,
+    cwd: PathBuf,
+    started: SystemTime,
+    start: Instant,
+}
+
+impl Started {
+    pub(crate) fn now(cmd: &CommandLine) -> Self {
+        Self {
+            command: cmd.to_string(),
+            cwd: cmd.environment.current_dir(),
+            started: SystemTime::now(),
+            start: Instant::now(),
+        }
+    }
+
+    pub(crate) fn finish(&self, status: Option<i32>) {
+        let mut hook = match HOOK.lock() {
+            Ok(hook) => hook,
+            Err(_) => return,
+        };
+
+        if let Some(hook) = hook.as_mut() {
+            hook(&AuditEntry {
+                command: self.command.clone(),
+                cwd: self.cwd.clone(),
+                started: self.started,
+                duration: self.start.elapsed(),
+                status,
+            });
+        }
+    }
+}
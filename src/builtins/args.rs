@@ -0,0 +1,132 @@
+use crate::new_string_symbol;
+use crate::symbol::symbol::{List, Object, Symbol};
+
+/// How a single option in an `args_parse` spec is read.
+struct OptionSpec {
+    name: String,
+    flag: bool,
+    required: bool,
+    short: Option<String>,
+    default: Symbol,
+}
+
+impl OptionSpec {
+    fn from(name: &str, symbol: &Symbol) -> Result<Self, String> {
+        let options = match symbol {
+            Symbol::Object(options) => options,
+            _ => {
+                return Err(format!(
+                    "option {} expected an object, found {}",
+                    name,
+                    symbol.kind()
+                ))
+            }
+        };
+
+        let is_set = |key: &str| options.get(key).is_some_and(|s| s.is_truthy());
+        let flag = is_set("flag");
+        let default = match options.get("default") {
+            Some(default) => default.clone(),
+            None if flag => Symbol::Boolean(false),
+            None => Symbol::None,
+        };
+
+        Ok(Self {
+            name: name.to_string(),
+            flag,
+            required: is_set("required"),
+            short: options.get("short").map(|s| s.raw_str()),
+            default,
+        })
+    }
+
+    /// Options are written with dashes on the command line, so `dry_run`
+    /// matches `--dry-run` as well as `--dry_run`.
+    fn matches(&self, long: &str) -> bool {
+        self.name == long || self.name.replace('_', "-") == long
+    }
+
+    /// Values take the type of the default, so numeric options are numbers.
+    fn value(&self, value: &str) -> Result<Symbol, String> {
+        match self.default {
+            Symbol::Number(_) => match value.parse::<f64>() {
+                Ok(n) => Ok(Symbol::Number(n)),
+                Err(_) => Err(format!(
+                    "option --{} expected a number, found '{}'",
+                    self.name, value
+                )),
+            },
+            _ => Ok(new_string_symbol!(value.to_string())),
+        }
+    }
+}
+
+/// Parses command line arguments against a spec of options. Each option in
+/// the result holds its value or default, and `positionals` holds the
+/// remaining arguments.
+pub fn parse(spec: &Object, argv: &[String]) -> Result<Symbol, String> {
+    let mut specs = vec![];
+    for (name, options) in spec.iter() {
+        specs.push(OptionSpec::from(name, options)?);
+    }
+
+    let mut result = Object::from(vec![]);
+    let mut positionals = vec![];
+    let mut args = argv.iter();
+    while let Some(arg) = args.next() {
+        if arg == "--" {
+            positionals.extend(args.by_ref().map(|arg| new_string_symbol!(arg.clone())));
+            break;
+        }
+
+        let (option, inline_value) = if let Some(long) = arg.strip_prefix("--") {
+            let (long, value) = match long.split_once('=') {
+                Some((long, value)) => (long, Some(value)),
+                None => (long, None),
+            };
+            (specs.iter().find(|s| s.matches(long)), value)
+        } else if let Some(short) = arg.strip_prefix('-').filter(|s| !s.is_empty()) {
+            (
+                specs.iter().find(|s| s.short.as_deref() == Some(short)),
+                None,
+            )
+        } else {
+            positionals.push(new_string_symbol!(arg.clone()));
+            continue;
+        };
+
+        let option = match option {
+            Some(option) => option,
+            None => return Err(format!("unknown option {}", arg)),
+        };
+
+        let value = match (option.flag, inline_value) {
+            (true, None) => Symbol::Boolean(true),
+            (true, Some(_)) => {
+                return Err(format!("option --{} does not take a value", option.name))
+            }
+            (false, Some(value)) => option.value(value)?,
+            (false, None) => match args.next() {
+                Some(value) => option.value(value)?,
+                None => return Err(format!("option --{} expects a value", option.name)),
+            },
+        };
+        result.insert(option.name.clone(), value);
+    }
+
+    for option in specs {
+        if result.get(&option.name).is_some() {
+            continue;
+        }
+        if option.required {
+            return Err(format!("missing required option --{}", option.name));
+        }
+        result.insert(option.name, option.default);
+    }
+
+    result.insert(
+        "positionals".to_string(),
+        Symbol::List(List::from(positionals)),
+    );
+    Ok(Symbol::Object(result))
+}
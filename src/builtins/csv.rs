@@ -0,0 +1,118 @@
+use super::expect_args;
+use crate::new_string_symbol;
+use crate::symbol::symbol::{List, Object, Symbol};
+
+/// `csv_parse(string, headers?)` returns a list of rows. With `headers` the
+/// first row names the columns and each row is an object instead of a list.
+pub fn csv_parse(args: Vec<Symbol>) -> Result<Symbol, String> {
+    let (input, headers) = match args.as_slice() {
+        [input] => (input, false),
+        [input, headers] => (input, headers.is_truthy()),
+        _ => {
+            return Err(format!(
+                "expected 1 or 2 arguments to csv_parse, found {}",
+                args.len()
+            ))
+        }
+    };
+
+    let input = input.raw_str();
+    let mut reader = csv::ReaderBuilder::new()
+        .has_headers(headers)
+        .flexible(!headers)
+        .from_reader(input.as_bytes());
+
+    let columns = match headers {
+        true => reader
+            .headers()
+            .map_err(|e| format!("invalid csv: {}", e))?
+            .iter()
+            .map(|column| column.to_string())
+            .collect(),
+        false => vec![],
+    };
+
+    let mut rows = vec![];
+    for record in reader.records() {
+        let record = record.map_err(|e| format!("invalid csv: {}", e))?;
+        let row = if headers {
+            let mut object = Object::from(vec![]);
+            for (column, field) in columns.iter().zip(record.iter()) {
+                object.insert(column.clone(), new_string_symbol!(field.to_string()));
+            }
+            Symbol::Object(object)
+        } else {
+            let fields = record
+                .iter()
+                .map(|field| new_string_symbol!(field.to_string()))
+                .collect();
+            Symbol::List(List::from(fields))
+        };
+        rows.push(row);
+    }
+
+    Ok(Symbol::List(List::from(rows)))
+}
+
+fn field(symbol: &Symbol) -> String {
+    match symbol {
+        Symbol::None => "".to_string(),
+        _ => symbol.raw_str(),
+    }
+}
+
+/// `csv_stringify(rows)` accepts a list of lists, or a list of objects which
+/// are written with a header row of the first object's keys.
+pub fn csv_stringify(args: Vec<Symbol>) -> Result<Symbol, String> {
+    expect_args("csv_stringify", &args, 1)?;
+    let rows = match &args[0] {
+        Symbol::List(list) => list.items(),
+        symbol => {
+            return Err(format!(
+                "csv_stringify expected a list, found {}",
+                symbol.kind()
+            ))
+        }
+    };
+
+    let mut writer = csv::Writer::from_writer(vec![]);
+    let mut columns: Option<Vec<String>> = None;
+    for row in rows {
+        let record: Vec<String> = match row {
+            Symbol::List(fields) => fields.items().iter().map(field).collect(),
+            Symbol::Object(obj) => {
+                let columns = match &columns {
+                    Some(columns) => columns,
+                    None => {
+                        let keys: Vec<String> = obj.iter().map(|(k, _)| k.clone()).collect();
+                        writer
+                            .write_record(&keys)
+                            .map_err(|e| format!("failed to write csv: {}", e))?;
+                        columns.insert(keys)
+                    }
+                };
+                columns
+                    .iter()
+                    .map(|column| obj.get(column).map(field).unwrap_or_default())
+                    .collect()
+            }
+            symbol => {
+                return Err(format!(
+                    "csv_stringify expected rows to be lists or objects, found {}",
+                    symbol.kind()
+                ))
+            }
+        };
+
+        writer
+            .write_record(&record)
+            .map_err(|e| format!("failed to write csv: {}", e))?;
+    }
+
+    let bytes = writer
+        .into_inner()
+        .map_err(|e| format!("failed to write csv: {}", e))?;
+    Ok(new_string_symbol!(
+        String::from_utf8_lossy(&bytes).to_string()
+    ))
+}
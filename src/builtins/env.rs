@@ -0,0 +1,61 @@
+use super::expect_args;
+use crate::commands::{self, Environment};
+use crate::new_string_symbol;
+use crate::symbol::symbol::Symbol;
+
+fn expect_name(fname: &str, symbol: &Symbol) -> Result<String, String> {
+    match symbol {
+        Symbol::String(_) => Ok(symbol.raw_str()),
+        _ => Err(format!(
+            "{} expected a variable name, found {}",
+            fname,
+            symbol.kind()
+        )),
+    }
+}
+
+pub fn get_env(environment: &mut Environment, args: Vec<Symbol>) -> Result<Symbol, String> {
+    expect_args("env", &args, 1)?;
+    let name = expect_name("env", &args[0])?;
+    match environment.var(&name) {
+        Some(value) => Ok(new_string_symbol!(value)),
+        None => Ok(Symbol::None),
+    }
+}
+
+/// Sets an environment variable for the rest of the script, including the
+/// commands it runs. Setting a variable to none removes it.
+pub fn set_env(environment: &mut Environment, args: Vec<Symbol>) -> Result<Symbol, String> {
+    expect_args("set_env", &args, 2)?;
+    let name = expect_name("set_env", &args[0])?;
+    if name.is_empty() || name.contains(['=', '\0']) {
+        return Err(format!("invalid environment variable name '{}'", name));
+    }
+
+    match &args[1] {
+        Symbol::None => environment.set_var(name, None),
+        value => environment.set_var(name, Some(value.raw_str())),
+    }
+
+    Ok(Symbol::None)
+}
+
+/// `which(name)` returns the path of the executable a command would run, or
+/// none if it isn't on PATH.
+pub fn which(environment: &mut Environment, args: Vec<Symbol>) -> Result<Symbol, String> {
+    expect_args("which", &args, 1)?;
+    let name = match &args[0] {
+        Symbol::String(_) => args[0].raw_str(),
+        symbol => {
+            return Err(format!(
+                "which expected a command name, found {}",
+                symbol.kind()
+            ))
+        }
+    };
+
+    match commands::which(&name, &environment.path()) {
+        Some(path) => Ok(new_string_symbol!(path.to_string_lossy().to_string())),
+        None => Ok(Symbol::None),
+    }
+}
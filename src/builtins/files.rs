@@ -0,0 +1,51 @@
+use std::fs;
+
+use super::expect_args;
+use crate::commands::Environment;
+use crate::symbol::symbol::Symbol;
+
+/// Reads a file as a string, or as bytes when it isn't valid utf8. A relative
+/// path is read from the directory the script's commands run in.
+pub fn read_file(environment: &mut Environment, args: Vec<Symbol>) -> Result<Symbol, String> {
+    expect_args("read_file", &args, 1)?;
+    let path = match &args[0] {
+        Symbol::String(_) => args[0].raw_str(),
+        symbol => {
+            return Err(format!(
+                "read_file expected a path, found {}",
+                symbol.kind()
+            ))
+        }
+    };
+
+    match fs::read(environment.resolve(&path)) {
+        Ok(contents) => Ok(Symbol::from_bytes(contents)),
+        Err(e) => Err(format!("failed to read {}: {}", path, e)),
+    }
+}
+
+/// `bytes(value)` converts a string, or a list of numbers from 0 to 255, to
+/// bytes.
+pub fn bytes(args: Vec<Symbol>) -> Result<Symbol, String> {
+    expect_args("bytes", &args, 1)?;
+    match &args[0] {
+        Symbol::String(_) | Symbol::Secret(_) => Ok(Symbol::Bytes(args[0].raw_str().into_bytes())),
+        Symbol::Bytes(bytes) => Ok(Symbol::Bytes(bytes.clone())),
+        Symbol::List(list) => {
+            let mut bytes = vec![];
+            for item in list.items() {
+                match item {
+                    Symbol::Number(n) if (0.0..=255.0).contains(n) && n.fract() == 0.0 => {
+                        bytes.push(*n as u8)
+                    }
+                    _ => return Err(format!("{} is not a byte", item)),
+                }
+            }
+            Ok(Symbol::Bytes(bytes))
+        }
+        symbol => Err(format!(
+            "bytes expected a string or list, found {}",
+            symbol.kind()
+        )),
+    }
+}
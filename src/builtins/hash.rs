@@ -0,0 +1,110 @@
+use std::fs::File;
+use std::io::Read;
+
+use md5::Md5;
+use sha2::{Digest, Sha256};
+
+use super::expect_args;
+use crate::commands::Environment;
+use crate::new_string_symbol;
+use crate::symbol::symbol::Symbol;
+
+enum Hasher {
+    Sha256(Sha256),
+    Md5(Md5),
+    Crc32(crc32fast::Hasher),
+}
+
+impl Hasher {
+    fn new(algorithm: &str) -> Result<Self, String> {
+        match algorithm {
+            "sha256" => Ok(Hasher::Sha256(Sha256::new())),
+            "md5" => Ok(Hasher::Md5(Md5::new())),
+            "crc32" => Ok(Hasher::Crc32(crc32fast::Hasher::new())),
+            _ => Err(format!("unknown hash algorithm '{}'", algorithm)),
+        }
+    }
+
+    fn update(&mut self, bytes: &[u8]) {
+        match self {
+            Hasher::Sha256(h) => h.update(bytes),
+            Hasher::Md5(h) => h.update(bytes),
+            Hasher::Crc32(h) => h.update(bytes),
+        }
+    }
+
+    /// The digest as lowercase hex, matching the output of `sha256sum` and
+    /// friends.
+    fn finish(self) -> String {
+        match self {
+            Hasher::Sha256(h) => to_hex(&h.finalize()),
+            Hasher::Md5(h) => to_hex(&h.finalize()),
+            Hasher::Crc32(h) => format!("{:08x}", h.finalize()),
+        }
+    }
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn hash_string(algorithm: &str, args: Vec<Symbol>) -> Result<Symbol, String> {
+    expect_args(algorithm, &args, 1)?;
+    let value = match &args[0] {
+        Symbol::String(_) | Symbol::Secret(_) => args[0].raw_str(),
+        symbol => {
+            return Err(format!(
+                "{} expected a string, found {}",
+                algorithm,
+                symbol.kind()
+            ))
+        }
+    };
+
+    let mut hasher = Hasher::new(algorithm)?;
+    hasher.update(value.as_bytes());
+    Ok(new_string_symbol!(hasher.finish()))
+}
+
+pub fn sha256(args: Vec<Symbol>) -> Result<Symbol, String> {
+    hash_string("sha256", args)
+}
+
+pub fn md5(args: Vec<Symbol>) -> Result<Symbol, String> {
+    hash_string("md5", args)
+}
+
+pub fn crc32(args: Vec<Symbol>) -> Result<Symbol, String> {
+    hash_string("crc32", args)
+}
+
+/// Hashes the contents of a file without reading it all into memory.
+pub fn hash_file(environment: &mut Environment, args: Vec<Symbol>) -> Result<Symbol, String> {
+    expect_args("hash_file", &args, 2)?;
+    let (path, algorithm) = match (&args[0], &args[1]) {
+        (Symbol::String(_), Symbol::String(_)) => (args[0].raw_str(), args[1].raw_str()),
+        _ => {
+            return Err(format!(
+                "hash_file expected a path and an algorithm, found {} and {}",
+                args[0].kind(),
+                args[1].kind()
+            ))
+        }
+    };
+
+    let mut hasher = Hasher::new(&algorithm)?;
+    let mut file = File::open(environment.resolve(&path))
+        .map_err(|e| format!("failed to open {}: {}", path, e))?;
+    let mut buffer = [0; 8192];
+    loop {
+        let read = file
+            .read(&mut buffer)
+            .map_err(|e| format!("failed to read {}: {}", path, e))?;
+        if read == 0 {
+            break;
+        }
+        hasher.update(&buffer[..read]);
+    }
+
+    Ok(new_string_symbol!(hasher.finish()))
+}
@@ -0,0 +1,103 @@
+use std::time::Duration;
+
+use crate::new_string_symbol;
+use crate::symbol::symbol::{Object, Symbol};
+
+const TIMEOUT: Duration = Duration::from_secs(30);
+
+fn expect_string(fname: &str, label: &str, symbol: &Symbol) -> Result<String, String> {
+    match symbol {
+        Symbol::String(_) | Symbol::Secret(_) => Ok(symbol.raw_str()),
+        _ => Err(format!(
+            "{} expected a string {}, found {}",
+            fname,
+            label,
+            symbol.kind()
+        )),
+    }
+}
+
+fn request(
+    fname: &str,
+    method: &str,
+    url: &Symbol,
+    headers: Option<&Symbol>,
+) -> Result<ureq::Request, String> {
+    let url = expect_string(fname, "url", url)?;
+    let mut request = ureq::request(method, &url).timeout(TIMEOUT);
+
+    match headers {
+        Some(Symbol::Object(headers)) => {
+            for (key, value) in headers.iter() {
+                request = request.set(key, &value.raw_str());
+            }
+        }
+        Some(Symbol::None) | None => (),
+        Some(symbol) => {
+            return Err(format!(
+                "{} expected an object of headers, found {}",
+                fname,
+                symbol.kind()
+            ))
+        }
+    }
+
+    Ok(request)
+}
+
+/// Converts a response into an object. Error statuses are still responses,
+/// only failing to reach the server is an error.
+fn response(result: Result<ureq::Response, ureq::Error>) -> Result<Symbol, String> {
+    let response = match result {
+        Ok(response) => response,
+        Err(ureq::Error::Status(_, response)) => response,
+        Err(e) => return Err(format!("request failed: {}", e)),
+    };
+
+    let mut headers = Object::from(vec![]);
+    for name in response.headers_names() {
+        if let Some(value) = response.header(&name) {
+            headers.insert(name.to_lowercase(), new_string_symbol!(value.to_string()));
+        }
+    }
+
+    let status = response.status();
+    let body = response
+        .into_string()
+        .map_err(|e| format!("failed to read response: {}", e))?;
+
+    Ok(Symbol::Object(Object::from(vec![
+        ("status", Symbol::Number(status as f64)),
+        ("headers", Symbol::Object(headers)),
+        ("body", new_string_symbol!(body)),
+    ])))
+}
+
+/// `http_get(url, headers?)`
+pub fn http_get(args: Vec<Symbol>) -> Result<Symbol, String> {
+    match args.as_slice() {
+        [url] | [url, _] => {
+            let request = request("http_get", "GET", url, args.get(1))?;
+            response(request.call())
+        }
+        _ => Err(format!(
+            "expected 1 or 2 arguments to http_get, found {}",
+            args.len()
+        )),
+    }
+}
+
+/// `http_post(url, body, headers?)`
+pub fn http_post(args: Vec<Symbol>) -> Result<Symbol, String> {
+    match args.as_slice() {
+        [url, body] | [url, body, _] => {
+            let body = expect_string("http_post", "body", body)?;
+            let request = request("http_post", "POST", url, args.get(2))?;
+            response(request.send_string(&body))
+        }
+        _ => Err(format!(
+            "expected 2 or 3 arguments to http_post, found {}",
+            args.len()
+        )),
+    }
+}
@@ -0,0 +1,51 @@
+use crate::new_string_symbol;
+use crate::symbol::symbol::{List, Object, Symbol};
+
+use super::expect_args;
+
+/// The optional cargo features this interpreter was compiled with.
+pub fn features() -> Vec<&'static str> {
+    let mut features = vec![];
+    if cfg!(feature = "csv") {
+        features.push("csv");
+    }
+    if cfg!(feature = "http") {
+        features.push("http");
+    }
+    if cfg!(feature = "json") {
+        features.push("json");
+    }
+    if cfg!(feature = "toml") {
+        features.push("toml");
+    }
+    if cfg!(feature = "yaml") {
+        features.push("yaml");
+    }
+    features
+}
+
+/// The `sod` global, describing the running interpreter.
+pub fn info() -> Symbol {
+    let features = features()
+        .into_iter()
+        .map(|feature| new_string_symbol!(feature.to_string()))
+        .collect();
+
+    Symbol::Object(Object::from(vec![
+        (
+            "version",
+            new_string_symbol!(env!("CARGO_PKG_VERSION").to_string()),
+        ),
+        ("features", Symbol::List(List::from(features))),
+    ]))
+}
+
+pub fn has(args: Vec<Symbol>) -> Result<Symbol, String> {
+    expect_args("has", &args, 1)?;
+    match &args[0] {
+        feature @ Symbol::String(_) => Ok(Symbol::Boolean(
+            features().contains(&feature.raw_str().as_str()),
+        )),
+        symbol => Err(format!("has expected a string, found {}", symbol.kind())),
+    }
+}
@@ -0,0 +1,16 @@
+use super::expect_args;
+use crate::new_string_symbol;
+use crate::symbol::symbol::Symbol;
+
+pub fn json_parse(args: Vec<Symbol>) -> Result<Symbol, String> {
+    expect_args("json_parse", &args, 1)?;
+    serde_json::from_str(&args[0].raw_str()).map_err(|e| format!("invalid json: {}", e))
+}
+
+pub fn json_stringify(args: Vec<Symbol>) -> Result<Symbol, String> {
+    expect_args("json_stringify", &args, 1)?;
+    match serde_json::to_string(&args[0]) {
+        Ok(json) => Ok(new_string_symbol!(json)),
+        Err(e) => Err(format!("unable to convert to json: {}", e)),
+    }
+}
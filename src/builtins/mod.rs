@@ -0,0 +1,114 @@
+use crate::commands::Environment;
+use crate::new_string_symbol;
+use crate::symbol::symbol::Symbol;
+
+pub mod args;
+#[cfg(feature = "csv")]
+pub mod csv;
+pub mod env;
+pub mod files;
+pub mod hash;
+#[cfg(feature = "http")]
+pub mod http;
+pub mod interpreter;
+#[cfg(feature = "json")]
+pub mod json;
+pub mod numbers;
+pub mod prompt;
+pub mod secrets;
+pub mod system;
+pub mod table;
+#[cfg(feature = "toml")]
+pub mod toml;
+#[cfg(feature = "yaml")]
+pub mod yaml;
+
+pub type Builtin = fn(Vec<Symbol>) -> Result<Symbol, String>;
+
+/// Looks up a builtin function. User defined functions take precedence, so
+/// this is only consulted when a name isn't in the symbol table. Methods on
+/// the global namespace objects are looked up as `namespace.method`.
+pub fn get(name: &str) -> Option<Builtin> {
+    let builtin: Builtin = match name {
+        "secret" => secrets::secret,
+        "prompt_secret" => secrets::prompt_secret,
+        "confirm" => prompt::confirm,
+        "select" => prompt::select,
+        "parse_num" => numbers::parse_num,
+        #[cfg(feature = "json")]
+        "json_parse" => json::json_parse,
+        #[cfg(feature = "json")]
+        "json_stringify" => json::json_stringify,
+        #[cfg(feature = "csv")]
+        "csv_parse" => csv::csv_parse,
+        #[cfg(feature = "csv")]
+        "csv_stringify" => csv::csv_stringify,
+        #[cfg(feature = "yaml")]
+        "yaml_parse" => yaml::yaml_parse,
+        #[cfg(feature = "toml")]
+        "toml_parse" => toml::toml_parse,
+        "bytes" => files::bytes,
+        "hostname" => system::hostname,
+        "whoami" => system::whoami,
+        "os" => system::os,
+        "cpus" => system::cpus,
+        "table" => table::table,
+        "sha256" => hash::sha256,
+        "md5" => hash::md5,
+        "crc32" => hash::crc32,
+        #[cfg(feature = "http")]
+        "http_get" => http::http_get,
+        #[cfg(feature = "http")]
+        "http_post" => http::http_post,
+        "sod.has" => interpreter::has,
+        "raw" => raw,
+        _ => return None,
+    };
+
+    Some(builtin)
+}
+
+/// A builtin that reads or changes the environment the script's commands
+/// run in.
+pub type EnvBuiltin = fn(&mut Environment, Vec<Symbol>) -> Result<Symbol, String>;
+
+/// Looks up a builtin that is given the script's environment, see `get`.
+pub fn get_env_builtin(name: &str) -> Option<EnvBuiltin> {
+    let builtin: EnvBuiltin = match name {
+        "env" => env::get_env,
+        "set_env" => env::set_env,
+        "which" => env::which,
+        "read_file" => files::read_file,
+        "hash_file" => hash::hash_file,
+        _ => return None,
+    };
+
+    Some(builtin)
+}
+
+/// Marks a value to be passed to a command as is, rather than quoted as a
+/// single argument. Outside of commands it is the value as a string.
+fn raw(args: Vec<Symbol>) -> Result<Symbol, String> {
+    expect_args("raw", &args, 1)?;
+    match &args[0] {
+        Symbol::Secret(_) => Ok(args[0].clone()),
+        Symbol::List(list) => {
+            let words: Vec<String> = list.items().iter().map(|item| item.raw_str()).collect();
+            Ok(new_string_symbol!(words.join(" ")))
+        }
+        symbol => Ok(new_string_symbol!(symbol.raw_str())),
+    }
+}
+
+pub fn expect_args(name: &str, args: &[Symbol], count: usize) -> Result<(), String> {
+    if args.len() != count {
+        return Err(format!(
+            "expected {} arguments to {}, found {}",
+            count,
+            name,
+            args.len()
+        ));
+    }
+
+    Ok(())
+}
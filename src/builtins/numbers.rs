@@ -0,0 +1,21 @@
+use super::expect_args;
+use crate::symbol::symbol::Symbol;
+
+pub fn parse_num(args: Vec<Symbol>) -> Result<Symbol, String> {
+    expect_args("parse_num", &args, 1)?;
+    let s = match &args[0] {
+        Symbol::String(_) => args[0].raw_str(),
+        Symbol::Number(n) => return Ok(Symbol::Number(*n)),
+        symbol => {
+            return Err(format!(
+                "parse_num expected a string, found {}",
+                symbol.kind()
+            ))
+        }
+    };
+
+    match s.trim().parse::<f64>() {
+        Ok(n) => Ok(Symbol::Number(n)),
+        Err(_) => Err(format!("unable to parse '{}' as a number", s.trim())),
+    }
+}
@@ -0,0 +1,103 @@
+use std::io::{self, BufRead, Write};
+
+use super::expect_args;
+use crate::symbol::symbol::Symbol;
+
+/// Writes a prompt and reads the answer, or `None` at the end of input.
+fn ask(
+    input: &mut impl BufRead,
+    output: &mut impl Write,
+    prompt: &str,
+) -> Result<Option<String>, String> {
+    write!(output, "{}", prompt)
+        .and_then(|_| output.flush())
+        .map_err(|e| format!("failed to write prompt: {}", e))?;
+
+    let mut line = String::new();
+    match input.read_line(&mut line) {
+        Ok(0) => Ok(None),
+        Ok(_) => Ok(Some(line.trim().to_string())),
+        Err(e) => Err(format!("failed to read answer: {}", e)),
+    }
+}
+
+/// Asks a yes or no question until it gets an answer. No is the default, so
+/// an empty answer or the end of input declines.
+pub fn read_confirm(
+    input: &mut impl BufRead,
+    output: &mut impl Write,
+    message: &str,
+) -> Result<bool, String> {
+    loop {
+        let answer = match ask(input, output, &format!("{} [y/N] ", message))? {
+            Some(answer) => answer.to_lowercase(),
+            None => return Ok(false),
+        };
+
+        match answer.as_str() {
+            "y" | "yes" => return Ok(true),
+            "" | "n" | "no" => return Ok(false),
+            _ => (),
+        }
+    }
+}
+
+/// Lists the options and asks for one by number until a valid one is chosen.
+pub fn read_select(
+    input: &mut impl BufRead,
+    output: &mut impl Write,
+    message: &str,
+    options: &[Symbol],
+) -> Result<usize, String> {
+    for (i, option) in options.iter().enumerate() {
+        writeln!(output, "{}) {}", i + 1, option.raw_str())
+            .map_err(|e| format!("failed to write prompt: {}", e))?;
+    }
+
+    loop {
+        let answer = match ask(input, output, &format!("{} ", message))? {
+            Some(answer) => answer,
+            None => return Err("no option selected".to_string()),
+        };
+
+        if let Ok(choice) = answer.parse::<usize>() {
+            if (1..=options.len()).contains(&choice) {
+                return Ok(choice - 1);
+            }
+        }
+    }
+}
+
+/// `confirm(message)`
+pub fn confirm(args: Vec<Symbol>) -> Result<Symbol, String> {
+    expect_args("confirm", &args, 1)?;
+    let confirmed = read_confirm(
+        &mut io::stdin().lock(),
+        &mut io::stderr(),
+        &args[0].raw_str(),
+    )?;
+    Ok(Symbol::Boolean(confirmed))
+}
+
+/// `select(message, options)` returns the chosen item.
+pub fn select(args: Vec<Symbol>) -> Result<Symbol, String> {
+    expect_args("select", &args, 2)?;
+    let options = match &args[1] {
+        Symbol::List(list) if !list.items().is_empty() => list.items(),
+        Symbol::List(_) => return Err("select expected at least one option".to_string()),
+        symbol => {
+            return Err(format!(
+                "select expected a list of options, found {}",
+                symbol.kind()
+            ))
+        }
+    };
+
+    let choice = read_select(
+        &mut io::stdin().lock(),
+        &mut io::stderr(),
+        &args[0].raw_str(),
+        options,
+    )?;
+    Ok(options[choice].clone())
+}
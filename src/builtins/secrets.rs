@@ -0,0 +1,33 @@
+use std::io::{self, IsTerminal};
+
+use super::expect_args;
+use crate::symbol::symbol::{StringSymbol, Symbol};
+
+/// Wraps a value so it is masked whenever it is displayed, while commands
+/// still receive the real value.
+pub fn secret(args: Vec<Symbol>) -> Result<Symbol, String> {
+    expect_args("secret", &args, 1)?;
+    Ok(Symbol::Secret(StringSymbol::new(args[0].raw_str())))
+}
+
+/// Reads a line from the terminal without echoing it. When stdin isn't a
+/// terminal the line is read from stdin instead.
+pub fn prompt_secret(args: Vec<Symbol>) -> Result<Symbol, String> {
+    expect_args("prompt_secret", &args, 1)?;
+    let message = args[0].raw_str();
+
+    let input = if io::stdin().is_terminal() {
+        rpassword::prompt_password(message)
+    } else {
+        eprint!("{}", message);
+        let mut line = String::new();
+        io::stdin()
+            .read_line(&mut line)
+            .map(|_| line.trim_end_matches(['\r', '\n']).to_string())
+    };
+
+    match input {
+        Ok(input) => Ok(Symbol::Secret(StringSymbol::new(input))),
+        Err(e) => Err(format!("failed to read secret: {}", e)),
+    }
+}
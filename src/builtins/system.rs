@@ -0,0 +1,87 @@
+#[cfg(unix)]
+use std::ffi::CStr;
+use std::thread;
+
+use super::expect_args;
+use crate::new_string_symbol;
+use crate::symbol::symbol::Symbol;
+
+pub fn hostname(args: Vec<Symbol>) -> Result<Symbol, String> {
+    expect_args("hostname", &args, 0)?;
+    read_hostname().map(|name| new_string_symbol!(name))
+}
+
+#[cfg(unix)]
+fn read_hostname() -> Result<String, String> {
+    let mut buffer = [0 as libc::c_char; 256];
+    // SAFETY: the buffer length passed leaves room for the nul terminator.
+    let result = unsafe { libc::gethostname(buffer.as_mut_ptr(), buffer.len() - 1) };
+    if result != 0 {
+        return Err("failed to read hostname".to_string());
+    }
+
+    // SAFETY: gethostname nul terminates the name and the last byte is
+    // never written, so the buffer always ends in nul.
+    let name = unsafe { CStr::from_ptr(buffer.as_ptr()) };
+    Ok(name.to_string_lossy().to_string())
+}
+
+#[cfg(windows)]
+fn read_hostname() -> Result<String, String> {
+    std::env::var("COMPUTERNAME").map_err(|_| "failed to read hostname".to_string())
+}
+
+/// The name of the effective user, falling back to `$USER` when the user
+/// has no passwd entry, as in some containers.
+pub fn whoami(args: Vec<Symbol>) -> Result<Symbol, String> {
+    expect_args("whoami", &args, 0)?;
+    let name = read_username().or_else(|| std::env::var("USER").ok());
+    match name {
+        Some(name) => Ok(new_string_symbol!(name)),
+        None => Err("failed to read the current user".to_string()),
+    }
+}
+
+#[cfg(unix)]
+fn read_username() -> Option<String> {
+    let mut passwd: libc::passwd = unsafe { std::mem::zeroed() };
+    let mut result: *mut libc::passwd = std::ptr::null_mut();
+    let mut buffer = vec![0 as libc::c_char; 4096];
+
+    // SAFETY: all pointers are valid for the lengths given, and result is
+    // only read after the call says it points at passwd.
+    unsafe {
+        let status = libc::getpwuid_r(
+            libc::geteuid(),
+            &mut passwd,
+            buffer.as_mut_ptr(),
+            buffer.len(),
+            &mut result,
+        );
+        if status == 0 && !result.is_null() {
+            Some(CStr::from_ptr(passwd.pw_name).to_string_lossy().to_string())
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(windows)]
+fn read_username() -> Option<String> {
+    std::env::var("USERNAME").ok()
+}
+
+/// The operating system, e.g. `linux` or `macos`.
+pub fn os(args: Vec<Symbol>) -> Result<Symbol, String> {
+    expect_args("os", &args, 0)?;
+    Ok(new_string_symbol!(std::env::consts::OS.to_string()))
+}
+
+/// The number of CPUs available to the script.
+pub fn cpus(args: Vec<Symbol>) -> Result<Symbol, String> {
+    expect_args("cpus", &args, 0)?;
+    match thread::available_parallelism() {
+        Ok(count) => Ok(Symbol::Number(count.get() as f64)),
+        Err(e) => Err(format!("failed to read cpu count: {}", e)),
+    }
+}
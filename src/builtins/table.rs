@@ -0,0 +1,112 @@
+use crate::new_string_symbol;
+use crate::symbol::symbol::Symbol;
+
+fn cell(symbol: &Symbol) -> String {
+    match symbol {
+        Symbol::None => "".to_string(),
+        _ => symbol.raw_str(),
+    }
+}
+
+fn columns_arg(symbol: &Symbol) -> Result<Vec<String>, String> {
+    match symbol {
+        Symbol::List(list) => Ok(list.items().iter().map(|c| c.raw_str()).collect()),
+        _ => Err(format!(
+            "table expected a list of columns, found {}",
+            symbol.kind()
+        )),
+    }
+}
+
+/// Pads each column to its widest cell, separating columns by two spaces.
+fn render(header: Option<Vec<String>>, rows: Vec<Vec<String>>) -> String {
+    let lines: Vec<Vec<String>> = header.into_iter().chain(rows).collect();
+    let mut widths: Vec<usize> = vec![];
+    for line in &lines {
+        for (i, cell) in line.iter().enumerate() {
+            let width = cell.chars().count();
+            match widths.get_mut(i) {
+                Some(max) => *max = (*max).max(width),
+                None => widths.push(width),
+            }
+        }
+    }
+
+    let mut output = String::new();
+    for line in lines {
+        let mut text = String::new();
+        for (cell, width) in line.iter().zip(&widths) {
+            text.push_str(&format!("{:<width$}  ", cell, width = width));
+        }
+        output.push_str(text.trim_end());
+        output.push('\n');
+    }
+    output
+}
+
+/// `table(rows, columns?)` renders a list of objects, with a header row, or a
+/// list of lists as aligned text. Columns default to the keys of the objects
+/// in the order they first appear.
+pub fn table(args: Vec<Symbol>) -> Result<Symbol, String> {
+    let (rows, columns) = match args.as_slice() {
+        [Symbol::List(rows)] => (rows.items(), None),
+        [Symbol::List(rows), columns] => (rows.items(), Some(columns_arg(columns)?)),
+        [symbol] | [symbol, _] => {
+            return Err(format!("table expected a list, found {}", symbol.kind()))
+        }
+        _ => {
+            return Err(format!(
+                "expected 1 or 2 arguments to table, found {}",
+                args.len()
+            ))
+        }
+    };
+
+    if rows.iter().all(|row| matches!(row, Symbol::List(_))) && columns.is_none() {
+        let rows = rows
+            .iter()
+            .map(|row| match row {
+                Symbol::List(cells) => cells.items().iter().map(cell).collect(),
+                _ => vec![],
+            })
+            .collect();
+        return Ok(new_string_symbol!(render(None, rows)));
+    }
+
+    let columns = match columns {
+        Some(columns) => columns,
+        None => {
+            let mut keys = vec![];
+            for row in rows {
+                if let Symbol::Object(obj) = row {
+                    for (key, _) in obj.iter() {
+                        if !keys.contains(key) {
+                            keys.push(key.clone());
+                        }
+                    }
+                }
+            }
+            keys
+        }
+    };
+
+    let mut lines = vec![];
+    for row in rows {
+        let line = match row {
+            Symbol::Object(obj) => columns
+                .iter()
+                .map(|column| obj.get(column).map(cell).unwrap_or_default())
+                .collect(),
+            Symbol::List(cells) => cells.items().iter().map(cell).collect(),
+            symbol => {
+                return Err(format!(
+                    "table expected rows to be objects or lists, found {}",
+                    symbol.kind()
+                ))
+            }
+        };
+        lines.push(line);
+    }
+
+    Ok(new_string_symbol!(render(Some(columns), lines)))
+}
@@ -0,0 +1,31 @@
+use toml::Value;
+
+use super::expect_args;
+use crate::new_string_symbol;
+use crate::symbol::symbol::{List, Object, Symbol};
+
+fn from_toml(value: Value) -> Symbol {
+    match value {
+        Value::String(s) => new_string_symbol!(s),
+        Value::Integer(n) => Symbol::Number(n as f64),
+        Value::Float(n) => Symbol::Number(n),
+        Value::Boolean(b) => Symbol::Boolean(b),
+        Value::Datetime(datetime) => new_string_symbol!(datetime.to_string()),
+        Value::Array(items) => Symbol::List(List::from(items.into_iter().map(from_toml).collect())),
+        Value::Table(entries) => {
+            let mut object = Object::from(vec![]);
+            for (key, value) in entries {
+                object.insert(key, from_toml(value));
+            }
+            Symbol::Object(object)
+        }
+    }
+}
+
+pub fn toml_parse(args: Vec<Symbol>) -> Result<Symbol, String> {
+    expect_args("toml_parse", &args, 1)?;
+    match args[0].raw_str().parse::<toml::Table>() {
+        Ok(table) => Ok(from_toml(Value::Table(table))),
+        Err(e) => Err(format!("invalid toml: {}", e)),
+    }
+}
@@ -0,0 +1,23 @@
+use serde::Deserialize;
+
+use super::expect_args;
+use crate::symbol::symbol::{List, Symbol};
+
+/// Parses a yaml document. A stream of several `---` separated documents,
+/// such as a Kubernetes manifest, is returned as a list of documents.
+pub fn yaml_parse(args: Vec<Symbol>) -> Result<Symbol, String> {
+    expect_args("yaml_parse", &args, 1)?;
+    let input = args[0].raw_str();
+
+    let mut documents = vec![];
+    for document in serde_yaml::Deserializer::from_str(&input) {
+        let document = Symbol::deserialize(document).map_err(|e| format!("invalid yaml: {}", e))?;
+        documents.push(document);
+    }
+
+    match documents.len() {
+        0 => Ok(Symbol::None),
+        1 => Ok(documents.remove(0)),
+        _ => Ok(Symbol::List(List::from(documents))),
+    }
+}
@@ -0,0 +1,155 @@
+use crate::repl;
+use sod::sandbox::Sandbox;
+use std::collections::VecDeque;
+
+pub const USAGE: &str = "\
+usage: sod [options] [script [args...]]
+       sod [options] -c <source> [args...]
+       sod [options] -i <script> [args...]
+       sod export-bash <script>
+       sod lint <script>
+
+Runs a script, read from stdin when it is -, or starts the REPL when none is
+given. Arguments after the script are passed to it as process.argv, options
+included.
+
+options:
+  -c <source>          runs source given as a string rather than a file
+  -i <script>          runs a script, then starts the REPL with what it defined
+  --dry-run            prints commands instead of running them
+  --trace              prints each statement and command to stderr as it runs
+  --vm                 compiles loops to bytecode, which runs them faster
+  --audit-log <file>   appends every command run to a file as json
+  --allow <a,b,...>    only lets the given commands run
+  --deny <a,b,...>     stops the given commands running
+  --rc                 runs ~/.sodrc before a script
+  --norc               starts the REPL without running ~/.sodrc
+  --no-color           prints REPL values without color
+  --login              imports the environment of a login shell first
+  --                   ends the options, the next argument is the script
+  -h, --help           shows this help
+  -V, --version        shows the version";
+
+/// What sod was asked to do.
+#[derive(Debug, PartialEq)]
+pub enum Mode {
+    /// Starts the REPL, first running a script if one was given with `-i`.
+    Repl,
+    /// Runs the script at the start of `argv`, read from stdin when it is
+    /// `-`.
+    Script,
+    /// Runs source given with `-c`.
+    Command(String),
+    /// Prints a script converted to bash.
+    ExportBash(String),
+    /// Prints warnings about a script without running it.
+    Lint(String),
+    Help,
+    Version,
+}
+
+/// The command line, parsed.
+#[derive(Debug)]
+pub struct Cli {
+    pub mode: Mode,
+    pub options: repl::Options,
+    pub login: bool,
+    pub audit_log: Option<String>,
+    pub sandbox: Option<Sandbox>,
+}
+
+/// Splits a comma separated list of command names from the command line.
+fn command_list(names: &str) -> Vec<String> {
+    names
+        .split(',')
+        .map(|name| name.trim().to_string())
+        .filter(|name| !name.is_empty())
+        .collect()
+}
+
+fn value(args: &mut VecDeque<String>, option: &str, what: &str) -> Result<String, String> {
+    args.pop_front()
+        .ok_or_else(|| format!("expected {} after {}", what, option))
+}
+
+/// Parses the arguments sod was started with, not including the program
+/// name. Options must come before the script, as everything after it is the
+/// script's own.
+pub fn parse(args: Vec<String>) -> Result<Cli, String> {
+    let mut args = VecDeque::from(args);
+    let mut cli = Cli {
+        mode: Mode::Repl,
+        options: repl::Options::default(),
+        login: false,
+        audit_log: None,
+        sandbox: None,
+    };
+
+    while let Some(arg) = args.pop_front() {
+        match arg.as_str() {
+            "-h" | "--help" => {
+                cli.mode = Mode::Help;
+                return Ok(cli);
+            }
+            "-V" | "--version" => {
+                cli.mode = Mode::Version;
+                return Ok(cli);
+            }
+            "-c" => {
+                cli.mode = Mode::Command(value(&mut args, &arg, "a string")?);
+                // argv[0] is "-c" where a script would have its name
+                cli.options.argv = [arg].into_iter().chain(args).collect();
+                return Ok(cli);
+            }
+            "-i" => {
+                if args.is_empty() {
+                    return Err("expected a script after -i".to_string());
+                }
+                cli.options.argv = args.into();
+                return Ok(cli);
+            }
+            "--" => {
+                if !args.is_empty() {
+                    cli.mode = Mode::Script;
+                    cli.options.argv = args.into();
+                }
+                return Ok(cli);
+            }
+            "--dry-run" => cli.options.dry_run = true,
+            "--trace" => cli.options.trace = true,
+            "--vm" => cli.options.vm = true,
+            "--no-color" => cli.options.no_color = true,
+            "--norc" => cli.options.no_rc = true,
+            "--rc" => cli.options.rc = true,
+            "--login" => cli.login = true,
+            "--audit-log" => cli.audit_log = Some(value(&mut args, &arg, "a file")?),
+            "--allow" => {
+                let names = command_list(&value(&mut args, &arg, "a list of commands")?);
+                cli.sandbox = Some(cli.sandbox.take().unwrap_or_default().allow(names));
+            }
+            "--deny" => {
+                let names = command_list(&value(&mut args, &arg, "a list of commands")?);
+                cli.sandbox = Some(cli.sandbox.take().unwrap_or_default().deny(names));
+            }
+            "export-bash" => {
+                cli.mode = Mode::ExportBash(value(&mut args, &arg, "a script")?);
+                return Ok(cli);
+            }
+            "lint" => {
+                cli.mode = Mode::Lint(value(&mut args, &arg, "a script")?);
+                return Ok(cli);
+            }
+            option if option.starts_with('-') && option != "-" => {
+                return Err(format!("unknown option {}, see sod --help", option));
+            }
+            _ => {
+                cli.mode = Mode::Script;
+                args.push_front(arg);
+                cli.options.argv = args.into();
+                return Ok(cli);
+            }
+        }
+    }
+
+    Ok(cli)
+}
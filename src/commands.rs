@@ -1,23 +1,39 @@
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
 use std::env;
 use std::fs;
-use std::io;
-use std::os::unix::fs::PermissionsExt;
+use std::io::{self, BufRead, Read, Write};
 use std::path;
 use std::process;
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
-fn is_executable(metadata: fs::Metadata) -> bool {
-    metadata.permissions().mode() & 0o111 != 0
-}
+use crate::audit;
+use crate::platform;
+use crate::sandbox;
+
+/// How long an interactive command may run before the elapsed time is shown.
+const INDICATOR_DELAY: Duration = Duration::from_secs(2);
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+static COMMAND_RUNNING: AtomicBool = AtomicBool::new(false);
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+/// The process id of the command in the foreground, 0 when there is none.
+static COMMAND_PID: AtomicU32 = AtomicU32::new(0);
+
+/// The commands found on PATH, along with the PATH value they were read from.
+static COMMANDS: Mutex<Option<(String, Arc<HashSet<String>>)>> = Mutex::new(None);
 
 fn entry_is_executable(file_path: path::PathBuf, entry: fs::DirEntry) -> Result<bool, io::Error> {
     let metadata = entry.metadata()?;
     if metadata.is_symlink() {
-        let canonical_path = fs::canonicalize(file_path)?;
-        return Ok(is_executable(fs::metadata(canonical_path)?));
+        let canonical_path = fs::canonicalize(&file_path)?;
+        let metadata = fs::metadata(canonical_path)?;
+        return Ok(platform::current().is_executable(&file_path, &metadata));
     }
 
-    Ok(is_executable(metadata))
+    Ok(platform::current().is_executable(&file_path, &metadata))
 }
 
 fn read_dir(commands: &mut HashSet<String>, dir: &str) {
@@ -39,44 +55,1396 @@ fn read_dir(commands: &mut HashSet<String>, dir: &str) {
 
         let full_path = path::Path::new(dir).join(filename.clone());
         if entry_is_executable(full_path, entry).unwrap_or(false) {
-            commands.insert(filename);
+            commands.extend(platform::current().command_names(&filename));
         }
     }
 }
 
-pub fn get_commands() -> HashSet<String> {
-    let path_str = match env::var("PATH") {
-        Ok(p) => p,
-        Err(_) => return HashSet::new(),
-    };
-
-    let directories: Vec<&str> = path_str.split(":").collect();
+fn scan_path(path_str: &str) -> HashSet<String> {
     let mut commands = HashSet::new();
+    if path_str.is_empty() {
+        return commands;
+    }
 
-    for directory in directories.iter().rev() {
-        read_dir(&mut commands, &directory);
+    for directory in path_str.split(platform::current().path_separator()).rev() {
+        read_dir(&mut commands, directory);
     }
 
     commands
 }
 
-pub fn run_cmd(cmd: &str) -> String {
-    let output = process::Command::new("sh")
-        .arg("-c")
-        .arg(cmd)
-        .output()
-        .expect("failed to execute process");
+/// Returns the executables on the interpreter's own PATH.
+pub fn get_commands() -> Arc<HashSet<String>> {
+    commands_on(&env::var("PATH").unwrap_or_default())
+}
+
+/// Returns the executables on a PATH value, such as the one a script has set
+/// with `set_env`. The scan is cached and only repeated when the PATH asked
+/// for differs from the last one scanned. A program is parsed before it runs,
+/// so a PATH it sets only applies to source parsed afterwards.
+pub fn commands_on(path_str: &str) -> Arc<HashSet<String>> {
+    let mut cache = COMMANDS.lock().unwrap_or_else(|e| e.into_inner());
+
+    if let Some((cached_path, commands)) = cache.as_ref() {
+        if cached_path == path_str {
+            return Arc::clone(commands);
+        }
+    }
+
+    let commands = Arc::new(scan_path(path_str));
+    *cache = Some((path_str.to_string(), Arc::clone(&commands)));
+    commands
+}
+
+/// Forgets the executables found on PATH, so the next lookup scans it again
+/// and finds commands installed since.
+pub fn refresh_commands() {
+    let mut cache = COMMANDS.lock().unwrap_or_else(|e| e.into_inner());
+    *cache = None;
+}
+
+/// Resolves a command to the first matching executable on a PATH value, as
+/// the shell would when running it.
+pub fn which(name: &str, path_str: &str) -> Option<path::PathBuf> {
+    if name.is_empty() || name.contains(path::is_separator) || !commands_on(path_str).contains(name)
+    {
+        return None;
+    }
+
+    let platform = platform::current();
+    path_str
+        .split(platform.path_separator())
+        .flat_map(|dir| platform.candidates(path::Path::new(dir), name))
+        .find(|file_path| {
+            fs::metadata(file_path)
+                .map(|metadata| metadata.is_file() && platform.is_executable(file_path, &metadata))
+                .unwrap_or(false)
+        })
+}
+
+/// Everything known about a finished command.
+#[derive(Debug, Clone)]
+pub struct CommandResult {
+    /// The command string after interpolation, as passed to the shell.
+    pub command: String,
+    pub stdout: Vec<u8>,
+    pub stderr: Vec<u8>,
+    /// The exit code, or `None` if the command was terminated by a signal.
+    pub status: Option<i32>,
+    /// The signal that terminated the command, if one did.
+    pub signal: Option<i32>,
+    /// The exit code of each command of a pipeline that sod ran a command at
+    /// a time, and empty for anything else.
+    pub statuses: Vec<Option<i32>>,
+    pub duration: Duration,
+}
+
+impl CommandResult {
+    /// The result given to a command that was not run, as in a dry run.
+    pub fn skipped(cmd: &CommandLine) -> Self {
+        Self {
+            command: cmd.to_string(),
+            stdout: vec![],
+            stderr: vec![],
+            status: Some(0),
+            signal: None,
+            statuses: vec![],
+            duration: Duration::ZERO,
+        }
+    }
+
+    pub fn success(&self) -> bool {
+        self.status == Some(0)
+    }
+}
+
+/// What a command runs, either a line interpreted by `sh` or a program
+/// executed directly with its arguments, so they are never parsed by a shell.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Program {
+    Shell(String),
+    Exec(String, Vec<String>),
+}
+
+/// A file that a command's output is written to instead of being captured.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Redirect {
+    pub path: path::PathBuf,
+    pub append: bool,
+}
+
+impl Redirect {
+    pub fn open(&self) -> Result<fs::File, String> {
+        fs::OpenOptions::new()
+            .create(true)
+            .write(true)
+            .append(self.append)
+            .truncate(!self.append)
+            .open(&self.path)
+            .map_err(|e| format!("failed to open {}: {}", self.path.display(), e))
+    }
+}
+
+/// The environment a script has set up for the commands it runs, its
+/// variables and working directory. It is kept
+/// by the interpreter and applied to each command as it is started, rather
+/// than set on the interpreter's own process, so other threads and a program
+/// sod is embedded in don't see it.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Environment {
+    /// Variables the script has set, or removed when none, in place of the
+    /// interpreter's own.
+    vars: HashMap<String, Option<String>>,
+    /// The directory commands run in, the interpreter's own when none.
+    cwd: Option<path::PathBuf>,
+}
+
+/// What variables were set to before a change, for `Environment::restore`.
+pub(crate) type EnvSnapshot = Vec<(String, Option<Option<String>>)>;
+
+impl Environment {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The value of a variable as the script's commands see it.
+    pub fn var(&self, name: &str) -> Option<String> {
+        match self.vars.get(name) {
+            Some(value) => value.clone(),
+            None => env::var(name).ok(),
+        }
+    }
+
+    /// Sets a variable for the commands run from now on, or removes it when
+    /// `value` is none.
+    pub fn set_var(&mut self, name: impl Into<String>, value: Option<String>) {
+        self.vars.insert(name.into(), value);
+    }
+
+    /// The PATH commands are looked up on.
+    pub fn path(&self) -> String {
+        self.var("PATH").unwrap_or_default()
+    }
+
+    /// The directory commands run in.
+    pub fn current_dir(&self) -> path::PathBuf {
+        match &self.cwd {
+            Some(cwd) => cwd.clone(),
+            None => env::current_dir().unwrap_or_default(),
+        }
+    }
+
+    /// Changes the directory commands run in, relative to the current one,
+    /// returning the directory set before for `restore_dir`.
+    pub fn set_current_dir(&mut self, dir: &str) -> Result<Option<path::PathBuf>, String> {
+        let dir = self.resolve(dir);
+        if !dir.is_dir() {
+            return Err(format!(
+                "failed to change directory to {}: not a directory",
+                dir.display()
+            ));
+        }
+        Ok(self.cwd.replace(dir))
+    }
+
+    /// Changes back to a directory returned by `set_current_dir`.
+    pub fn restore_dir(&mut self, previous: Option<path::PathBuf>) {
+        self.cwd = previous;
+    }
+
+    /// A path relative to the directory commands run in, as a command given
+    /// it would find it.
+    pub fn resolve(&self, file_path: impl AsRef<path::Path>) -> path::PathBuf {
+        match &self.cwd {
+            Some(cwd) => cwd.join(file_path),
+            None => file_path.as_ref().to_path_buf(),
+        }
+    }
+
+    /// Sets variables, returning what they were before so they can be put
+    /// back with `restore`.
+    pub(crate) fn set_vars(&mut self, vars: Vec<(String, String)>) -> EnvSnapshot {
+        let mut snapshot = vec![];
+        for (name, value) in vars {
+            let previous = self.vars.insert(name.clone(), Some(value));
+            snapshot.push((name, previous));
+        }
+        snapshot
+    }
+
+    /// Puts variables back as they were before `set_vars`.
+    pub(crate) fn restore(&mut self, snapshot: EnvSnapshot) {
+        for (name, previous) in snapshot.into_iter().rev() {
+            match previous {
+                Some(value) => self.vars.insert(name, value),
+                None => self.vars.remove(&name),
+            };
+        }
+    }
+
+    fn apply(&self, command: &mut process::Command) {
+        for (name, value) in &self.vars {
+            match value {
+                Some(value) => command.env(name, value),
+                None => command.env_remove(name),
+            };
+        }
+        if let Some(cwd) = &self.cwd {
+            command.current_dir(cwd);
+        }
+    }
+}
+
+/// A command to run, along with environment variables set for it alone.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CommandLine {
+    pub program: Program,
+    pub envs: Vec<(String, String)>,
+    pub redirect: Option<Redirect>,
+    /// The environment set up by the script, which `envs` add to.
+    pub environment: Environment,
+    /// How the command is displayed, when it holds secrets.
+    masked: Option<Box<Masked>>,
+}
+
+/// A command's program and variables with the values of secrets as `*****`.
+#[derive(Debug, Clone, PartialEq)]
+struct Masked {
+    program: Program,
+    envs: Vec<(String, String)>,
+}
+
+impl CommandLine {
+    pub fn shell(cmd: String) -> Self {
+        Self {
+            program: Program::Shell(cmd),
+            envs: vec![],
+            redirect: None,
+            environment: Environment::default(),
+            masked: None,
+        }
+    }
+
+    pub fn exec(program: String, args: Vec<String>) -> Self {
+        Self {
+            program: Program::Exec(program, args),
+            envs: vec![],
+            redirect: None,
+            environment: Environment::default(),
+            masked: None,
+        }
+    }
+
+    /// Sets how the program and variables are displayed, with the values of
+    /// secrets as `*****`. Call it once `envs` are set.
+    pub fn with_masked(mut self, program: Program, envs: Vec<(String, String)>) -> Self {
+        self.masked = (program != self.program || envs != self.envs)
+            .then(|| Box::new(Masked { program, envs }));
+        self
+    }
+
+    pub fn with_envs(mut self, envs: Vec<(String, String)>) -> Self {
+        self.envs = envs;
+        self
+    }
+
+    pub fn with_environment(mut self, environment: Environment) -> Self {
+        self.environment = environment;
+        self
+    }
+
+    pub fn with_redirect(mut self, redirect: Option<Redirect>) -> Self {
+        self.redirect = redirect;
+        self
+    }
+
+    /// Where the command's stdout goes, the redirect file if there is one.
+    pub(crate) fn stdout(&self, default: process::Stdio) -> Result<process::Stdio, String> {
+        match &self.redirect {
+            Some(redirect) => Ok(redirect.open()?.into()),
+            None => Ok(default),
+        }
+    }
+
+    /// The process to spawn, once the command has been checked against the
+    /// sandbox.
+    pub(crate) fn to_process(&self) -> Result<process::Command, String> {
+        sandbox::check(self)?;
+        let mut command = match &self.program {
+            Program::Shell(cmd) => platform::current().shell(cmd),
+            Program::Exec(program, args) => {
+                let mut command = process::Command::new(program);
+                command.args(args);
+                command
+            }
+        };
+
+        self.environment.apply(&mut command);
+        command.envs(self.envs.iter().map(|(name, value)| (name, value)));
+        Ok(command)
+    }
+}
+
+/// Shows the command as it is run, other than the values of secrets, so it
+/// can be printed and logged.
+impl std::fmt::Display for CommandLine {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let (program, envs) = match self.masked.as_deref() {
+            Some(masked) => (&masked.program, &masked.envs),
+            None => (&self.program, &self.envs),
+        };
+
+        for (name, value) in envs {
+            write!(f, "{}={} ", name, platform::current().quote(value))?;
+        }
+
+        match program {
+            Program::Shell(cmd) => write!(f, "{}", cmd)?,
+            Program::Exec(program, args) => {
+                let words: Vec<String> = std::iter::once(program)
+                    .chain(args)
+                    .map(|word| platform::current().quote(word))
+                    .collect();
+                write!(f, "{}", words.join(" "))?;
+            }
+        }
+
+        if let Some(redirect) = &self.redirect {
+            let op = if redirect.append { ">>" } else { ">" };
+            let path = redirect.path.to_string_lossy();
+            write!(f, " {} {}", op, platform::current().quote(&path))?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Which of a command's outputs are written to the terminal while it runs.
+/// Both are captured in the result either way.
+#[derive(Debug, Clone, Copy)]
+pub struct Echo {
+    pub stdout: bool,
+    pub stderr: bool,
+}
+
+impl Echo {
+    pub const NONE: Echo = Echo {
+        stdout: false,
+        stderr: false,
+    };
+}
+
+/// A writer shared with the threads that echo a command's output.
+pub type SharedWriter = Arc<Mutex<dyn Write + Send>>;
+
+/// Where a program's output is written: the output echoed from commands,
+/// along with what the evaluator prints itself. The terminal by default.
+#[derive(Clone)]
+pub struct Output {
+    pub stdout: SharedWriter,
+    pub stderr: SharedWriter,
+}
+
+impl Output {
+    pub fn terminal() -> Self {
+        Self {
+            stdout: Arc::new(Mutex::new(io::stdout())),
+            stderr: Arc::new(Mutex::new(io::stderr())),
+        }
+    }
+}
+
+impl Default for Output {
+    fn default() -> Self {
+        Self::terminal()
+    }
+}
+
+/// Writes a line to one of a program's outputs, ignoring failures as
+/// `println!` to a closed terminal would not.
+pub fn write_line(writer: &SharedWriter, line: &str) {
+    if let Ok(mut writer) = writer.lock() {
+        let _ = writeln!(writer, "{}", line).and_then(|_| writer.flush());
+    }
+}
+
+/// Writes output as it is, for output that isn't text or a whole line.
+pub fn write_bytes(writer: &SharedWriter, bytes: &[u8]) {
+    if let Ok(mut writer) = writer.lock() {
+        let _ = writer.write_all(bytes).and_then(|_| writer.flush());
+    }
+}
+
+/// Output kept in memory, such as a program's output captured in a test.
+/// Clones share the same buffer.
+#[derive(Debug, Clone, Default)]
+pub struct OutputBuffer {
+    buffer: Arc<Mutex<Vec<u8>>>,
+}
+
+impl OutputBuffer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Everything written so far.
+    pub fn contents(&self) -> Vec<u8> {
+        self.buffer.lock().map(|b| b.clone()).unwrap_or_default()
+    }
+}
+
+impl Write for OutputBuffer {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self.buffer.lock() {
+            Ok(mut buffer) => buffer.write(buf),
+            Err(_) => Err(io::Error::other("output buffer poisoned")),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Routes Ctrl-C to the running command instead of the interpreter. When no
+/// command is running the interpreter exits as it would without the handler,
+/// unless the script has set a trap.
+pub fn install_interrupt_handler() -> Result<(), String> {
+    ctrlc::set_handler(|| {
+        if COMMAND_RUNNING.load(Ordering::SeqCst) {
+            INTERRUPTED.store(true, Ordering::SeqCst);
+            let pid = COMMAND_PID.load(Ordering::SeqCst);
+            if pid != 0 {
+                platform::current().interrupt(pid);
+            }
+        }
+
+        if HANDLING_SIGNALS.load(Ordering::SeqCst) {
+            signal_received(Signal::Int);
+        } else if !COMMAND_RUNNING.load(Ordering::SeqCst) {
+            process::exit(130);
+        }
+    })
+    .map_err(|e| format!("failed to install interrupt handler: {}", e))
+}
+
+/// A signal that a script can handle with `trap`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Signal {
+    Int,
+    Term,
+    Hup,
+}
+
+impl Signal {
+    pub const ALL: [Signal; 3] = [Signal::Int, Signal::Term, Signal::Hup];
+
+    /// Parses a signal name, with or without the `SIG` prefix.
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.strip_prefix("SIG").unwrap_or(name) {
+            "INT" => Some(Signal::Int),
+            "TERM" => Some(Signal::Term),
+            "HUP" => Some(Signal::Hup),
+            _ => None,
+        }
+    }
+
+    pub fn from_number(number: i32) -> Option<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|signal| signal.number() == number)
+    }
+
+    /// The POSIX signal number, which is also how a shell reports a process
+    /// stopped by it, as 128 plus the number.
+    pub fn number(&self) -> i32 {
+        match self {
+            Signal::Int => 2,
+            Signal::Term => 15,
+            Signal::Hup => 1,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Signal::Int => "SIGINT",
+            Signal::Term => "SIGTERM",
+            Signal::Hup => "SIGHUP",
+        }
+    }
+
+    fn bit(&self) -> u32 {
+        1 << self.number()
+    }
+}
+
+/// Set once a script has a trap, so signals are left for it to handle rather
+/// than ending the interpreter.
+static HANDLING_SIGNALS: AtomicBool = AtomicBool::new(false);
+/// The signals received and not yet handled, as bits by signal number.
+static PENDING_SIGNALS: AtomicU32 = AtomicU32::new(0);
+
+/// Starts catching a signal so the script can handle it, see `take_signal`.
+pub fn handle_signal(signal: Signal) -> Result<(), String> {
+    HANDLING_SIGNALS.store(true, Ordering::SeqCst);
+    match signal {
+        // Ctrl-C is caught by the interrupt handler
+        Signal::Int => Ok(()),
+        signal => platform::current().catch(signal),
+    }
+}
+
+/// Records a signal for the script. A command that is running is sent the
+/// signal too, as it would have been by the terminal or a process manager.
+///
+/// This runs inside a signal handler, so it only touches atomics.
+pub fn signal_received(signal: Signal) {
+    PENDING_SIGNALS.fetch_or(signal.bit(), Ordering::SeqCst);
+    if signal != Signal::Int {
+        let pid = COMMAND_PID.load(Ordering::SeqCst);
+        if pid != 0 {
+            platform::current().forward(pid, signal);
+        }
+    }
+}
+
+/// Takes a signal received since the last call, if any.
+pub fn take_signal() -> Option<Signal> {
+    let pending = PENDING_SIGNALS.load(Ordering::SeqCst);
+    let signal = Signal::ALL.into_iter().find(|s| pending & s.bit() != 0)?;
+    PENDING_SIGNALS.fetch_and(!signal.bit(), Ordering::SeqCst);
+    Some(signal)
+}
+
+/// Marks a command as the one Ctrl-C is forwarded to, until dropped.
+#[derive(Debug)]
+struct Foreground;
+
+impl Foreground {
+    fn enter(child: &process::Child) -> Self {
+        INTERRUPTED.store(false, Ordering::SeqCst);
+        COMMAND_PID.store(child.id(), Ordering::SeqCst);
+        COMMAND_RUNNING.store(true, Ordering::SeqCst);
+
+        // a signal that arrived just before the command started is meant
+        // for it as well
+        let pending = PENDING_SIGNALS.load(Ordering::SeqCst);
+        for signal in Signal::ALL {
+            if signal != Signal::Int && pending & signal.bit() != 0 {
+                platform::current().forward(child.id(), signal);
+            }
+        }
+
+        Foreground
+    }
+
+    fn interrupted(&self) -> bool {
+        INTERRUPTED.load(Ordering::SeqCst)
+    }
+}
+
+impl Drop for Foreground {
+    fn drop(&mut self) {
+        COMMAND_RUNNING.store(false, Ordering::SeqCst);
+        COMMAND_PID.store(0, Ordering::SeqCst);
+    }
+}
+
+/// Set while the elapsed time of an interactive command is on screen, so
+/// streamed output can clear it first.
+static INDICATOR_SHOWN: AtomicBool = AtomicBool::new(false);
+
+fn clear_indicator() {
+    if INDICATOR_SHOWN.swap(false, Ordering::SeqCst) {
+        eprint!("\r\x1b[K");
+        let _ = io::stderr().flush();
+    }
+}
+
+/// Collects everything from a pipe, writing each chunk to `echo` as soon as
+/// it arrives.
+fn read_pipe(
+    pipe: Option<impl Read + Send + 'static>,
+    echo: Option<SharedWriter>,
+    streamed: Arc<AtomicBool>,
+) -> thread::JoinHandle<Vec<u8>> {
+    thread::spawn(move || {
+        let mut buffer = vec![];
+        let mut pipe = match pipe {
+            Some(pipe) => pipe,
+            None => return buffer,
+        };
+
+        let mut chunk = [0; 8192];
+        loop {
+            let read = match pipe.read(&mut chunk) {
+                Ok(0) | Err(_) => break,
+                Ok(read) => read,
+            };
+
+            if let Some(Ok(mut out)) = echo.as_ref().map(|echo| echo.lock()) {
+                streamed.store(true, Ordering::SeqCst);
+                clear_indicator();
+                let _ = out.write_all(&chunk[..read]).and_then(|_| out.flush());
+            }
+            buffer.extend_from_slice(&chunk[..read]);
+        }
+        buffer
+    })
+}
+
+/// A command that has been started with its output being read.
+#[derive(Debug)]
+struct Running {
+    child: process::Child,
+    stdout: thread::JoinHandle<Vec<u8>>,
+    stderr: thread::JoinHandle<Vec<u8>>,
+    streamed: Arc<AtomicBool>,
+    start: Instant,
+    audit: audit::Started,
+}
+
+impl Running {
+    /// Starts a command, reading from the terminal when `terminal` is set.
+    /// Otherwise it has no input and a process group of its own, which the
+    /// terminal's Ctrl-C doesn't reach, so it is passed on by sod instead.
+    fn spawn(
+        cmd: &CommandLine,
+        echo: Echo,
+        output: &Output,
+        terminal: bool,
+    ) -> Result<Self, String> {
+        let mut command = cmd.to_process()?;
+        if terminal {
+            command.stdin(process::Stdio::inherit());
+        } else {
+            command.stdin(process::Stdio::null());
+            platform::current().own_group(&mut command);
+        }
+        let mut child = command
+            .stdout(cmd.stdout(process::Stdio::piped())?)
+            .stderr(process::Stdio::piped())
+            .spawn()
+            .map_err(|e| format!("failed to execute process: {}", e))?;
+
+        let streamed = Arc::new(AtomicBool::new(false));
+        let stdout = echo.stdout.then(|| Arc::clone(&output.stdout));
+        let stderr = echo.stderr.then(|| Arc::clone(&output.stderr));
+
+        Ok(Self {
+            stdout: read_pipe(child.stdout.take(), stdout, Arc::clone(&streamed)),
+            stderr: read_pipe(child.stderr.take(), stderr, Arc::clone(&streamed)),
+            child,
+            streamed,
+            start: Instant::now(),
+            audit: audit::Started::now(cmd),
+        })
+    }
+
+    fn finish(self, cmd: &CommandLine, status: process::ExitStatus) -> CommandResult {
+        self.audit.finish(status.code());
+        CommandResult {
+            command: cmd.to_string(),
+            stdout: self.stdout.join().unwrap_or_default(),
+            stderr: self.stderr.join().unwrap_or_default(),
+            status: status.code(),
+            signal: platform::current().signal(&status),
+            statuses: vec![],
+            duration: self.start.elapsed(),
+        }
+    }
+}
+
+/// Runs a command, streaming the outputs chosen by `echo` to the terminal as
+/// they are produced.
+pub fn run_cmd(cmd: &CommandLine, echo: Echo) -> Result<CommandResult, String> {
+    run_cmd_to(cmd, echo, &Output::terminal())
+}
+
+/// Runs a command, streaming the outputs chosen by `echo` to `output` as
+/// they are produced.
+pub fn run_cmd_to(cmd: &CommandLine, echo: Echo, output: &Output) -> Result<CommandResult, String> {
+    let mut running = Running::spawn(cmd, echo, output, false)?;
+
+    let foreground = Foreground::enter(&running.child);
+    let status = running.child.wait();
+    if foreground.interrupted() {
+        running.audit.finish(None);
+        return Err("command interrupted".to_string());
+    }
+    let status = status.map_err(|e| format!("failed to wait for process: {}", e))?;
+
+    Ok(running.finish(cmd, status))
+}
+
+/// Runs the commands a program asks for in place of running them here, so
+/// a program sod is embedded in can record them, mock their results or run
+/// them in a container or on another host. A closure taking the command
+/// and `Echo` is an executor too.
+pub trait CommandExecutor {
+    /// Runs a command to completion. The outputs chosen by `echo` are meant
+    /// to be shown as they are produced, and both are kept in the result.
+    fn run(&mut self, cmd: &CommandLine, echo: Echo) -> Result<CommandResult, String>;
+
+    /// Starts a command in the background. Unless an executor can run jobs
+    /// alongside the program, the command has finished by the time its job
+    /// is returned.
+    fn spawn(&mut self, cmd: &CommandLine) -> Result<Job, String> {
+        Ok(Job::finished(self.run(cmd, Echo::NONE)?))
+    }
+
+    /// Runs commands, at most `limit` at a time, and returns their results in
+    /// the order the commands were given. One at a time unless an executor
+    /// does better.
+    fn run_parallel(
+        &mut self,
+        cmds: &[CommandLine],
+        _limit: usize,
+    ) -> Result<Vec<CommandResult>, String> {
+        cmds.iter().map(|cmd| self.run(cmd, Echo::NONE)).collect()
+    }
+}
+
+impl<F> CommandExecutor for F
+where
+    F: FnMut(&CommandLine, Echo) -> Result<CommandResult, String>,
+{
+    fn run(&mut self, cmd: &CommandLine, echo: Echo) -> Result<CommandResult, String> {
+        self(cmd, echo)
+    }
+}
+
+/// Runs commands on this machine as the evaluator does without an executor,
+/// for executors that wrap it.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct LocalExecutor;
+
+impl CommandExecutor for LocalExecutor {
+    fn run(&mut self, cmd: &CommandLine, echo: Echo) -> Result<CommandResult, String> {
+        run_cmd(cmd, echo)
+    }
+}
+
+/// Runs a command with an executor, checked against the sandbox and audited
+/// as it would be if it ran here.
+pub fn run_with(
+    executor: &mut dyn CommandExecutor,
+    cmd: &CommandLine,
+    echo: Echo,
+) -> Result<CommandResult, String> {
+    sandbox::check(cmd)?;
+    let audit = audit::Started::now(cmd);
+    let result = executor.run(cmd, echo);
+    audit.finish(result.as_ref().ok().and_then(|result| result.status));
+    result
+}
+
+/// Starts a command in the background with an executor, checked against the
+/// sandbox and audited once the job has finished.
+pub fn spawn_with(executor: &mut dyn CommandExecutor, cmd: &CommandLine) -> Result<Job, String> {
+    sandbox::check(cmd)?;
+    let audit = audit::Started::now(cmd);
+    let mut job = executor.spawn(cmd)?;
+    match (&mut job.pending, &job.result) {
+        (Some(pending), _) => pending.audit = Some(audit),
+        (None, Some(result)) => audit.finish(result.status),
+        (None, None) => {}
+    }
+    Ok(job)
+}
+
+/// Runs commands with an executor, at most `limit` at a time, each checked
+/// against the sandbox and audited.
+pub fn run_parallel_with(
+    executor: &mut dyn CommandExecutor,
+    cmds: &[CommandLine],
+    limit: usize,
+) -> Result<Vec<CommandResult>, String> {
+    for cmd in cmds {
+        sandbox::check(cmd)?;
+    }
+    let audits: Vec<_> = cmds.iter().map(audit::Started::now).collect();
+    let results = executor.run_parallel(cmds, limit)?;
+    for (audit, result) in audits.iter().zip(&results) {
+        audit.finish(result.status);
+    }
+    Ok(results)
+}
+
+/// A job an executor runs elsewhere, whose result arrives on a channel.
+struct Pending {
+    result: mpsc::Receiver<Result<CommandResult, String>>,
+    kill: Option<Box<dyn FnOnce() + Send>>,
+    audit: Option<audit::Started>,
+}
+
+impl std::fmt::Debug for Pending {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Pending").finish_non_exhaustive()
+    }
+}
+
+/// A command running in the background. Its output is captured rather than
+/// echoed, and handed back as a result once the job has finished.
+#[derive(Debug)]
+pub struct Job {
+    command: CommandLine,
+    pid: u32,
+    running: Option<Running>,
+    pending: Option<Pending>,
+    result: Option<CommandResult>,
+}
+
+impl Job {
+    pub fn spawn(cmd: &CommandLine) -> Result<Self, String> {
+        let running = Running::spawn(cmd, Echo::NONE, &Output::terminal(), false)?;
+        Ok(Self {
+            command: cmd.clone(),
+            pid: running.child.id(),
+            running: Some(running),
+            pending: None,
+            result: None,
+        })
+    }
+
+    /// A job that has already finished with `result`. It has no process, so
+    /// its pid is 0.
+    pub fn finished(result: CommandResult) -> Self {
+        Self {
+            command: CommandLine::shell(result.command.clone()),
+            pid: 0,
+            running: None,
+            pending: None,
+            result: Some(result),
+        }
+    }
+
+    /// A job an executor is running, whose result is sent on `result` once
+    /// it has finished. `kill` is called to stop it early.
+    pub fn pending(
+        command: CommandLine,
+        pid: u32,
+        result: mpsc::Receiver<Result<CommandResult, String>>,
+        kill: impl FnOnce() + Send + 'static,
+    ) -> Self {
+        Self {
+            command,
+            pid,
+            running: None,
+            pending: Some(Pending {
+                result,
+                kill: Some(Box::new(kill)),
+                audit: None,
+            }),
+            result: None,
+        }
+    }
+
+    /// Keeps the result received for a pending job.
+    fn settle(
+        &mut self,
+        pending: Pending,
+        result: Result<CommandResult, String>,
+    ) -> Result<(), String> {
+        let result = result?;
+        if let Some(audit) = pending.audit {
+            audit.finish(result.status);
+        }
+        self.result = Some(result);
+        Ok(())
+    }
+
+    pub fn command(&self) -> &CommandLine {
+        &self.command
+    }
+
+    pub fn pid(&self) -> u32 {
+        self.pid
+    }
+
+    /// Blocks until the job has finished.
+    pub fn wait(&mut self) -> Result<&CommandResult, String> {
+        if let Some(mut running) = self.running.take() {
+            let status = running
+                .child
+                .wait()
+                .map_err(|e| format!("failed to wait for process: {}", e))?;
+            self.result = Some(running.finish(&self.command, status));
+        }
+        if let Some(pending) = self.pending.take() {
+            let result = pending
+                .result
+                .recv()
+                .map_err(|_| format!("job {} stopped without a result", self.pid))?;
+            self.settle(pending, result)?;
+        }
+
+        self.result
+            .as_ref()
+            .ok_or_else(|| format!("job {} has no result", self.pid))
+    }
+
+    /// Returns the result if the job has finished, without blocking.
+    pub fn poll(&mut self) -> Result<Option<&CommandResult>, String> {
+        let finished = match self.running.as_mut() {
+            Some(running) => running
+                .child
+                .try_wait()
+                .map_err(|e| format!("failed to wait for process: {}", e))?
+                .is_some(),
+            None => match self.pending.take() {
+                Some(pending) => match pending.result.try_recv() {
+                    Ok(result) => {
+                        self.settle(pending, result)?;
+                        true
+                    }
+                    Err(mpsc::TryRecvError::Empty) => {
+                        self.pending = Some(pending);
+                        false
+                    }
+                    Err(mpsc::TryRecvError::Disconnected) => {
+                        return Err(format!("job {} stopped without a result", self.pid))
+                    }
+                },
+                None => true,
+            },
+        };
+
+        if !finished {
+            return Ok(None);
+        }
+
+        self.wait().map(Some)
+    }
+
+    /// Kills the job if it is still running.
+    pub fn kill(&mut self) -> Result<(), String> {
+        if let Some(running) = self.running.as_mut() {
+            running
+                .child
+                .kill()
+                .map_err(|e| format!("failed to kill process {}: {}", self.pid, e))?;
+        }
+        if let Some(kill) = self
+            .pending
+            .as_mut()
+            .and_then(|pending| pending.kill.take())
+        {
+            kill();
+        }
+
+        self.wait().map(|_| ())
+    }
+}
+
+/// A job that was never waited for is still audited, with no status if it
+/// hadn't finished.
+impl Drop for Job {
+    fn drop(&mut self) {
+        if let Some(running) = self.running.as_mut() {
+            let status = running.child.try_wait().ok().flatten();
+            running
+                .audit
+                .finish(status.and_then(|status| status.code()));
+        }
+        if let Some(pending) = self.pending.take() {
+            let result = pending.result.try_recv().ok().and_then(Result::ok);
+            if let Some(audit) = pending.audit {
+                audit.finish(result.and_then(|result| result.status));
+            }
+        }
+    }
+}
+
+/// Runs commands in the background, at most `limit` at a time, and returns
+/// their results in the order the commands were given.
+pub fn run_parallel(cmds: &[CommandLine], limit: usize) -> Result<Vec<CommandResult>, String> {
+    let mut results: Vec<Option<CommandResult>> = vec![None; cmds.len()];
+    let mut running: Vec<(usize, Job)> = vec![];
+    let mut next = 0;
+
+    while next < cmds.len() || !running.is_empty() {
+        while next < cmds.len() && running.len() < limit.max(1) {
+            running.push((next, Job::spawn(&cmds[next])?));
+            next += 1;
+        }
+
+        let mut i = 0;
+        while i < running.len() {
+            match running[i].1.poll()? {
+                Some(result) => {
+                    results[running[i].0] = Some(result.clone());
+                    running.remove(i);
+                }
+                None => i += 1,
+            }
+        }
+
+        if !running.is_empty() {
+            thread::sleep(POLL_INTERVAL);
+        }
+    }
+
+    Ok(results.into_iter().flatten().collect())
+}
+
+/// Where a command of a pipeline reads its input from.
+#[derive(Debug)]
+pub enum PipeInput {
+    /// Nothing, for the first command.
+    None,
+    /// The lines written to it with `write`, such as a function's values.
+    Lines,
+    /// The output of the command before it, connected to it directly.
+    Command(process::ChildStdout),
+}
+
+/// A command in a pipeline. Output that goes to a sod function, or is the
+/// pipeline's own, is read a line at a time so each line can be handled as
+/// soon as it is produced.
+#[derive(Debug)]
+pub struct PipeCommand {
+    child: process::Child,
+    stdin: Option<process::ChildStdin>,
+    stdout: Option<process::ChildStdout>,
+    lines: mpsc::Receiver<Vec<u8>>,
+    stderr: Option<thread::JoinHandle<Vec<u8>>>,
+    foreground: Option<Foreground>,
+    status: Option<process::ExitStatus>,
+    stopped: bool,
+    audit: audit::Started,
+}
+
+impl PipeCommand {
+    /// Starts a command. The first command of a pipeline has no input and
+    /// is the one Ctrl-C is forwarded to. When `to_command` is set its output
+    /// is left for `take_stdout`, to connect to the next command, rather
+    /// than read as lines. Its stderr is written to `stderr` as it arrives,
+    /// if given, as well as kept.
+    pub fn spawn(
+        cmd: &CommandLine,
+        input: PipeInput,
+        to_command: bool,
+        stderr: Option<SharedWriter>,
+    ) -> Result<Self, String> {
+        let first = matches!(input, PipeInput::None);
+        let stdin = match input {
+            PipeInput::None => process::Stdio::null(),
+            PipeInput::Lines => process::Stdio::piped(),
+            PipeInput::Command(stdout) => process::Stdio::from(stdout),
+        };
+        let mut command = cmd.to_process()?;
+        platform::current().own_group(&mut command);
+        let mut child = command
+            .stdin(stdin)
+            .stdout(cmd.stdout(process::Stdio::piped())?)
+            .stderr(process::Stdio::piped())
+            .spawn()
+            .map_err(|e| format!("failed to execute process: {}", e))?;
+
+        let stderr = read_pipe(
+            child.stderr.take(),
+            stderr,
+            Arc::new(AtomicBool::new(false)),
+        );
+        let (sender, lines) = mpsc::channel();
+        let mut stdout = child.stdout.take();
+        if let Some(stdout) = stdout.take_if(|_| !to_command) {
+            thread::spawn(move || {
+                let mut reader = io::BufReader::new(stdout);
+                let mut line = vec![];
+                while matches!(reader.read_until(b'\n', &mut line), Ok(n) if n > 0) {
+                    if sender.send(std::mem::take(&mut line)).is_err() {
+                        break;
+                    }
+                }
+            });
+        }
+
+        Ok(Self {
+            stdin: child.stdin.take(),
+            stdout,
+            foreground: first.then(|| Foreground::enter(&child)),
+            child,
+            lines,
+            stderr: Some(stderr),
+            status: None,
+            stopped: false,
+            audit: audit::Started::now(cmd),
+        })
+    }
+
+    /// The command's output, for the next command to read directly.
+    pub fn take_stdout(&mut self) -> Option<process::ChildStdout> {
+        self.stdout.take()
+    }
+
+    /// Writes to the command's input. Returns false once the command has
+    /// stopped reading, as `head` does.
+    pub fn write(&mut self, bytes: &[u8]) -> bool {
+        let stdin = match self.stdin.as_mut() {
+            Some(stdin) => stdin,
+            None => return false,
+        };
+
+        if stdin.write_all(bytes).is_err() {
+            self.stdin = None;
+            return false;
+        }
+        true
+    }
+
+    /// Closes the command's input, so it sees the end of the file.
+    pub fn close_input(&mut self) {
+        self.stdin = None;
+    }
+
+    /// The next line of output if one is ready, without blocking. Lines
+    /// keep their newline, which the last may not have.
+    pub fn try_line(&self) -> Option<Vec<u8>> {
+        self.lines.try_recv().ok()
+    }
+
+    /// Blocks until the next line of output, or `None` once the command has
+    /// closed its output.
+    pub fn next_line(&self) -> Option<Vec<u8>> {
+        self.lines.recv().ok()
+    }
+
+    pub fn interrupted(&self) -> bool {
+        self.foreground.as_ref().is_some_and(|f| f.interrupted())
+    }
+
+    /// Waits for the command to exit and returns its exit code.
+    pub fn wait(&mut self) -> Result<Option<i32>, String> {
+        self.close_input();
+        if self.status.is_none() {
+            let status = self
+                .child
+                .wait()
+                .map_err(|e| format!("failed to wait for process: {}", e))?;
+            self.status = Some(status);
+            self.audit.finish(status.code());
+            // it may have been exiting by itself when it was killed, and a
+            // command whose reader has gone is stopped by the closed pipe
+            self.stopped &= status.code().is_none();
+            self.stopped |= platform::current().broken_pipe(&status);
+        }
+
+        Ok(self.status.and_then(|status| status.code()))
+    }
+
+    /// Stops the command if it is still running, as a shell does when the
+    /// rest of the pipeline no longer reads its output.
+    pub fn stop(&mut self) -> Result<Option<i32>, String> {
+        if self.status.is_none() {
+            let running = self
+                .child
+                .try_wait()
+                .map_err(|e| format!("failed to wait for process: {}", e))?
+                .is_none();
+            if running {
+                self.stopped = self.child.kill().is_ok();
+            }
+        }
+
+        self.wait()
+    }
 
-    if let Ok(s) = String::from_utf8(output.stderr) {
-        if s.len() > 0 {
-            eprintln!("{}", s)
+    /// Everything the command wrote to stderr, once it has exited.
+    pub fn take_stderr(&mut self) -> Vec<u8> {
+        match self.stderr.take() {
+            Some(stderr) => stderr.join().unwrap_or_default(),
+            None => vec![],
         }
     }
 
-    let out_string = match String::from_utf8(output.stdout) {
-        Ok(s) => s,
-        Err(_) => "".to_string(),
+    /// The signal that ended the command, once it has exited.
+    pub fn signal(&self) -> Option<i32> {
+        self.status
+            .and_then(|status| platform::current().signal(&status))
+    }
+
+    /// Whether `stop` killed the command, rather than it exiting by itself.
+    pub fn stopped(&self) -> bool {
+        self.stopped
+    }
+}
+
+impl Drop for PipeCommand {
+    fn drop(&mut self) {
+        let _ = self.stop();
+    }
+}
+
+/// Runs a command for the REPL. Once the command has been running for a
+/// while without output its elapsed time is displayed, and Ctrl-C kills only
+/// the command.
+pub fn run_cmd_interactive(
+    cmd: &CommandLine,
+    echo: Echo,
+    output: &Output,
+) -> Result<CommandResult, String> {
+    let mut running = Running::spawn(cmd, echo, output, true)?;
+
+    let foreground = Foreground::enter(&running.child);
+    let result = loop {
+        if INTERRUPTED.load(Ordering::SeqCst) {
+            let _ = running.child.kill();
+            let _ = running.child.wait();
+            break Err("command interrupted".to_string());
+        }
+
+        match running.child.try_wait() {
+            Ok(Some(status)) => break Ok(status),
+            Ok(None) => (),
+            Err(e) => break Err(format!("failed to wait for process: {}", e)),
+        }
+
+        let elapsed = running.start.elapsed();
+        if elapsed >= INDICATOR_DELAY && !running.streamed.load(Ordering::SeqCst) {
+            eprint!("\r[{:.1}s] {}", elapsed.as_secs_f64(), cmd);
+            let _ = io::stderr().flush();
+            INDICATOR_SHOWN.store(true, Ordering::SeqCst);
+        }
+
+        thread::sleep(POLL_INTERVAL);
+    };
+
+    drop(foreground);
+    clear_indicator();
+    let status = result.inspect_err(|_| running.audit.finish(None))?;
+
+    Ok(running.finish(cmd, status))
+}
+
+/// Runs a command attached to the terminal, so it can read from the user and
+/// draw on the screen. Its output isn't captured, only the exit status is.
+pub fn run_cmd_tty(cmd: &CommandLine) -> Result<CommandResult, String> {
+    let start = Instant::now();
+    let audit = audit::Started::now(cmd);
+    let mut child = cmd
+        .to_process()?
+        .stdin(process::Stdio::inherit())
+        .stdout(cmd.stdout(process::Stdio::inherit())?)
+        .stderr(process::Stdio::inherit())
+        .spawn()
+        .map_err(|e| format!("failed to execute process: {}", e))?;
+
+    // Ctrl-C belongs to the command while it has the terminal.
+    let foreground = Foreground::enter(&child);
+    let status = child.wait();
+    drop(foreground);
+    let status = status.map_err(|e| format!("failed to wait for process: {}", e))?;
+    audit.finish(status.code());
+
+    Ok(CommandResult {
+        command: cmd.to_string(),
+        stdout: vec![],
+        stderr: vec![],
+        status: status.code(),
+        signal: platform::current().signal(&status),
+        statuses: vec![],
+        duration: start.elapsed(),
+    })
+}
+
+/// Variables describing the login shell process itself rather than the user's
+/// configuration.
+const LOGIN_ENV_IGNORED: [&str; 5] = ["_", "SHLVL", "PWD", "OLDPWD", "PS1"];
+
+/// What the user's login shell is set up with, for `--login`.
+#[derive(Debug, Default, PartialEq)]
+pub struct LoginEnv {
+    /// The exported variables.
+    pub vars: Vec<(String, String)>,
+    pub aliases: Vec<(String, String)>,
+}
+
+/// Sources the user's shell profile in a login subshell and reads the
+/// resulting exported variables and aliases, for the interpreter to start
+/// its commands with as the user's own shell would.
+pub fn import_login_env() -> Result<LoginEnv, String> {
+    let shell = env::var("SHELL").unwrap_or_else(|_| "sh".to_string());
+    // what the profile prints comes before the marker and is skipped
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.subsec_nanos())
+        .unwrap_or_default();
+    let marker = format!("sod-login-{}-{}", process::id(), nanos);
+    let output = process::Command::new(&shell)
+        .args([
+            "-l",
+            "-i",
+            "-c",
+            &format!("printf '%s\\n' {}; alias; printf '\\0'; env -0", marker),
+        ])
+        .stdin(process::Stdio::null())
+        .stderr(process::Stdio::null())
+        .output()
+        .map_err(|e| format!("failed to run login shell {}: {}", shell, e))?;
+
+    if !output.status.success() {
+        return Err(format!(
+            "login shell {} exited with {}",
+            shell, output.status
+        ));
+    }
+
+    Ok(parse_login_output(&output.stdout, &marker))
+}
+
+/// Reads what the login shell printed after `marker`: its aliases, ended by
+/// a nul, then its variables, each ended by a nul.
+pub fn parse_login_output(output: &[u8], marker: &str) -> LoginEnv {
+    let marker = format!("{}\n", marker);
+    let start = match output
+        .windows(marker.len())
+        .position(|window| window == marker.as_bytes())
+    {
+        Some(start) => start + marker.len(),
+        None => return LoginEnv::default(),
     };
 
-    out_string
+    let mut entries = output[start..].split(|b| *b == 0);
+    let aliases = parse_aliases(&String::from_utf8_lossy(entries.next().unwrap_or_default()));
+    let mut vars = vec![];
+    for entry in entries {
+        let entry = String::from_utf8_lossy(entry);
+        if let Some((key, value)) = entry.split_once('=') {
+            if !key.is_empty() && !LOGIN_ENV_IGNORED.contains(&key) {
+                vars.push((key.to_string(), value.to_string()));
+            }
+        }
+    }
+
+    LoginEnv { vars, aliases }
+}
+
+/// Reads the output of a shell's `alias` command, with lines such as
+/// `alias ll='ls -l'` in bash or `ll='ls -l'` in zsh. Aliases whose name sod
+/// can't use are left out.
+pub fn parse_aliases(output: &str) -> Vec<(String, String)> {
+    let mut aliases = vec![];
+    for line in output.lines() {
+        let line = line.strip_prefix("alias ").unwrap_or(line);
+        let (name, value) = match line.split_once('=') {
+            Some(alias) => alias,
+            None => continue,
+        };
+
+        let valid_name = name.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+            && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+        if !valid_name {
+            continue;
+        }
+
+        let value = match value.strip_prefix('\'').and_then(|v| v.strip_suffix('\'')) {
+            Some(quoted) => quoted.replace("'\\''", "'"),
+            None => value.to_string(),
+        };
+        aliases.push((name.to_string(), value));
+    }
+
+    aliases
 }
@@ -0,0 +1,96 @@
+use crate::ast::evaluator::ASTEvaluator;
+use crate::commands::CommandExecutor;
+use crate::error::Error;
+use crate::limits::Limits;
+use crate::parser::{Declarations, Parser};
+use crate::symbol::symbol::Symbol;
+
+/// Runs sod from another Rust program. The variables and functions each
+/// evaluation defines are kept for the next, as they are in the REPL.
+pub struct Engine {
+    evaluator: ASTEvaluator,
+    /// The functions and aliases declared so far, so later source parses
+    /// the same way it would in one script.
+    declarations: Declarations,
+}
+
+impl Default for Engine {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Engine {
+    pub fn new() -> Self {
+        Self::with_args(vec![])
+    }
+
+    /// An engine whose programs see `argv` as `process.argv`, the first
+    /// being the script name.
+    pub fn with_args(argv: Vec<String>) -> Self {
+        Self {
+            evaluator: ASTEvaluator::new(argv),
+            declarations: Declarations::default(),
+        }
+    }
+
+    /// Limits how long each evaluation may run and how much it may do.
+    pub fn with_limits(mut self, limits: Limits) -> Self {
+        self.evaluator.set_limits(limits);
+        self
+    }
+
+    /// Evaluates source, returning the value of its last statement, none if
+    /// it has no value.
+    pub fn eval_str(&mut self, src: &str) -> Result<Option<Symbol>, Error> {
+        let mut parser = Parser::new(src)
+            .with_declarations(self.declarations.clone())
+            .with_path(self.evaluator.environment().path());
+        let program = parser.parse()?;
+        self.declarations = parser.declarations().clone();
+
+        let mut values = self.evaluator.eval(program)?;
+        Ok(values.pop().flatten())
+    }
+
+    /// The value of a variable or function defined at the top level.
+    pub fn get(&self, name: &str) -> Option<&Symbol> {
+        self.evaluator.symbol_table().get(name)
+    }
+
+    /// Sets a variable at the top level, for later evaluations to read.
+    pub fn set(&mut self, name: &str, value: Symbol) {
+        self.evaluator.symbol_table_mut().set(name, value);
+    }
+
+    /// Makes a Rust function callable from sod by name, see
+    /// `ASTEvaluator::register_fn`.
+    pub fn register_fn(
+        &mut self,
+        name: impl Into<String>,
+        function: impl FnMut(Vec<Symbol>) -> Result<Symbol, String> + 'static,
+    ) {
+        self.evaluator.register_fn(name, function);
+    }
+
+    /// Runs commands with `executor` rather than on this machine, see
+    /// `ASTEvaluator::set_command_executor`.
+    pub fn set_command_executor(&mut self, executor: impl CommandExecutor + 'static) {
+        self.evaluator.set_command_executor(executor);
+    }
+
+    /// Calls a sod function, or a builtin, by name.
+    pub fn call(&mut self, name: &str, args: Vec<Symbol>) -> Result<Symbol, Error> {
+        Ok(self.evaluator.call(name, args)?)
+    }
+
+    /// The status passed to `process.exit`, once a program has called it.
+    pub fn exit_status(&self) -> Option<i32> {
+        self.evaluator.exit_status()
+    }
+
+    /// The evaluator underneath, for settings such as dry runs.
+    pub fn evaluator_mut(&mut self) -> &mut ASTEvaluator {
+        &mut self.evaluator
+    }
+}
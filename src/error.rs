@@ -0,0 +1,192 @@
+use std::fmt;
+
+/// A range of bytes in the source, from `start` up to `end`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+}
+
+impl Span {
+    pub fn new(start: usize, end: usize) -> Self {
+        Self { start, end }
+    }
+
+    /// The line and column the span starts at in `src`, both counted from 1.
+    pub fn line_column(&self, src: &str) -> (usize, usize) {
+        let before = &src.as_bytes()[..self.start.min(src.len())];
+        let before = String::from_utf8_lossy(before);
+        let line = before.matches('\n').count() + 1;
+        let column = match before.rfind('\n') {
+            Some(i) => before[i + 1..].chars().count() + 1,
+            None => before.chars().count() + 1,
+        };
+        (line, column)
+    }
+}
+
+/// What kind of mistake a parse error is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ParseErrorKind {
+    /// A token that can't appear where it was found.
+    UnexpectedToken,
+    /// The source ended part way through a statement.
+    UnexpectedEof,
+    /// Source the lexer couldn't read, such as a number too large to hold.
+    InvalidToken,
+    /// Source that reads correctly but isn't allowed, such as an alias for
+    /// something other than a command.
+    Invalid,
+}
+
+/// Why source couldn't be parsed, and where.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParseError {
+    pub kind: ParseErrorKind,
+    pub message: String,
+    pub span: Span,
+}
+
+impl ParseError {
+    pub fn new(kind: ParseErrorKind, message: impl Into<String>, span: Span) -> Self {
+        Self {
+            kind,
+            message: message.into(),
+            span,
+        }
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+/// What kind of failure a runtime error is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RuntimeErrorKind {
+    /// A variable, function or property that doesn't exist.
+    Undefined,
+    /// A value of the wrong kind for what was done with it.
+    Type,
+    /// A function given the wrong number of arguments, or one out of range.
+    Argument,
+    /// A command that couldn't be run or was interrupted.
+    Command,
+    /// A file or directory that couldn't be read, written or changed to.
+    Io,
+    /// A signal stopped the script, or Ctrl-C the line in the REPL.
+    Signal,
+    /// `process.exit` unwinding the script. `eval` stops there and returns
+    /// what ran before, rather than this error.
+    Exit,
+    /// A limit reached, such as functions calling each other too deeply.
+    Limit,
+    /// A statement without a value used where one is needed, such as
+    /// assigning what `interactive` returns.
+    NoValue,
+    /// Anything else, such as an error from a builtin.
+    Other,
+}
+
+/// Why evaluation failed.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RuntimeError {
+    pub kind: RuntimeErrorKind,
+    pub message: String,
+    /// Where the statement that failed is, the innermost one when it is in
+    /// a block or function. It is in the source the statement was parsed
+    /// from, which for a function is the source that declared it. None for
+    /// errors outside any statement, such as calling a missing function
+    /// from Rust.
+    pub span: Option<Span>,
+}
+
+impl RuntimeError {
+    pub fn new(kind: RuntimeErrorKind, message: impl Into<String>) -> Self {
+        Self {
+            kind,
+            message: message.into(),
+            span: None,
+        }
+    }
+
+    /// Records the statement the error happened in, unless it already has
+    /// one nested deeper.
+    pub(crate) fn at(mut self, span: Option<&Span>) -> Self {
+        if self.span.is_none() {
+            self.span = span.copied();
+        }
+        self
+    }
+}
+
+impl fmt::Display for RuntimeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+impl std::error::Error for RuntimeError {}
+
+/// Errors from builtins and values, which don't say what kind they are.
+impl From<String> for RuntimeError {
+    fn from(message: String) -> Self {
+        Self::new(RuntimeErrorKind::Other, message)
+    }
+}
+
+/// An error from parsing or running a script.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Error {
+    Parse(ParseError),
+    Runtime(RuntimeError),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Parse(e) => e.fmt(f),
+            Error::Runtime(e) => e.fmt(f),
+        }
+    }
+}
+
+impl Error {
+    /// Where in the source the error is, when it is known.
+    pub fn span(&self) -> Option<Span> {
+        match self {
+            Error::Parse(e) => Some(e.span),
+            Error::Runtime(e) => e.span,
+        }
+    }
+
+    /// The error as `file:line:column: message`, for source read from
+    /// `file`, or only the message when where it is isn't known.
+    pub fn located(&self, file: &str, src: &str) -> String {
+        match self.span() {
+            Some(span) => {
+                let (line, column) = span.line_column(src);
+                format!("{}:{}:{}: {}", file, line, column, self)
+            }
+            None => self.to_string(),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<ParseError> for Error {
+    fn from(e: ParseError) -> Self {
+        Error::Parse(e)
+    }
+}
+
+impl From<RuntimeError> for Error {
+    fn from(e: RuntimeError) -> Self {
+        Error::Runtime(e)
+    }
+}
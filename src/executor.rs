@@ -0,0 +1,279 @@
+use std::future::{self, Future};
+use std::process::Stdio;
+use std::sync::{mpsc, Arc};
+use std::thread;
+use std::time::Duration;
+
+use tokio::io::{AsyncRead, AsyncReadExt};
+use tokio::process::{Child, Command};
+use tokio::runtime::{self, Handle, Runtime, RuntimeFlavor};
+use tokio::sync::{oneshot, Semaphore};
+use tokio::task;
+use tokio::time::{self, Instant};
+
+use crate::commands::{
+    CommandExecutor, CommandLine, CommandResult, Echo, Job, Output, SharedWriter,
+};
+use crate::platform;
+
+/// Runs commands with `tokio::process` on a tokio runtime, so background
+/// jobs and `parallel` run alongside each other and the program, and
+/// commands can be given a time limit. While the program waits for a
+/// command, the runtime's other tasks carry on.
+pub struct TokioExecutor {
+    handle: Handle,
+    /// A runtime of its own, when created outside a multi-threaded one.
+    runtime: Option<Runtime>,
+    output: Output,
+    timeout: Option<Duration>,
+    deadline: Option<Instant>,
+}
+
+impl TokioExecutor {
+    /// An executor on the multi-threaded runtime it is created in. Outside
+    /// one, it starts a runtime of its own, as the current-thread runtime
+    /// can't run jobs while the program waits for them.
+    pub fn new() -> Result<Self, String> {
+        let (handle, runtime) = match Handle::try_current() {
+            Ok(handle) if handle.runtime_flavor() == RuntimeFlavor::MultiThread => (handle, None),
+            _ => {
+                let runtime = runtime::Builder::new_multi_thread()
+                    .worker_threads(1)
+                    .enable_all()
+                    .build()
+                    .map_err(|e| format!("failed to start a tokio runtime: {}", e))?;
+                (runtime.handle().clone(), Some(runtime))
+            }
+        };
+
+        Ok(Self {
+            handle,
+            runtime,
+            output: Output::terminal(),
+            timeout: None,
+            deadline: None,
+        })
+    }
+
+    /// Writes the output echoed from commands to `output`.
+    pub fn with_output(mut self, output: Output) -> Self {
+        self.output = output;
+        self
+    }
+
+    /// Kills each command that runs for longer than `timeout`.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Kills any command still running at `deadline`.
+    pub fn deadline(mut self, deadline: std::time::Instant) -> Self {
+        self.deadline = Some(deadline.into());
+        self
+    }
+
+    fn start(&self, cmd: &CommandLine) -> Result<Child, String> {
+        let command = Command::from(cmd.to_process()?);
+        let _guard = self.handle.enter();
+        start(command, cmd.stdout(Stdio::piped())?)
+    }
+
+    /// Waits for a future from the synchronous evaluator. On a worker of a
+    /// multi-threaded runtime, its other tasks are moved to other workers
+    /// while this one blocks.
+    fn block_on<F>(&self, future: F) -> F::Output
+    where
+        F: Future + Send,
+        F::Output: Send,
+    {
+        match Handle::try_current().map(|handle| handle.runtime_flavor()) {
+            Ok(RuntimeFlavor::MultiThread) => task::block_in_place(|| self.handle.block_on(future)),
+            Ok(_) => thread::scope(|scope| {
+                scope
+                    .spawn(|| self.handle.block_on(future))
+                    .join()
+                    .expect("command thread panicked")
+            }),
+            Err(_) => self.handle.block_on(future),
+        }
+    }
+}
+
+/// A runtime the executor started is shut down without waiting, as a
+/// runtime can't be dropped from inside another.
+impl Drop for TokioExecutor {
+    fn drop(&mut self) {
+        if let Some(runtime) = self.runtime.take() {
+            runtime.shutdown_background();
+        }
+    }
+}
+
+impl CommandExecutor for TokioExecutor {
+    fn run(&mut self, cmd: &CommandLine, echo: Echo) -> Result<CommandResult, String> {
+        let child = self.start(cmd)?;
+        let finish = finish(
+            child,
+            cmd.to_string(),
+            echo,
+            self.output.clone(),
+            command_deadline(self.timeout, self.deadline),
+            None,
+        );
+        self.block_on(finish)
+    }
+
+    fn spawn(&mut self, cmd: &CommandLine) -> Result<Job, String> {
+        let child = self.start(cmd)?;
+        let pid = child.id().unwrap_or(0);
+        let (kill, killed) = oneshot::channel();
+        let (sender, result) = mpsc::channel();
+        let finish = finish(
+            child,
+            cmd.to_string(),
+            Echo::NONE,
+            self.output.clone(),
+            command_deadline(self.timeout, self.deadline),
+            Some(killed),
+        );
+        self.handle.spawn(async move {
+            let _ = sender.send(finish.await);
+        });
+
+        Ok(Job::pending(cmd.clone(), pid, result, move || {
+            let _ = kill.send(());
+        }))
+    }
+
+    fn run_parallel(
+        &mut self,
+        cmds: &[CommandLine],
+        limit: usize,
+    ) -> Result<Vec<CommandResult>, String> {
+        let permits = Arc::new(Semaphore::new(limit.max(1)));
+        let mut tasks = vec![];
+        for cmd in cmds {
+            let command = Command::from(cmd.to_process()?);
+            let stdout = cmd.stdout(Stdio::piped())?;
+            let permits = Arc::clone(&permits);
+            let name = cmd.to_string();
+            let output = self.output.clone();
+            let timeout = self.timeout;
+            let deadline = self.deadline;
+            tasks.push(self.handle.spawn(async move {
+                let _permit = permits.acquire().await;
+                let child = start(command, stdout)?;
+                let deadline = command_deadline(timeout, deadline);
+                finish(child, name, Echo::NONE, output, deadline, None).await
+            }));
+        }
+
+        self.block_on(async {
+            let mut results = vec![];
+            for task in tasks {
+                results.push(task.await.map_err(|e| e.to_string())??);
+            }
+            Ok(results)
+        })
+    }
+}
+
+/// When a command started now has to have finished, if it has a time limit.
+fn command_deadline(timeout: Option<Duration>, deadline: Option<Instant>) -> Option<Instant> {
+    let timeout = timeout.map(|timeout| Instant::now() + timeout);
+    match (timeout, deadline) {
+        (Some(a), Some(b)) => Some(a.min(b)),
+        (a, b) => a.or(b),
+    }
+}
+
+fn start(mut command: Command, stdout: Stdio) -> Result<Child, String> {
+    command
+        .stdin(Stdio::null())
+        .stdout(stdout)
+        .stderr(Stdio::piped())
+        .kill_on_drop(true)
+        .spawn()
+        .map_err(|e| format!("failed to execute process: {}", e))
+}
+
+/// Collects everything from a pipe, writing each chunk to `echo` as soon as
+/// it arrives.
+async fn read_pipe(pipe: Option<impl AsyncRead + Unpin>, echo: Option<SharedWriter>) -> Vec<u8> {
+    let mut buffer = vec![];
+    let mut pipe = match pipe {
+        Some(pipe) => pipe,
+        None => return buffer,
+    };
+
+    let mut chunk = [0; 8192];
+    loop {
+        let read = match pipe.read(&mut chunk).await {
+            Ok(0) | Err(_) => break,
+            Ok(read) => read,
+        };
+
+        if let Some(Ok(mut out)) = echo.as_ref().map(|echo| echo.lock()) {
+            let _ = out.write_all(&chunk[..read]).and_then(|_| out.flush());
+        }
+        buffer.extend_from_slice(&chunk[..read]);
+    }
+    buffer
+}
+
+/// Waits for a command to finish, killing it when told to on `killed`. Once
+/// `deadline` has passed it is killed without waiting for its output, which
+/// a process it started may still be holding open.
+async fn finish(
+    mut child: Child,
+    command: String,
+    echo: Echo,
+    output: Output,
+    deadline: Option<Instant>,
+    killed: Option<oneshot::Receiver<()>>,
+) -> Result<CommandResult, String> {
+    let start = Instant::now();
+    let stdout = read_pipe(child.stdout.take(), echo.stdout.then_some(output.stdout));
+    let stderr = read_pipe(child.stderr.take(), echo.stderr.then_some(output.stderr));
+
+    // a job that is dropped, rather than killed, is left to finish
+    let killed = async {
+        if let Some(killed) = killed {
+            if killed.await.is_ok() {
+                return;
+            }
+        }
+        future::pending().await
+    };
+    let status = async {
+        tokio::select! {
+            status = child.wait() => status,
+            _ = killed => {
+                let _ = child.start_kill();
+                child.wait().await
+            }
+        }
+    };
+    let expired = async {
+        match deadline {
+            Some(deadline) => time::sleep_until(deadline).await,
+            None => future::pending().await,
+        }
+    };
+
+    let (stdout, stderr, status) = tokio::select! {
+        finished = async { tokio::join!(stdout, stderr, status) } => finished,
+        _ = expired => return Err(format!("command timed out: {}", command)),
+    };
+    let status = status.map_err(|e| format!("failed to wait for process: {}", e))?;
+    Ok(CommandResult {
+        command,
+        stdout,
+        stderr,
+        status: status.code(),
+        signal: platform::current().signal(&status),
+        statuses: vec![],
+        duration: start.elapsed(),
+    })
+}
@@ -0,0 +1,41 @@
+use std::cell::RefCell;
+use std::collections::HashSet;
+use std::rc::Rc;
+
+/// The name of a variable, function or property. Names are shared rather
+/// than copied, so a syntax tree can be cloned, and a variable set or looked
+/// up, without allocating.
+pub type Name = Rc<str>;
+
+/// Hands out one shared copy of each name it is given.
+#[derive(Debug, Default)]
+pub struct Interner {
+    names: RefCell<HashSet<Name>>,
+}
+
+impl Interner {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The shared copy of `name`, made the first time it is asked for.
+    pub fn intern(&self, name: &str) -> Name {
+        let mut names = self.names.borrow_mut();
+        if let Some(interned) = names.get(name) {
+            return interned.clone();
+        }
+
+        let interned: Name = Rc::from(name);
+        names.insert(interned.clone());
+        interned
+    }
+
+    /// How many different names have been interned.
+    pub fn len(&self) -> usize {
+        self.names.borrow().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.names.borrow().is_empty()
+    }
+}
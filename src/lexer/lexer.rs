@@ -1,14 +1,17 @@
-use std::error::Error;
+use std::borrow::Cow;
 
 use super::token::TokenType;
+use crate::error::Span;
+use crate::intern::{Interner, Name};
 
-pub fn bytes_to_string(bytes: Vec<u8>) -> String {
-    String::from_utf8_lossy(&bytes).to_string()
-}
-
-pub struct Lexer {
-    src: Vec<u8>,
+/// Reads tokens from source without copying it. Tokens with text, such as
+/// strings, borrow it from the source, and identifiers are interned.
+pub struct Lexer<'a> {
+    src: &'a str,
     cursor: usize,
+    /// Where the last token read starts.
+    token_start: usize,
+    interner: Interner,
 }
 
 fn is_whitespace(byte: u8) -> bool {
@@ -18,32 +21,39 @@ fn is_whitespace(byte: u8) -> bool {
     }
 }
 
-impl Lexer {
-    pub fn new(src: &str) -> Lexer {
+impl<'a> Lexer<'a> {
+    pub fn new(src: &'a str) -> Lexer<'a> {
         Lexer {
-            src: src.as_bytes().to_owned(),
+            src,
             cursor: 0,
+            token_start: 0,
+            interner: Interner::new(),
         }
     }
 
     fn peak_byte(&self, distance: usize) -> Option<&u8> {
-        self.src.get(self.cursor + distance)
+        self.src.as_bytes().get(self.cursor + distance)
     }
 
-    fn read_while(&self, mut pred: impl FnMut(&u8) -> bool, offset: usize) -> (Vec<u8>, usize) {
-        let mut bytes = vec![];
-        for byte in self.src.iter().skip(self.cursor + offset) {
-            if pred(byte) {
-                bytes.push(byte.clone())
-            } else {
-                break;
-            }
-        }
-        let bytes_read = bytes.len();
-        (bytes, bytes_read)
+    /// The source from `offset` bytes past the cursor, `len` bytes long.
+    /// Tokens end on ASCII bytes, so this is always on character
+    /// boundaries.
+    fn slice(&self, offset: usize, len: usize) -> &'a str {
+        let start = self.cursor + offset;
+        &self.src[start..start + len]
     }
 
-    fn read_digit(&self) -> Result<(TokenType, usize), Box<dyn Error>> {
+    /// How many bytes from `offset` past the cursor match `pred`.
+    fn read_while(&self, mut pred: impl FnMut(&u8) -> bool, offset: usize) -> usize {
+        let rest = self
+            .src
+            .as_bytes()
+            .get(self.cursor + offset..)
+            .unwrap_or_default();
+        rest.iter().position(|b| !pred(b)).unwrap_or(rest.len())
+    }
+
+    fn read_digit(&self) -> (TokenType<'a>, usize) {
         let mut seen_dot = false;
         let read = self.read_while(
             |b| {
@@ -61,51 +71,46 @@ impl Lexer {
         );
 
         // trim trailing dots
-        let (bytes, bytes_read, is_decimal) = {
-            let (mut bytes, original_len) = read;
-            for i in (0..bytes.len()).rev() {
-                if bytes[i] == b'.' {
-                    bytes.pop();
-                } else {
-                    break;
-                }
-            }
-            let len = bytes.len();
-            (bytes, len, seen_dot && len == original_len)
-        };
+        let s = self.slice(0, read).trim_end_matches('.');
+        let bytes_read = s.len();
+        let is_decimal = seen_dot && bytes_read == read;
 
-        let s = String::from_utf8(bytes)?;
+        let token = match is_decimal {
+            true => s.parse().map(TokenType::Decimal).map_err(|e| e.to_string()),
+            false => s.parse().map(TokenType::Integer).map_err(|e| e.to_string()),
+        };
 
-        if is_decimal {
-            let dec = s.parse()?;
-            return Ok((TokenType::Decimal(dec), bytes_read));
+        match token {
+            Ok(token) => (token, bytes_read),
+            Err(e) => (
+                TokenType::Error(format!("invalid number {}: {}", s, e)),
+                bytes_read,
+            ),
         }
-
-        let num = s.parse()?;
-        Ok((TokenType::Integer(num), bytes_read))
     }
 
-    fn read_identifier(&self) -> (TokenType, usize) {
-        let (bytes, bytes_read) = self.read_while(|b| b.is_ascii_alphanumeric() || *b == b'_', 0);
+    fn read_identifier(&self) -> (TokenType<'a>, usize) {
+        let bytes_read = self.read_while(|b| b.is_ascii_alphanumeric() || *b == b'_', 0);
 
-        (TokenType::Identifier(bytes_to_string(bytes)), bytes_read)
+        let name = self.interner.intern(self.slice(0, bytes_read));
+        (TokenType::Identifier(name), bytes_read)
     }
 
-    fn read_equals(&self) -> (TokenType, usize) {
+    fn read_equals(&self) -> (TokenType<'a>, usize) {
         match self.peak_byte(1) {
             Some(b) if b == &b'=' => (TokenType::DoubleEquals, 2),
             _ => (TokenType::Equals, 1),
         }
     }
 
-    fn read_not_equals(&self) -> (TokenType, usize) {
+    fn read_not_equals(&self) -> (TokenType<'a>, usize) {
         match self.peak_byte(1) {
             Some(b) if b == &b'=' => (TokenType::NotEquals, 2),
             _ => (TokenType::NotEquals, 1),
         }
     }
 
-    fn read_left_arrow(&self) -> (TokenType, usize) {
+    fn read_left_arrow(&self) -> (TokenType<'a>, usize) {
         let next = match self.peak_byte(1) {
             Some(b) => b,
             _ => return (TokenType::LessThan, 1),
@@ -117,7 +122,7 @@ impl Lexer {
         }
     }
 
-    fn read_right_arrow(&self) -> (TokenType, usize) {
+    fn read_right_arrow(&self) -> (TokenType<'a>, usize) {
         let next = match self.peak_byte(1) {
             Some(b) => b,
             _ => return (TokenType::GreaterThan, 1),
@@ -129,61 +134,126 @@ impl Lexer {
         }
     }
 
-    fn read_and(&self) -> (TokenType, usize) {
+    fn read_and(&self) -> (TokenType<'a>, usize) {
         match self.peak_byte(1) {
             Some(b) if b == &b'&' => (TokenType::And, 2),
-            _ => self.read_catch_all(b'&'),
+            _ => self.read_catch_all(),
         }
     }
 
-    fn read_pipe(&self) -> (TokenType, usize) {
+    fn read_pipe(&self) -> (TokenType<'a>, usize) {
         match self.peak_byte(1) {
             Some(b) if b == &b'|' => (TokenType::Or, 2),
-            _ => self.read_catch_all(b'|'),
+            Some(b) if b == &b'>' => (TokenType::PipeForward, 2),
+            _ => self.read_catch_all(),
         }
     }
 
-    fn read_string(&self, quote: u8) -> (TokenType, usize) {
-        let (s_bytes, inner_bytes_read) = self.read_while(|b| *b != quote, 1);
-        let s = bytes_to_string(s_bytes);
+    fn read_string(&self, quote: u8) -> (TokenType<'a>, usize) {
+        let inner_bytes_read = self.read_while(|b| *b != quote, 1);
+        if self.peak_byte(inner_bytes_read + 1).is_none() {
+            let error = format!(
+                "unterminated string starting at line {}",
+                self.line_of(self.cursor)
+            );
+            return (TokenType::Error(error), inner_bytes_read + 1);
+        }
+        let s = Cow::Borrowed(self.slice(1, inner_bytes_read));
         let bytes_read = inner_bytes_read + 2;
 
         if quote == b'"' {
             return (TokenType::TemplateString(s), bytes_read);
         }
 
+        if quote == b'`' {
+            return (TokenType::CommandString(s), bytes_read);
+        }
+
         (TokenType::String(s), bytes_read)
     }
 
-    fn read_escaped_identifier(&self) -> (TokenType, usize) {
-        let (bytes, bytes_read) = self.read_while(|b| b.is_ascii_alphanumeric(), 1);
+    /// `${expr}`, up to the brace that closes the opening one.
+    fn read_escaped_expression(&self) -> (TokenType<'a>, usize) {
+        let mut depth = 0;
+        let mut quote = None;
+        let bytes_read = self.read_while(
+            |b| {
+                match (quote, *b) {
+                    (Some(q), b) if b == q => quote = None,
+                    (Some(_), _) => (),
+                    (None, b'\'' | b'"') => quote = Some(*b),
+                    (None, b'{') => depth += 1,
+                    (None, b'}') if depth == 0 => return false,
+                    (None, b'}') => depth -= 1,
+                    _ => (),
+                }
+                true
+            },
+            2,
+        );
 
+        if self.peak_byte(bytes_read + 2).is_none() {
+            let error = format!(
+                "unclosed '${{' opened at line {}",
+                self.line_of(self.cursor)
+            );
+            return (TokenType::Error(error), bytes_read + 2);
+        }
         (
-            TokenType::EscapedIdentifier(bytes_to_string(bytes)),
-            bytes_read + 1,
+            TokenType::EscapedExpression(Cow::Borrowed(self.slice(2, bytes_read))),
+            bytes_read + 3,
         )
     }
 
-    fn read_line_comment(&self) -> (TokenType, usize) {
-        let (_, bytes_read) = self.read_while(|b| *b != b'\n', 0);
+    /// `$name` or `$name!`, the latter being passed to commands unquoted. A
+    /// `$` without a name after it is just a `$`.
+    fn read_escaped_identifier(&self) -> (TokenType<'a>, usize) {
+        if self.peak_byte(1) == Some(&b'{') {
+            return self.read_escaped_expression();
+        }
+
+        let bytes_read = self.read_while(|b| b.is_ascii_alphanumeric(), 1);
+        if bytes_read == 0 {
+            return self.read_catch_all();
+        }
+        let name = Cow::Borrowed(self.slice(1, bytes_read));
+
+        let raw = self.peak_byte(bytes_read + 1) == Some(&b'!')
+            && self.peak_byte(bytes_read + 2) != Some(&b'=');
+        if raw {
+            return (TokenType::RawIdentifier(name), bytes_read + 2);
+        }
+
+        (TokenType::EscapedIdentifier(name), bytes_read + 1)
+    }
+
+    fn read_line_comment(&self) -> (TokenType<'a>, usize) {
+        let bytes_read = self.read_while(|b| *b != b'\n', 0);
         (TokenType::LineComment, bytes_read)
     }
 
-    fn read_whitespace(&self) -> (TokenType, usize) {
-        let (_, bytes_read) = self.read_while(|b| is_whitespace(*b), 0);
+    fn read_whitespace(&self) -> (TokenType<'a>, usize) {
+        let bytes_read = self.read_while(|b| is_whitespace(*b), 0);
         (TokenType::Whitespace, bytes_read)
     }
 
-    fn read_catch_all(&self, byte: u8) -> (TokenType, usize) {
-        let s = match String::from_utf8(vec![byte]) {
-            Ok(s) => s,
-            Err(_) => panic!("invalid character {}", byte as char),
-        };
-
-        (TokenType::CatchAll(s), 1)
+    /// The character at the cursor, which may take several bytes.
+    fn read_catch_all(&self) -> (TokenType<'a>, usize) {
+        let rest = self.src.get(self.cursor..).unwrap_or_default();
+
+        match rest.chars().next() {
+            Some(c) => (
+                TokenType::CatchAll(Cow::Borrowed(&rest[..c.len_utf8()])),
+                c.len_utf8(),
+            ),
+            None => (
+                TokenType::Error(format!("invalid character at byte {}", self.cursor)),
+                1,
+            ),
+        }
     }
 
-    fn peak(&self) -> (TokenType, usize) {
+    fn peak(&self) -> (TokenType<'a>, usize) {
         let byte = match self.peak_byte(0) {
             Some(b) => b,
             None => return (TokenType::EOF, 0),
@@ -192,6 +262,7 @@ impl Lexer {
         match byte {
             b'-' => (TokenType::Minus, 1),
             b',' => (TokenType::Comma, 1),
+            b':' => (TokenType::Colon, 1),
             b';' => (TokenType::SemiColon, 1),
             b'.' => (TokenType::Dot, 1),
             b'(' => (TokenType::OpenParen, 1),
@@ -213,21 +284,19 @@ impl Lexer {
             b'>' => self.read_right_arrow(),
             b'<' => self.read_left_arrow(),
             b'#' => self.read_line_comment(),
-            b if *b == b'"' || *b == b'\'' => self.read_string(*b),
+            b if *b == b'"' || *b == b'\'' || *b == b'`' => self.read_string(*b),
             b'$' => self.read_escaped_identifier(),
             b if is_whitespace(*b) => self.read_whitespace(),
-            b if b.is_ascii_digit() => match self.read_digit() {
-                Ok(r) => r,
-                Err(e) => panic!("{}", e),
-            },
+            b if b.is_ascii_digit() => self.read_digit(),
             b if b.is_ascii_alphabetic() => self.read_identifier(),
-            _ => self.read_catch_all(*byte),
+            _ => self.read_catch_all(),
         }
     }
 
-    fn next(&mut self) -> TokenType {
+    fn next(&mut self) -> TokenType<'a> {
         loop {
             let (token, bytes_read) = self.peak();
+            self.token_start = self.cursor;
             self.cursor += bytes_read;
             if token != TokenType::LineComment {
                 return token;
@@ -235,7 +304,7 @@ impl Lexer {
         }
     }
 
-    pub fn next_token(&mut self) -> TokenType {
+    pub fn next_token(&mut self) -> TokenType<'a> {
         loop {
             let token = self.next();
             if token != TokenType::Whitespace {
@@ -244,13 +313,130 @@ impl Lexer {
         }
     }
 
-    pub fn next_cmd_token(&mut self) -> TokenType {
+    pub fn next_cmd_token(&mut self) -> TokenType<'a> {
         self.next()
     }
 
-    pub fn lookahead(&mut self, distance: usize) -> TokenType {
+    /// The line a position in the source is on, counted from 1.
+    pub fn line_of(&self, pos: usize) -> usize {
+        let end = pos.min(self.src.len());
+        self.src.as_bytes()[..end]
+            .iter()
+            .filter(|b| **b == b'\n')
+            .count()
+            + 1
+    }
+
+    /// The shared copy of a name, the same one identifiers with that name
+    /// are read as.
+    pub fn intern(&self, name: &str) -> Name {
+        self.interner.intern(name)
+    }
+
+    /// Where the last token read is in the source.
+    pub fn span(&self) -> Span {
+        Span::new(self.token_start, self.cursor)
+    }
+
+    /// The source a span covers, or an empty string if it isn't on
+    /// character boundaries.
+    pub fn text(&self, span: Span) -> &'a str {
+        self.src.get(span.start..span.end).unwrap_or_default()
+    }
+
+    pub fn next_byte_is(&self, byte: u8) -> bool {
+        self.peak_byte(0) == Some(&byte)
+    }
+
+    /// Whether the rest of the line belongs to the current shell command,
+    /// rather than `;`, `&&`, `||`, `&` or `|` starting another one.
+    pub fn rest_is_one_command(&self) -> bool {
+        let src = self.src.as_bytes();
+        let mut pos = self.cursor;
+        while let Some(&b) = src.get(pos) {
+            let next = src.get(pos + 1);
+            match b {
+                b'\n' => break,
+                b'\'' | b'"' | b'`' => {
+                    pos += 1;
+                    while src.get(pos).is_some_and(|c| *c != b) {
+                        pos += 1;
+                    }
+                }
+                b';' => return false,
+                // `|>` pipes into sod
+                b'|' if next == Some(&b'>') => break,
+                b'|' => return false,
+                // part of a redirection such as 2>&1 or &>file
+                b'&' if pos > 0 && matches!(src[pos - 1], b'>' | b'<') => (),
+                b'&' if next == Some(&b'>') => (),
+                b'&' => return false,
+                _ => (),
+            }
+            pos += 1;
+        }
+        true
+    }
+
+    /// Reads a shell word starting at `pos`, skipping over quoted sections,
+    /// and returns the position after it.
+    fn skip_word(&self, mut pos: usize) -> usize {
+        let src = self.src.as_bytes();
+        while let Some(&b) = src.get(pos) {
+            match b {
+                b'\'' | b'"' | b'`' => {
+                    pos += 1;
+                    while src.get(pos).is_some_and(|c| *c != b) {
+                        pos += 1;
+                    }
+                }
+                b if is_whitespace(b) || b == b'\n' => break,
+                _ => (),
+            }
+            pos += 1;
+        }
+        pos
+    }
+
+    /// With the cursor after an identifier, as with `FOO` in `FOO=bar cmd`,
+    /// skips any `NAME=value` assignments and returns the word that follows
+    /// them, so the parser can tell whether they prefix a command.
+    pub fn word_after_assignments(&self) -> Option<&'a str> {
+        let src = self.src.as_bytes();
+        let mut pos = self.cursor;
+        loop {
+            match (src.get(pos), src.get(pos + 1)) {
+                (Some(b'='), Some(b)) if *b != b'=' && !is_whitespace(*b) && *b != b'\n' => (),
+                _ => return None,
+            }
+
+            pos = self.skip_word(pos + 1);
+            while src.get(pos).is_some_and(|b| is_whitespace(*b)) {
+                pos += 1;
+            }
+
+            let start = pos;
+            while src
+                .get(pos)
+                .is_some_and(|b| b.is_ascii_alphanumeric() || *b == b'_')
+            {
+                pos += 1;
+            }
+
+            if pos == start || src.get(pos) != Some(&b'=') {
+                let end = self.skip_word(start);
+                if end == start {
+                    return None;
+                }
+                return self.src.get(start..end);
+            }
+        }
+    }
+
+    pub fn lookahead(&mut self, distance: usize) -> TokenType<'a> {
         let mut i = distance as u32;
         let cursor_snapshot = self.cursor;
+        let token_start_snapshot = self.token_start;
 
         loop {
             let token = self.next();
@@ -261,6 +447,7 @@ impl Lexer {
             i -= 1;
             if i <= 0 || token == TokenType::EOF {
                 self.cursor = cursor_snapshot;
+                self.token_start = token_start_snapshot;
                 return token;
             }
         }
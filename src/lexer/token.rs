@@ -1,10 +1,15 @@
+use crate::intern::Name;
+use std::borrow::Cow;
+
+/// A token, borrowing any text it has from the source it was read from.
 #[derive(Debug, Clone, PartialEq)]
-pub enum TokenType {
+pub enum TokenType<'a> {
     And,
     Asterisk,
     Carat,
     CloseBraces,
     CloseParen,
+    Colon,
     Comma,
     BackSlash,
     Dot,
@@ -25,6 +30,7 @@ pub enum TokenType {
     OpenBraces,
     OpenParen,
     Or,
+    PipeForward,
     Plus,
     SemiColon,
     SingleQuote,
@@ -33,23 +39,84 @@ pub enum TokenType {
     LineComment,
     Integer(usize),
     Decimal(f64),
-    String(String),
-    TemplateString(String),
-    Identifier(String),
-    EscapedIdentifier(String),
-    CatchAll(String),
+    String(Cow<'a, str>),
+    TemplateString(Cow<'a, str>),
+    CommandString(Cow<'a, str>),
+    Identifier(Name),
+    EscapedIdentifier(Cow<'a, str>),
+    RawIdentifier(Cow<'a, str>),
+    EscapedExpression(Cow<'a, str>),
+    CatchAll(Cow<'a, str>),
+    /// Source the lexer couldn't read, with the reason, which the parser
+    /// reports as a parse error.
+    Error(String),
 }
 
-impl TokenType {
+impl TokenType<'_> {
     pub fn is_end_line(&self) -> bool {
         match self {
             TokenType::EOF | TokenType::Newline => true,
             _ => false,
         }
     }
+
+    /// The token with its text copied, so it no longer borrows the source,
+    /// as operators are when they are kept in the syntax tree.
+    pub fn into_owned(self) -> TokenType<'static> {
+        match self {
+            TokenType::And => TokenType::And,
+            TokenType::Asterisk => TokenType::Asterisk,
+            TokenType::Carat => TokenType::Carat,
+            TokenType::CloseBraces => TokenType::CloseBraces,
+            TokenType::CloseParen => TokenType::CloseParen,
+            TokenType::Colon => TokenType::Colon,
+            TokenType::Comma => TokenType::Comma,
+            TokenType::BackSlash => TokenType::BackSlash,
+            TokenType::Dot => TokenType::Dot,
+            TokenType::DoubleEquals => TokenType::DoubleEquals,
+            TokenType::EOF => TokenType::EOF,
+            TokenType::Equals => TokenType::Equals,
+            TokenType::ForwardSlash => TokenType::ForwardSlash,
+            TokenType::Ge => TokenType::Ge,
+            TokenType::GreaterThan => TokenType::GreaterThan,
+            TokenType::Le => TokenType::Le,
+            TokenType::LessThan => TokenType::LessThan,
+            TokenType::OpenSqBracket => TokenType::OpenSqBracket,
+            TokenType::CloseSqBracket => TokenType::CloseSqBracket,
+            TokenType::Minus => TokenType::Minus,
+            TokenType::Newline => TokenType::Newline,
+            TokenType::Not => TokenType::Not,
+            TokenType::NotEquals => TokenType::NotEquals,
+            TokenType::OpenBraces => TokenType::OpenBraces,
+            TokenType::OpenParen => TokenType::OpenParen,
+            TokenType::Or => TokenType::Or,
+            TokenType::PipeForward => TokenType::PipeForward,
+            TokenType::Plus => TokenType::Plus,
+            TokenType::SemiColon => TokenType::SemiColon,
+            TokenType::SingleQuote => TokenType::SingleQuote,
+            TokenType::Whitespace => TokenType::Whitespace,
+            TokenType::Underscore => TokenType::Underscore,
+            TokenType::LineComment => TokenType::LineComment,
+            TokenType::Integer(i) => TokenType::Integer(i),
+            TokenType::Decimal(d) => TokenType::Decimal(d),
+            TokenType::Identifier(name) => TokenType::Identifier(name),
+            TokenType::String(s) => TokenType::String(Cow::Owned(s.into_owned())),
+            TokenType::TemplateString(s) => TokenType::TemplateString(Cow::Owned(s.into_owned())),
+            TokenType::CommandString(s) => TokenType::CommandString(Cow::Owned(s.into_owned())),
+            TokenType::EscapedIdentifier(s) => {
+                TokenType::EscapedIdentifier(Cow::Owned(s.into_owned()))
+            }
+            TokenType::RawIdentifier(s) => TokenType::RawIdentifier(Cow::Owned(s.into_owned())),
+            TokenType::EscapedExpression(s) => {
+                TokenType::EscapedExpression(Cow::Owned(s.into_owned()))
+            }
+            TokenType::CatchAll(s) => TokenType::CatchAll(Cow::Owned(s.into_owned())),
+            TokenType::Error(e) => TokenType::Error(e),
+        }
+    }
 }
 
-impl std::fmt::Display for TokenType {
+impl std::fmt::Display for TokenType<'_> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         let s = match &self {
             TokenType::And => "&&",
@@ -57,6 +124,7 @@ impl std::fmt::Display for TokenType {
             TokenType::Carat => "^",
             TokenType::CloseBraces => "}",
             TokenType::CloseParen => ")",
+            TokenType::Colon => ":",
             TokenType::Comma => ",",
             TokenType::BackSlash => "\\",
             TokenType::Dot => ".",
@@ -71,25 +139,30 @@ impl std::fmt::Display for TokenType {
             TokenType::Le => "<=",
             TokenType::LessThan => "<",
             TokenType::Minus => "-",
-            TokenType::Newline => "\n",
+            TokenType::Newline => "\\n",
             TokenType::Not => "!",
             TokenType::NotEquals => "!=",
             TokenType::OpenBraces => "{",
             TokenType::OpenParen => "(",
             TokenType::Or => "||",
+            TokenType::PipeForward => "|>",
             TokenType::Plus => "+",
             TokenType::SemiColon => ";",
             TokenType::SingleQuote => "'",
             TokenType::Whitespace => " ",
             TokenType::Underscore => "_",
             TokenType::LineComment => "",
-            TokenType::EscapedIdentifier(s) => s.as_str(),
+            TokenType::EscapedIdentifier(s) => s,
+            TokenType::RawIdentifier(s) => return write!(f, "{}!", s),
+            TokenType::EscapedExpression(s) => return write!(f, "${{{}}}", s),
             TokenType::Integer(i) => return write!(f, "{}", i),
             TokenType::Decimal(d) => return write!(f, "{}", d),
             TokenType::Identifier(s) => return write!(f, "{}", s),
             TokenType::String(s) => return write!(f, "'{}'", s),
             TokenType::TemplateString(s) => return write!(f, r#""{}""#, s),
-            TokenType::CatchAll(s) => s.as_str(),
+            TokenType::CommandString(s) => return write!(f, "`{}`", s),
+            TokenType::CatchAll(s) => s,
+            TokenType::Error(e) => e.as_str(),
         };
 
         write!(f, "{}", s)
@@ -1,5 +1,17 @@
 pub mod ast;
+pub mod audit;
+pub mod builtins;
 pub mod commands;
+pub mod engine;
+pub mod error;
+#[cfg(feature = "async")]
+pub mod executor;
+pub mod intern;
 pub mod lexer;
+pub mod limits;
 pub mod parser;
+pub mod platform;
+pub mod sandbox;
 pub mod symbol;
+
+pub use engine::Engine;
@@ -0,0 +1,74 @@
+use std::time::Duration;
+
+use crate::ast::evaluator::MAX_CALL_DEPTH;
+
+/// How much a program may do before it is stopped with an error, for
+/// running scripts that can't be trusted inside a larger application. The
+/// call depth is limited to `MAX_CALL_DEPTH` by default and nothing else is
+/// limited.
+///
+/// The limits apply to each call to `ASTEvaluator::eval` or
+/// `ASTEvaluator::call`, so in the REPL to each line.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Limits {
+    pub(crate) loop_iterations: Option<u64>,
+    pub(crate) call_depth: usize,
+    pub(crate) symbols: Option<usize>,
+    pub(crate) value_size: Option<usize>,
+    pub(crate) timeout: Option<Duration>,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Self {
+            loop_iterations: None,
+            call_depth: MAX_CALL_DEPTH,
+            symbols: None,
+            value_size: None,
+            timeout: None,
+        }
+    }
+}
+
+impl Limits {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// How many times loops may run their body, counting every pass of
+    /// every loop.
+    pub fn loop_iterations(mut self, max: u64) -> Self {
+        self.loop_iterations = Some(max);
+        self
+    }
+
+    /// How many calls deep functions may go. The stack grows as calls need
+    /// it, so this only bounds how much memory they take.
+    pub fn call_depth(mut self, max: usize) -> Self {
+        self.call_depth = max;
+        self
+    }
+
+    /// How many symbols the symbol table may hold at once: the globals, the
+    /// variables of every scope in use and the arguments of each running
+    /// call.
+    pub fn symbols(mut self, max: usize) -> Self {
+        self.symbols = Some(max);
+        self
+    }
+
+    /// How many items a list or object, or bytes a string, may hold. It is
+    /// checked as values are built: by list and object literals, `+` and
+    /// member functions such as `push` and `extend`.
+    pub fn value_size(mut self, max: usize) -> Self {
+        self.value_size = Some(max);
+        self
+    }
+
+    /// How long a program may run. It is checked between statements, loop
+    /// passes and calls, so a command that doesn't finish isn't stopped.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+}
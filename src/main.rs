@@ -1,81 +1,253 @@
+use sod::ast::bash;
 use sod::ast::evaluator::ASTEvaluator;
-use sod::parser::Parser;
+use sod::ast::lint;
+use sod::audit;
+use sod::commands;
+use sod::error::Error;
+use sod::parser::{Declarations, Parser};
+use sod::sandbox;
 use std::env;
 use std::fs;
-use std::io::{self, Write};
+use std::io::{self, Read};
+use std::path::Path;
 use std::process;
+use std::thread;
 
-fn get_argv(env_args: Vec<String>) -> Vec<String> {
-    let mut argv = env_args.clone();
-    argv.remove(0);
-    argv
+use cli::Mode;
+
+mod cli;
+mod repl;
+
+/// A script that fails with an error exits with this status.
+const EXIT_ERROR: i32 = 1;
+/// The status for a script that doesn't parse, or options that don't, as in
+/// bash.
+const EXIT_USAGE: i32 = 2;
+/// The statuses for a script that can't be read or doesn't exist, as bash
+/// reports a command it can't run or find.
+const EXIT_CANNOT_RUN: i32 = 126;
+const EXIT_NOT_FOUND: i32 = 127;
+
+/// The stack sod runs on. Evaluation recurses into the syntax tree, which
+/// the most deeply nested source the parser accepts takes more than the main
+/// thread's few megabytes for in a debug build. Function calls grow the
+/// stack themselves. Only what is used is actually allocated.
+const STACK_SIZE: usize = 256 * 1024 * 1024;
+
+/// Evaluates the startup file ahead of a script, returning the aliases and
+/// functions it declares for the script to use. Errors are reported but don't
+/// stop the script.
+fn load_rc(evaluator: &mut ASTEvaluator) -> Declarations {
+    let path = match repl::rc_path() {
+        Some(path) => path,
+        None => return Declarations::default(),
+    };
+    let src = match fs::read_to_string(&path) {
+        Ok(s) => s,
+        Err(err) => {
+            eprintln!("failed to read {}: {}", path.display(), err);
+            return Declarations::default();
+        }
+    };
+
+    let mut parser = Parser::new(&src);
+    let result = match parser.parse() {
+        Ok(ast) => evaluator.eval(ast).map_err(Error::from),
+        Err(e) => Err(Error::from(e)),
+    };
+    if let Err(e) = result {
+        eprintln!("{}", e.located(&path.display().to_string(), &src));
+    }
+    parser.declarations().clone()
 }
 
-fn parse_file(env_args: Vec<String>) {
-    let argv = get_argv(env_args);
-    let filename = argv.get(0).unwrap();
-    let src = match fs::read_to_string(filename) {
+/// Reads a script, or stdin when `path` is `-`, with an error naming the
+/// file when it can't be.
+fn read_script(path: &str) -> String {
+    if path == "-" {
+        let mut src = String::new();
+        if let Err(err) = io::stdin().read_to_string(&mut src) {
+            eprintln!("failed to read stdin: {}", err);
+            process::exit(EXIT_CANNOT_RUN);
+        }
+        return src;
+    }
+
+    match fs::read_to_string(path) {
         Ok(s) => s,
         Err(err) => {
-            eprintln!("failed to read file: {}", err.to_string());
-            process::exit(1);
+            match err.kind() {
+                io::ErrorKind::NotFound => {
+                    eprintln!("no such file: {}", path);
+                    process::exit(EXIT_NOT_FOUND);
+                }
+                io::ErrorKind::PermissionDenied => eprintln!("permission denied: {}", path),
+                _ => eprintln!("failed to read {}: {}", path, err),
+            }
+            process::exit(EXIT_CANNOT_RUN);
         }
+    }
+}
+
+/// Runs a script with `argv` as `process.argv`, exiting if it fails or
+/// calls `process.exit`. Like a shell, a script that ends with a command
+/// exits with the command's status.
+fn run(src: &str, argv: Vec<String>, options: &repl::Options) {
+    if let Err(e) = commands::install_interrupt_handler() {
+        eprintln!("{}", e);
+    }
+
+    let mut evaluator = ASTEvaluator::new(argv);
+    evaluator.set_dry_run(options.dry_run);
+    evaluator.set_trace(options.trace);
+    evaluator.set_vm(options.vm);
+    repl::set_login_vars(&mut evaluator, options);
+    let declarations = match options.rc {
+        true => load_rc(&mut evaluator),
+        false => Declarations::default(),
     };
+    if let Some(status) = evaluator.exit_status() {
+        evaluator.run_exit_trap();
+        process::exit(evaluator.exit_status().unwrap_or(status));
+    }
 
-    let ast = match Parser::new(&src).parse() {
-        Ok(ast) => ast,
-        Err(e) => {
-            eprintln!("{}", e.to_string());
-            process::exit(1);
+    // each statement is parsed once those before it have run, so a PATH
+    // they set finds the commands after them
+    let mut parser = Parser::new(src)
+        .with_declarations(declarations)
+        .with_path(evaluator.environment().path());
+    let result = loop {
+        let statement = match parser.next_statement() {
+            Ok(Some(statement)) => statement,
+            Ok(None) => break Ok(()),
+            Err(e) => break Err((Error::from(e), EXIT_USAGE)),
+        };
+        if let Err(e) = evaluator.eval(statement) {
+            break Err((Error::from(e), EXIT_ERROR));
+        }
+        if evaluator.exit_status().is_some() {
+            break Ok(());
         }
+        parser.set_path(evaluator.environment().path());
     };
 
-    let mut evaluator = ASTEvaluator::new(argv);
-    if let Err(e) = evaluator.eval(ast) {
+    evaluator.run_exit_trap();
+    if let Err((e, status)) = result {
+        // argv[0] is the script, or -c for source given as an argument
+        eprintln!("{}", e.located(&options.argv[0], src));
+        process::exit(evaluator.exit_status().unwrap_or(status));
+    }
+
+    if let Some(code) = evaluator.exit_status() {
+        process::exit(code);
+    }
+    if evaluator.ended_with_command() {
+        process::exit(evaluator.last_status().unwrap_or(EXIT_ERROR));
+    }
+}
+
+fn enable_audit_log(path: &str) {
+    if let Err(e) = audit::log_to_file(Path::new(path)) {
         eprintln!("{}", e);
         process::exit(1);
     }
 }
 
-fn interpret() {
-    let mut evaluator = ASTEvaluator::new(vec![]);
-    loop {
-        print!("> ");
-        std::io::stdout().flush().unwrap();
+fn export_bash(filename: &str) {
+    let src = read_script(filename);
+    let script = Parser::new(&src)
+        .parse()
+        .map_err(|e| e.to_string())
+        .and_then(|ast| bash::export(&ast));
 
-        let mut buffer = String::new();
-        io::stdin().read_line(&mut buffer).unwrap();
-
-        let program = match Parser::new(&buffer).parse() {
-            Ok(prog) => prog,
-            Err(e) => {
-                eprintln!("{}", e);
-                continue;
-            }
-        };
+    match script {
+        Ok(script) => print!("{}", script),
+        Err(e) => {
+            eprintln!("{}", e);
+            process::exit(1);
+        }
+    }
+}
 
-        let lines = match evaluator.eval(program) {
-            Ok(r) => r,
-            Err(e) => {
-                eprintln!("{}", e);
-                continue;
-            }
-        };
+/// Prints the warnings for a script to stderr, each with the line it is
+/// about, exiting with 1 if there are any.
+fn lint_script(filename: &str) {
+    let src = read_script(filename);
+    let ast = match Parser::new(&src).parse() {
+        Ok(ast) => ast,
+        Err(e) => {
+            eprintln!("{}", e);
+            process::exit(EXIT_USAGE);
+        }
+    };
 
-        for option in lines {
-            if let Some(value) = option {
-                println!("{}", value);
-            }
+    let mut warnings = lint::lint(&ast);
+    warnings.sort_by_key(|warning| warning.span.map(|span| span.start));
+    for warning in &warnings {
+        match warning.span {
+            Some(span) => eprintln!("{}:{}: {}", filename, span.line_column(&src).0, warning),
+            None => eprintln!("{}: {}", filename, warning),
         }
     }
+    if !warnings.is_empty() {
+        process::exit(EXIT_ERROR);
+    }
 }
 
 fn main() {
-    let args: Vec<String> = env::args().collect();
+    let started = thread::Builder::new()
+        .name("main".to_string())
+        .stack_size(STACK_SIZE)
+        .spawn(start);
+    let finished = match started {
+        Ok(handle) => handle.join(),
+        Err(e) => {
+            eprintln!("failed to start: {}", e);
+            process::exit(EXIT_ERROR);
+        }
+    };
+    // the panic has been reported, exit as it would have on the main thread
+    if finished.is_err() {
+        process::exit(101);
+    }
+}
 
-    if args.len() >= 2 {
-        parse_file(args);
-    } else {
-        interpret()
+fn start() {
+    let cli = match cli::parse(env::args().skip(1).collect()) {
+        Ok(cli) => cli,
+        Err(e) => {
+            eprintln!("{}", e);
+            process::exit(EXIT_USAGE);
+        }
+    };
+
+    match cli.mode {
+        Mode::Help => println!("{}", cli::USAGE),
+        Mode::Version => println!("sod {}", env!("CARGO_PKG_VERSION")),
+        Mode::ExportBash(path) => export_bash(&path),
+        Mode::Lint(path) => lint_script(&path),
+        mode => {
+            let mut options = cli.options;
+            if cli.login {
+                match commands::import_login_env() {
+                    Ok(login) => options.login = login,
+                    Err(e) => eprintln!("{}", e),
+                }
+            }
+            if let Some(path) = &cli.audit_log {
+                enable_audit_log(path);
+            }
+            sandbox::set(cli.sandbox);
+
+            match mode {
+                Mode::Script => run(
+                    &read_script(&options.argv[0]),
+                    options.argv.clone(),
+                    &options,
+                ),
+                Mode::Command(src) => run(&src, options.argv.clone(), &options),
+                _ => repl::interpret(options),
+            }
+        }
     }
 }
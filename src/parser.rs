@@ -1,52 +1,277 @@
-use std::collections::HashSet;
+use std::cell::OnceCell;
+use std::collections::{HashMap, HashSet};
+use std::rc::Rc;
+use std::sync::Arc;
 
 use crate::{
     ast::ast::{
         self, ASTNode, BinaryExpression, BlockStatement, CallExpression, ForStatement,
         FunctionStatement, IfStatement, IndexExpression, MemberExpression, RangeExpression,
-        TemplateString, TemplateToken, VariableExpression,
+        TemplateString, TemplateToken, TrapStatement, VariableExpression, WithKind, WithStatement,
     },
     commands,
+    error::{ParseError, ParseErrorKind, Span},
+    intern::Name,
     lexer::{lexer, token::TokenType},
 };
 
-pub struct Parser {
-    lexer: lexer::Lexer,
-    curr_token: TokenType,
-    commands: HashSet<String>,
+/// How deeply expressions and blocks may be nested. Evaluating a syntax tree
+/// recurses into it, so this keeps even a debug build well inside its stack.
+pub const MAX_DEPTH: usize = 200;
+
+/// Whether a token begins a path to an executable, e.g. `./build.sh`,
+/// `/usr/local/bin/tool` or `~/bin/tool`.
+fn is_path_start(token: &TokenType) -> bool {
+    match token {
+        TokenType::Dot | TokenType::ForwardSlash => true,
+        TokenType::CatchAll(s) => s == "~",
+        _ => false,
+    }
+}
+
+/// Shorthand commands declared with `alias`, by name, each with the words it
+/// expands to.
+pub type Aliases = HashMap<String, Vec<ASTNode>>;
+
+/// The names a script has declared so far, which decide whether a word is
+/// read as a command.
+#[derive(Debug, Clone, Default)]
+pub struct Declarations {
+    pub aliases: Aliases,
+    /// Functions, which are called in place of commands of the same name.
+    pub functions: HashSet<String>,
+}
+
+/// How tightly a binary operator binds, higher binding tighter.
+pub(crate) fn precedence_of(operator: &TokenType) -> usize {
+    match operator {
+        TokenType::Carat => 5,
+        TokenType::Asterisk => 3,
+        TokenType::ForwardSlash => 3,
+        TokenType::Plus => 2,
+        TokenType::Minus => 2,
+        TokenType::DoubleEquals => 1,
+        TokenType::NotEquals => 1,
+        TokenType::GreaterThan => 1,
+        TokenType::LessThan => 1,
+        TokenType::Ge => 1,
+        TokenType::Le => 1,
+        TokenType::And => 1,
+        TokenType::Or => 1,
+        TokenType::PipeForward => 1,
+        _ => 0,
+    }
+}
+
+pub struct Parser<'a> {
+    lexer: lexer::Lexer<'a>,
+    curr_token: TokenType<'a>,
+    /// The commands on PATH, looked up the first time a word might be one.
+    commands: OnceCell<Arc<HashSet<String>>>,
+    /// The PATH to look commands up on, the interpreter's own if none.
+    path: Option<String>,
+    declarations: Declarations,
+    /// The first source the lexer couldn't read, reported in place of
+    /// whatever the parser made of it.
+    lexer_error: Option<ParseError>,
+    /// The brackets that have been opened and not yet closed, innermost
+    /// last, to name the one left open when the source ends.
+    open: Vec<(TokenType<'a>, Span)>,
+    /// How deeply the expression or block being parsed is nested.
+    depth: usize,
+    /// Where the last token moved past ends, which ends the statement
+    /// before the current token.
+    last_end: usize,
 }
 
-impl Parser {
-    pub fn new(src: &str) -> Parser {
-        let mut lexer = lexer::Lexer::new(src);
-        let curr_token = lexer.next_token();
-        Parser {
+impl<'a> Parser<'a> {
+    pub fn new(src: &'a str) -> Parser<'a> {
+        let lexer = lexer::Lexer::new(src);
+        let mut parser = Parser {
             lexer,
-            curr_token,
-            commands: commands::get_commands(),
+            curr_token: TokenType::EOF,
+            commands: OnceCell::new(),
+            path: None,
+            declarations: Declarations::default(),
+            lexer_error: None,
+            open: vec![],
+            depth: 0,
+            last_end: 0,
+        };
+        parser.advance_token();
+        parser
+    }
+
+    /// Starts with names declared earlier, such as on previous REPL lines.
+    pub fn with_declarations(mut self, declarations: Declarations) -> Self {
+        self.declarations = declarations;
+        self
+    }
+
+    /// Looks commands up on `path` rather than the interpreter's own PATH,
+    /// such as the PATH a script has set with `set_env`.
+    pub fn with_path(mut self, path: impl Into<String>) -> Self {
+        self.path = Some(path.into());
+        self
+    }
+
+    /// Looks later commands up on `path`, such as after a statement has
+    /// changed the PATH.
+    pub fn set_path(&mut self, path: impl Into<String>) {
+        let path = path.into();
+        if self.path.as_ref() != Some(&path) {
+            self.path = Some(path);
+            self.commands = OnceCell::new();
+        }
+    }
+
+    pub fn declarations(&self) -> &Declarations {
+        &self.declarations
+    }
+
+    fn set_token(&mut self, token: TokenType<'a>) {
+        if let TokenType::Error(e) = &token {
+            if self.lexer_error.is_none() {
+                self.lexer_error = Some(self.error(ParseErrorKind::InvalidToken, e.clone()));
+            }
         }
+        self.curr_token = token;
     }
 
     fn advance_token(&mut self) {
-        self.curr_token = self.lexer.next_token();
+        match self.curr_token {
+            TokenType::OpenBraces | TokenType::OpenParen | TokenType::OpenSqBracket => {
+                self.open.push((self.curr_token.clone(), self.lexer.span()));
+            }
+            TokenType::CloseBraces | TokenType::CloseParen | TokenType::CloseSqBracket => {
+                self.open.pop();
+            }
+            _ => (),
+        }
+        self.last_end = self.lexer.span().end;
+        let token = self.lexer.next_token();
+        self.set_token(token);
     }
 
     fn advance_cmd_token(&mut self) {
-        self.curr_token = self.lexer.next_cmd_token();
+        self.last_end = self.lexer.span().end;
+        let token = self.lexer.next_cmd_token();
+        self.set_token(token);
+    }
+
+    pub fn parse(&mut self) -> Result<ASTNode, ParseError> {
+        let program = self.program();
+        match self.lexer_error.take() {
+            Some(e) => Err(e),
+            None => program,
+        }
+    }
+
+    /// Parses the next statement as a program of its own, or `None` at the
+    /// end of the source. A script run a statement at a time finds the
+    /// commands on a PATH the statements before have set.
+    pub fn next_statement(&mut self) -> Result<Option<ASTNode>, ParseError> {
+        while self.curr_token == TokenType::Newline {
+            self.eat(&TokenType::Newline)?;
+        }
+        if self.curr_token == TokenType::EOF {
+            return match self.lexer_error.take() {
+                Some(e) => Err(e),
+                None => Ok(None),
+            };
+        }
+
+        let mut statement = BlockStatement::default();
+        let result = self
+            .spanned_statement(&mut statement)
+            .and_then(|_| match self.curr_token {
+                TokenType::EOF => Ok(()),
+                _ => self.eat(&TokenType::Newline).map(|_| ()),
+            });
+        match self.lexer_error.take() {
+            Some(e) => Err(e),
+            None => result.map(|_| Some(ASTNode::Program(statement))),
+        }
     }
 
-    pub fn parse(&mut self) -> Result<ASTNode, String> {
-        self.program()
+    /// An error at the current token.
+    fn error(&self, kind: ParseErrorKind, message: impl Into<String>) -> ParseError {
+        ParseError::new(kind, message, self.lexer.span())
     }
 
-    fn lookahead(&mut self, distance: usize) -> TokenType {
+    /// Goes one level deeper into the source, failing past `MAX_DEPTH` as
+    /// a tree that deep could overflow the stack when it is evaluated.
+    fn enter(&mut self) -> Result<(), ParseError> {
+        self.depth += 1;
+        if self.depth > MAX_DEPTH {
+            return Err(self.error(
+                ParseErrorKind::Invalid,
+                format!("expression nested more than {} levels deep", MAX_DEPTH),
+            ));
+        }
+        Ok(())
+    }
+
+    /// Parses something nested inside what is being parsed, such as the
+    /// expression in brackets or the body of a block.
+    fn deeper<T>(
+        &mut self,
+        parse: impl FnOnce(&mut Self) -> Result<T, ParseError>,
+    ) -> Result<T, ParseError> {
+        let depth = self.depth;
+        let result = self.enter().and_then(|_| parse(self));
+        self.depth = depth;
+        result
+    }
+
+    /// An error for a token that doesn't belong where it is. When the
+    /// source has ended, or a line has ended inside `(` or `[`, it names
+    /// the bracket left open.
+    fn unexpected(&self, message: String) -> ParseError {
+        let kind = match self.curr_token {
+            TokenType::EOF => ParseErrorKind::UnexpectedEof,
+            TokenType::Newline
+                if matches!(
+                    self.open.last(),
+                    Some((TokenType::OpenParen | TokenType::OpenSqBracket, _))
+                ) =>
+            {
+                ParseErrorKind::UnexpectedToken
+            }
+            _ => return self.error(ParseErrorKind::UnexpectedToken, message),
+        };
+
+        match self.open.last() {
+            Some((token, span)) => ParseError::new(
+                kind,
+                format!(
+                    "unclosed '{}' opened at line {}",
+                    token,
+                    self.lexer.line_of(span.start)
+                ),
+                *span,
+            ),
+            None => self.error(ParseErrorKind::UnexpectedEof, message),
+        }
+    }
+
+    /// Places an error from parsing source inside the current token, such as
+    /// a trap's handler, at the token.
+    fn nested(&self, e: ParseError) -> ParseError {
+        ParseError {
+            span: self.lexer.span(),
+            ..e
+        }
+    }
+
+    fn lookahead(&mut self, distance: usize) -> TokenType<'a> {
         match distance {
             0 => self.curr_token.clone(),
             _ => self.lexer.lookahead(distance),
         }
     }
 
-    fn eat_literal(&mut self) -> Result<ASTNode, String> {
+    fn eat_literal(&mut self) -> Result<ASTNode, ParseError> {
         let node = match &self.curr_token {
             TokenType::Decimal(dec) => ASTNode::Number(*dec),
             TokenType::Integer(int) => {
@@ -60,8 +285,9 @@ impl Parser {
                 }
             }
             TokenType::String(s) => ASTNode::String(s.to_string()),
-            TokenType::TemplateString(ts) => self.read_template_string(ts.as_str()),
-            _ => return Err(format!("unexpected token '{}'", self.curr_token)),
+            TokenType::TemplateString(ts) => self.read_template_string(&ts.clone()),
+            TokenType::Error(e) => return Err(self.error(ParseErrorKind::InvalidToken, e.clone())),
+            _ => return Err(self.unexpected(format!("unexpected token '{}'", self.curr_token))),
         };
 
         self.advance_token();
@@ -73,7 +299,7 @@ impl Parser {
         ASTNode::Boolean(b)
     }
 
-    fn eat_operator(&mut self) -> Result<TokenType, String> {
+    fn eat_operator(&mut self) -> Result<TokenType<'a>, ParseError> {
         match self.curr_token {
             TokenType::Plus
             | TokenType::Minus
@@ -88,34 +314,36 @@ impl Parser {
             | TokenType::NotEquals
             | TokenType::And
             | TokenType::Or => self.eat(&self.curr_token.clone()),
-            _ => Err(format!(
+            _ => Err(self.unexpected(format!(
                 "unexpected token '{}', expected an operator",
                 self.curr_token
-            )),
+            ))),
         }
     }
 
-    fn eat_identifier(&mut self) -> Result<String, String> {
+    fn eat_identifier(&mut self) -> Result<Name, ParseError> {
         let curr_token = self.curr_token.clone();
-        match &curr_token {
-            TokenType::Identifier(ident) | TokenType::EscapedIdentifier(ident) => {
-                self.eat(&curr_token)?;
-                Ok(ident.clone())
+        let ident = match &curr_token {
+            TokenType::Identifier(ident) => ident.clone(),
+            TokenType::EscapedIdentifier(ident) => self.lexer.intern(ident),
+            _ => {
+                return Err(self.unexpected(format!(
+                    "unexpected token '{}', expected an identifier",
+                    self.curr_token
+                )))
             }
-            _ => Err(format!(
-                "unexpected token '{}', expected an identifier",
-                self.curr_token
-            )),
-        }
+        };
+        self.eat(&curr_token)?;
+        Ok(ident)
     }
 
-    fn eat(&mut self, expected_token: &TokenType) -> Result<TokenType, String> {
+    fn eat(&mut self, expected_token: &TokenType) -> Result<TokenType<'a>, ParseError> {
         if self.curr_token == TokenType::EOF {
-            return Err(format!("EOF"));
+            return Err(self.unexpected("EOF".to_string()));
         }
 
         if expected_token != &self.curr_token {
-            return Err(format!("unexpected token '{}'", self.curr_token));
+            return Err(self.unexpected(format!("unexpected token '{}'", self.curr_token)));
         }
 
         let previous_token = self.curr_token.clone();
@@ -123,40 +351,21 @@ impl Parser {
         Ok(previous_token)
     }
 
-    fn get_precedence(&self, operator: &TokenType) -> usize {
-        match operator {
-            &TokenType::Carat => 5,
-            &TokenType::Asterisk => 3,
-            &TokenType::ForwardSlash => 3,
-            &TokenType::Plus => 2,
-            &TokenType::Minus => 2,
-            &TokenType::DoubleEquals => 1,
-            &TokenType::NotEquals => 1,
-            &TokenType::GreaterThan => 1,
-            &TokenType::LessThan => 1,
-            &TokenType::Ge => 1,
-            &TokenType::Le => 1,
-            &TokenType::And => 1,
-            &TokenType::Or => 1,
-            _ => 0,
-        }
-    }
-
     /**
      * Program
      *    = statement_list
      */
-    fn program(&mut self) -> Result<ASTNode, String> {
+    fn program(&mut self) -> Result<ASTNode, ParseError> {
         let statement_list = self.statement_list()?;
-        Ok(ASTNode::Program(Box::new(statement_list)))
+        Ok(ASTNode::Program(statement_list))
     }
 
     /**
      * statement_list
      *    = statement+
      */
-    fn statement_list(&mut self) -> Result<Vec<ASTNode>, String> {
-        let mut statements = vec![];
+    fn statement_list(&mut self) -> Result<BlockStatement, ParseError> {
+        let mut statements = BlockStatement::default();
 
         while self.curr_token != TokenType::EOF {
             if self.curr_token == TokenType::Newline {
@@ -164,7 +373,7 @@ impl Parser {
                 continue;
             }
 
-            statements.push(self.statement()?);
+            self.spanned_statement(&mut statements)?;
 
             if self.curr_token != TokenType::EOF {
                 self.eat(&TokenType::Newline)?;
@@ -174,19 +383,31 @@ impl Parser {
         Ok(statements)
     }
 
+    /// Parses a statement onto the end of `block`, along with where it is.
+    fn spanned_statement(&mut self, block: &mut BlockStatement) -> Result<(), ParseError> {
+        let start = self.lexer.span().start;
+        block.body.push(self.statement()?);
+        block.spans.push(Span::new(start, self.last_end.max(start)));
+        Ok(())
+    }
+
     /**
      * statement
      *   = variable_statement
      *   / function_expression
      *   / if_statement
+     *   / for_statement
+     *   / with_statement
      *   / expression
      */
-    fn statement(&mut self) -> Result<ASTNode, String> {
+    fn statement(&mut self) -> Result<ASTNode, ParseError> {
         if let TokenType::Identifier(ident) = &self.curr_token {
-            match ident.as_str() {
+            match &**ident {
                 "func" => return Ok(self.function_expression()?),
                 "if" => return self.if_statement(),
                 "for" => return self.for_statement(),
+                "with_env" => return self.with_statement(WithKind::Env),
+                "with_dir" => return self.with_statement(WithKind::Dir),
                 _ => (),
             };
         };
@@ -196,27 +417,66 @@ impl Parser {
 
     /**
      * for_statement
-     *   = "for" identifier range_expression block_statement
+     *   = "for" for_binding "in" iterable block_statement
      */
-    fn for_statement(&mut self) -> Result<ASTNode, String> {
-        self.eat(&TokenType::Identifier("for".to_string()))?;
-        let variable = self.eat_identifier()?;
-        self.eat(&TokenType::Identifier("in".to_string()))?;
+    fn for_statement(&mut self) -> Result<ASTNode, ParseError> {
+        self.eat(&TokenType::Identifier("for".into()))?;
+        let binding = self.for_binding()?;
+        self.eat(&TokenType::Identifier("in".into()))?;
         let iterable = self.iterable()?;
         let body = self.block_statement()?;
 
         Ok(ASTNode::ForStatement(ForStatement {
-            variable,
+            binding,
             iterable: Box::new(iterable),
             body: Box::new(body),
         }))
     }
 
+    /**
+     * with_statement
+     *   = ("with_env" | "with_dir") "(" expression ")" block_statement
+     */
+    fn with_statement(&mut self, kind: WithKind) -> Result<ASTNode, ParseError> {
+        self.eat_identifier()?;
+        self.eat(&TokenType::OpenParen)?;
+        let argument = self.expression(0)?;
+        self.eat(&TokenType::CloseParen)?;
+        let body = self.block_statement()?;
+
+        Ok(ASTNode::WithStatement(WithStatement {
+            kind,
+            argument: Box::new(argument),
+            body: Box::new(body),
+        }))
+    }
+
+    /**
+     * for_binding
+     *   = identifier
+     *   / "(" identifier ("," identifier)* ")"
+     */
+    fn for_binding(&mut self) -> Result<ast::ForBinding, ParseError> {
+        if self.curr_token != TokenType::OpenParen {
+            return Ok(ast::ForBinding::Identifier(self.eat_identifier()?));
+        }
+
+        self.eat(&TokenType::OpenParen)?;
+        let mut names = vec![self.eat_identifier()?];
+        while self.curr_token == TokenType::Comma {
+            self.eat(&TokenType::Comma)?;
+            names.push(self.eat_identifier()?);
+        }
+        self.eat(&TokenType::CloseParen)?;
+
+        Ok(ast::ForBinding::Destructure(names))
+    }
+
     /**
      * iterable
      *   = (range_expression | expression)
      */
-    fn iterable(&mut self) -> Result<ast::Iterable, String> {
+    fn iterable(&mut self) -> Result<ast::Iterable, ParseError> {
         let expression = self.expression(0)?;
         let iterable = match self.curr_token {
             TokenType::Dot => ast::Iterable::RangeExpression(self.range_expression(expression)?),
@@ -230,7 +490,7 @@ impl Parser {
      *  range_expression
      *   = start_expression ".." end_expression (".." increment_expression)?
      */
-    fn range_expression(&mut self, start: ASTNode) -> Result<RangeExpression, String> {
+    fn range_expression(&mut self, start: ASTNode) -> Result<RangeExpression, ParseError> {
         self.eat(&TokenType::Dot)?;
         self.eat(&TokenType::Dot)?;
         let (end, increment) = match self.expression(0)? {
@@ -249,8 +509,8 @@ impl Parser {
      * if_statement
      *   = "if" block_statement else_statement?
      */
-    fn if_statement(&mut self) -> Result<ASTNode, String> {
-        self.eat(&TokenType::Identifier("if".to_string()))?;
+    fn if_statement(&mut self) -> Result<ASTNode, ParseError> {
+        self.eat(&TokenType::Identifier("if".into()))?;
         let condition = self.expression(0)?;
         let consequence = self.block_statement()?;
         let alternative = match self.else_statement()? {
@@ -269,13 +529,13 @@ impl Parser {
      * else_statement
      *   = "else" (if_statement|block_statement)
      */
-    fn else_statement(&mut self) -> Result<Option<ASTNode>, String> {
-        if self.curr_token != TokenType::Identifier("else".to_string()) {
+    fn else_statement(&mut self) -> Result<Option<ASTNode>, ParseError> {
+        if self.curr_token != TokenType::Identifier("else".into()) {
             return Ok(None);
         }
         self.eat_identifier()?;
 
-        if self.curr_token == TokenType::Identifier("if".to_string()) {
+        if self.curr_token == TokenType::Identifier("if".into()) {
             return Ok(Some(self.if_statement()?));
         }
 
@@ -288,30 +548,34 @@ impl Parser {
      *         block_body
      *     "}"
      */
-    fn block_statement(&mut self) -> Result<ASTNode, String> {
-        self.eat(&TokenType::OpenBraces)?;
-        self.eat(&TokenType::Newline)?;
-        let body = self.block_body()?;
-        self.eat(&TokenType::CloseBraces)?;
-
-        Ok(ASTNode::BlockStatement(BlockStatement {
-            body: Box::new(body),
-        }))
+    fn block_statement(&mut self) -> Result<ASTNode, ParseError> {
+        self.deeper(|parser| {
+            parser.eat(&TokenType::OpenBraces)?;
+            parser.eat(&TokenType::Newline)?;
+            // functions declared in a block are gone once it ends
+            let functions = parser.declarations.functions.clone();
+            let block = parser.block_body();
+            parser.declarations.functions = functions;
+            let block = block?;
+            parser.eat(&TokenType::CloseBraces)?;
+
+            Ok(ASTNode::BlockStatement(block))
+        })
     }
 
     /**
      * block_body
      *    = statement+
      */
-    fn block_body(&mut self) -> Result<Vec<ASTNode>, String> {
-        let mut statements = vec![];
+    fn block_body(&mut self) -> Result<BlockStatement, ParseError> {
+        let mut statements = BlockStatement::default();
         while self.curr_token != TokenType::CloseBraces {
             if self.curr_token == TokenType::Newline {
                 self.eat(&TokenType::Newline)?;
                 continue;
             }
 
-            statements.push(self.statement()?);
+            self.spanned_statement(&mut statements)?;
             self.eat(&TokenType::Newline)?;
         }
 
@@ -322,21 +586,26 @@ impl Parser {
      * expression
      *  = prefix (infix)*
      */
-    fn expression(&mut self, precedence: usize) -> Result<ASTNode, String> {
-        let mut left = self.prefix()?;
-
-        while !self.curr_token.is_end_line() && precedence < self.get_precedence(&self.curr_token) {
-            left = self.infix(left, &self.curr_token.clone())?;
-        }
+    fn expression(&mut self, precedence: usize) -> Result<ASTNode, ParseError> {
+        self.deeper(|parser| {
+            let mut left = parser.prefix()?;
+
+            while !parser.curr_token.is_end_line() && precedence < precedence_of(&parser.curr_token)
+            {
+                // each operator nests what came before it a level deeper
+                parser.enter()?;
+                left = parser.infix(left, &parser.curr_token.clone())?;
+            }
 
-        Ok(left)
+            Ok(left)
+        })
     }
 
     /**
      * variable_statement
      *   = expression "=" expression
      */
-    fn variable_statement(&mut self, lhs: ASTNode) -> Result<ASTNode, String> {
+    fn variable_statement(&mut self, lhs: ASTNode) -> Result<ASTNode, ParseError> {
         self.eat(&TokenType::Equals)?;
         let expression = self.expression(0)?;
 
@@ -350,9 +619,11 @@ impl Parser {
      * function_expression
      *   = "func" identifier "(" function_expression_args ")" block_statement
      */
-    fn function_expression(&mut self) -> Result<ASTNode, String> {
-        self.eat(&TokenType::Identifier("func".to_string()))?;
-        let name = self.eat_identifier()?;
+    fn function_expression(&mut self) -> Result<ASTNode, ParseError> {
+        self.eat(&TokenType::Identifier("func".into()))?;
+        let name = self.eat_identifier()?.to_string();
+        self.declarations.aliases.remove(&name);
+        self.declarations.functions.insert(name.clone());
         self.eat(&TokenType::OpenParen)?;
         let func_args = self.function_expression_args()?;
         self.eat(&TokenType::CloseParen)?;
@@ -362,6 +633,7 @@ impl Parser {
             name,
             body: Box::new(body),
             args: func_args,
+            compiled: OnceCell::new(),
         }))
     }
 
@@ -369,7 +641,7 @@ impl Parser {
      * function_expression_args
      *   = (identifier,)*
      */
-    fn function_expression_args(&mut self) -> Result<Vec<String>, String> {
+    fn function_expression_args(&mut self) -> Result<Vec<Name>, ParseError> {
         if self.curr_token == TokenType::CloseParen {
             return Ok(vec![]);
         }
@@ -395,21 +667,49 @@ impl Parser {
      *   / command
      *   / symbol
      */
-    fn prefix(&mut self) -> Result<ASTNode, String> {
+    fn prefix(&mut self) -> Result<ASTNode, ParseError> {
         match &self.curr_token {
             TokenType::OpenParen => self.parenthesized_expression(),
             TokenType::Minus => self.unary_expression(),
-            TokenType::Identifier(ident) => self.parse_identifier(ident.to_owned()),
+            TokenType::Identifier(ident) => self.parse_identifier(ident.clone()),
             TokenType::OpenSqBracket => return self.list_literal(),
+            TokenType::OpenBraces => self.object_literal(),
+            TokenType::CommandString(src) => self.command_string(&src.clone()),
+            token if is_path_start(token) => self.command(token.to_string()),
             _ => return self.eat_literal(),
         }
     }
 
+    /// Whether a token begins a command, either a name found on PATH or a
+    /// path to an executable.
+    fn is_command_start(&self, token: &TokenType) -> bool {
+        match token {
+            TokenType::Identifier(name) => self.is_command_name(name),
+            TokenType::CommandString(_) => true,
+            token => is_path_start(token),
+        }
+    }
+
+    fn is_command_name(&self, name: &str) -> bool {
+        if self.declarations.functions.contains(name) {
+            return false;
+        }
+
+        self.declarations.aliases.contains_key(name) || self.commands().contains(name)
+    }
+
+    fn commands(&self) -> &HashSet<String> {
+        self.commands.get_or_init(|| match &self.path {
+            Some(path) => commands::commands_on(path),
+            None => commands::get_commands(),
+        })
+    }
+
     /**
      * list
      *   = [(expression),*]
      */
-    fn list_literal(&mut self) -> Result<ASTNode, String> {
+    fn list_literal(&mut self) -> Result<ASTNode, ParseError> {
         self.eat(&TokenType::OpenSqBracket)?;
 
         let mut items = vec![];
@@ -430,7 +730,58 @@ impl Parser {
         Ok(ASTNode::List(Box::new(items)))
     }
 
-    fn parse_identifier(&mut self, ident: String) -> Result<ASTNode, String> {
+    /**
+     * object
+     *   = {(object_key ":" expression),*}
+     */
+    fn object_literal(&mut self) -> Result<ASTNode, ParseError> {
+        self.eat(&TokenType::OpenBraces)?;
+
+        let mut entries = vec![];
+        if self.curr_token == TokenType::CloseBraces {
+            self.eat(&TokenType::CloseBraces)?;
+            return Ok(ASTNode::Object(Box::new(entries)));
+        }
+
+        loop {
+            let key = self.object_key()?;
+            self.eat(&TokenType::Colon)?;
+            entries.push((key, self.expression(0)?));
+            if self.curr_token == TokenType::CloseBraces {
+                self.eat(&TokenType::CloseBraces)?;
+                break;
+            }
+            self.eat(&TokenType::Comma)?;
+        }
+
+        Ok(ASTNode::Object(Box::new(entries)))
+    }
+
+    /**
+     * object_key
+     *   = identifier | string
+     */
+    fn object_key(&mut self) -> Result<String, ParseError> {
+        let key = match &self.curr_token {
+            TokenType::Identifier(s) => s.to_string(),
+            TokenType::String(s) | TokenType::TemplateString(s) => s.to_string(),
+            _ => {
+                return Err(self.unexpected(format!(
+                    "unexpected token '{}', expected an object key",
+                    self.curr_token
+                )))
+            }
+        };
+
+        self.advance_token();
+        Ok(key)
+    }
+
+    fn parse_identifier(&mut self, ident: Name) -> Result<ASTNode, ParseError> {
+        if self.is_env_prefix() {
+            return self.env_command(ident.to_string());
+        }
+
         match self.lookahead(1) {
             TokenType::OpenParen => {
                 self.advance_token();
@@ -440,10 +791,40 @@ impl Parser {
                 self.advance_token();
                 return self.member_expression(ASTNode::Identifier(ident));
             }
+            TokenType::OpenBraces if &*ident == "time" => return self.time_expression(),
+            TokenType::Identifier(_)
+                if &*ident == "alias" && self.lookahead(2) == TokenType::Equals =>
+            {
+                return self.alias_statement();
+            }
+            TokenType::String(_) | TokenType::TemplateString(_) if &*ident == "trap" => {
+                return self.trap_statement();
+            }
+            // a function named like a command runs when used on its own, as
+            // the command would have
+            TokenType::EOF | TokenType::Newline | TokenType::CloseBraces
+                if self.declarations.functions.contains(&*ident)
+                    && self.commands().contains(&*ident) =>
+            {
+                self.advance_token();
+                return Ok(ASTNode::CallExpression(CallExpression {
+                    base: Box::new(ASTNode::Identifier(ident)),
+                    args: vec![],
+                }));
+            }
+            next if &*ident == "capture" && self.is_command_start(&next) => {
+                return self.capture_expression();
+            }
+            next if &*ident == "spawn" && self.is_command_start(&next) => {
+                return self.spawn_expression();
+            }
+            next if &*ident == "interactive" && self.is_command_start(&next) => {
+                return self.interactive_expression();
+            }
             _ => (),
         };
 
-        let node = match ident.as_str() {
+        let node = match &*ident {
             "return" => self.return_expression()?,
             "true" => self.eat_bool(true),
             "false" => self.eat_bool(false),
@@ -451,7 +832,7 @@ impl Parser {
                 self.eat(&TokenType::Identifier(ident))?;
                 ASTNode::None
             }
-            s if self.commands.contains(s) => self.command(ident),
+            s if self.is_command_name(s) => self.command(ident.to_string())?,
             _ => {
                 let node = ASTNode::Identifier(self.eat_identifier()?);
                 if self.curr_token == TokenType::Equals {
@@ -466,31 +847,81 @@ impl Parser {
     }
 
     /**
-     * identifier member_prefix_expression
+     * time_expression
+     *   = "time" block_statement
      */
-    fn member_expression(&mut self, base: ASTNode) -> Result<ASTNode, String> {
-        let mut base = base;
-        loop {
-            let (new_base, more) = self.member_prefix_expression(base)?;
-            base = new_base;
-            if !more {
-                break;
-            }
+    fn time_expression(&mut self) -> Result<ASTNode, ParseError> {
+        self.eat_identifier()?;
+        let body = self.block_statement()?;
+        Ok(ASTNode::TimeExpression(Box::new(body)))
+    }
+
+    /**
+     * capture_expression
+     *   = "capture" command
+     */
+    fn capture_expression(&mut self) -> Result<ASTNode, ParseError> {
+        self.advance_token();
+        match self.prefixed_command("capture")? {
+            ASTNode::Command(tokens) => Ok(ASTNode::Capture(tokens)),
+            node => Ok(node),
         }
+    }
 
-        // this could be parsed better
-        if self.curr_token == TokenType::Equals {
-            self.variable_statement(base)
-        } else {
-            Ok(base)
+    /**
+     * spawn_expression
+     *   = "spawn" command
+     */
+    fn spawn_expression(&mut self) -> Result<ASTNode, ParseError> {
+        self.advance_token();
+        match self.prefixed_command("spawn")? {
+            ASTNode::Command(tokens) => Ok(ASTNode::Spawn(tokens)),
+            node => Ok(node),
+        }
+    }
+
+    /**
+     * interactive_expression
+     *   = "interactive" command
+     */
+    fn interactive_expression(&mut self) -> Result<ASTNode, ParseError> {
+        self.advance_token();
+        match self.prefixed_command("interactive")? {
+            ASTNode::Command(tokens) => Ok(ASTNode::Interactive(tokens)),
+            node => Ok(node),
         }
     }
 
+    /**
+     * identifier member_prefix_expression
+     */
+    fn member_expression(&mut self, base: ASTNode) -> Result<ASTNode, ParseError> {
+        self.deeper(|parser| {
+            let mut base = base;
+            loop {
+                // as with operators, each member nests the base a level deeper
+                parser.enter()?;
+                let (new_base, more) = parser.member_prefix_expression(base)?;
+                base = new_base;
+                if !more {
+                    break;
+                }
+            }
+
+            // this could be parsed better
+            if parser.curr_token == TokenType::Equals {
+                parser.variable_statement(base)
+            } else {
+                Ok(base)
+            }
+        })
+    }
+
     /**
      * member_prefix_expression =
      *    member_expression | index_expression | call_expression
      */
-    fn member_prefix_expression(&mut self, base: ASTNode) -> Result<(ASTNode, bool), String> {
+    fn member_prefix_expression(&mut self, base: ASTNode) -> Result<(ASTNode, bool), ParseError> {
         let expression = match &self.curr_token {
             &TokenType::Dot => {
                 self.eat(&TokenType::Dot)?;
@@ -523,8 +954,121 @@ impl Parser {
      * command
      * = command (node)*
      */
-    fn command(&mut self, cmd: String) -> ASTNode {
-        let mut tokens = vec![ASTNode::String(cmd)];
+    fn command(&mut self, cmd: String) -> Result<ASTNode, ParseError> {
+        match self.declarations.aliases.get(&cmd) {
+            Some(words) => {
+                let words = words.clone();
+                self.alias_command(words)
+            }
+            None => self.command_from(ASTNode::String(cmd)),
+        }
+    }
+
+    /// Reads the command that follows a keyword or prefix such as `capture`.
+    fn prefixed_command(&mut self, prefix: &str) -> Result<ASTNode, ParseError> {
+        match self.curr_token.clone() {
+            TokenType::CommandString(src) => self.command_string(&src),
+            token if self.is_command_start(&token) => self.command(token.to_string()),
+            token => Err(self.unexpected(format!(
+                "expected a command after {}, found '{}'",
+                prefix, token
+            ))),
+        }
+    }
+
+    /**
+     * alias_statement
+     *   = "alias" identifier "=" command
+     */
+    fn alias_statement(&mut self) -> Result<ASTNode, ParseError> {
+        self.eat(&TokenType::Identifier("alias".into()))?;
+        let name = self.eat_identifier()?.to_string();
+        if self.curr_token != TokenType::Equals {
+            return Err(self.unexpected(format!(
+                "unexpected token '{}', expected '='",
+                self.curr_token
+            )));
+        }
+        self.advance_token();
+
+        let command = self.prefixed_command(&format!("alias {} =", name))?;
+
+        match command {
+            ASTNode::Command(cmd) if cmd.stages.is_empty() && cmd.redirect.is_none() => {
+                self.declarations.functions.remove(&name);
+                self.declarations.aliases.insert(name.clone(), cmd.tokens);
+                Ok(ASTNode::AliasStatement(name))
+            }
+            _ => Err(self.error(
+                ParseErrorKind::Invalid,
+                format!("alias {} must be a single command", name),
+            )),
+        }
+    }
+
+    /**
+     * trap_statement
+     *   = "trap" string identifier+
+     */
+    fn trap_statement(&mut self) -> Result<ASTNode, ParseError> {
+        self.eat(&TokenType::Identifier("trap".into()))?;
+        let src = match &self.curr_token {
+            TokenType::String(s) | TokenType::TemplateString(s) => s.to_string(),
+            token => {
+                return Err(
+                    self.unexpected(format!("expected a handler after trap, found '{}'", token))
+                )
+            }
+        };
+        let mut handler = Parser::new(&src).with_declarations(self.declarations.clone());
+        handler.path = self.path.clone();
+        let handler = handler.parse().map_err(|e| self.nested(e))?;
+        self.advance_token();
+
+        let mut signals = vec![];
+        while let TokenType::Identifier(name) = &self.curr_token {
+            let name = name.to_uppercase();
+            if name != "EXIT" && commands::Signal::from_name(&name).is_none() {
+                return Err(self.error(
+                    ParseErrorKind::Invalid,
+                    format!("trap can't handle signal '{}'", name),
+                ));
+            }
+            signals.push(name);
+            self.advance_token();
+        }
+
+        if signals.is_empty() {
+            return Err(self.unexpected(format!(
+                "expected a signal for trap, found '{}'",
+                self.curr_token
+            )));
+        }
+
+        Ok(ASTNode::TrapStatement(TrapStatement {
+            handler: Box::new(handler),
+            signals,
+        }))
+    }
+
+    /// Reads a command that starts with an alias, with the alias replaced by
+    /// the words it stands for.
+    fn alias_command(&mut self, words: Vec<ASTNode>) -> Result<ASTNode, ParseError> {
+        let mut command = match self.command_from(ASTNode::String(String::new()))? {
+            ASTNode::Command(command) => command,
+            node => return Ok(node),
+        };
+
+        command.tokens.splice(..1, words);
+        Ok(ASTNode::Command(command))
+    }
+
+    /// Reads the rest of a command line following its first word.
+    fn command_from(&mut self, first: ASTNode) -> Result<ASTNode, ParseError> {
+        let mut tokens = vec![first];
+        let mut first_tokens = None;
+        let mut stages = vec![];
+        let mut redirect = None;
 
         let mut prev = self.curr_token.clone();
         self.advance_cmd_token();
@@ -534,28 +1078,262 @@ impl Parser {
                 break;
             }
 
-            let node = match &self.curr_token {
-                TokenType::EscapedIdentifier(ident) => ASTNode::Identifier(ident.to_string()),
-                TokenType::TemplateString(s) => self.read_template_string(s.as_str()),
-                t => ASTNode::String(t.to_string()),
-            };
+            if self.curr_token == TokenType::PipeForward {
+                if prev == TokenType::Whitespace {
+                    tokens.pop();
+                }
+                break;
+            }
+
+            if prev == TokenType::Whitespace && self.is_function_stage() {
+                tokens.pop();
+                let segment = std::mem::take(&mut tokens);
+                match first_tokens {
+                    None => first_tokens = Some(segment),
+                    Some(_) if !segment.is_empty() => stages.push(ast::PipeStage::Command(segment)),
+                    Some(_) => (),
+                }
+
+                stages.push(ast::PipeStage::Function(self.function_stage()?));
+                prev = TokenType::Whitespace;
+                continue;
+            }
 
+            // the `|` between a function and the command that follows it
+            if tokens.is_empty() && self.curr_token == TokenType::CatchAll("|".into()) {
+                self.advance_token();
+                if self.curr_token.is_end_line() {
+                    return Err(
+                        self.unexpected("expected a command or function after |".to_string())
+                    );
+                }
+                continue;
+            }
+
+            // `2>` and `>&2` are left to the shell, as is a redirect that
+            // another command follows, as in `cmd > file; other`
+            if self.curr_token == TokenType::GreaterThan
+                && prev == TokenType::Whitespace
+                && redirect.is_none()
+                && !self.lexer.next_byte_is(b'&')
+                && self.lexer.rest_is_one_command()
+            {
+                tokens.pop();
+                redirect = Some(self.redirect()?);
+                prev = TokenType::Whitespace;
+                continue;
+            }
+
+            let node = self.command_token()?;
             prev = self.curr_token.clone();
             self.advance_cmd_token();
             tokens.push(node);
         }
 
-        ASTNode::Command(Box::new(tokens))
+        let tokens = match first_tokens {
+            Some(first_tokens) => {
+                if !tokens.is_empty() {
+                    stages.push(ast::PipeStage::Command(tokens));
+                }
+                first_tokens
+            }
+            None => tokens,
+        };
+
+        Ok(ASTNode::Command(Box::new(ast::Command {
+            envs: vec![],
+            tokens,
+            redirect,
+            stages,
+        })))
+    }
+
+    /// Whether the current `|` pipes into a sod function rather than a
+    /// command, which is the case when the next word alone isn't a command.
+    fn is_function_stage(&mut self) -> bool {
+        if self.curr_token != TokenType::CatchAll("|".into()) {
+            return false;
+        }
+
+        match self.lexer.lookahead(1) {
+            TokenType::Identifier(name) if !self.is_command_name(&name) => {
+                let next = self.lexer.lookahead(2);
+                next.is_end_line()
+                    || next == TokenType::GreaterThan
+                    || next == TokenType::CatchAll("|".into())
+            }
+            _ => false,
+        }
+    }
+
+    /**
+     * function_stage
+     *   = "|" identifier
+     */
+    fn function_stage(&mut self) -> Result<String, ParseError> {
+        self.advance_token();
+        Ok(self.eat_identifier()?.to_string())
+    }
+
+    /**
+     * redirect
+     *   = (">" / ">>") word
+     */
+    fn redirect(&mut self) -> Result<ast::Redirect, ParseError> {
+        self.advance_cmd_token();
+        let append = self.curr_token == TokenType::GreaterThan;
+        if append {
+            self.advance_cmd_token();
+        }
+
+        while self.curr_token == TokenType::Whitespace {
+            self.advance_cmd_token();
+        }
+
+        let target = self.word()?;
+        if target.is_empty() {
+            return Err(self.unexpected(format!(
+                "expected a file after {}, found '{}'",
+                if append { ">>" } else { ">" },
+                self.curr_token
+            )));
+        }
+
+        Ok(ast::Redirect { append, target })
+    }
+
+    /// Reads the tokens up to the next whitespace, with quoted strings taken
+    /// as their contents.
+    fn word(&mut self) -> Result<Vec<ASTNode>, ParseError> {
+        let mut word = vec![];
+        while self.curr_token != TokenType::Whitespace
+            && self.curr_token != TokenType::PipeForward
+            && !self.curr_token.is_end_line()
+        {
+            let node = match &self.curr_token {
+                TokenType::String(s) => ASTNode::String(s.to_string()),
+                _ => self.command_token()?,
+            };
+            word.push(node);
+            self.advance_cmd_token();
+        }
+
+        Ok(word)
+    }
+
+    /**
+     * command_token
+     *   = "$" identifier "!"? / "${" expression "}" / template_string / word
+     */
+    fn command_token(&self) -> Result<ASTNode, ParseError> {
+        let node = match &self.curr_token {
+            TokenType::EscapedIdentifier(ident) => ASTNode::Identifier(self.lexer.intern(ident)),
+            TokenType::RawIdentifier(ident) => ASTNode::CallExpression(CallExpression {
+                base: Box::new(ASTNode::Identifier(self.lexer.intern("raw"))),
+                args: vec![ASTNode::Identifier(self.lexer.intern(ident))],
+            }),
+            TokenType::EscapedExpression(src) => {
+                let mut parser = Parser::new(src);
+                parser.path = self.path.clone();
+                let expression = parser.expression(0).map_err(|e| self.nested(e))?;
+                if parser.curr_token != TokenType::EOF {
+                    return Err(self.error(
+                        ParseErrorKind::UnexpectedToken,
+                        format!("unexpected token '{}' in ${{{}}}", parser.curr_token, src),
+                    ));
+                }
+                expression
+            }
+            TokenType::TemplateString(s) => self.read_template_string(s),
+            t => ASTNode::String(t.to_string()),
+        };
+
+        Ok(node)
+    }
+
+    /// Whether the current identifier starts `NAME=value cmd`, rather than
+    /// being a variable assigned without spaces.
+    fn is_env_prefix(&mut self) -> bool {
+        match self.lexer.word_after_assignments() {
+            Some(word) => word.starts_with(['.', '/', '~', '`']) || self.is_command_name(word),
+            None => false,
+        }
+    }
+
+    /**
+     * env_command
+     *   = (identifier "=" word)+ command
+     */
+    fn env_command(&mut self, first: String) -> Result<ASTNode, ParseError> {
+        let mut envs = vec![];
+        let mut name = first;
+        loop {
+            // the identifier, then "="
+            self.advance_cmd_token();
+            self.advance_cmd_token();
+
+            let value = self.word()?;
+            envs.push((name, value));
+            self.advance_token();
+
+            match &self.curr_token {
+                TokenType::Identifier(next) if self.lexer.next_byte_is(b'=') => {
+                    name = next.to_string()
+                }
+                _ => break,
+            }
+        }
+
+        let command = self.prefixed_command(&format!("{}=", envs[envs.len() - 1].0))?;
+
+        match command {
+            ASTNode::Command(mut cmd) => {
+                cmd.envs = envs;
+                Ok(ASTNode::Command(cmd))
+            }
+            node => Ok(node),
+        }
+    }
+
+    /**
+     * command_string
+     *   = "`" command "`"
+     *
+     * Backticks run their contents as a command whether or not it was on PATH
+     * when the script was parsed.
+     */
+    fn command_string(&mut self, src: &str) -> Result<ASTNode, ParseError> {
+        let mut parser = Parser::new(src);
+        if parser.curr_token.is_end_line() {
+            return Err(self.error(
+                ParseErrorKind::UnexpectedToken,
+                "expected a command between backticks",
+            ));
+        }
+
+        let command = parser
+            .command_token()
+            .and_then(|first| parser.command_from(first));
+        if let Some(e) = parser.lexer_error.take() {
+            return Err(self.nested(e));
+        }
+        let command = command.map_err(|e| self.nested(e))?;
+        self.advance_token();
+        Ok(command)
     }
 
     /**
      * infix
      *    = ("+" / "-" / "*" / "/" / "^" / "==" / ">" / "<" / ">=" / "<=" / "&&" / "||") expression
      */
-    fn infix(&mut self, left: ASTNode, operator: &TokenType) -> Result<ASTNode, String> {
+    fn infix(&mut self, left: ASTNode, operator: &TokenType<'a>) -> Result<ASTNode, ParseError> {
+        if operator == &TokenType::PipeForward {
+            return self.pipe_expression(left);
+        }
+
         self.eat_operator()?;
 
-        let operator_precedence = self.get_precedence(operator);
+        let operator_precedence = precedence_of(operator);
         let precedence = if operator == &TokenType::Carat {
             operator_precedence - 1
         } else {
@@ -564,16 +1342,47 @@ impl Parser {
 
         Ok(ASTNode::BinaryExpression(BinaryExpression {
             left: Box::new(left),
-            operator: operator.clone(),
+            operator: operator.clone().into_owned(),
             right: Box::new(self.expression(precedence)?),
         }))
     }
 
+    /**
+     * pipe_expression
+     *    = expression "|>" identifier (member_prefix_expression)*
+     *
+     * The piped value becomes the first argument of the call, commands on the
+     * left are captured rather than echoed.
+     */
+    fn pipe_expression(&mut self, left: ASTNode) -> Result<ASTNode, ParseError> {
+        self.eat(&TokenType::PipeForward)?;
+        let name = self.eat_identifier()?;
+        let target = self.member_expression(ASTNode::Identifier(name))?;
+
+        let value = match left {
+            ASTNode::Command(tokens) => ASTNode::Capture(tokens),
+            node => node,
+        };
+
+        let call = match target {
+            ASTNode::CallExpression(mut ce) => {
+                ce.args.insert(0, value);
+                ce
+            }
+            base => CallExpression {
+                base: Box::new(base),
+                args: vec![value],
+            },
+        };
+
+        Ok(ASTNode::CallExpression(call))
+    }
+
     /**
      * parenthesized_expression
      *    = "(" expression ")"
      */
-    fn parenthesized_expression(&mut self) -> Result<ASTNode, String> {
+    fn parenthesized_expression(&mut self) -> Result<ASTNode, ParseError> {
         self.eat(&TokenType::OpenParen)?;
         let expression = self.expression(0)?;
         self.eat(&TokenType::CloseParen)?;
@@ -584,8 +1393,8 @@ impl Parser {
      * return_expression
      *    = "return" expression
      */
-    fn return_expression(&mut self) -> Result<ASTNode, String> {
-        self.eat(&TokenType::Identifier("return".to_string()))?;
+    fn return_expression(&mut self) -> Result<ASTNode, ParseError> {
+        self.eat(&TokenType::Identifier("return".into()))?;
         let expression = self.expression(0)?;
         Ok(ASTNode::ReturnStatement(Box::new(expression)))
     }
@@ -594,7 +1403,7 @@ impl Parser {
      * call_expression
      *    = identifier "(" call_expression_args ")"
      */
-    fn call_expression(&mut self, base: ASTNode) -> Result<ASTNode, String> {
+    fn call_expression(&mut self, base: ASTNode) -> Result<ASTNode, ParseError> {
         self.eat(&TokenType::OpenParen)?;
         let args = self.call_expression_args()?;
         self.eat(&TokenType::CloseParen)?;
@@ -615,7 +1424,7 @@ impl Parser {
      * call_expression_args
      *   = "(" (expression,)* ")"
      */
-    fn call_expression_args(&mut self) -> Result<Vec<ASTNode>, String> {
+    fn call_expression_args(&mut self) -> Result<Vec<ASTNode>, ParseError> {
         if self.curr_token == TokenType::CloseParen {
             return Ok(vec![]);
         }
@@ -638,7 +1447,7 @@ impl Parser {
      * unary_expression
      *    = "-" expression
      */
-    fn unary_expression(&mut self) -> Result<ASTNode, String> {
+    fn unary_expression(&mut self) -> Result<ASTNode, ParseError> {
         self.eat(&TokenType::Minus)?;
         Ok(ASTNode::UnaryExpression(Box::new(self.expression(4)?)))
     }
@@ -646,25 +1455,25 @@ impl Parser {
     fn read_template_string(&self, value: &str) -> ASTNode {
         let mut tokens = vec![];
 
-        let mut tail = 0;
-        while tail < value.len() {
-            if value.chars().nth(tail).unwrap() == '$' {
-                tail += 1;
-                let head = tail;
-                while tail < value.len() && value.chars().nth(tail).unwrap() != ' ' {
-                    tail += 1;
-                }
-                if tail == head {
-                    tokens.push(TemplateToken::Literal("$".to_string()))
-                } else {
-                    tokens.push(TemplateToken::Expression(value[head..tail].to_string()))
+        let mut rest = value;
+        while !rest.is_empty() {
+            // '$' and ' ' are single bytes, so the text either side of them
+            // is always sliced on a character boundary
+            match rest.strip_prefix('$') {
+                Some(after) => {
+                    let end = after.find(' ').unwrap_or(after.len());
+                    if end == 0 {
+                        tokens.push(TemplateToken::Literal(Rc::from("$")))
+                    } else {
+                        tokens.push(TemplateToken::Expression(self.lexer.intern(&after[..end])))
+                    }
+                    rest = &after[end..];
                 }
-            } else {
-                let head = tail;
-                while tail < value.len() && value.chars().nth(tail).unwrap() != '$' {
-                    tail += 1;
+                None => {
+                    let end = rest.find('$').unwrap_or(rest.len());
+                    tokens.push(TemplateToken::Literal(Rc::from(&rest[..end])));
+                    rest = &rest[end..];
                 }
-                tokens.push(TemplateToken::Literal(value[head..tail].to_string()))
             }
         }
 
@@ -0,0 +1,255 @@
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process;
+
+use crate::commands::Signal;
+
+/// The operating system specific parts of finding and running commands.
+pub trait Platform: Sync {
+    /// The separator between directories in PATH.
+    fn path_separator(&self) -> char;
+
+    /// Whether a file found on PATH can be run.
+    fn is_executable(&self, file_path: &Path, metadata: &fs::Metadata) -> bool;
+
+    /// The names an executable file can be run by.
+    fn command_names(&self, filename: &str) -> Vec<String>;
+
+    /// The files in a PATH directory that a command name could refer to, in
+    /// the order they are tried.
+    fn candidates(&self, dir: &Path, name: &str) -> Vec<PathBuf>;
+
+    /// A process that runs a command line with the platform's shell.
+    fn shell(&self, cmd: &str) -> process::Command;
+
+    /// Quotes a word so the shell passes it to the command unchanged.
+    fn quote(&self, word: &str) -> String;
+
+    /// Starts a command in a process group of its own, so a signal passed
+    /// on to it reaches every process it starts as well.
+    fn own_group(&self, command: &mut process::Command);
+
+    /// The number of the signal that ended a process, if one did.
+    fn signal(&self, status: &process::ExitStatus) -> Option<i32>;
+
+    /// Whether a process ended because the pipe it wrote to was closed, as
+    /// `yes` does in `yes | head`.
+    fn broken_pipe(&self, status: &process::ExitStatus) -> bool;
+
+    /// Passes Ctrl-C on to a running command.
+    fn interrupt(&self, pid: u32);
+
+    /// Starts catching a signal, passing it to `commands::signal_received`
+    /// instead of ending the process.
+    fn catch(&self, signal: Signal) -> Result<(), String>;
+
+    /// Sends a signal to a running command.
+    fn forward(&self, pid: u32, signal: Signal);
+
+    /// The current user's home directory.
+    fn home_dir(&self) -> Option<PathBuf>;
+}
+
+/// Characters that no shell treats specially, so words made of them are
+/// left unquoted.
+fn is_safe_word(word: &str) -> bool {
+    let safe = |c: char| c.is_ascii_alphanumeric() || "-_./=:,+@%".contains(c);
+    !word.is_empty() && word.chars().all(safe)
+}
+
+#[cfg(unix)]
+pub struct Unix;
+
+#[cfg(unix)]
+impl Platform for Unix {
+    fn path_separator(&self) -> char {
+        ':'
+    }
+
+    fn is_executable(&self, _file_path: &Path, metadata: &fs::Metadata) -> bool {
+        use std::os::unix::fs::PermissionsExt;
+        metadata.permissions().mode() & 0o111 != 0
+    }
+
+    fn command_names(&self, filename: &str) -> Vec<String> {
+        vec![filename.to_string()]
+    }
+
+    fn candidates(&self, dir: &Path, name: &str) -> Vec<PathBuf> {
+        vec![dir.join(name)]
+    }
+
+    fn shell(&self, cmd: &str) -> process::Command {
+        let mut command = process::Command::new("sh");
+        command.arg("-c").arg(cmd);
+        command
+    }
+
+    fn quote(&self, word: &str) -> String {
+        if is_safe_word(word) {
+            return word.to_string();
+        }
+
+        format!("'{}'", word.replace('\'', "'\\''"))
+    }
+
+    fn own_group(&self, command: &mut process::Command) {
+        use std::os::unix::process::CommandExt;
+        command.process_group(0);
+    }
+
+    fn signal(&self, status: &process::ExitStatus) -> Option<i32> {
+        use std::os::unix::process::ExitStatusExt;
+        status.signal()
+    }
+
+    /// A command run by the shell that is ended by the signal leaves the
+    /// shell exiting with 128 plus its number.
+    fn broken_pipe(&self, status: &process::ExitStatus) -> bool {
+        self.signal(status) == Some(libc::SIGPIPE) || status.code() == Some(128 + libc::SIGPIPE)
+    }
+
+    fn interrupt(&self, pid: u32) {
+        self.forward(pid, Signal::Int);
+    }
+
+    fn catch(&self, signal: Signal) -> Result<(), String> {
+        extern "C" fn on_signal(number: libc::c_int) {
+            if let Some(signal) = Signal::from_number(number) {
+                crate::commands::signal_received(signal);
+            }
+        }
+
+        let handler = on_signal as extern "C" fn(libc::c_int) as libc::sighandler_t;
+        // SAFETY: the handler only stores to atomics and calls kill, both of
+        // which are safe inside a signal handler.
+        let previous = unsafe { libc::signal(signal.number(), handler) };
+        if previous == libc::SIG_ERR {
+            return Err(format!("failed to catch {}", signal.name()));
+        }
+        Ok(())
+    }
+
+    /// A command started in a group of its own is signalled along with
+    /// everything it started, such as the commands run by `sh -c`.
+    fn forward(&self, pid: u32, signal: Signal) {
+        // SAFETY: kill has no memory safety requirements, at worst the
+        // process has already exited and the call fails. A group's id is its
+        // first process's, so it can't belong to another command.
+        unsafe {
+            if libc::kill(-(pid as libc::pid_t), signal.number()) != 0 {
+                libc::kill(pid as libc::pid_t, signal.number());
+            }
+        }
+    }
+
+    fn home_dir(&self) -> Option<PathBuf> {
+        env::var_os("HOME")
+            .filter(|home| !home.is_empty())
+            .map(PathBuf::from)
+    }
+}
+
+/// Not built by the tests, which only run on unix, so changes here should be
+/// checked with `cargo check --target x86_64-pc-windows-gnu`.
+#[cfg(windows)]
+pub struct Windows;
+
+#[cfg(windows)]
+impl Windows {
+    /// The extensions that make a file executable, from PATHEXT, e.g. `.exe`.
+    fn extensions(&self) -> Vec<String> {
+        env::var("PATHEXT")
+            .unwrap_or_else(|_| ".COM;.EXE;.BAT;.CMD".to_string())
+            .split(';')
+            .filter(|ext| !ext.is_empty())
+            .map(|ext| ext.to_lowercase())
+            .collect()
+    }
+}
+
+#[cfg(windows)]
+impl Platform for Windows {
+    fn path_separator(&self) -> char {
+        ';'
+    }
+
+    fn is_executable(&self, file_path: &Path, _metadata: &fs::Metadata) -> bool {
+        let extension = match file_path.extension() {
+            Some(ext) => format!(".{}", ext.to_string_lossy().to_lowercase()),
+            None => return false,
+        };
+
+        self.extensions().contains(&extension)
+    }
+
+    /// Executables can be run with or without their extension, so `git.exe`
+    /// is found as both `git.exe` and `git`.
+    fn command_names(&self, filename: &str) -> Vec<String> {
+        let filename = filename.to_lowercase();
+        let mut names = vec![filename.clone()];
+        if let Some((stem, _)) = filename.rsplit_once('.') {
+            names.push(stem.to_string());
+        }
+        names
+    }
+
+    fn candidates(&self, dir: &Path, name: &str) -> Vec<PathBuf> {
+        let mut candidates = vec![dir.join(name)];
+        for ext in self.extensions() {
+            candidates.push(dir.join(format!("{}{}", name, ext)));
+        }
+        candidates
+    }
+
+    fn shell(&self, cmd: &str) -> process::Command {
+        let shell = env::var("COMSPEC").unwrap_or_else(|_| "cmd.exe".to_string());
+        let mut command = process::Command::new(shell);
+        command.arg("/C").arg(cmd);
+        command
+    }
+
+    fn quote(&self, word: &str) -> String {
+        if is_safe_word(word) {
+            return word.to_string();
+        }
+
+        format!("\"{}\"", word.replace('"', "\"\""))
+    }
+
+    fn own_group(&self, _command: &mut process::Command) {}
+
+    fn signal(&self, _status: &process::ExitStatus) -> Option<i32> {
+        None
+    }
+
+    /// Writing to a closed pipe is an error the process handles itself.
+    fn broken_pipe(&self, _status: &process::ExitStatus) -> bool {
+        false
+    }
+
+    /// Ctrl-C is delivered to every process attached to the console, so the
+    /// command has already received it.
+    fn interrupt(&self, _pid: u32) {}
+
+    fn catch(&self, signal: Signal) -> Result<(), String> {
+        Err(format!("{} can't be trapped on Windows", signal.name()))
+    }
+
+    fn forward(&self, _pid: u32, _signal: Signal) {}
+
+    fn home_dir(&self) -> Option<PathBuf> {
+        env::var_os("USERPROFILE")
+            .filter(|home| !home.is_empty())
+            .map(PathBuf::from)
+    }
+}
+
+/// The platform sod was built for.
+pub fn current() -> &'static dyn Platform {
+    #[cfg(unix)]
+    return &Unix;
+    #[cfg(windows)]
+    return &Windows;
+}
@@ -0,0 +1,385 @@
+use rustyline::error::ReadlineError;
+use rustyline::{Config, DefaultEditor};
+use sod::ast::evaluator::ASTEvaluator;
+use sod::commands::{self, LoginEnv, Signal};
+use sod::error::Error;
+use sod::parser::{Declarations, Parser};
+use sod::platform;
+use sod::symbol::pretty::pretty;
+use sod::symbol::symbol::Symbol;
+use std::collections::HashSet;
+use std::env;
+use std::fs;
+use std::io::{self, IsTerminal};
+use std::path::{Path, PathBuf};
+use std::process;
+
+const DEFAULT_HISTORY_SIZE: usize = 1000;
+
+/// How much of a value `:vars` shows before cutting it short.
+const PREVIEW_LENGTH: usize = 60;
+
+const HELP: &str = "\
+:help            shows this help
+:vars            lists the variables that have been set
+:funcs           lists the functions that have been defined
+:load <file>     runs a script in this session, keeping what it defines
+:reset           forgets every variable, function and alias defined
+:rehash          looks for commands on PATH again, after installing one
+:clear           clears the screen
+:quit [status]   exits, with status 0 unless one is given";
+
+/// The file REPL history is kept in, `SOD_HISTORY` or `~/.sod_history` by
+/// default. Setting `SOD_HISTORY` to an empty string keeps history for the
+/// session only.
+fn history_path() -> Option<PathBuf> {
+    match env::var_os("SOD_HISTORY") {
+        Some(path) if path.is_empty() => None,
+        Some(path) => Some(PathBuf::from(path)),
+        None => platform::current()
+            .home_dir()
+            .map(|home| home.join(".sod_history")),
+    }
+}
+
+/// The startup file, `SOD_RC` or `~/.sodrc` by default, if there is one.
+/// Setting `SOD_RC` to an empty string skips it.
+pub fn rc_path() -> Option<PathBuf> {
+    let path = match env::var_os("SOD_RC") {
+        Some(path) if path.is_empty() => None,
+        Some(path) => Some(PathBuf::from(path)),
+        None => platform::current()
+            .home_dir()
+            .map(|home| home.join(".sodrc")),
+    };
+    path.filter(|path| path.exists())
+}
+
+/// A line editor that remembers up to `SOD_HISTORY_SIZE` lines.
+fn new_editor() -> Result<DefaultEditor, ReadlineError> {
+    let size = match env::var("SOD_HISTORY_SIZE") {
+        Ok(size) => size.trim().parse().unwrap_or_else(|_| {
+            eprintln!("SOD_HISTORY_SIZE must be a number, found '{}'", size);
+            DEFAULT_HISTORY_SIZE
+        }),
+        Err(_) => DEFAULT_HISTORY_SIZE,
+    };
+
+    let config = Config::builder().max_history_size(size)?.build();
+    DefaultEditor::with_config(config)
+}
+
+/// A value as the REPL prints it, put back on one line and shortened.
+fn preview(symbol: &Symbol) -> String {
+    let text = pretty(symbol, false)
+        .lines()
+        .map(str::trim)
+        .collect::<Vec<_>>()
+        .join(" ");
+    match text.char_indices().nth(PREVIEW_LENGTH) {
+        Some((end, _)) => format!("{}...", &text[..end]),
+        None => text,
+    }
+}
+
+fn new_evaluator(options: &Options) -> ASTEvaluator {
+    let mut evaluator = ASTEvaluator::new(options.argv.clone());
+    evaluator.set_interactive(true);
+    evaluator.set_dry_run(options.dry_run);
+    evaluator.set_trace(options.trace);
+    evaluator.set_vm(options.vm);
+    set_login_vars(&mut evaluator, options);
+    evaluator
+}
+
+/// Starts the script's commands with the variables imported from the login
+/// shell, leaving the interpreter's own environment as it is.
+pub fn set_login_vars(evaluator: &mut ASTEvaluator, options: &Options) {
+    for (name, value) in &options.login.vars {
+        evaluator
+            .environment_mut()
+            .set_var(name.clone(), Some(value.clone()));
+    }
+}
+
+fn global_names(evaluator: &ASTEvaluator) -> HashSet<String> {
+    let scopes = evaluator.symbol_table().scopes();
+    scopes
+        .last()
+        .map(|globals| globals.bindings())
+        .unwrap_or_default()
+        .iter()
+        .map(|binding| binding.name.to_string())
+        .collect()
+}
+
+/// How the REPL was started from the command line.
+#[derive(Debug, Default)]
+pub struct Options {
+    pub dry_run: bool,
+    pub trace: bool,
+    pub vm: bool,
+    pub no_color: bool,
+    /// Skips the startup file in the REPL.
+    pub no_rc: bool,
+    /// Runs the startup file before a script too.
+    pub rc: bool,
+    /// A script given with `-i` followed by its arguments, run before the
+    /// first prompt.
+    pub argv: Vec<String>,
+    /// Variables and aliases imported from the login shell with `--login`.
+    pub login: LoginEnv,
+}
+
+/// What a meta-command asks the REPL to do next.
+enum Action {
+    Continue,
+    Quit(i32),
+}
+
+struct Repl {
+    editor: DefaultEditor,
+    history: Option<PathBuf>,
+    evaluator: ASTEvaluator,
+    /// What the evaluator was created with, to start it again on `:reset`.
+    options: Options,
+    /// The functions and aliases declared so far, so later lines parse the
+    /// same way a script would.
+    declarations: Declarations,
+    /// The globals every session starts with, left out of `:vars`.
+    builtin_globals: HashSet<String>,
+    /// Whether values are printed in color.
+    color: bool,
+}
+
+impl Repl {
+    fn new(options: Options) -> Result<Self, String> {
+        let mut editor =
+            new_editor().map_err(|e| format!("failed to start the line editor: {}", e))?;
+
+        let history = history_path();
+        if let Some(path) = &history {
+            match editor.load_history(path) {
+                Ok(()) => (),
+                Err(ReadlineError::Io(e)) if e.kind() == io::ErrorKind::NotFound => (),
+                Err(e) => eprintln!("failed to read history from {}: {}", path.display(), e),
+            }
+        }
+
+        let evaluator = new_evaluator(&options);
+        let builtin_globals = global_names(&evaluator);
+
+        Ok(Self {
+            editor,
+            history,
+            evaluator,
+            declarations: Declarations::default(),
+            builtin_globals,
+            color: !options.no_color
+                && env::var_os("NO_COLOR").is_none()
+                && io::stdout().is_terminal(),
+            options,
+        })
+    }
+
+    /// Reads and evaluates lines until the session ends, returning the exit
+    /// status.
+    fn run(&mut self) -> i32 {
+        let mut quit_status = 0;
+        loop {
+            let line = match self.editor.readline("> ") {
+                Ok(line) => line,
+                // Ctrl-C clears the line
+                Err(ReadlineError::Interrupted) => continue,
+                Err(ReadlineError::Eof) => break,
+                Err(e) => {
+                    eprintln!("failed to read line: {}", e);
+                    break;
+                }
+            };
+            if !line.trim().is_empty() {
+                let _ = self.editor.add_history_entry(line.as_str());
+                if let Some(path) = &self.history {
+                    let _ = self.editor.save_history(path);
+                }
+            }
+
+            let result = match line.trim().strip_prefix(':') {
+                Some(meta) => self.meta_command(meta),
+                None => self
+                    .eval(&line, true)
+                    .map(|_| Action::Continue)
+                    .map_err(|e| e.to_string()),
+            };
+            match result {
+                Ok(Action::Continue) => (),
+                Ok(Action::Quit(status)) => {
+                    quit_status = status;
+                    break;
+                }
+                Err(e) => eprintln!("{}", e),
+            }
+
+            if self.evaluator.exit_status().is_some() {
+                break;
+            }
+        }
+
+        self.evaluator.run_exit_trap();
+        self.evaluator.exit_status().unwrap_or(quit_status)
+    }
+
+    /// Evaluates source in the session, printing the value of each line when
+    /// `show` is set.
+    fn eval(&mut self, src: &str, show: bool) -> Result<(), Error> {
+        // a statement at a time, as a script is run
+        let mut parser = Parser::new(src)
+            .with_declarations(self.declarations.clone())
+            .with_path(self.evaluator.environment().path());
+        let result = loop {
+            let program = match parser.next_statement() {
+                Ok(Some(program)) => program,
+                Ok(None) => break Ok(()),
+                Err(e) => break Err(Error::from(e)),
+            };
+            let values = match self.evaluator.eval(program) {
+                Ok(values) => values,
+                Err(e) => break Err(Error::from(e)),
+            };
+            if show {
+                for value in values.into_iter().flatten() {
+                    println!("{}", pretty(&value, self.color));
+                }
+            }
+            if self.evaluator.exit_status().is_some() {
+                break Ok(());
+            }
+            parser.set_path(self.evaluator.environment().path());
+        };
+
+        self.declarations = parser.declarations().clone();
+        result
+    }
+
+    /// Declares the aliases imported from the login shell. Any sod can't
+    /// take, such as one with a redirect, is skipped.
+    fn declare_aliases(&mut self) {
+        for (name, value) in self.options.login.aliases.clone() {
+            let _ = self.eval(&format!("alias {} = {}", name, value), false);
+        }
+    }
+
+    /// Runs a script in the session, keeping the variables and functions it
+    /// defines.
+    fn load(&mut self, path: &Path) -> Result<(), String> {
+        let src = fs::read_to_string(path)
+            .map_err(|e| format!("failed to read {}: {}", path.display(), e))?;
+        self.eval(&src, false)
+            .map_err(|e| e.located(&path.display().to_string(), &src))
+    }
+
+    fn meta_command(&mut self, meta: &str) -> Result<Action, String> {
+        let (name, arg) = match meta.split_once(char::is_whitespace) {
+            Some((name, arg)) => (name, arg.trim()),
+            None => (meta, ""),
+        };
+
+        match name {
+            "help" => println!("{}", HELP),
+            "vars" => self.list(false),
+            "funcs" => self.list(true),
+            "load" => {
+                if arg.is_empty() {
+                    return Err("usage: :load <file>".to_string());
+                }
+                self.load(Path::new(arg))?;
+            }
+            "reset" => {
+                self.evaluator = new_evaluator(&self.options);
+                self.declarations = Declarations::default();
+                self.declare_aliases();
+            }
+            "rehash" => commands::refresh_commands(),
+            "clear" => self
+                .editor
+                .clear_screen()
+                .map_err(|e| format!("failed to clear the screen: {}", e))?,
+            "quit" => {
+                let status = match arg {
+                    "" => 0,
+                    _ => arg
+                        .parse()
+                        .map_err(|_| format!("expected a number after :quit, found '{}'", arg))?,
+                };
+                return Ok(Action::Quit(status));
+            }
+            _ => return Err(format!("unknown command ':{}', see :help", name)),
+        }
+
+        Ok(Action::Continue)
+    }
+
+    /// Prints the global variables, or the functions when `functions` is set.
+    fn list(&self, functions: bool) {
+        let scopes = self.evaluator.symbol_table().scopes();
+        let globals = match scopes.last() {
+            Some(globals) => globals.bindings(),
+            None => return,
+        };
+
+        for binding in globals {
+            if self.builtin_globals.contains(binding.name) {
+                continue;
+            }
+
+            match binding.symbol {
+                Symbol::Function(func) if functions => {
+                    println!("{}({})", binding.name, func.args.join(", "))
+                }
+                Symbol::Function(_) => (),
+                _ if functions => (),
+                symbol => println!("{} = {}", binding.name, preview(symbol)),
+            }
+        }
+    }
+}
+
+/// Runs the REPL until the user exits, then exits the process.
+pub fn interpret(options: Options) {
+    let script = options.argv.first().cloned();
+    let no_rc = options.no_rc;
+    let mut repl = match Repl::new(options) {
+        Ok(repl) => repl,
+        Err(e) => {
+            eprintln!("{}", e);
+            process::exit(1);
+        }
+    };
+
+    // installed after the line editor, which sets a Ctrl-C handler of its own
+    if let Err(e) = commands::install_interrupt_handler() {
+        eprintln!("{}", e);
+    }
+    // Ctrl-C stops what the current line is doing rather than the REPL
+    if let Err(e) = commands::handle_signal(Signal::Int) {
+        eprintln!("{}", e);
+    }
+
+    repl.declare_aliases();
+    let rc = match no_rc {
+        true => None,
+        false => rc_path(),
+    };
+    // an error leaves the session as the script left it, to be looked into
+    for path in rc.into_iter().chain(script.map(PathBuf::from)) {
+        if let Err(e) = repl.load(&path) {
+            eprintln!("{}", e);
+        }
+        if let Some(status) = repl.evaluator.exit_status() {
+            repl.evaluator.run_exit_trap();
+            process::exit(repl.evaluator.exit_status().unwrap_or(status));
+        }
+    }
+
+    process::exit(repl.run());
+}
@@ -0,0 +1,188 @@
+use std::collections::HashSet;
+use std::path::Path;
+use std::sync::Mutex;
+
+use crate::commands::{CommandLine, Program};
+
+/// Which external commands may run. Without an allowlist any command that
+/// isn't denied can run.
+#[derive(Debug, Clone, Default)]
+pub struct Sandbox {
+    allowed: Option<HashSet<String>>,
+    denied: HashSet<String>,
+}
+
+impl Sandbox {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Only lets the given commands run, along with any allowed before.
+    pub fn allow(mut self, names: Vec<String>) -> Self {
+        self.allowed.get_or_insert_with(HashSet::new).extend(names);
+        self
+    }
+
+    /// Stops the given commands running, even if they are allowed.
+    pub fn deny(mut self, names: Vec<String>) -> Self {
+        self.denied.extend(names);
+        self
+    }
+
+    /// Checks every program a command line would run. A shell command line
+    /// can run several, e.g. `ls; rm -rf x`, so each one is checked, and
+    /// command substitution is refused as what it runs can't be known.
+    pub fn check(&self, cmd: &CommandLine) -> Result<(), String> {
+        let names = match &cmd.program {
+            Program::Shell(line) => program_names(line)?,
+            Program::Exec(program, _) => vec![program.clone()],
+        };
+
+        for name in names {
+            if !self.permits(&name) {
+                return Err(format!("'{}' is not allowed in restricted mode", name));
+            }
+        }
+        Ok(())
+    }
+
+    /// Denied commands are matched by file name too, so `/bin/rm` is denied
+    /// along with `rm`. Allowed commands must match exactly.
+    fn permits(&self, name: &str) -> bool {
+        let file_name = Path::new(name)
+            .file_name()
+            .map(|f| f.to_string_lossy().to_string());
+        if self.denied.contains(name) || file_name.is_some_and(|f| self.denied.contains(&f)) {
+            return false;
+        }
+
+        match &self.allowed {
+            Some(allowed) => allowed.contains(name),
+            None => true,
+        }
+    }
+}
+
+/// A word such as `FOO=bar` that sets a variable for the command after it.
+fn is_assignment(word: &str) -> bool {
+    match word.split_once('=') {
+        Some((name, _)) => {
+            !name.is_empty()
+                && !name.starts_with(|c: char| c.is_ascii_digit())
+                && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+        }
+        None => false,
+    }
+}
+
+/// The first word of each command in a shell command line, after any
+/// variable assignments. A first word the shell would expand, such as
+/// `/bin/r?`, is refused as the command it names can't be known.
+fn program_names(line: &str) -> Result<Vec<String>, String> {
+    let substitution = || Err("command substitution is not allowed in restricted mode".to_string());
+
+    let mut names = vec![];
+    let mut word = String::new();
+    let mut in_word = false;
+    let mut expands = false;
+    let mut command_start = true;
+    let mut quote = None;
+
+    let mut end_word =
+        |word: &mut String, in_word: &mut bool, expands: &mut bool, command_start: &mut bool| {
+            if *in_word && *command_start && !is_assignment(word) {
+                if *expands {
+                    return Err(format!(
+                        "'{}' is expanded by the shell, which is not allowed in restricted mode",
+                        word
+                    ));
+                }
+                names.push(word.clone());
+                *command_start = false;
+            }
+            word.clear();
+            *in_word = false;
+            *expands = false;
+            Ok(())
+        };
+
+    let mut chars = line.chars().peekable();
+    while let Some(c) = chars.next() {
+        match quote {
+            Some('\'') => {
+                match c {
+                    '\'' => quote = None,
+                    c => word.push(c),
+                }
+                continue;
+            }
+            Some(_) => {
+                match c {
+                    '"' => quote = None,
+                    '`' => return substitution(),
+                    '$' if chars.peek() == Some(&'(') => return substitution(),
+                    '\\' => word.extend(chars.next()),
+                    '$' => {
+                        word.push(c);
+                        expands = true;
+                    }
+                    c => word.push(c),
+                }
+                continue;
+            }
+            None => (),
+        }
+
+        match c {
+            '\'' | '"' => {
+                quote = Some(c);
+                in_word = true;
+            }
+            '\\' => {
+                word.extend(chars.next());
+                in_word = true;
+            }
+            '`' => return substitution(),
+            '$' if chars.peek() == Some(&'(') => return substitution(),
+            // part of a redirection such as 2>&1 or &>file
+            '&' if word.ends_with(['>', '<']) || chars.peek() == Some(&'>') => {
+                word.push(c);
+                in_word = true;
+            }
+            ';' | '&' | '|' | '(' | ')' | '\n' => {
+                end_word(&mut word, &mut in_word, &mut expands, &mut command_start)?;
+                command_start = true;
+            }
+            c if c.is_whitespace() => {
+                end_word(&mut word, &mut in_word, &mut expands, &mut command_start)?
+            }
+            c => {
+                word.push(c);
+                in_word = true;
+                expands |= matches!(c, '*' | '?' | '[' | '{' | '$' | '~');
+            }
+        }
+    }
+    end_word(&mut word, &mut in_word, &mut expands, &mut command_start)?;
+
+    Ok(names)
+}
+
+static SANDBOX: Mutex<Option<Sandbox>> = Mutex::new(None);
+
+/// Restricts the commands run from now on, or lifts the restriction when
+/// `sandbox` is none.
+pub fn set(sandbox: Option<Sandbox>) {
+    if let Ok(mut current) = SANDBOX.lock() {
+        *current = sandbox;
+    }
+}
+
+/// Checks a command against the sandbox, if one has been set.
+pub(crate) fn check(cmd: &CommandLine) -> Result<(), String> {
+    let sandbox = SANDBOX.lock().map_err(|e| e.to_string())?;
+    match sandbox.as_ref() {
+        Some(sandbox) => sandbox.check(cmd),
+        None => Ok(()),
+    }
+}
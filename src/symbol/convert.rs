@@ -0,0 +1,154 @@
+use super::symbol::{List, Object, Range, StringSymbol, Symbol};
+
+impl From<f64> for Symbol {
+    fn from(n: f64) -> Self {
+        Symbol::Number(n)
+    }
+}
+
+macro_rules! from_integer {
+    ($($t:ty),*) => {
+        $(
+            impl From<$t> for Symbol {
+                fn from(n: $t) -> Self {
+                    Symbol::Number(n as f64)
+                }
+            }
+        )*
+    };
+}
+
+from_integer!(i32, i64, u32, u64, usize);
+
+impl From<bool> for Symbol {
+    fn from(b: bool) -> Self {
+        Symbol::Boolean(b)
+    }
+}
+
+impl From<&str> for Symbol {
+    fn from(s: &str) -> Self {
+        Symbol::String(StringSymbol::new(s.to_string()))
+    }
+}
+
+impl From<String> for Symbol {
+    fn from(s: String) -> Self {
+        Symbol::String(StringSymbol::new(s))
+    }
+}
+
+impl From<List> for Symbol {
+    fn from(list: List) -> Self {
+        Symbol::List(list)
+    }
+}
+
+impl From<Object> for Symbol {
+    fn from(object: Object) -> Self {
+        Symbol::Object(object)
+    }
+}
+
+impl From<Range> for Symbol {
+    fn from(range: Range) -> Self {
+        Symbol::Range(range)
+    }
+}
+
+impl<T: Into<Symbol>> From<Vec<T>> for Symbol {
+    fn from(items: Vec<T>) -> Self {
+        items.into_iter().collect()
+    }
+}
+
+/// None when there is no value.
+impl<T: Into<Symbol>> From<Option<T>> for Symbol {
+    fn from(value: Option<T>) -> Self {
+        value.map_or(Symbol::None, Into::into)
+    }
+}
+
+/// Collects values into a list.
+impl<T: Into<Symbol>> FromIterator<T> for Symbol {
+    fn from_iter<I: IntoIterator<Item = T>>(items: I) -> Self {
+        Symbol::List(List::from(items.into_iter().map(Into::into).collect()))
+    }
+}
+
+/// The error converting from a value of the wrong kind, as a builtin would
+/// give it.
+fn expected(what: &str, symbol: &Symbol) -> String {
+    format!("expected {}, found {}", what, symbol.kind())
+}
+
+impl TryFrom<Symbol> for f64 {
+    type Error = String;
+
+    fn try_from(symbol: Symbol) -> Result<Self, String> {
+        match symbol {
+            Symbol::Number(n) => Ok(n),
+            symbol => Err(expected("a number", &symbol)),
+        }
+    }
+}
+
+/// Numbers are all `f64` in sod, so only whole ones convert.
+impl TryFrom<Symbol> for i64 {
+    type Error = String;
+
+    fn try_from(symbol: Symbol) -> Result<Self, String> {
+        match symbol {
+            Symbol::Number(n) if n.fract() == 0.0 && n.abs() < i64::MAX as f64 => Ok(n as i64),
+            Symbol::Number(n) => Err(format!("expected a whole number, found {}", n)),
+            symbol => Err(expected("a number", &symbol)),
+        }
+    }
+}
+
+impl TryFrom<Symbol> for bool {
+    type Error = String;
+
+    fn try_from(symbol: Symbol) -> Result<Self, String> {
+        match symbol {
+            Symbol::Boolean(b) => Ok(b),
+            symbol => Err(expected("a boolean", &symbol)),
+        }
+    }
+}
+
+/// The text of a string, or of a secret.
+impl TryFrom<Symbol> for String {
+    type Error = String;
+
+    fn try_from(symbol: Symbol) -> Result<Self, String> {
+        match symbol {
+            Symbol::String(s) | Symbol::Secret(s) => Ok(s.into_string()),
+            symbol => Err(expected("a string", &symbol)),
+        }
+    }
+}
+
+/// The items of a list, or the numbers of a range.
+impl TryFrom<Symbol> for Vec<Symbol> {
+    type Error = String;
+
+    fn try_from(symbol: Symbol) -> Result<Self, String> {
+        match symbol {
+            Symbol::List(list) => Ok(list.into_items()),
+            Symbol::Range(range) => Ok(range.collect()),
+            symbol => Err(expected("a list", &symbol)),
+        }
+    }
+}
+
+impl TryFrom<Symbol> for Object {
+    type Error = String;
+
+    fn try_from(symbol: Symbol) -> Result<Self, String> {
+        match symbol {
+            Symbol::Object(object) => Ok(object),
+            symbol => Err(expected("an object", &symbol)),
+        }
+    }
+}
@@ -1,3 +1,7 @@
+pub mod convert;
+pub mod pretty;
 pub mod scope;
+#[cfg(feature = "serde")]
+pub mod serialize;
 pub mod symbol;
 pub mod table;
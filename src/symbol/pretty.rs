@@ -0,0 +1,131 @@
+use super::symbol::Symbol;
+
+/// Lists and objects longer than this are split over several lines.
+const WIDTH: usize = 80;
+const INDENT: usize = 2;
+
+const DIM: &str = "2";
+const GREEN: &str = "32";
+const YELLOW: &str = "33";
+const CYAN: &str = "36";
+
+/// Renders a value for the REPL. Lists and objects that don't fit on one
+/// line have an item per line, indented by depth, and with `color` each kind
+/// of value is colored with ANSI escapes.
+pub fn pretty(symbol: &Symbol, color: bool) -> String {
+    Printer { color }.value(symbol, 0)
+}
+
+struct Printer {
+    color: bool,
+}
+
+impl Printer {
+    fn paint(&self, code: &str, text: String) -> String {
+        match self.color {
+            true => format!("\x1b[{}m{}\x1b[0m", code, text),
+            false => text,
+        }
+    }
+
+    fn value(&self, symbol: &Symbol, indent: usize) -> String {
+        match symbol {
+            Symbol::List(list) => {
+                let entries = list.items().iter().map(|item| (None, item)).collect();
+                self.collection('[', ']', entries, indent)
+            }
+            Symbol::Object(object) => {
+                let entries = object
+                    .iter()
+                    .map(|(key, value)| (Some(key.as_str()), value))
+                    .collect();
+                self.collection('{', '}', entries, indent)
+            }
+            Symbol::Number(_) | Symbol::Boolean(_) => self.paint(YELLOW, symbol.to_string()),
+            Symbol::String(_) => self.paint(GREEN, quote(&symbol.raw_str())),
+            Symbol::None | Symbol::Secret(_) => self.paint(DIM, symbol.to_string()),
+            Symbol::Function(func) => self.paint(
+                CYAN,
+                format!("func {}({})", func.name, func.args.join(", ")),
+            ),
+            Symbol::Bytes(_) | Symbol::Job(_) | Symbol::Range(_) => symbol.to_string(),
+        }
+    }
+
+    fn collection(
+        &self,
+        open: char,
+        close: char,
+        entries: Vec<(Option<&str>, &Symbol)>,
+        indent: usize,
+    ) -> String {
+        if entries.is_empty() {
+            return format!("{}{}", open, close);
+        }
+
+        let plain = Printer { color: false };
+        let items: Vec<String> = entries
+            .iter()
+            .map(|(key, value)| plain.entry(*key, value, indent))
+            .collect();
+        let line = format!("{} {} {}", open, items.join(", "), close);
+        if !line.contains('\n') && indent + line.chars().count() <= WIDTH {
+            if !self.color {
+                return line;
+            }
+            let items: Vec<String> = entries
+                .iter()
+                .map(|(key, value)| self.entry(*key, value, indent))
+                .collect();
+            return format!("{} {} {}", open, items.join(", "), close);
+        }
+
+        let inner = indent + INDENT;
+        let items: Vec<String> = entries
+            .iter()
+            .map(|(key, value)| format!("{}{}", " ".repeat(inner), self.entry(*key, value, inner)))
+            .collect();
+        format!(
+            "{}\n{}\n{}{}",
+            open,
+            items.join(",\n"),
+            " ".repeat(indent),
+            close
+        )
+    }
+
+    fn entry(&self, key: Option<&str>, value: &Symbol, indent: usize) -> String {
+        match key {
+            Some(key) => format!("{}: {}", object_key(key), self.value(value, indent)),
+            None => self.value(value, indent),
+        }
+    }
+}
+
+/// Quotes a string the way it would be written in sod, escaping characters
+/// that would otherwise break the line.
+fn quote(s: &str) -> String {
+    let mut quoted = String::from("'");
+    for c in s.chars() {
+        match c {
+            '\'' => quoted.push_str("\\'"),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\t' => quoted.push_str("\\t"),
+            '\r' => quoted.push_str("\\r"),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('\'');
+    quoted
+}
+
+/// Keys that are valid identifiers are shown bare, as in `{name: 'x'}`.
+fn object_key(key: &str) -> String {
+    let is_identifier = key.starts_with(|c: char| c.is_alphabetic() || c == '_')
+        && key.chars().all(|c| c.is_alphanumeric() || c == '_');
+    match is_identifier {
+        true => key.to_string(),
+        false => quote(key),
+    }
+}
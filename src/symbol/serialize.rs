@@ -0,0 +1,118 @@
+use std::fmt;
+
+use serde::de::{self, Deserialize, Deserializer, EnumAccess, MapAccess, SeqAccess, VariantAccess};
+use serde::ser::{self, Serialize, SerializeMap, Serializer};
+
+use super::symbol::{List, Object, Symbol};
+use crate::new_string_symbol;
+
+/// Values convert to and from the data model shared by json, yaml and the
+/// other formats serde supports: none is a unit, a whole number an integer,
+/// a range the list of its numbers and an object a map in order. Functions,
+/// jobs and bytes can't be serialized.
+impl Serialize for Symbol {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            Symbol::None => serializer.serialize_unit(),
+            Symbol::Boolean(b) => serializer.serialize_bool(*b),
+            Symbol::Number(n) if n.fract() == 0.0 && n.abs() < i64::MAX as f64 => {
+                serializer.serialize_i64(*n as i64)
+            }
+            Symbol::Number(n) if n.is_finite() => serializer.serialize_f64(*n),
+            Symbol::Number(n) => Err(ser::Error::custom(format!("unable to serialize {}", n))),
+            Symbol::String(_) | Symbol::Secret(_) => serializer.serialize_str(&self.raw_str()),
+            Symbol::List(list) => serializer.collect_seq(list.items()),
+            Symbol::Range(range) => serializer.collect_seq(range.clone()),
+            Symbol::Object(object) => {
+                let mut map = serializer.serialize_map(None)?;
+                for (key, value) in object.iter() {
+                    map.serialize_entry(key, value)?;
+                }
+                map.end()
+            }
+            Symbol::Function(_) | Symbol::Bytes(_) | Symbol::Job(_) => Err(ser::Error::custom(
+                format!("unable to serialize {}", self.kind()),
+            )),
+        }
+    }
+}
+
+struct SymbolVisitor;
+
+impl<'de> de::Visitor<'de> for SymbolVisitor {
+    type Value = Symbol;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("a value sod can hold")
+    }
+
+    fn visit_unit<E: de::Error>(self) -> Result<Symbol, E> {
+        Ok(Symbol::None)
+    }
+
+    fn visit_none<E: de::Error>(self) -> Result<Symbol, E> {
+        Ok(Symbol::None)
+    }
+
+    fn visit_some<D: Deserializer<'de>>(self, deserializer: D) -> Result<Symbol, D::Error> {
+        Symbol::deserialize(deserializer)
+    }
+
+    fn visit_bool<E: de::Error>(self, b: bool) -> Result<Symbol, E> {
+        Ok(Symbol::Boolean(b))
+    }
+
+    fn visit_i64<E: de::Error>(self, n: i64) -> Result<Symbol, E> {
+        Ok(Symbol::Number(n as f64))
+    }
+
+    fn visit_u64<E: de::Error>(self, n: u64) -> Result<Symbol, E> {
+        Ok(Symbol::Number(n as f64))
+    }
+
+    fn visit_f64<E: de::Error>(self, n: f64) -> Result<Symbol, E> {
+        Ok(Symbol::Number(n))
+    }
+
+    fn visit_str<E: de::Error>(self, s: &str) -> Result<Symbol, E> {
+        Ok(new_string_symbol!(s.to_string()))
+    }
+
+    fn visit_string<E: de::Error>(self, s: String) -> Result<Symbol, E> {
+        Ok(new_string_symbol!(s))
+    }
+
+    fn visit_bytes<E: de::Error>(self, bytes: &[u8]) -> Result<Symbol, E> {
+        Ok(Symbol::Bytes(bytes.to_vec()))
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Symbol, A::Error> {
+        let mut items = Vec::with_capacity(seq.size_hint().unwrap_or(0));
+        while let Some(item) = seq.next_element()? {
+            items.push(item);
+        }
+        Ok(Symbol::List(List::from(items)))
+    }
+
+    /// Keys that aren't strings, as yaml allows, become the string they
+    /// would be printed as.
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Symbol, A::Error> {
+        let mut object = Object::from(vec![]);
+        while let Some((key, value)) = map.next_entry::<Symbol, Symbol>()? {
+            object.insert(key.raw_str(), value);
+        }
+        Ok(Symbol::Object(object))
+    }
+
+    /// A tagged value, such as yaml's `!Ref name`, is read as its value.
+    fn visit_enum<A: EnumAccess<'de>>(self, data: A) -> Result<Symbol, A::Error> {
+        let (_, variant) = data.variant::<de::IgnoredAny>()?;
+        variant.newtype_variant()
+    }
+}
+
+impl<'de> Deserialize<'de> for Symbol {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_any(SymbolVisitor)
+    }
+}
@@ -1,20 +1,52 @@
-use std::collections::HashMap;
+use indexmap::IndexMap;
+use std::cell::RefCell;
+use std::cmp::Ordering;
+use std::rc::Rc;
+
+use regex::Regex;
 
 use crate::ast::ast::FunctionStatement;
+use crate::builtins;
+use crate::commands::{CommandResult, Job};
 use crate::lexer::token::TokenType;
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Clone, PartialEq)]
 pub enum Symbol {
     Number(f64),
     Boolean(bool),
     String(StringSymbol),
+    Secret(StringSymbol),
+    Bytes(Vec<u8>),
+    Job(JobRef),
     List(List),
     Range(Range),
     None,
-    Function(Box<FunctionStatement>),
+    Function(Rc<FunctionStatement>),
     Object(Object),
 }
 
+/// Invokes a sod function on behalf of a member function, e.g. the callback
+/// passed to `list.map(func)`.
+pub type FunctionCaller<'a> =
+    dyn FnMut(&FunctionStatement, Vec<Symbol>) -> Result<Symbol, String> + 'a;
+
+/// Member functions that take a sod function and need to call back into the
+/// evaluator.
+pub fn is_higher_order(fname: &str) -> bool {
+    matches!(fname, "map" | "filter" | "reduce" | "find")
+}
+
+pub fn expect_function<'a>(
+    fname: &str,
+    symbol: Option<&'a Symbol>,
+) -> Result<&'a FunctionStatement, String> {
+    match symbol {
+        Some(Symbol::Function(f)) => Ok(f),
+        Some(s) => Err(format!("{} expected a function, found {}", fname, s.kind())),
+        None => Err(format!("{} expected a function", fname)),
+    }
+}
+
 #[macro_export]
 macro_rules! new_string_symbol {
     ($v:expr) => {
@@ -24,31 +56,98 @@ macro_rules! new_string_symbol {
 
 pub fn get_global_vars(argv: Vec<String>) -> Vec<(&'static str, Symbol)> {
     // change process to script?
-    vec![(
-        "process",
-        Symbol::Object(Object::from(vec![(
-            "argv",
-            Symbol::List(List::from(
-                argv.iter()
-                    .map(|arg| new_string_symbol!(arg.to_string()))
-                    .collect(),
-            )),
-        )])),
-    )]
+    vec![
+        (
+            "process",
+            Symbol::Object(Object::from(vec![
+                (
+                    "argv",
+                    Symbol::List(List::from(
+                        argv.iter()
+                            .map(|arg| new_string_symbol!(arg.to_string()))
+                            .collect(),
+                    )),
+                ),
+                ("env", Symbol::Object(env_object())),
+                ("status", Symbol::Number(0.0)),
+            ])),
+        ),
+        ("sod", builtins::interpreter::info()),
+        ("last_result", Symbol::None),
+    ]
+}
+
+/// A snapshot of the environment the interpreter was started with.
+fn env_object() -> Object {
+    let mut object = Object::from(vec![]);
+    for (key, value) in std::env::vars_os() {
+        if let (Ok(key), Ok(value)) = (key.into_string(), value.into_string()) {
+            object.insert(key, new_string_symbol!(value));
+        }
+    }
+    object
+}
+
+impl From<&CommandResult> for Symbol {
+    fn from(result: &CommandResult) -> Self {
+        let code = |status: &Option<i32>| match status {
+            Some(code) => Symbol::Number(*code as f64),
+            None => Symbol::None,
+        };
+        let status = code(&result.status);
+        let statuses = match result.statuses.is_empty() {
+            true => vec![status.clone()],
+            false => result.statuses.iter().map(code).collect(),
+        };
+
+        Symbol::Object(Object::from(vec![
+            ("command", new_string_symbol!(result.command.clone())),
+            ("stdout", Symbol::from_bytes(result.stdout.clone())),
+            ("stderr", Symbol::from_bytes(result.stderr.clone())),
+            ("status", status),
+            ("statuses", Symbol::List(List::from(statuses))),
+            ("success", Symbol::Boolean(result.success())),
+            ("duration", Symbol::Number(result.duration.as_secs_f64())),
+        ]))
+    }
+}
+
+/// A background job. Copies of the symbol share the job, so waiting on one
+/// is seen by all of them.
+#[derive(Debug, Clone)]
+pub struct JobRef(Rc<RefCell<Job>>);
+
+impl JobRef {
+    pub fn new(job: Job) -> Self {
+        Self(Rc::new(RefCell::new(job)))
+    }
+
+    pub fn pid(&self) -> u32 {
+        self.0.borrow().pid()
+    }
+}
+
+impl PartialEq for JobRef {
+    fn eq(&self, other: &Self) -> bool {
+        Rc::ptr_eq(&self.0, &other.0)
+    }
 }
 
 #[derive(PartialEq, Debug, Clone)]
 pub struct Object {
-    mapping: HashMap<String, Symbol>,
+    /// Shared between copies of the object until one of them changes.
+    mapping: Rc<IndexMap<String, Symbol>>,
 }
 
 impl Object {
     pub fn from(items: Vec<(&str, Symbol)>) -> Self {
-        let mut mapping = HashMap::new();
+        let mut mapping = IndexMap::new();
         for (key, value) in items {
             mapping.insert(key.to_string(), value);
         }
-        Self { mapping }
+        Self {
+            mapping: Rc::new(mapping),
+        }
     }
 
     pub fn get(&self, key: &str) -> Option<&Symbol> {
@@ -56,7 +155,86 @@ impl Object {
     }
 
     pub fn get_mut(&mut self, key: &str) -> Option<&mut Symbol> {
-        self.mapping.get_mut(key)
+        Rc::make_mut(&mut self.mapping).get_mut(key)
+    }
+
+    pub fn insert(&mut self, key: String, value: Symbol) {
+        Rc::make_mut(&mut self.mapping).insert(key, value);
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&String, &Symbol)> {
+        self.mapping.iter()
+    }
+
+    fn expect_key(fname: &str, args: &[Symbol]) -> Result<String, String> {
+        if args.len() != 1 {
+            return Err(format!(
+                "expected 1 arguments to {}, found {}",
+                fname,
+                args.len()
+            ));
+        }
+
+        match &args[0] {
+            Symbol::String(ss) => Ok(ss.value.to_string()),
+            symbol => Err(format!(
+                "object keys must be strings, found {}",
+                symbol.kind()
+            )),
+        }
+    }
+
+    pub fn keys(&self) -> Symbol {
+        let items = self
+            .mapping
+            .keys()
+            .map(|key| new_string_symbol!(key.clone()))
+            .collect();
+        Symbol::List(List::from(items))
+    }
+
+    pub fn values(&self) -> Symbol {
+        let items = self.mapping.values().cloned().collect();
+        Symbol::List(List::from(items))
+    }
+
+    pub fn items(&self) -> Symbol {
+        let items = self
+            .mapping
+            .iter()
+            .map(|(key, value)| {
+                Symbol::List(List::from(vec![
+                    new_string_symbol!(key.clone()),
+                    value.clone(),
+                ]))
+            })
+            .collect();
+        Symbol::List(List::from(items))
+    }
+
+    pub fn has(&self, args: Vec<Symbol>) -> Result<Symbol, String> {
+        let key = Object::expect_key("has", &args)?;
+        Ok(Symbol::Boolean(self.mapping.contains_key(&key)))
+    }
+
+    pub fn delete(&mut self, args: Vec<Symbol>) -> Result<Symbol, String> {
+        let key = Object::expect_key("delete", &args)?;
+        Ok(Rc::make_mut(&mut self.mapping)
+            .shift_remove(&key)
+            .unwrap_or(Symbol::None))
+    }
+
+    pub fn call(&mut self, fname: &str, args: Vec<Symbol>) -> Result<Symbol, String> {
+        let option = match fname {
+            "keys" => self.keys(),
+            "values" => self.values(),
+            "items" => self.items(),
+            "has" => self.has(args)?,
+            "delete" => self.delete(args)?,
+            _ => return Err(format!("object has no member '{}'", fname)),
+        };
+
+        Ok(option)
     }
 }
 
@@ -89,6 +267,67 @@ impl Range {
         self.ticker += self.increment;
         Some(result)
     }
+
+    fn count(&self) -> Result<usize, String> {
+        if self.increment == 0 {
+            return Err("range increment can't be zero".to_string());
+        }
+
+        let span = self.end as i64 - self.start as i64;
+        let step = self.increment as i64;
+        if span == 0 || span.signum() != step.signum() {
+            return Ok(0);
+        }
+
+        Ok(((span + step - step.signum()) / step) as usize)
+    }
+
+    pub fn len(&self) -> Result<Symbol, String> {
+        Ok(Symbol::Number(self.count()? as f64))
+    }
+
+    pub fn to_list(&self) -> Result<Symbol, String> {
+        let items = (0..self.count()?)
+            .map(|i| Symbol::Number((self.start + i as i32 * self.increment) as f64))
+            .collect();
+        Ok(Symbol::List(List::from(items)))
+    }
+
+    pub fn contains(&self, args: Vec<Symbol>) -> Result<Symbol, String> {
+        if args.len() != 1 {
+            return Err(format!(
+                "expected 1 arguments to contains, found {}",
+                args.len()
+            ));
+        }
+
+        let n = match &args[0] {
+            Symbol::Number(n) => *n,
+            symbol => {
+                return Err(format!(
+                    "range contains expected a number, found {}",
+                    symbol.kind()
+                ))
+            }
+        };
+
+        let count = self.count()? as f64;
+        let steps = (n - self.start as f64) / self.increment as f64;
+        Ok(Symbol::Boolean(
+            steps.fract() == 0.0 && steps >= 0.0 && steps < count,
+        ))
+    }
+
+    pub fn call(&mut self, fname: &str, args: Vec<Symbol>) -> Result<Symbol, String> {
+        let option = match fname {
+            "len" => self.len()?,
+            "to_list" => self.to_list()?,
+            "contains" => self.contains(args)?,
+            _ => return Err(format!("range has no member '{}'", fname)),
+        };
+
+        Ok(option)
+    }
 }
 
 impl Iterator for Range {
@@ -105,14 +344,21 @@ impl PartialEq for Range {
     }
 }
 
+/// A string's text is shared between copies of it, and copied only when
+/// one of them changes.
 #[derive(Debug, Clone)]
 pub struct StringSymbol {
-    value: String,
+    value: Rc<String>,
 }
 
 impl StringSymbol {
     pub fn new(s: String) -> Self {
-        Self { value: s }
+        Self { value: Rc::new(s) }
+    }
+
+    /// Takes the text, copying it only if another string shares it.
+    pub fn into_string(self) -> String {
+        Rc::unwrap_or_clone(self.value)
     }
 
     pub fn get(&self, index: usize) -> Result<Symbol, String> {
@@ -144,11 +390,11 @@ impl StringSymbol {
         }
 
         let string = match args.get(1).unwrap() {
-            Symbol::String(s) => &s.value,
+            Symbol::String(s) => s.value.as_str(),
             _ => return Err(format!("can only insert string into a string")),
         };
 
-        self.value.insert_str(index, string.as_str());
+        Rc::make_mut(&mut self.value).insert_str(index, string);
 
         Ok(())
     }
@@ -167,12 +413,12 @@ impl StringSymbol {
             return Err(format!("string remove index out of range"));
         }
 
-        let removed = self.value.remove(index);
+        let removed = Rc::make_mut(&mut self.value).remove(index);
         Ok(new_string_symbol!(removed.to_string()))
     }
 
     pub fn pop(&mut self) -> Symbol {
-        if let Some(popped) = self.value.pop() {
+        if let Some(popped) = Rc::make_mut(&mut self.value).pop() {
             return new_string_symbol!(popped.to_string());
         }
 
@@ -185,11 +431,11 @@ impl StringSymbol {
         }
 
         let symbol = match args.get(0).unwrap() {
-            Symbol::String(ss) => &ss.value,
+            Symbol::String(ss) => ss.value.as_str(),
             _ => return Err(format!("can only add a string to a string")),
         };
 
-        self.value.push_str(symbol);
+        Rc::make_mut(&mut self.value).push_str(symbol);
         Ok(self.len())
     }
 
@@ -207,14 +453,46 @@ impl StringSymbol {
         }
 
         let needle = match &args[0] {
-            Symbol::String(ss) => &ss.value,
+            Symbol::String(ss) => ss.value.as_str(),
             _ => return Err(format!("string contains expected a string")),
         };
         Ok(Symbol::Boolean(self.value.contains(needle)))
     }
 
+    pub fn extract(&self, args: Vec<Symbol>) -> Result<Symbol, String> {
+        if args.len() != 1 {
+            return Err(format!(
+                "expected 1 arguments to extract, found {}",
+                args.len()
+            ));
+        }
+
+        let pattern = match &args[0] {
+            Symbol::String(ss) => ss.value.as_str(),
+            _ => return Err("string extract expected a string pattern".to_string()),
+        };
+        let regex = Regex::new(pattern).map_err(|e| format!("invalid pattern: {}", e))?;
+
+        let captures = match regex.captures(&self.value) {
+            Some(captures) => captures,
+            None => return Ok(Symbol::None),
+        };
+
+        let mut object = Object::from(vec![]);
+        for name in regex.capture_names().flatten() {
+            let value = match captures.name(name) {
+                Some(m) => new_string_symbol!(m.as_str().to_string()),
+                None => Symbol::None,
+            };
+            object.insert(name.to_string(), value);
+        }
+
+        Ok(Symbol::Object(object))
+    }
+
     pub fn call(&mut self, fname: &str, args: Vec<Symbol>) -> Result<Symbol, String> {
         let option = match fname {
+            "extract" => self.extract(args)?,
             "insert" => {
                 self.insert(args)?;
                 Symbol::None
@@ -231,8 +509,10 @@ impl StringSymbol {
     }
 }
 
+/// Iterates over the characters of a string, each as a string of its own.
 pub struct StringSymbolIterator {
     value: String,
+    /// The byte the next character starts at.
     index: usize,
 }
 
@@ -240,13 +520,9 @@ impl Iterator for StringSymbolIterator {
     type Item = Symbol;
 
     fn next(&mut self) -> Option<Self::Item> {
-        if self.index < self.value.len() {
-            let next = new_string_symbol!(self.value.chars().nth(self.index).unwrap().to_string());
-            self.index += 1;
-            Some(next)
-        } else {
-            None
-        }
+        let (_, c) = self.value[self.index..].char_indices().next()?;
+        self.index += c.len_utf8();
+        Some(new_string_symbol!(c.to_string()))
     }
 }
 
@@ -256,7 +532,7 @@ impl IntoIterator for StringSymbol {
 
     fn into_iter(self) -> Self::IntoIter {
         StringSymbolIterator {
-            value: self.value,
+            value: Rc::unwrap_or_clone(self.value),
             index: 0,
         }
     }
@@ -268,14 +544,32 @@ impl PartialEq for StringSymbol {
     }
 }
 
+/// A list's items are shared between copies of it, and copied only when
+/// one of them changes.
 #[derive(Debug, Clone, PartialEq)]
 pub struct List {
-    pub items: Vec<Symbol>,
+    items: Rc<Vec<Symbol>>,
 }
 
 impl List {
     pub fn from(items: Vec<Symbol>) -> Self {
-        Self { items }
+        Self {
+            items: Rc::new(items),
+        }
+    }
+
+    pub fn items(&self) -> &[Symbol] {
+        &self.items
+    }
+
+    /// The items to change, copied first if another list shares them.
+    pub fn items_mut(&mut self) -> &mut Vec<Symbol> {
+        Rc::make_mut(&mut self.items)
+    }
+
+    /// Takes the items, copying them only if another list shares them.
+    pub fn into_items(self) -> Vec<Symbol> {
+        Rc::unwrap_or_clone(self.items)
     }
 
     pub fn len(&self) -> Symbol {
@@ -283,7 +577,7 @@ impl List {
     }
 
     pub fn pop(&mut self) -> Symbol {
-        if let Some(symbol) = self.items.pop() {
+        if let Some(symbol) = self.items_mut().pop() {
             return symbol;
         }
         Symbol::None
@@ -295,12 +589,37 @@ impl List {
         }
 
         let symbol = args.get(0).unwrap().to_owned();
-        self.items.push(symbol);
+        self.items_mut().push(symbol);
         Ok(self.len())
     }
 
+    pub fn extend(&mut self, args: Vec<Symbol>) -> Result<Symbol, String> {
+        if args.len() != 1 {
+            return Err(format!(
+                "expected 1 arguments to extend, found {}",
+                args.len()
+            ));
+        }
+
+        match &args[0] {
+            Symbol::List(other) => self.items_mut().extend(other.items.iter().cloned()),
+            symbol => {
+                return Err(format!(
+                    "can only extend a list with a list, found {}",
+                    symbol.kind()
+                ))
+            }
+        }
+
+        Ok(self.len())
+    }
+
+    pub fn clear(&mut self) {
+        self.items_mut().clear();
+    }
+
     pub fn get_mut(&mut self, index: usize) -> Result<&mut Symbol, String> {
-        match self.items.get_mut(index) {
+        match self.items_mut().get_mut(index) {
             Some(s) => Ok(s),
             None => Err(format!("list index out of range")),
         }
@@ -327,7 +646,7 @@ impl List {
             return Err(format!("list remove index out of range"));
         }
 
-        Ok(self.items.remove(index))
+        Ok(self.items_mut().remove(index))
     }
 
     pub fn insert(&mut self, args: Vec<Symbol>) -> Result<(), String> {
@@ -348,7 +667,7 @@ impl List {
         }
 
         let symbol = args.get(1).unwrap().to_owned();
-        self.items.insert(index, symbol);
+        self.items_mut().insert(index, symbol);
         Ok(())
     }
 
@@ -364,6 +683,161 @@ impl List {
         Ok(Symbol::Boolean(self.items.contains(symbol)))
     }
 
+    pub fn index_of(&self, args: Vec<Symbol>) -> Result<Symbol, String> {
+        if args.len() != 1 {
+            return Err(format!(
+                "expected 1 arguments to index_of, found {}",
+                args.len()
+            ));
+        }
+
+        match self.items.iter().position(|item| item == &args[0]) {
+            Some(index) => Ok(Symbol::Number(index as f64)),
+            None => Ok(Symbol::None),
+        }
+    }
+
+    pub fn sort(&mut self) -> Result<(), String> {
+        for pair in self.items.windows(2) {
+            if compare_symbols(&pair[0], &pair[1]).is_none() {
+                return Err(format!(
+                    "unable to sort list containing {} and {}",
+                    pair[0].kind(),
+                    pair[1].kind()
+                ));
+            }
+        }
+
+        self.items_mut()
+            .sort_by(|left, right| compare_symbols(left, right).unwrap_or(Ordering::Equal));
+        Ok(())
+    }
+
+    pub fn reverse(&mut self) {
+        self.items_mut().reverse();
+    }
+
+    fn numbers(&self, fname: &str) -> Result<Vec<f64>, String> {
+        let mut numbers = vec![];
+        for item in self.items.iter() {
+            match item {
+                Symbol::Number(n) => numbers.push(*n),
+                _ => {
+                    return Err(format!(
+                        "{} expected a list of numbers, found {}",
+                        fname,
+                        item.kind()
+                    ))
+                }
+            }
+        }
+
+        Ok(numbers)
+    }
+
+    pub fn sum(&self) -> Result<Symbol, String> {
+        Ok(Symbol::Number(self.numbers("sum")?.iter().sum()))
+    }
+
+    pub fn min(&self) -> Result<Symbol, String> {
+        let min = self.numbers("min")?.into_iter().reduce(f64::min);
+        Ok(min.map_or(Symbol::None, Symbol::Number))
+    }
+
+    pub fn max(&self) -> Result<Symbol, String> {
+        let max = self.numbers("max")?.into_iter().reduce(f64::max);
+        Ok(max.map_or(Symbol::None, Symbol::Number))
+    }
+
+    pub fn unique(&self) -> Symbol {
+        let mut items: Vec<Symbol> = vec![];
+        for item in self.items.iter() {
+            if !items.contains(item) {
+                items.push(item.clone());
+            }
+        }
+
+        Symbol::List(List::from(items))
+    }
+
+    pub fn flatten(&self) -> Symbol {
+        let mut items = vec![];
+        for item in self.items.iter() {
+            match item {
+                Symbol::List(inner) => items.extend(inner.items.iter().cloned()),
+                _ => items.push(item.clone()),
+            }
+        }
+
+        Symbol::List(List::from(items))
+    }
+
+    pub fn map(&self, args: Vec<Symbol>, caller: &mut FunctionCaller) -> Result<Symbol, String> {
+        let func = expect_function("map", args.first())?;
+        let mut items = vec![];
+        for item in self.items.iter() {
+            items.push(caller(func, vec![item.clone()])?);
+        }
+
+        Ok(Symbol::List(List::from(items)))
+    }
+
+    pub fn filter(&self, args: Vec<Symbol>, caller: &mut FunctionCaller) -> Result<Symbol, String> {
+        let func = expect_function("filter", args.first())?;
+        let mut items = vec![];
+        for item in self.items.iter() {
+            if caller(func, vec![item.clone()])?.is_truthy() {
+                items.push(item.clone());
+            }
+        }
+
+        Ok(Symbol::List(List::from(items)))
+    }
+
+    pub fn reduce(&self, args: Vec<Symbol>, caller: &mut FunctionCaller) -> Result<Symbol, String> {
+        let func = expect_function("reduce", args.first())?;
+        let mut items = self.items.iter();
+        let mut accumulator = match args.get(1) {
+            Some(initial) => initial.clone(),
+            None => match items.next() {
+                Some(first) => first.clone(),
+                None => return Err("reduce of empty list with no initial value".to_string()),
+            },
+        };
+
+        for item in items {
+            accumulator = caller(func, vec![accumulator, item.clone()])?;
+        }
+
+        Ok(accumulator)
+    }
+
+    pub fn find(&self, args: Vec<Symbol>, caller: &mut FunctionCaller) -> Result<Symbol, String> {
+        let func = expect_function("find", args.first())?;
+        for item in self.items.iter() {
+            if caller(func, vec![item.clone()])?.is_truthy() {
+                return Ok(item.clone());
+            }
+        }
+
+        Ok(Symbol::None)
+    }
+
+    pub fn call_with(
+        &self,
+        fname: &str,
+        args: Vec<Symbol>,
+        caller: &mut FunctionCaller,
+    ) -> Result<Symbol, String> {
+        match fname {
+            "map" => self.map(args, caller),
+            "filter" => self.filter(args, caller),
+            "reduce" => self.reduce(args, caller),
+            "find" => self.find(args, caller),
+            _ => Err(format!("list has no member '{}'", fname)),
+        }
+    }
+
     pub fn call(&mut self, fname: &str, args: Vec<Symbol>) -> Result<Symbol, String> {
         let option = match fname {
             "len" => self.len(),
@@ -371,10 +845,29 @@ impl List {
             "push" => self.push(args)?,
             "remove" => self.remove(args)?,
             "contains" => self.contains(args)?,
+            "index_of" => self.index_of(args)?,
+            "extend" => self.extend(args)?,
+            "clear" => {
+                self.clear();
+                Symbol::None
+            }
             "insert" => {
                 self.insert(args)?;
                 Symbol::None
             }
+            "sort" => {
+                self.sort()?;
+                Symbol::None
+            }
+            "reverse" => {
+                self.reverse();
+                Symbol::None
+            }
+            "unique" => self.unique(),
+            "sum" => self.sum()?,
+            "min" => self.min()?,
+            "max" => self.max()?,
+            "flatten" => self.flatten(),
             _ => return Err(format!("list has no member '{}'", fname)),
         };
 
@@ -382,6 +875,123 @@ impl List {
     }
 }
 
+fn expect_places(fname: &str, args: &[Symbol]) -> Result<usize, String> {
+    match args.first() {
+        Some(Symbol::Number(places)) if *places >= 0.0 => Ok(*places as usize),
+        Some(symbol) => Err(format!(
+            "{} expected a positive number of places, found {}",
+            fname, symbol
+        )),
+        None => Ok(0),
+    }
+}
+
+/// Rounds half away from zero, so 2.5 becomes 3, and never to -0.
+fn round_to(n: f64, places: usize) -> f64 {
+    let scale = 10f64.powi(places as i32);
+    (n * scale).round() / scale + 0.0
+}
+
+fn call_number(n: f64, fname: &str, args: Vec<Symbol>) -> Result<Symbol, String> {
+    if args.len() > 1 {
+        return Err(format!(
+            "expected at most 1 arguments to {}, found {}",
+            fname,
+            args.len()
+        ));
+    }
+
+    let option = match fname {
+        "to_fixed" => {
+            let places = expect_places(fname, &args)?;
+            new_string_symbol!(format!("{:.*}", places, round_to(n, places)))
+        }
+        "round" => Symbol::Number(round_to(n, expect_places(fname, &args)?)),
+        _ => return Err(format!("number has no member '{}'", fname)),
+    };
+
+    Ok(option)
+}
+
+fn expect_byte_index(fname: &str, symbol: &Symbol, len: usize) -> Result<usize, String> {
+    match symbol {
+        Symbol::Number(n) if *n >= 0.0 && n.fract() == 0.0 && *n as usize <= len => Ok(*n as usize),
+        Symbol::Number(n) => Err(format!("{} index {} out of range", fname, n)),
+        _ => Err(format!(
+            "{} expected a number, found {}",
+            fname,
+            symbol.kind()
+        )),
+    }
+}
+
+fn decode_bytes(bytes: &[u8], encoding: &str) -> Result<String, String> {
+    match encoding {
+        "utf8" => String::from_utf8(bytes.to_vec()).map_err(|e| format!("invalid utf8: {}", e)),
+        "lossy" => Ok(String::from_utf8_lossy(bytes).to_string()),
+        "latin1" => Ok(bytes.iter().map(|b| *b as char).collect()),
+        "hex" => Ok(bytes.iter().map(|b| format!("{:02x}", b)).collect()),
+        _ => Err(format!("unknown encoding '{}'", encoding)),
+    }
+}
+
+fn call_job(job: &JobRef, fname: &str, args: Vec<Symbol>) -> Result<Symbol, String> {
+    if !args.is_empty() {
+        return Err(format!(
+            "wrong number of arguments to {}, found {}",
+            fname,
+            args.len()
+        ));
+    }
+
+    let mut job = job.0.borrow_mut();
+    let option = match fname {
+        "wait" => Symbol::from(job.wait()?),
+        "status" => match job.poll()? {
+            Some(CommandResult {
+                status: Some(code), ..
+            }) => Symbol::Number(*code as f64),
+            _ => Symbol::None,
+        },
+        "running" => Symbol::Boolean(job.poll()?.is_none()),
+        "kill" => {
+            job.kill()?;
+            Symbol::None
+        }
+        "pid" => Symbol::Number(job.pid() as f64),
+        "command" => new_string_symbol!(job.command().to_string()),
+        _ => return Err(format!("job has no member '{}'", fname)),
+    };
+
+    Ok(option)
+}
+
+fn call_bytes(bytes: &[u8], fname: &str, args: Vec<Symbol>) -> Result<Symbol, String> {
+    let option = match (fname, args.as_slice()) {
+        ("len", []) => Symbol::Number(bytes.len() as f64),
+        ("to_string", []) => new_string_symbol!(decode_bytes(bytes, "utf8")?),
+        ("to_string", [encoding]) => new_string_symbol!(decode_bytes(bytes, &encoding.raw_str())?),
+        ("slice", [start]) | ("slice", [start, _]) => {
+            let end = match args.get(1) {
+                Some(end) => expect_byte_index(fname, end, bytes.len())?,
+                None => bytes.len(),
+            };
+            let start = expect_byte_index(fname, start, end)?;
+            Symbol::Bytes(bytes[start..end].to_vec())
+        }
+        ("len", _) | ("to_string", _) | ("slice", _) => {
+            return Err(format!(
+                "wrong number of arguments to {}, found {}",
+                fname,
+                args.len()
+            ))
+        }
+        _ => return Err(format!("bytes has no member '{}'", fname)),
+    };
+
+    Ok(option)
+}
+
 fn compare_literal<T>(left: &T, operator: &TokenType, right: &T) -> Result<bool, String>
 where
     T: std::cmp::PartialEq + std::cmp::PartialOrd + std::fmt::Display,
@@ -402,6 +1012,17 @@ where
     Ok(b)
 }
 
+/// Orders two symbols of the same kind, numbers numerically and strings
+/// lexicographically. Returns `None` when the symbols can't be compared.
+fn compare_symbols(left: &Symbol, right: &Symbol) -> Option<Ordering> {
+    match (left, right) {
+        (Symbol::Number(lv), Symbol::Number(rv)) => Some(lv.total_cmp(rv)),
+        (Symbol::Boolean(lv), Symbol::Boolean(rv)) => Some(lv.cmp(rv)),
+        (Symbol::String(lv), Symbol::String(rv)) => Some(lv.value.cmp(&rv.value)),
+        _ => None,
+    }
+}
+
 fn compare_relational(left: &Symbol, op: &TokenType, right: &Symbol) -> Result<bool, String> {
     match (left, right) {
         (Symbol::Number(lv), Symbol::Number(rv)) => compare_literal(lv, op, rv),
@@ -495,6 +1116,25 @@ impl std::ops::Div for &Symbol {
     }
 }
 
+// Written by hand so a secret's value never ends up in debug output.
+impl std::fmt::Debug for Symbol {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Symbol::Number(n) => f.debug_tuple("Number").field(n).finish(),
+            Symbol::Boolean(b) => f.debug_tuple("Boolean").field(b).finish(),
+            Symbol::String(s) => f.debug_tuple("String").field(s).finish(),
+            Symbol::Secret(_) => write!(f, "Secret(*****)"),
+            Symbol::Bytes(bytes) => f.debug_tuple("Bytes").field(bytes).finish(),
+            Symbol::Job(job) => f.debug_tuple("Job").field(job).finish(),
+            Symbol::List(list) => f.debug_tuple("List").field(list).finish(),
+            Symbol::Range(range) => f.debug_tuple("Range").field(range).finish(),
+            Symbol::None => write!(f, "None"),
+            Symbol::Function(func) => f.debug_tuple("Function").field(func).finish(),
+            Symbol::Object(obj) => f.debug_tuple("Object").field(obj).finish(),
+        }
+    }
+}
+
 impl std::fmt::Display for Symbol {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         let s = match self {
@@ -502,9 +1142,12 @@ impl std::fmt::Display for Symbol {
             Symbol::Boolean(b) => b.to_string(),
             Symbol::Function(f) => format!("func {}", f.name),
             Symbol::String(s) => format!("'{}'", s.value),
+            Symbol::Secret(_) => "*****".to_string(),
+            Symbol::Bytes(bytes) => format!("bytes({})", bytes.len()),
+            Symbol::Job(job) => format!("job({})", job.pid()),
             Symbol::None => "none".to_string(),
             Symbol::List(list) => {
-                let items: Vec<String> = list.items.iter().map(|f| f.to_string()).collect();
+                let items: Vec<String> = list.items().iter().map(|f| f.to_string()).collect();
                 format!("[ {} ]", items.join(", "))
             }
             Symbol::Range(range) => format!("{}..{}..{}", range.start, range.end, range.increment),
@@ -520,6 +1163,23 @@ impl Symbol {
         match self {
             Symbol::List(list) => list.call(call, args),
             Symbol::String(ss) => ss.call(call, args),
+            Symbol::Object(obj) => obj.call(call, args),
+            Symbol::Range(range) => range.call(call, args),
+            Symbol::Number(n) => call_number(*n, call, args),
+            Symbol::Bytes(bytes) => call_bytes(bytes, call, args),
+            Symbol::Job(job) => call_job(job, call, args),
+            _ => Err(format!("{} has no member {}", self.kind(), call)),
+        }
+    }
+
+    pub fn call_with(
+        &self,
+        call: &str,
+        args: Vec<Symbol>,
+        caller: &mut FunctionCaller,
+    ) -> Result<Self, String> {
+        match self {
+            Symbol::List(list) => list.call_with(call, args, caller),
             _ => Err(format!("{} has no member {}", self.kind(), call)),
         }
     }
@@ -538,6 +1198,9 @@ impl Symbol {
             Symbol::Boolean(b) => *b,
             Symbol::Function(_) => true,
             Symbol::String(s) => s.value.len() > 0,
+            Symbol::Secret(s) => !s.value.is_empty(),
+            Symbol::Bytes(bytes) => !bytes.is_empty(),
+            Symbol::Job(_) => true,
             Symbol::List(_) => true,
             Symbol::None => false,
             Symbol::Range(_) => true,
@@ -551,6 +1214,9 @@ impl Symbol {
             Symbol::Boolean(_) => "boolean",
             Symbol::Function(_) => "function",
             Symbol::String(_) => "string",
+            Symbol::Secret(_) => "secret",
+            Symbol::Bytes(_) => "bytes",
+            Symbol::Job(_) => "job",
             Symbol::List(_) => "list",
             Symbol::None => "none",
             Symbol::Range(_) => "range",
@@ -560,9 +1226,54 @@ impl Symbol {
         s.to_string()
     }
 
+    /// Approximate number of bytes used by the symbol, including the heap
+    /// allocations it owns.
+    pub fn memory_size(&self) -> usize {
+        let heap = match self {
+            Symbol::String(ss) | Symbol::Secret(ss) => ss.value.capacity(),
+            Symbol::Bytes(bytes) => bytes.capacity(),
+            Symbol::List(list) => list.items().iter().map(|item| item.memory_size()).sum(),
+            Symbol::Object(obj) => obj
+                .mapping
+                .iter()
+                .map(|(key, value)| key.capacity() + value.memory_size())
+                .sum(),
+            Symbol::Function(_) => std::mem::size_of::<FunctionStatement>(),
+            Symbol::Number(_)
+            | Symbol::Boolean(_)
+            | Symbol::Range(_)
+            | Symbol::Job(_)
+            | Symbol::None => 0,
+        };
+
+        std::mem::size_of::<Symbol>() + heap
+    }
+
+    /// How many items a list or object holds, or bytes a string or bytes
+    /// value does, not counting the items nested inside them.
+    pub fn size(&self) -> usize {
+        match self {
+            Symbol::String(ss) | Symbol::Secret(ss) => ss.value.len(),
+            Symbol::Bytes(bytes) => bytes.len(),
+            Symbol::List(list) => list.items().len(),
+            Symbol::Object(obj) => obj.mapping.len(),
+            _ => 0,
+        }
+    }
+
+    /// Output from commands and files is a string when it is valid utf8 and
+    /// bytes otherwise, so binary data isn't mangled.
+    pub fn from_bytes(bytes: Vec<u8>) -> Symbol {
+        match String::from_utf8(bytes) {
+            Ok(s) => new_string_symbol!(s),
+            Err(e) => Symbol::Bytes(e.into_bytes()),
+        }
+    }
+
     pub fn raw_str(&self) -> String {
         match self {
-            Symbol::String(ss) => ss.value.clone(),
+            Symbol::String(ss) | Symbol::Secret(ss) => ss.value.to_string(),
+            Symbol::Bytes(bytes) => String::from_utf8_lossy(bytes).to_string(),
             s => s.to_string(),
         }
     }
@@ -1,26 +1,79 @@
 use std::collections::HashMap;
 
 use super::{
-    scope::{ScopeKind, ScopeStack, GLOBAL_SCOPE_ID},
+    scope::{ScopeKind, GLOBAL_SCOPE_ID},
     symbol::Symbol,
 };
+use crate::intern::Name;
 
 type ScopeID = usize;
-type SymbolName = String;
+type SymbolName = Name;
+
+/// A read-only view of a single scope, for tooling that inspects the state of
+/// a running program.
+pub struct ScopeView<'a> {
+    pub id: ScopeID,
+    pub kind: ScopeKind,
+    bindings: Vec<Binding<'a>>,
+}
+
+impl<'a> ScopeView<'a> {
+    /// The variables declared in this scope, sorted by name.
+    pub fn bindings(&self) -> Vec<Binding<'a>> {
+        self.bindings.clone()
+    }
+
+    pub fn get(&self, name: &str) -> Option<&'a Symbol> {
+        self.bindings
+            .iter()
+            .find(|binding| binding.name == name)
+            .map(|binding| binding.symbol)
+    }
+}
+
+#[derive(Clone, Copy)]
+pub struct Binding<'a> {
+    pub name: &'a str,
+    pub symbol: &'a Symbol,
+}
+
+impl Binding<'_> {
+    pub fn kind(&self) -> String {
+        self.symbol.kind()
+    }
+
+    pub fn size(&self) -> usize {
+        self.symbol.memory_size()
+    }
+}
+
+/// A scope inside the global one, such as a function call or a loop.
+struct Frame {
+    kind: ScopeKind,
+    /// Where the scope's variables start in `SymbolTable::locals`.
+    start: usize,
+    /// Where the variables visible from this scope start in `locals`. A
+    /// function can't see the variables of the scopes that called it, so
+    /// for a function this is the same as `start`.
+    visible: usize,
+}
 
+/// The variables of a running program. Those of the scopes inside the
+/// global one are kept together in a single vector, innermost last, so
+/// entering and leaving a scope allocates nothing and a variable is found by
+/// looking back over the few declared since the function started.
 pub struct SymbolTable {
-    pub scoped_table: HashMap<ScopeID, HashMap<SymbolName, Symbol>>,
-    scope: ScopeStack,
+    globals: HashMap<SymbolName, Symbol>,
+    locals: Vec<(SymbolName, Symbol)>,
+    frames: Vec<Frame>,
 }
 
 impl SymbolTable {
     pub fn from(global_vars: Vec<(&str, Symbol)>) -> Self {
-        let mut scoped_table = HashMap::new();
-        scoped_table.insert(GLOBAL_SCOPE_ID, HashMap::new());
-
         let mut symbol_table = SymbolTable {
-            scoped_table,
-            scope: ScopeStack::new(),
+            globals: HashMap::new(),
+            locals: vec![],
+            frames: vec![],
         };
 
         for (key, value) in global_vars {
@@ -30,63 +83,129 @@ impl SymbolTable {
         symbol_table
     }
 
-    fn find(&self, symbol_name: &str) -> Option<(ScopeID, &Symbol)> {
-        for scope in self.scope.curr_stack().iter().rev() {
-            if let Some(symbol) = self
-                .scoped_table
-                .get(&scope.id)
-                .and_then(|symbol_table| symbol_table.get(symbol_name))
-            {
-                return Some((scope.id, symbol));
-            }
-        }
+    /// How many variables are defined, in every scope including those of
+    /// the functions that called the running one.
+    pub fn len(&self) -> usize {
+        self.globals.len() + self.locals.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The variables visible from the current scope, other than globals.
+    fn visible(&self) -> usize {
+        self.frames.last().map_or(0, |frame| frame.visible)
+    }
 
-        None
+    fn find_local(&self, symbol_name: &str) -> Option<usize> {
+        let visible = self.visible();
+        self.locals[visible..]
+            .iter()
+            .rposition(|(name, _)| &**name == symbol_name)
+            .map(|i| visible + i)
     }
 
     pub fn get(&self, symbol_name: &str) -> Option<&Symbol> {
-        if let Some((_, symbol)) = self.find(symbol_name) {
-            return Some(symbol);
+        match self.find_local(symbol_name) {
+            Some(i) => Some(&self.locals[i].1),
+            None => self.globals.get(symbol_name),
         }
-
-        None
     }
 
     pub fn get_mut(&mut self, symbol_name: &str) -> Option<&mut Symbol> {
-        let scope_id = match self.find(symbol_name) {
-            Some((id, _)) => id,
-            None => return None,
-        };
-
-        let symbol = self
-            .scoped_table
-            .get_mut(&scope_id)
-            .and_then(|symbol_table| symbol_table.get_mut(symbol_name))
-            .unwrap();
-
-        Some(symbol)
+        match self.find_local(symbol_name) {
+            Some(i) => Some(&mut self.locals[i].1),
+            None => self.globals.get_mut(symbol_name),
+        }
     }
 
-    pub fn set(&mut self, name: &str, symbol: Symbol) {
-        if let Some(existing_symbol) = self.get_mut(name) {
+    /// Sets a variable where it is defined, or in the current scope if it
+    /// isn't. A `Name` from the syntax tree is kept as the key without
+    /// copying it.
+    pub fn set(&mut self, name: impl AsRef<str> + Into<Name>, symbol: Symbol) {
+        if let Some(existing_symbol) = self.get_mut(name.as_ref()) {
             *existing_symbol = symbol;
             return;
         }
 
-        let curr_scope_id = self.scope.curr().id;
-        match self.scoped_table.get_mut(&curr_scope_id) {
-            Some(symbol_table) => symbol_table.insert(name.to_string(), symbol),
-            None => panic!("scope {} not found", curr_scope_id),
+        self.declare(name, symbol);
+    }
+
+    /// Defines a variable in the current scope, hiding any of the same name
+    /// outside it, such as a global with the name of a function argument.
+    pub fn declare(&mut self, name: impl Into<Name>, symbol: Symbol) {
+        match self.frames.is_empty() {
+            true => self.globals.insert(name.into(), symbol),
+            false => {
+                self.locals.push((name.into(), symbol));
+                None
+            }
         };
     }
 
+    /// Sets a variable in the global scope regardless of the current scope.
+    pub fn set_global(&mut self, name: impl Into<Name>, symbol: Symbol) {
+        self.globals.insert(name.into(), symbol);
+    }
+
+    pub fn get_global_mut(&mut self, name: &str) -> Option<&mut Symbol> {
+        self.globals.get_mut(name)
+    }
+
+    /// The scopes visible from the current position, innermost first and
+    /// ending with the global scope.
+    pub fn scopes(&self) -> Vec<ScopeView<'_>> {
+        let mut scopes = vec![];
+        let mut end = self.locals.len();
+        for (i, frame) in self.frames.iter().enumerate().rev() {
+            scopes.push(ScopeView {
+                id: i + 1,
+                kind: frame.kind,
+                bindings: sorted(self.locals[frame.start..end].iter().map(|(n, s)| (n, s))),
+            });
+            // the scopes below a function are those of its caller
+            if frame.kind == ScopeKind::FunctionBlock {
+                break;
+            }
+            end = frame.start;
+        }
+
+        scopes.push(ScopeView {
+            id: GLOBAL_SCOPE_ID,
+            kind: ScopeKind::Global,
+            bindings: sorted(self.globals.iter()),
+        });
+        scopes
+    }
+
     pub fn push_scope(&mut self, kind: ScopeKind) {
-        let scope_id = self.scope.push(kind);
-        self.scoped_table.insert(scope_id, HashMap::new());
+        let start = self.locals.len();
+        let visible = match kind {
+            ScopeKind::FunctionBlock => start,
+            ScopeKind::Global => panic!("not able to push another global scope"),
+            _ => self.visible(),
+        };
+        self.frames.push(Frame {
+            kind,
+            start,
+            visible,
+        });
     }
 
     pub fn pop_scope(&mut self) {
-        let popped_scope = self.scope.pop();
-        self.scoped_table.remove(&popped_scope.id);
+        let frame = match self.frames.pop() {
+            Some(frame) => frame,
+            None => panic!("scope out of bounds"),
+        };
+        self.locals.truncate(frame.start);
     }
 }
+
+fn sorted<'a>(bindings: impl Iterator<Item = (&'a SymbolName, &'a Symbol)>) -> Vec<Binding<'a>> {
+    let mut bindings: Vec<Binding<'a>> = bindings
+        .map(|(name, symbol)| Binding { name, symbol })
+        .collect();
+    bindings.sort_by(|a, b| a.name.cmp(b.name));
+    bindings
+}
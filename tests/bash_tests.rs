@@ -1,5 +1,15 @@
-use common::utils::assert_expr;
+use common::utils::{assert_expr, assert_exprs, eval_expr};
+use sod::ast::evaluator::{ASTEvaluator, MAX_CALL_DEPTH};
+use sod::commands::{CommandLine, CommandResult, Echo, OutputBuffer};
+use sod::error::{ParseErrorKind, RuntimeErrorKind, Span};
+use sod::limits::Limits;
 use sod::new_string_symbol;
+use sod::parser::{Parser, MAX_DEPTH};
+use sod::symbol::symbol::{List, Symbol};
+use sod::Engine;
+use std::cell::RefCell;
+use std::rc::Rc;
+use std::time::Duration;
 
 mod common;
 
@@ -30,3 +40,1666 @@ echo "$x""#,
         new_string_symbol!("foo\n".to_string()),
     );
 }
+
+#[test]
+fn with_env() {
+    assert_expr(
+        r#"x = ""
+with_env({"SOD_WITH_ENV": "foo"}) {
+x = printenv SOD_WITH_ENV
+}
+x"#,
+        new_string_symbol!("foo\n".to_string()),
+    );
+    assert_expr(
+        r#"with_env({SOD_WITH_ENV_RESTORED: 1}) {
+printenv SOD_WITH_ENV_RESTORED
+}
+printenv SOD_WITH_ENV_RESTORED"#,
+        new_string_symbol!("".to_string()),
+    );
+    assert_expr(
+        r#"x = ""
+with_env({SOD_WITH_ENV_READ: "a"}) {
+x = env("SOD_WITH_ENV_READ")
+}
+[x, env("SOD_WITH_ENV_READ")]"#,
+        Symbol::List(List::from(vec![
+            new_string_symbol!("a".to_string()),
+            Symbol::None,
+        ])),
+    );
+}
+
+#[test]
+fn with_env_leaves_process_env() {
+    // the variables are the commands' alone, not the interpreter's
+    let mut evaluator = ASTEvaluator::new(vec![]);
+    evaluator.register_fn("process_var", |_| {
+        Ok(match std::env::var("SOD_WITH_ENV_PROCESS") {
+            Ok(value) => new_string_symbol!(value),
+            Err(_) => Symbol::None,
+        })
+    });
+    let src = "x = 1\nwith_env({SOD_WITH_ENV_PROCESS: 'a'}) {\nx = process_var()\n}\nx";
+    let values = evaluator.eval(Parser::new(src).parse().unwrap()).unwrap();
+    assert_eq!(Some(Symbol::None), values[values.len() - 1]);
+}
+
+#[test]
+fn with_dir() {
+    assert_expr(
+        r#"x = ""
+with_dir("/") {
+x = pwd
+}
+x"#,
+        new_string_symbol!("/\n".to_string()),
+    );
+    assert_expr(
+        r#"x = ""
+with_dir("/") {
+with_dir("tmp") {
+x = pwd
+}
+}
+x"#,
+        new_string_symbol!("/tmp\n".to_string()),
+    );
+    let program = Parser::new("with_dir('/sod_missing_dir') {\necho x\n}").parse();
+    assert!(ASTEvaluator::new(vec![]).eval(program.unwrap()).is_err());
+}
+
+#[test]
+fn with_dir_leaves_process_dir() {
+    let dir = std::env::temp_dir().join("sod_with_dir");
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(dir.join("data"), "contents").unwrap();
+
+    let mut evaluator = ASTEvaluator::new(vec![]);
+    evaluator.register_fn("process_dir", |_| {
+        Ok(new_string_symbol!(std::env::current_dir()
+            .unwrap()
+            .display()
+            .to_string()))
+    });
+    let src = format!(
+        "x = none\nwith_dir('{}') {{\necho out > written\nx = [process_dir(), read_file('data')]\n}}\nx",
+        dir.display()
+    );
+    let values = evaluator.eval(Parser::new(&src).parse().unwrap());
+    let written = std::fs::read_to_string(dir.join("written"));
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    let cwd = std::env::current_dir().unwrap().display().to_string();
+    assert_eq!(
+        Some(Symbol::List(List::from(vec![
+            new_string_symbol!(cwd),
+            new_string_symbol!("contents".to_string()),
+        ]))),
+        values.unwrap().pop().unwrap()
+    );
+    assert_eq!("out\n", written.unwrap());
+}
+
+#[test]
+fn env_vars() {
+    assert_expr("env('SOD_UNSET_VAR')", Symbol::None);
+    assert_exprs(
+        vec![
+            "set_env('SOD_SET_ENV', 'foo')",
+            "env('SOD_SET_ENV')",
+            "printenv SOD_SET_ENV",
+            "set_env('SOD_SET_ENV', none)",
+            "env('SOD_SET_ENV')",
+        ],
+        vec![
+            Symbol::None,
+            new_string_symbol!("foo".to_string()),
+            new_string_symbol!("foo\n".to_string()),
+            Symbol::None,
+            Symbol::None,
+        ],
+    );
+    assert_expr(
+        "set_env('SOD_SET_ENV_PROCESS', 'foo')\nenv('SOD_SET_ENV_PROCESS')",
+        new_string_symbol!("foo".to_string()),
+    );
+    assert!(std::env::var("SOD_SET_ENV_PROCESS").is_err());
+}
+
+#[test]
+fn path_change_refreshes_commands() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let dir = std::env::temp_dir().join("sod_path_change");
+    std::fs::create_dir_all(&dir).unwrap();
+    let cmd = dir.join("sod_path_change_cmd");
+    std::fs::write(&cmd, "#!/bin/sh\necho found\n").unwrap();
+    std::fs::set_permissions(&cmd, std::fs::Permissions::from_mode(0o755)).unwrap();
+
+    // later source is parsed with the PATH the script has set
+    let mut engine = Engine::new();
+    let set_path = format!("set_env('PATH', '{}:' + env('PATH'))", dir.display());
+    engine.eval_str(&set_path).unwrap();
+    let found = engine.eval_str("sod_path_change_cmd");
+
+    // and so are the statements after it in the same script
+    let script = std::process::Command::new(env!("CARGO_BIN_EXE_sod"))
+        .arg("-c")
+        .arg(format!(
+            "{}\nsod_path_change_cmd\nsod_path_change_cmd a b",
+            set_path
+        ))
+        .output()
+        .unwrap();
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    assert_eq!(
+        Some(new_string_symbol!("found\n".to_string())),
+        found.unwrap()
+    );
+    assert!(script.status.success());
+    assert_eq!(b"found\nfound\n".to_vec(), script.stdout);
+}
+
+#[test]
+fn command_results() {
+    assert_expr("last_result", Symbol::None);
+    assert_exprs(
+        vec![
+            "r = run('echo foo; echo bar >&2; exit 3')\nr.stdout",
+            "r.stderr",
+            "r.status",
+            "r.success",
+            "r.command",
+            "echo baz",
+            "last_result.stdout",
+            "last_result.status",
+            "last_result.success",
+            "last_result.duration >= 0",
+        ],
+        vec![
+            new_string_symbol!("foo\n".to_string()),
+            new_string_symbol!("bar\n".to_string()),
+            Symbol::Number(3.0),
+            Symbol::Boolean(false),
+            new_string_symbol!("echo foo; echo bar >&2; exit 3".to_string()),
+            new_string_symbol!("baz\n".to_string()),
+            new_string_symbol!("baz\n".to_string()),
+            Symbol::Number(0.0),
+            Symbol::Boolean(true),
+            Symbol::Boolean(true),
+        ],
+    );
+}
+
+#[test]
+fn process_env() {
+    assert_expr("process.env.PATH == env('PATH')", Symbol::Boolean(true));
+    assert_expr(
+        "\"$process.env.PATH\" == env('PATH')",
+        Symbol::Boolean(true),
+    );
+}
+
+#[test]
+fn process_exit() {
+    let program = Parser::new("x = 1\nx\nprocess.exit(3)\nx = 2\nx")
+        .parse()
+        .unwrap();
+    let mut evaluator = ASTEvaluator::new(vec![]);
+    let results = evaluator.eval(program).unwrap();
+    assert_eq!(Some(3), evaluator.exit_status());
+    assert_eq!(Some(Symbol::Number(1.0)), results[1]);
+    assert_eq!(2, results.len());
+
+    let program = Parser::new("func f() {\nprocess.exit()\n}\nf()")
+        .parse()
+        .unwrap();
+    let mut evaluator = ASTEvaluator::new(vec![]);
+    evaluator.eval(program).unwrap();
+    assert_eq!(Some(0), evaluator.exit_status());
+}
+
+#[test]
+#[should_panic]
+fn process_exit_invalid_panic() {
+    eval_expr("process.exit('a')");
+}
+
+#[test]
+fn binary_output() {
+    assert_expr(
+        "printf '\\377\\000a'",
+        Symbol::Bytes(vec![0xff, 0x00, b'a']),
+    );
+}
+
+#[test]
+fn exit_status() {
+    assert_exprs(
+        vec![
+            "process.status",
+            "test -e /sod_missing_file",
+            "process.status",
+            "test -e /",
+            "process.status",
+        ],
+        vec![
+            Symbol::Number(0.0),
+            new_string_symbol!("".to_string()),
+            Symbol::Number(1.0),
+            new_string_symbol!("".to_string()),
+            Symbol::Number(0.0),
+        ],
+    );
+}
+
+#[test]
+fn stderr_capture() {
+    assert_exprs(
+        vec![
+            "capture_stderr(true)",
+            "echo err >&2",
+            "last_result.stderr",
+            "pipefail(true)\nsh -c 'echo a >&2' | sh -c 'cat; echo b >&2'\nlast_result.stderr",
+        ],
+        vec![
+            Symbol::None,
+            new_string_symbol!("".to_string()),
+            new_string_symbol!("err\n".to_string()),
+            new_string_symbol!("a\nb\n".to_string()),
+        ],
+    );
+}
+
+#[test]
+fn quiet_capture() {
+    assert_exprs(
+        vec!["out = capture echo hello\nout", "capture echo $out"],
+        vec![
+            new_string_symbol!("hello\n".to_string()),
+            new_string_symbol!("hello\n\n".to_string()),
+        ],
+    );
+}
+
+#[test]
+fn pipe_forward() {
+    assert_expr(
+        "func trimmed(s) {\nreturn s.trim()\n}\necho hello |> trimmed",
+        new_string_symbol!("hello".to_string()),
+    );
+}
+
+#[test]
+fn background_jobs() {
+    assert_expr(
+        "job = spawn echo hi\nr = job.wait()\nr.stdout",
+        new_string_symbol!("hi\n".to_string()),
+    );
+    assert_expr(
+        "job = spawn sh -c 'exit 3'\njob.wait()\njob.status()",
+        Symbol::Number(3.0),
+    );
+    assert_exprs(
+        vec![
+            "job = spawn sleep 5\njob.running()",
+            "job.kill()",
+            "job.running()",
+        ],
+        vec![Symbol::Boolean(true), Symbol::None, Symbol::Boolean(false)],
+    );
+}
+
+#[test]
+fn dry_run() {
+    let path = std::env::temp_dir().join("sod_dry_run_test");
+    let _ = std::fs::remove_file(&path);
+    let src = format!(
+        "p = '{}'\nout = touch $p\njob = spawn touch $p\nr = job.wait()\nout == '' && r.success && last_result.command",
+        path.display()
+    );
+
+    let program = Parser::new(&src).parse().unwrap();
+    let mut evaluator = ASTEvaluator::new(vec![]);
+    evaluator.set_dry_run(true);
+    let results = evaluator.eval(program).unwrap();
+
+    assert!(!path.exists());
+    assert_eq!(
+        Some(new_string_symbol!(format!("touch {}", path.display()))),
+        results[results.len() - 1]
+    );
+}
+
+#[test]
+fn dry_run_secrets() {
+    let stdout = OutputBuffer::new();
+    let mut evaluator = ASTEvaluator::new(vec![]);
+    evaluator.set_stdout(stdout.clone());
+    evaluator.set_dry_run(true);
+    let src = "t = secret('hunter2')\nh = \"Bearer $t\"\nTOKEN=$t curl -H $h ${[t, 'x']} $t!\nlast_result.command";
+    let results = evaluator.eval(Parser::new(src).parse().unwrap()).unwrap();
+
+    let printed = String::from_utf8(stdout.contents()).unwrap();
+    assert_eq!("TOKEN='*****' curl -H ***** ***** x *****\n", printed);
+    assert_eq!(
+        Some(new_string_symbol!(printed.trim_end().to_string())),
+        results[results.len() - 1]
+    );
+}
+
+#[test]
+fn commands_by_path() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let dir = std::env::temp_dir().join("sod_commands_by_path");
+    std::fs::create_dir_all(&dir).unwrap();
+    let script = dir.join("greet.sh");
+    std::fs::write(&script, "#!/bin/sh\necho hello $1\n").unwrap();
+    std::fs::set_permissions(&script, std::fs::Permissions::from_mode(0o755)).unwrap();
+
+    assert_expr(
+        &format!("{} world", script.display()),
+        new_string_symbol!("hello world\n".to_string()),
+    );
+    assert_expr(
+        &format!(
+            "x = ''\nwith_dir('{}') {{\nx = ./greet.sh there\n}}\nx",
+            dir.display()
+        ),
+        new_string_symbol!("hello there\n".to_string()),
+    );
+    assert_expr(
+        &format!("x = capture {}\nx", script.display()),
+        new_string_symbol!("hello\n".to_string()),
+    );
+}
+
+#[test]
+fn command_strings() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let dir = std::env::temp_dir().join("sod_command_strings");
+    std::fs::create_dir_all(&dir).unwrap();
+    let tool = dir.join("sod_late_tool");
+    std::fs::write(&tool, "#!/bin/sh\necho installed\n").unwrap();
+    std::fs::set_permissions(&tool, std::fs::Permissions::from_mode(0o755)).unwrap();
+
+    assert_expr(
+        &format!(
+            "p = env('PATH')\np = \"{}:$p\"\nx = ''\nwith_env({{PATH: p}}) {{\nx = `sod_late_tool`\n}}\nx",
+            dir.display()
+        ),
+        new_string_symbol!("installed\n".to_string()),
+    );
+    assert_expr(
+        "y = 'a b'\nx = `echo $y \"c\"`\nx",
+        new_string_symbol!("a b c\n".to_string()),
+    );
+}
+
+#[test]
+#[should_panic]
+fn empty_command_string_panic() {
+    eval_expr("x = ``");
+}
+
+#[test]
+fn quoted_interpolation() {
+    assert_expr(
+        "f = 'a b; echo injected'\nprintf '[%s]' $f",
+        new_string_symbol!("[a b; echo injected]".to_string()),
+    );
+    assert_expr(
+        "f = \"it's\"\nprintf '[%s]' $f \"$f too\"",
+        new_string_symbol!("[it's][it's too]".to_string()),
+    );
+    assert_expr(
+        "f = 'a b'\nprintf '[%s]' $f!",
+        new_string_symbol!("[a][b]".to_string()),
+    );
+    assert_expr(
+        "f = ''\nprintf '[%s]' $f",
+        new_string_symbol!("[]".to_string()),
+    );
+}
+
+#[test]
+fn expression_interpolation() {
+    assert_expr(
+        "files = ['a b', 'c']\nprintf '[%s]' ${files[0]} ${files.len() + 1}",
+        new_string_symbol!("[a b][3]".to_string()),
+    );
+    assert_expr(
+        "d = ' x '\nprintf '[%s]' ${d.trim()} ${'}'}",
+        new_string_symbol!("[x][}]".to_string()),
+    );
+    assert_expr(
+        "f = 'a b'\nprintf '[%s]' ${raw(f)}",
+        new_string_symbol!("[a][b]".to_string()),
+    );
+}
+
+#[test]
+#[should_panic]
+fn expression_interpolation_invalid_panic() {
+    eval_expr("echo ${1 +}");
+}
+
+#[test]
+fn command_env_prefix() {
+    assert_expr(
+        "SOD_A=1 SOD_B='x y' printenv SOD_A SOD_B",
+        new_string_symbol!("1\nx y\n".to_string()),
+    );
+    assert_expr(
+        "v = 'a b'\nSOD_A=$v sh -c 'echo \"$SOD_A\"'",
+        new_string_symbol!("a b\n".to_string()),
+    );
+    assert_exprs(
+        vec!["SOD_A=1 true", "env('SOD_A')", "last_result.command"],
+        vec![
+            new_string_symbol!("".to_string()),
+            Symbol::None,
+            new_string_symbol!("SOD_A=1 true".to_string()),
+        ],
+    );
+    assert_expr("x=1\nx", Symbol::Number(1.0));
+}
+
+#[test]
+fn list_arguments() {
+    assert_expr(
+        "files = ['a b', 'c', 1]\nprintf '[%s]' $files",
+        new_string_symbol!("[a b][c][1]".to_string()),
+    );
+    assert_expr(
+        "files = []\nprintf '[%s]' x $files",
+        new_string_symbol!("[x]".to_string()),
+    );
+    assert_expr(
+        "flags = ['-a b', '-c']\nprintf '[%s]' $flags!",
+        new_string_symbol!("[-a][b][-c]".to_string()),
+    );
+}
+
+#[test]
+fn interactive_commands() {
+    assert_expr(
+        "interactive sh -c 'exit 3'\nprocess.status",
+        Symbol::Number(3.0),
+    );
+    assert_expr(
+        "interactive echo hi\nlast_result.stdout",
+        new_string_symbol!("".to_string()),
+    );
+}
+
+#[test]
+fn output_redirection() {
+    let path = std::env::temp_dir().join("sod redirect test");
+    let _ = std::fs::remove_file(&path);
+    let src = format!(
+        "p = '{}'\nout = echo a > $p\necho b >> $p\necho c 2>&1 >> \"$p\"\nout",
+        path.display()
+    );
+
+    let results = eval_expr(&src);
+    assert_eq!(
+        Some(new_string_symbol!("".to_string())),
+        results[results.len() - 1]
+    );
+    assert_eq!("a\nb\nc\n", std::fs::read_to_string(&path).unwrap());
+
+    let src = format!(
+        "p = '{}'
+sh -c 'echo out; echo err 1>&2' >$p 2>&1
+last_result.command",
+        path.display()
+    );
+    let results = eval_expr(&src);
+    assert_eq!("out\nerr\n", std::fs::read_to_string(&path).unwrap());
+    assert_eq!(
+        Some(new_string_symbol!(format!(
+            "sh -c 'echo out; echo err 1>&2' 2>&1 > '{}'",
+            path.display()
+        ))),
+        results[results.len() - 1]
+    );
+}
+
+#[test]
+fn output_redirection_before_another_command() {
+    let path = std::env::temp_dir().join("sod_redirect_then_command");
+    let _ = std::fs::remove_file(&path);
+    let src = format!("out = echo a > {}; echo b\nout", path.display());
+
+    let results = eval_expr(&src);
+    assert_eq!(
+        Some(new_string_symbol!("b\n".to_string())),
+        results[results.len() - 1]
+    );
+    assert_eq!("a\n", std::fs::read_to_string(&path).unwrap());
+}
+
+#[test]
+#[should_panic]
+fn output_redirection_missing_file_panic() {
+    eval_expr("echo a >");
+}
+
+const PIPE_FUNCTIONS: &str = "func tag(line) {\nreturn '[' + line + ']'\n}\nfunc wrap(line) {\nreturn {n: line}\n}\nfunc unwrap(o) {\nreturn o.n\n}\nfunc skip_two(line) {\nout = line\nif line == '2' {\nout = none\n}\nreturn out\n}\n";
+
+#[test]
+fn function_pipelines() {
+    let pipeline = |src: &str| format!("{}{}", PIPE_FUNCTIONS, src);
+    assert_expr(
+        &pipeline("seq 1 3 | tag"),
+        new_string_symbol!("[1]\n[2]\n[3]\n".to_string()),
+    );
+    assert_expr(
+        &pipeline("seq 1 3 | skip_two | tag"),
+        new_string_symbol!("[1]\n[3]\n".to_string()),
+    );
+    assert_expr(
+        &pipeline("seq 1 3 | tag | sort -r | skip_two"),
+        new_string_symbol!("[3]\n[2]\n[1]\n".to_string()),
+    );
+    assert_expr(
+        &pipeline("seq 1 2 | wrap | unwrap | tag"),
+        new_string_symbol!("[1]\n[2]\n".to_string()),
+    );
+    assert_expr(
+        &pipeline("seq 1 100000 | tag | head -n 2"),
+        new_string_symbol!("[1]\n[2]\n".to_string()),
+    );
+    assert_expr(
+        &pipeline("seq 2 | tag | sh -c 'cat > /dev/null; exit 4'\nprocess.status"),
+        Symbol::Number(4.0),
+    );
+    assert_expr(
+        &pipeline("seq 2 | tag | tr -d '[]'\nlast_result.command"),
+        new_string_symbol!("seq 2 | tag | tr -d '[]'".to_string()),
+    );
+
+    let path = std::env::temp_dir().join("sod_pipeline_redirect");
+    let src = format!("p = '{}'\nseq 2 | tag > $p", path.display());
+    eval_expr(&pipeline(&src));
+    assert_eq!("[1]\n[2]\n", std::fs::read_to_string(&path).unwrap());
+}
+
+#[test]
+fn pipefail() {
+    assert_exprs(
+        vec![
+            "sh -c 'exit 3' | cat\nprocess.status",
+            "pipefail(true)\nsh -c 'exit 3' | cat\nlast_result.status",
+            "last_result.statuses",
+            "last_result.command",
+            "echo a 2>&1 | sh -c 'cat; exit 2' | tr a b",
+            "last_result.statuses",
+            "last_result.success",
+            "seq 1 100000 | head -n 1\nlast_result.success",
+            "pipefail(false)\nsh -c 'exit 3' | cat\nlast_result.statuses",
+        ],
+        vec![
+            Symbol::Number(0.0),
+            Symbol::Number(3.0),
+            Symbol::List(List::from(vec![Symbol::Number(3.0), Symbol::Number(0.0)])),
+            new_string_symbol!("sh -c 'exit 3' | cat".to_string()),
+            new_string_symbol!("b\n".to_string()),
+            Symbol::List(List::from(vec![
+                Symbol::Number(0.0),
+                Symbol::Number(2.0),
+                Symbol::Number(0.0),
+            ])),
+            Symbol::Boolean(false),
+            Symbol::Boolean(true),
+            Symbol::List(List::from(vec![Symbol::Number(0.0)])),
+        ],
+    );
+    assert_expr(
+        &format!(
+            "{}pipefail(true)\nsh -c 'seq 2; exit 5' | tag\nprocess.status",
+            PIPE_FUNCTIONS
+        ),
+        Symbol::Number(5.0),
+    );
+    // what passes between commands is left as it is
+    assert_exprs(
+        vec![
+            "pipefail(true)\nprintf 'a\\nb' | cat",
+            "printf '\\377\\376\\n\\375' | cat | cat",
+        ],
+        vec![
+            new_string_symbol!("a\nb".to_string()),
+            Symbol::Bytes(vec![255, 254, b'\n', 253]),
+        ],
+    );
+}
+
+#[test]
+#[should_panic]
+fn spawn_function_pipeline_panic() {
+    eval_expr(&format!("{}spawn seq 3 | tag", PIPE_FUNCTIONS));
+}
+
+#[test]
+fn aliases() {
+    assert_expr(
+        "alias say = printf '[%s]' hi\nsay there",
+        new_string_symbol!("[hi][there]".to_string()),
+    );
+    assert_expr(
+        "x = 'a b'\nalias say = printf '[%s]' $x\nalias say_more = say more\nx = 'c'\nsay_more 1",
+        new_string_symbol!("[c][more][1]".to_string()),
+    );
+    assert_expr(
+        "alias say = printf '[%s]'\nout = capture say 1\nSOD_A=2 say $out",
+        new_string_symbol!("[[1]]".to_string()),
+    );
+}
+
+#[test]
+#[should_panic]
+fn alias_redirect_panic() {
+    eval_expr("alias l = ls > listing.txt");
+}
+
+#[test]
+fn functions_shadow_commands() {
+    assert_expr(
+        "func echo(s) {\nreturn 'shadowed ' + s\n}\necho('x')",
+        new_string_symbol!("shadowed x".to_string()),
+    );
+    assert_expr(
+        "func pwd() {\nreturn 'mine'\n}\nx = pwd\nx",
+        new_string_symbol!("mine".to_string()),
+    );
+    assert_expr(
+        "func echo() {\nreturn capture `echo real`\n}\nx = echo\nx",
+        new_string_symbol!("real\n".to_string()),
+    );
+    assert_expr(
+        "func tr(line) {\nreturn '<' + line + '>'\n}\nseq 2 | tr",
+        new_string_symbol!("<1>\n<2>\n".to_string()),
+    );
+    assert_expr(
+        "func pwd() {\nreturn 'mine'\n}\nalias pwd = echo aliased\npwd",
+        new_string_symbol!("aliased\n".to_string()),
+    );
+    assert_expr(
+        "if false {\nfunc echo(s) {\nreturn 'shadowed'\n}\n}\necho unshadowed",
+        new_string_symbol!("unshadowed\n".to_string()),
+    );
+}
+
+#[test]
+fn exit_trap() {
+    let program = Parser::new("x = 1\ntrap \"x = 2\" EXIT\nx")
+        .parse()
+        .unwrap();
+    let mut evaluator = ASTEvaluator::new(vec![]);
+    let results = evaluator.eval(program).unwrap();
+    assert_eq!(Some(Symbol::Number(1.0)), results[results.len() - 1]);
+
+    evaluator.run_exit_trap();
+    assert_eq!(
+        Some(&Symbol::Number(2.0)),
+        evaluator.symbol_table().get("x")
+    );
+    assert_eq!(None, evaluator.exit_status());
+
+    let program = Parser::new("trap \"process.exit(4)\" EXIT\nprocess.exit(1)")
+        .parse()
+        .unwrap();
+    let mut evaluator = ASTEvaluator::new(vec![]);
+    evaluator.eval(program).unwrap();
+    evaluator.run_exit_trap();
+    assert_eq!(Some(4), evaluator.exit_status());
+}
+
+#[test]
+fn signal_trap() {
+    let dir = std::env::temp_dir().join("sod_signal_trap");
+    std::fs::create_dir_all(&dir).unwrap();
+    let ready = dir.join("ready");
+    let cleaned = dir.join("cleaned");
+    let _ = std::fs::remove_file(&ready);
+    let _ = std::fs::remove_file(&cleaned);
+
+    let script = dir.join("trap.sod");
+    let src = format!(
+        "func cleanup() {{\ntouch '{}'\n}}\ntrap \"cleanup()\" TERM\ntouch '{}'\nsleep 1\n",
+        cleaned.display(),
+        ready.display()
+    );
+    std::fs::write(&script, src).unwrap();
+
+    let mut child = std::process::Command::new(env!("CARGO_BIN_EXE_sod"))
+        .arg(&script)
+        .spawn()
+        .unwrap();
+    while !ready.exists() {
+        std::thread::sleep(std::time::Duration::from_millis(20));
+    }
+    std::process::Command::new("kill")
+        .args(["-TERM", &child.id().to_string()])
+        .status()
+        .unwrap();
+
+    assert_eq!(Some(143), child.wait().unwrap().code());
+    assert!(cleaned.exists());
+}
+
+#[test]
+fn interrupt_reaches_shell_children() {
+    let dir = std::env::temp_dir().join("sod_interrupt_group");
+    std::fs::create_dir_all(&dir).unwrap();
+    let ready = dir.join("ready");
+    let _ = std::fs::remove_file(&ready);
+
+    // sleep runs under sh rather than in its place, and holds the output
+    // open until it is stopped too
+    let script = dir.join("interrupt.sod");
+    let src = format!("touch '{}'\nsleep 10; echo after\n", ready.display());
+    std::fs::write(&script, src).unwrap();
+
+    let start = std::time::Instant::now();
+    let child = std::process::Command::new(env!("CARGO_BIN_EXE_sod"))
+        .arg(&script)
+        .stdout(std::process::Stdio::piped())
+        .stderr(std::process::Stdio::piped())
+        .spawn()
+        .unwrap();
+    while !ready.exists() {
+        std::thread::sleep(std::time::Duration::from_millis(20));
+    }
+    std::thread::sleep(std::time::Duration::from_millis(200));
+    std::process::Command::new("kill")
+        .args(["-INT", &child.id().to_string()])
+        .status()
+        .unwrap();
+
+    let output = child.wait_with_output().unwrap();
+    std::fs::remove_dir_all(&dir).unwrap();
+    assert!(start.elapsed() < std::time::Duration::from_secs(5));
+    assert!(!output.status.success());
+    assert_eq!("", String::from_utf8_lossy(&output.stdout));
+}
+
+#[test]
+#[should_panic]
+fn trap_unknown_signal_panic() {
+    eval_expr("trap \"x = 1\" USR9");
+}
+
+#[test]
+fn audit_log() {
+    let dir = std::env::temp_dir().join("sod_audit_log");
+    std::fs::create_dir_all(&dir).unwrap();
+    let log = dir.join("audit.log");
+    let _ = std::fs::remove_file(&log);
+
+    let script = dir.join("audit.sod");
+    std::fs::write(&script, "echo audited\nrun(\"exit 3\")\n").unwrap();
+
+    let status = std::process::Command::new(env!("CARGO_BIN_EXE_sod"))
+        .arg("--audit-log")
+        .arg(&log)
+        .arg(&script)
+        .current_dir(&dir)
+        .output()
+        .unwrap()
+        .status;
+    assert!(status.success());
+
+    let contents = std::fs::read_to_string(&log).unwrap();
+    let lines: Vec<&str> = contents.lines().collect();
+    assert_eq!(2, lines.len());
+    let cwd = format!("\"cwd\":\"{}\"", dir.display());
+    assert!(lines[0].contains("\"command\":\"echo audited\""));
+    assert!(lines[0].contains(&cwd));
+    assert!(lines[0].contains("\"status\":0}"));
+    assert!(lines[1].contains("\"command\":\"exit 3\""));
+    assert!(lines[1].contains("\"status\":3}"));
+}
+
+#[test]
+fn audit_log_secrets() {
+    let dir = std::env::temp_dir().join("sod_audit_log_secrets");
+    std::fs::create_dir_all(&dir).unwrap();
+    let log = dir.join("audit.log");
+    let _ = std::fs::remove_file(&log);
+
+    let script = dir.join("audit.sod");
+    let src = "t = secret('hunter2')\ntest $t\nrun(\"test $t\")\nexec('test', [t])\nparallel([\"test $t\"])\necho ${last_result.command}\n";
+    std::fs::write(&script, src).unwrap();
+
+    let output = std::process::Command::new(env!("CARGO_BIN_EXE_sod"))
+        .arg("--audit-log")
+        .arg(&log)
+        .arg(&script)
+        .current_dir(&dir)
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    assert_eq!("test '*****'\n", String::from_utf8_lossy(&output.stdout));
+
+    let contents = std::fs::read_to_string(&log).unwrap();
+    assert_eq!(5, contents.lines().count());
+    assert!(!contents.contains("hunter2"));
+}
+
+#[test]
+fn audit_entry_json() {
+    use sod::audit::AuditEntry;
+
+    let entry = AuditEntry {
+        command: "echo \"a\"\tb".to_string(),
+        cwd: std::path::PathBuf::from("/tmp"),
+        started: std::time::UNIX_EPOCH + std::time::Duration::from_millis(1500),
+        duration: std::time::Duration::from_millis(250),
+        status: None,
+    };
+    assert_eq!(
+        "{\"started\":1.500,\"command\":\"echo \\\"a\\\"\\tb\",\"cwd\":\"/tmp\",\"duration\":0.250,\"status\":null}",
+        entry.to_json()
+    );
+}
+
+#[test]
+fn sandbox() {
+    use sod::commands::CommandLine;
+    use sod::sandbox::Sandbox;
+
+    let check = |sandbox: &Sandbox, cmd: &str| sandbox.check(&CommandLine::shell(cmd.to_string()));
+    let names = |names: &[&str]| names.iter().map(|n| n.to_string()).collect();
+
+    let allowed = Sandbox::new().allow(names(&["ls", "grep", "echo"]));
+    assert!(check(&allowed, "ls -la | grep 'a; rm' 2>&1").is_ok());
+    assert!(check(&allowed, "FOO=1 echo \"$HOME\" > out.txt").is_ok());
+    assert!(check(&allowed, "ls; rm -rf build").is_err());
+    assert!(check(&allowed, "ls && (cat x)").is_err());
+    assert!(check(&allowed, "echo $(rm x)").is_err());
+    assert!(check(&allowed, "echo \"`rm x`\"").is_err());
+    assert!(check(&allowed, "/bin/ls").is_err());
+    assert!(allowed
+        .check(&CommandLine::exec(
+            "ls".to_string(),
+            vec!["; rm".to_string()]
+        ))
+        .is_ok());
+
+    let denied = Sandbox::new().deny(names(&["rm"]));
+    assert!(check(&denied, "ls -la").is_ok());
+    assert!(check(&denied, "ls & /bin/rm x").is_err());
+    assert_eq!(
+        Err(
+            "'/bin/r?' is expanded by the shell, which is not allowed in restricted mode"
+                .to_string()
+        ),
+        check(&denied, "/bin/r? x")
+    );
+    assert!(check(&denied, "\"$RM\" x").is_err());
+    assert!(check(&denied, "~/bin/rm x").is_err());
+    assert!(check(&denied, "'/bin/r?' x").is_ok());
+    assert!(check(&denied, "ls /bin/r?").is_ok());
+    assert_eq!(
+        Err("'rm' is not allowed in restricted mode".to_string()),
+        check(&denied, "echo a || rm b")
+    );
+}
+
+#[test]
+fn sandbox_flags() {
+    let dir = std::env::temp_dir().join("sod_sandbox_flags");
+    std::fs::create_dir_all(&dir).unwrap();
+    let script = dir.join("sandbox.sod");
+    std::fs::write(&script, "echo allowed\nrm -f missing\necho not reached\n").unwrap();
+
+    let output = std::process::Command::new(env!("CARGO_BIN_EXE_sod"))
+        .args(["--allow", "echo,ls", "--deny", "ls"])
+        .arg(&script)
+        .output()
+        .unwrap();
+    assert_eq!(Some(1), output.status.code());
+    assert_eq!("allowed\n", String::from_utf8_lossy(&output.stdout));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("'rm' is not allowed"));
+}
+
+#[test]
+fn repl_history_file() {
+    use std::io::Write;
+
+    let history = std::env::temp_dir().join("sod_repl_history");
+    let _ = std::fs::remove_file(&history);
+
+    for line in ["1 + 1", "", "2 + 2", "3 + 3"] {
+        let mut child = std::process::Command::new(env!("CARGO_BIN_EXE_sod"))
+            .env("SOD_HISTORY", &history)
+            .env("SOD_HISTORY_SIZE", "2")
+            .stdin(std::process::Stdio::piped())
+            .stdout(std::process::Stdio::null())
+            .spawn()
+            .unwrap();
+        writeln!(child.stdin.take().unwrap(), "{}", line).unwrap();
+        assert!(child.wait().unwrap().success());
+    }
+
+    let contents = std::fs::read_to_string(&history).unwrap();
+    let lines: Vec<&str> = contents.lines().skip(1).collect();
+    assert_eq!(vec!["2 + 2", "3 + 3"], lines);
+}
+
+fn run_repl(input: &str) -> std::process::Output {
+    run_repl_with(&[], input)
+}
+
+fn run_repl_with(args: &[&str], input: &str) -> std::process::Output {
+    use std::io::Write;
+
+    let mut child = std::process::Command::new(env!("CARGO_BIN_EXE_sod"))
+        .args(args)
+        .env("SOD_HISTORY", "")
+        .env("SOD_RC", "")
+        .stdin(std::process::Stdio::piped())
+        .stdout(std::process::Stdio::piped())
+        .stderr(std::process::Stdio::piped())
+        .spawn()
+        .unwrap();
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(input.as_bytes())
+        .unwrap();
+    child.wait_with_output().unwrap()
+}
+
+#[test]
+fn repl_meta_commands() {
+    let script = std::env::temp_dir().join("sod_repl_load.sod");
+    std::fs::write(&script, "func add(a, b) {\nreturn a + b\n}\ntotal = 0\n").unwrap();
+
+    let output = run_repl(&format!(
+        "x = 'hi'\ny = [1, {{\"a\": \"b\"}}]\n:load {}\nadd(1, 2)\n:vars\n:funcs\n:nope\n:quit 3\n1 + 1\n",
+        script.display()
+    ));
+    assert_eq!(Some(3), output.status.code());
+    assert_eq!(
+        "3\ntotal = 0\nx = 'hi'\ny = [ 1, { a: 'b' } ]\nadd(a, b)\n",
+        String::from_utf8_lossy(&output.stdout)
+    );
+    assert_eq!(
+        "unknown command ':nope', see :help\n",
+        String::from_utf8_lossy(&output.stderr)
+    );
+}
+
+#[test]
+fn repl_loads_script() {
+    let script = std::env::temp_dir().join("sod_repl_script.sod");
+    std::fs::write(
+        &script,
+        "name = process.argv[1]\nfunc greet(n) {\nreturn 'hi ' + n\n}\nnot_defined\n",
+    )
+    .unwrap();
+    let script = script.to_string_lossy().to_string();
+
+    let output = run_repl_with(&["-i", &script, "bob"], "greet(name)\n");
+    assert!(output.status.success());
+    assert_eq!("'hi bob'\n", String::from_utf8_lossy(&output.stdout));
+    assert_eq!(
+        format!("{}:5:1: 'not_defined' is not defined\n", script),
+        String::from_utf8_lossy(&output.stderr)
+    );
+}
+
+#[test]
+fn repl_rehash() {
+    let dir = std::env::temp_dir().join("sod_repl_rehash");
+    std::fs::create_dir_all(&dir).unwrap();
+    let cmd = dir.join("sod_rehash_cmd");
+    let _ = std::fs::remove_file(&cmd);
+    let path = format!("{}:{}", dir.display(), std::env::var("PATH").unwrap());
+
+    let mut child = std::process::Command::new(env!("CARGO_BIN_EXE_sod"))
+        .env("SOD_HISTORY", "")
+        .env("SOD_RC", "")
+        .env("PATH", path)
+        .stdin(std::process::Stdio::piped())
+        .stdout(std::process::Stdio::piped())
+        .stderr(std::process::Stdio::piped())
+        .spawn()
+        .unwrap();
+    let input = format!(
+        "cp /bin/echo {}\nsod_rehash_cmd\n:rehash\nsod_rehash_cmd found\n",
+        cmd.display()
+    );
+    use std::io::Write;
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(input.as_bytes())
+        .unwrap();
+    let output = child.wait_with_output().unwrap();
+
+    // a command's output is printed, then its value
+    assert_eq!(
+        "''\nfound\n'found\\n'\n",
+        String::from_utf8_lossy(&output.stdout)
+    );
+    assert_eq!(
+        "'sod_rehash_cmd' is not defined\n",
+        String::from_utf8_lossy(&output.stderr)
+    );
+}
+
+#[test]
+fn repl_reset() {
+    let output = run_repl_with(
+        &["-i", "/dev/null", "arg"],
+        "x = 1\nalias l = echo listed\n:reset\nx\nl\nprocess.argv\n",
+    );
+    assert_eq!(
+        "[ '/dev/null', 'arg' ]\n",
+        String::from_utf8_lossy(&output.stdout)
+    );
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.starts_with("'x' is not defined\n"));
+    assert!(stderr.contains("'l' is not defined"));
+}
+
+#[test]
+fn repl_interrupt() {
+    use std::io::Write;
+
+    let dir = std::env::temp_dir().join("sod_repl_interrupt");
+    std::fs::create_dir_all(&dir).unwrap();
+    let script = dir.join("loop.sod");
+    std::fs::write(&script, "x = 0\nfor i in 0..100000000 {\nx = i\n}\n").unwrap();
+
+    let mut child = std::process::Command::new(env!("CARGO_BIN_EXE_sod"))
+        .env("SOD_HISTORY", "")
+        .stdin(std::process::Stdio::piped())
+        .stdout(std::process::Stdio::piped())
+        .stderr(std::process::Stdio::piped())
+        .spawn()
+        .unwrap();
+    let mut stdin = child.stdin.take().unwrap();
+    writeln!(stdin, ":load {}", script.display()).unwrap();
+    std::thread::sleep(std::time::Duration::from_millis(500));
+    std::process::Command::new("kill")
+        .args(["-INT", &child.id().to_string()])
+        .status()
+        .unwrap();
+    writeln!(stdin, "x > 0").unwrap();
+    drop(stdin);
+
+    let output = child.wait_with_output().unwrap();
+    assert!(output.status.success());
+    assert_eq!("true\n", String::from_utf8_lossy(&output.stdout));
+    // the loop is stopped at either of its lines
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.starts_with(&format!("{}:", script.display())));
+    assert!(stderr.ends_with(": interrupted\n"));
+}
+
+#[test]
+fn login_env() {
+    use std::io::Write;
+    use std::os::unix::fs::PermissionsExt;
+
+    assert_eq!(
+        vec![
+            ("ll".to_string(), "ls -l".to_string()),
+            ("say".to_string(), "echo it's".to_string()),
+            ("g".to_string(), "git".to_string()),
+        ],
+        sod::commands::parse_aliases(
+            "alias ll='ls -l'\nsay='echo it'\\''s'\ng=git\nrun-help=man\n"
+        )
+    );
+
+    // what the profile prints before the marker isn't taken as an alias
+    assert_eq!(
+        sod::commands::LoginEnv {
+            vars: vec![("A".to_string(), "1".to_string())],
+            aliases: vec![("g".to_string(), "git".to_string())],
+        },
+        sod::commands::parse_login_output(b"FOO=bar\nmark\ng=git\n\0A=1\0_=x\0", "mark")
+    );
+
+    let dir = std::env::temp_dir().join("sod_login_env");
+    std::fs::create_dir_all(&dir).unwrap();
+    let shell = dir.join("shell");
+    std::fs::write(
+        &shell,
+        "#!/bin/sh\necho FOO=bar\nalias() {\necho \"alias greet='echo hi'\"\n}\nexport SOD_LOGIN_VAR=imported\neval \"$4\"\n",
+    )
+    .unwrap();
+    std::fs::set_permissions(&shell, std::fs::Permissions::from_mode(0o755)).unwrap();
+
+    // --login may come after other options
+    let mut child = std::process::Command::new(env!("CARGO_BIN_EXE_sod"))
+        .args(["--norc", "--login"])
+        .env("SOD_HISTORY", "")
+        .env("SHELL", &shell)
+        .stdin(std::process::Stdio::piped())
+        .stdout(std::process::Stdio::piped())
+        .stderr(std::process::Stdio::piped())
+        .spawn()
+        .unwrap();
+    let mut stdin = child.stdin.take().unwrap();
+    stdin
+        .write_all(b"greet there\nenv('SOD_LOGIN_VAR')\nprintenv SOD_LOGIN_VAR\nFOO = 1\n")
+        .unwrap();
+    drop(stdin);
+    let output = child.wait_with_output().unwrap();
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    assert_eq!("", String::from_utf8_lossy(&output.stderr));
+    assert_eq!(
+        "hi there\n'hi there\\n'\n'imported'\nimported\n'imported\\n'\n",
+        String::from_utf8_lossy(&output.stdout)
+    );
+}
+
+#[test]
+fn rc_file() {
+    use std::io::Write;
+
+    let dir = std::env::temp_dir().join("sod_rc_file");
+    std::fs::create_dir_all(&dir).unwrap();
+    let rc = dir.join("sodrc");
+    std::fs::write(
+        &rc,
+        "alias greet = echo hello\nfunc double(n) {\nreturn n * 2\n}\nset_env(\"SOD_RC_TEST\", \"set\")\n",
+    )
+    .unwrap();
+    let script = dir.join("script.sod");
+    std::fs::write(
+        &script,
+        "greet\nx = double(2)\necho $x\ny = env(\"SOD_RC_TEST\")\necho $y\n",
+    )
+    .unwrap();
+
+    let run = |args: &[&str], input: &str| {
+        let mut child = std::process::Command::new(env!("CARGO_BIN_EXE_sod"))
+            .args(args)
+            .env("SOD_HISTORY", "")
+            .env("SOD_RC", &rc)
+            .stdin(std::process::Stdio::piped())
+            .stdout(std::process::Stdio::piped())
+            .stderr(std::process::Stdio::piped())
+            .spawn()
+            .unwrap();
+        let mut stdin = child.stdin.take().unwrap();
+        stdin.write_all(input.as_bytes()).unwrap();
+        drop(stdin);
+        child.wait_with_output().unwrap()
+    };
+
+    let output = run(&[], "double(4)\nenv(\"SOD_RC_TEST\")\n");
+    assert_eq!("8\n'set'\n", String::from_utf8_lossy(&output.stdout));
+
+    let output = run(&["--norc"], "double(4)\n");
+    assert_eq!(
+        "'double' is not defined\n",
+        String::from_utf8_lossy(&output.stderr)
+    );
+
+    let script = script.to_string_lossy().to_string();
+    let output = run(&["--rc", &script], "");
+    assert!(output.status.success());
+    assert_eq!("hello\n4\nset\n", String::from_utf8_lossy(&output.stdout));
+
+    let output = run(&[&script], "");
+    assert!(!output.status.success());
+}
+
+#[test]
+fn command_string() {
+    let output = std::process::Command::new(env!("CARGO_BIN_EXE_sod"))
+        .args([
+            "-c",
+            "x = 1 + 1\necho $x\nargs = process.argv\necho $args",
+            "a",
+        ])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    assert_eq!("2\n-c a\n", String::from_utf8_lossy(&output.stdout));
+
+    let status = std::process::Command::new(env!("CARGO_BIN_EXE_sod"))
+        .args(["-c", "process.exit(3)"])
+        .status()
+        .unwrap();
+    assert_eq!(Some(3), status.code());
+}
+
+#[test]
+fn command_line_options() {
+    let sod = |args: &[&str]| {
+        std::process::Command::new(env!("CARGO_BIN_EXE_sod"))
+            .args(args)
+            .output()
+            .unwrap()
+    };
+
+    let output = sod(&["--help"]);
+    assert!(output.status.success());
+    assert!(String::from_utf8_lossy(&output.stdout).starts_with("usage: sod"));
+
+    let output = sod(&["--version"]);
+    assert_eq!(
+        format!("sod {}\n", env!("CARGO_PKG_VERSION")),
+        String::from_utf8_lossy(&output.stdout)
+    );
+
+    let output = sod(&["--bogus"]);
+    assert_eq!(Some(2), output.status.code());
+    assert_eq!(
+        "unknown option --bogus, see sod --help\n",
+        String::from_utf8_lossy(&output.stderr)
+    );
+
+    let output = sod(&["missing.sod"]);
+    assert_eq!(Some(127), output.status.code());
+    assert_eq!(
+        "no such file: missing.sod\n",
+        String::from_utf8_lossy(&output.stderr)
+    );
+
+    let script = std::env::temp_dir().join("sod_command_line_options.sod");
+    std::fs::write(&script, "args = process.argv\necho $args\n").unwrap();
+    let script = script.to_string_lossy().to_string();
+
+    let output = sod(&[&script, "--dry-run", "-x"]);
+    assert_eq!(
+        format!("{} --dry-run -x\n", script),
+        String::from_utf8_lossy(&output.stdout)
+    );
+
+    let output = sod(&["--", &script, "--help"]);
+    assert_eq!(
+        format!("{} --help\n", script),
+        String::from_utf8_lossy(&output.stdout)
+    );
+}
+
+#[test]
+fn script_from_stdin() {
+    use std::io::Write;
+
+    let mut child = std::process::Command::new(env!("CARGO_BIN_EXE_sod"))
+        .args(["-", "a"])
+        .stdin(std::process::Stdio::piped())
+        .stdout(std::process::Stdio::piped())
+        .spawn()
+        .unwrap();
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(b"for i in 1..3 {\necho $i\n}\nargs = process.argv\necho $args\n")
+        .unwrap();
+
+    let output = child.wait_with_output().unwrap();
+    assert!(output.status.success());
+    assert_eq!("1\n2\n- a\n", String::from_utf8_lossy(&output.stdout));
+}
+
+#[test]
+fn trace() {
+    let script = std::env::temp_dir().join("sod_trace.sod");
+    std::fs::write(
+        &script,
+        "token = secret(\"hunter2\")\nfunc greet(who) {\necho hi $who\n}\nfor i in 1..3 {\ngreet(i * 2)\n}\nx = echo $token\nt = secret(\"e\")\necho hello $t\n",
+    )
+    .unwrap();
+
+    let output = std::process::Command::new(env!("CARGO_BIN_EXE_sod"))
+        .arg("--trace")
+        .arg(&script)
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    assert_eq!(
+        "hi 2\nhi 4\nhunter2\nhello e\n",
+        String::from_utf8_lossy(&output.stdout)
+    );
+    assert_eq!(
+        "+ token = secret(\"hunter2\")\n\
+         + func greet(who) {\n\
+         + for i in 1..3 {\n\
+         +   greet(i * 2)\n\
+         +     echo hi 2\n\
+         +   greet(i * 2)\n\
+         +     echo hi 4\n\
+         + x = echo $token\n\
+         + echo *****\n\
+         + t = secret(\"e\")\n\
+         + echo hello *****\n",
+        String::from_utf8_lossy(&output.stderr)
+    );
+}
+
+#[test]
+fn script_exit_status() {
+    let status = |src: &str| {
+        std::process::Command::new(env!("CARGO_BIN_EXE_sod"))
+            .args(["-c", src])
+            .output()
+            .unwrap()
+            .status
+            .code()
+    };
+
+    assert_eq!(Some(0), status("x = 1"));
+    assert_eq!(Some(4), status("echo a\nsh -c 'exit 4'"));
+    assert_eq!(Some(0), status("sh -c 'exit 4'\necho a"));
+    assert_eq!(Some(0), status("x = sh -c 'exit 4'"));
+    assert_eq!(Some(3), status("if true {\nsh -c 'exit 3'\n}"));
+    assert_eq!(Some(3), status("for i in 1..2 {\nsh -c 'exit 3'\n}"));
+    assert_eq!(Some(6), status("func f() {\nsh -c 'exit 6'\n}\nf()"));
+    assert_eq!(Some(0), status("if true {\nsh -c 'exit 3'\nx = 1\n}"));
+    assert_eq!(Some(143), status("sh -c 'kill -TERM $PPID'"));
+    assert_eq!(Some(1), status("not_defined()"));
+    assert_eq!(Some(2), status("x = 1 +"));
+    assert_eq!(Some(5), status("process.exit(5)"));
+}
+
+#[test]
+fn error_locations() {
+    let script = std::env::temp_dir().join("sod_error_locations.sod");
+    let stderr = |src: &str| {
+        std::fs::write(&script, src).unwrap();
+        let output = std::process::Command::new(env!("CARGO_BIN_EXE_sod"))
+            .arg(&script)
+            .output()
+            .unwrap();
+        String::from_utf8_lossy(&output.stderr).to_string()
+    };
+
+    let name = script.display();
+    assert_eq!(
+        format!("{}:3:3: 'z' is not defined\n", name),
+        stderr("x = 1\nif x {\n  y = z\n}\n")
+    );
+    assert_eq!(
+        format!(
+            "{}:2:7: unexpected token '.', expected an identifier\n",
+            name
+        ),
+        stderr("x = 1\ny = x..\n")
+    );
+    std::fs::remove_file(&script).unwrap();
+}
+
+#[test]
+fn lexer_errors() {
+    let error = Parser::new("x = 99999999999999999999999")
+        .parse()
+        .unwrap_err();
+    assert!(error.message.starts_with("invalid number"), "{}", error);
+    assert_eq!(ParseErrorKind::InvalidToken, error.kind);
+    assert!(Parser::new("x = 1 € 2").parse().is_err());
+
+    let output = std::process::Command::new(env!("CARGO_BIN_EXE_sod"))
+        .args(["-c", "echo héllo €"])
+        .output()
+        .unwrap();
+    assert_eq!("héllo €\n", String::from_utf8_lossy(&output.stdout));
+    assert_eq!(Some(0), output.status.code());
+}
+
+#[test]
+fn error_kinds() {
+    let error = Parser::new("x = 1\ny = (2 +").parse().unwrap_err();
+    assert_eq!(ParseErrorKind::UnexpectedEof, error.kind);
+    let error = Parser::new("x = 1\ny = ]").parse().unwrap_err();
+    assert_eq!(ParseErrorKind::UnexpectedToken, error.kind);
+    assert_eq!(Span::new(10, 11), error.span);
+    assert_eq!((2, 5), error.span.line_column("x = 1\ny = ]"));
+    let error = Parser::new("trap 'x = 1' NOPE").parse().unwrap_err();
+    assert_eq!(ParseErrorKind::Invalid, error.kind);
+
+    let kind = |src: &str| {
+        let program = Parser::new(src).parse().unwrap();
+        ASTEvaluator::new(vec![]).eval(program).unwrap_err().kind
+    };
+    assert_eq!(RuntimeErrorKind::Undefined, kind("y = x"));
+    assert_eq!(
+        RuntimeErrorKind::Undefined,
+        kind("func f(x) {\nreturn y\n}\nxs = [1]\nxs.map(f)")
+    );
+    assert_eq!(RuntimeErrorKind::Type, kind("for x in 1 {\n}"));
+    assert_eq!(RuntimeErrorKind::Type, kind("x = 1\nx.a = 2"));
+    assert_eq!(RuntimeErrorKind::Argument, kind("func f(a) {\n}\nf()"));
+    assert_eq!(RuntimeErrorKind::NoValue, kind("x = interactive true"));
+
+    let failed = |src: &str| {
+        let program = Parser::new(src).parse().unwrap();
+        let span = ASTEvaluator::new(vec![]).eval(program).unwrap_err().span;
+        span.map(|span| src[span.start..span.end].to_string())
+    };
+    assert_eq!(Some("y = z".to_string()), failed("x = 1\ny = z # no z"));
+    assert_eq!(
+        Some("return y".to_string()),
+        failed("func f() {\n  return y\n}\nf()")
+    );
+    assert_eq!(
+        Some("echo ${nope}".to_string()),
+        failed("if true {\n  ls\n  echo ${nope}\n}")
+    );
+}
+
+#[test]
+fn unclosed_brackets() {
+    let error = |src: &str| Parser::new(src).parse().unwrap_err();
+
+    let e = error("x = 1\nif x > 0 {\necho hi\n");
+    assert_eq!("unclosed '{' opened at line 2", e.message);
+    assert_eq!(ParseErrorKind::UnexpectedEof, e.kind);
+    assert_eq!(Span::new(15, 16), e.span);
+    assert_eq!("unclosed '(' opened at line 1", error("x = (1 + 2").message);
+    assert_eq!("unclosed '[' opened at line 1", error("x = [1, 2").message);
+    // a line ends inside the bracket before the source does
+    let e = error("x = (1 + 2\n");
+    assert_eq!("unclosed '(' opened at line 1", e.message);
+    assert_eq!(Span::new(4, 5), e.span);
+    assert_eq!(
+        "unclosed '[' opened at line 1",
+        error("x = [1, 2\n").message
+    );
+    assert_eq!(
+        "unclosed '(' opened at line 2",
+        error("y = 1\nx = [foo(1\ny = 2").message
+    );
+    assert_eq!("unexpected token '\\n'", error("x = 1 +\ny = 2").message);
+    assert_eq!(
+        "unterminated string starting at line 2",
+        error("x = 1\ny = \"abc\n").message
+    );
+}
+
+#[test]
+fn depth_limits() {
+    let nested = |depth: usize| format!("x = {}1{}", "(".repeat(depth), ")".repeat(depth));
+    assert!(Parser::new(&nested(100)).parse().is_ok());
+    let error = Parser::new(&nested(MAX_DEPTH)).parse().unwrap_err();
+    assert_eq!(ParseErrorKind::Invalid, error.kind);
+    assert_eq!(
+        format!("expression nested more than {} levels deep", MAX_DEPTH),
+        error.message
+    );
+    let chain = format!("x = 1{}", " + 1".repeat(MAX_DEPTH));
+    assert!(Parser::new(&chain).parse().is_err());
+    let depth = MAX_DEPTH + 1;
+    let blocks = format!("{}{}", "if true {\n".repeat(depth), "}\n".repeat(depth));
+    assert!(Parser::new(&blocks).parse().is_err());
+
+    // calls are run by the binary, which has the stack for them
+    let run = |depth: usize| {
+        let src = format!(
+            "func down(n) {{\nif n == 0 {{\nreturn 0\n}}\nreturn 1 + down(n - 1)\n}}\nx = down({})\necho $x",
+            depth
+        );
+        std::process::Command::new(env!("CARGO_BIN_EXE_sod"))
+            .args(["-c", &src])
+            .output()
+            .unwrap()
+    };
+    let output = run(MAX_CALL_DEPTH - 1);
+    assert_eq!(
+        format!("{}\n", MAX_CALL_DEPTH - 1),
+        String::from_utf8_lossy(&output.stdout)
+    );
+    let output = run(MAX_CALL_DEPTH);
+    assert_eq!(
+        format!(
+            "-c:5:1: maximum call depth of {} exceeded in function down\n",
+            MAX_CALL_DEPTH
+        ),
+        String::from_utf8_lossy(&output.stderr)
+    );
+    assert_eq!(Some(1), output.status.code());
+}
+
+#[test]
+fn resource_limits() {
+    let run = |limits: Limits, vm: bool, src: &str| {
+        let mut evaluator = ASTEvaluator::new(vec![]);
+        evaluator.set_limits(limits);
+        evaluator.set_vm(vm);
+        evaluator.eval(Parser::new(src).parse().unwrap())
+    };
+
+    for vm in [false, true] {
+        let limits = Limits::new().loop_iterations(100);
+        assert!(run(limits.clone(), vm, "for i in 0..100 {\nx = i\n}").is_ok());
+        let error = run(
+            limits,
+            vm,
+            "for i in 0..10 {\nfor j in 0..10 {\nx = j\n}\n}",
+        )
+        .unwrap_err();
+        assert_eq!(RuntimeErrorKind::Limit, error.kind);
+        assert_eq!("limit of 100 loop iterations exceeded", error.message);
+    }
+
+    let error = run(
+        Limits::new().call_depth(10),
+        false,
+        "func down(n) {\nif n == 0 {\nreturn 0\n}\nreturn 1 + down(n - 1)\n}\ndown(20)",
+    )
+    .unwrap_err();
+    assert_eq!(
+        "maximum call depth of 10 exceeded in function down",
+        error.message
+    );
+
+    let globals = ASTEvaluator::new(vec![]).symbol_table().len();
+    let limits = Limits::new().symbols(globals + 2);
+    assert!(run(limits.clone(), false, "a = 1\nb = 2").is_ok());
+    let error = run(limits, false, "a = 1\nb = 2\nc = 3\nd = 4").unwrap_err();
+    assert_eq!(RuntimeErrorKind::Limit, error.kind);
+    assert_eq!(
+        format!("limit of {} symbols exceeded", globals + 2),
+        error.message
+    );
+
+    // a tail call runs in place of its caller, but still counts against time
+    let error = run(
+        Limits::new().timeout(Duration::from_millis(50)),
+        false,
+        "func spin() {\nreturn spin()\n}\nspin()",
+    )
+    .unwrap_err();
+    assert_eq!(RuntimeErrorKind::Limit, error.kind);
+    assert_eq!("time limit of 50ms exceeded", error.message);
+
+    // the limits start again with each program
+    let mut evaluator = ASTEvaluator::new(vec![]);
+    evaluator.set_limits(Limits::new().loop_iterations(10));
+    for _ in 0..3 {
+        let program = Parser::new("for i in 0..10 {\nx = i\n}").parse().unwrap();
+        assert!(evaluator.eval(program).is_ok());
+    }
+}
+
+#[test]
+fn command_executor() {
+    let ran = Rc::new(RefCell::new(vec![]));
+    let record = ran.clone();
+    let mut evaluator = ASTEvaluator::new(vec![]);
+    evaluator.set_command_executor(move |cmd: &CommandLine, _: Echo| {
+        record.borrow_mut().push(cmd.to_string());
+        let mut result = CommandResult::skipped(cmd);
+        result.stdout = b"mocked\n".to_vec();
+        result.status = Some(if cmd.to_string().starts_with("false") {
+            1
+        } else {
+            0
+        });
+        Ok(result)
+    });
+
+    let src = r#"name = "a b"
+x = `echo $name`
+j = spawn sleep 10
+r = j.wait()
+failed = run("false")
+results = parallel(["ls", "pwd"])
+exec("git", ["status"])
+x"#;
+    let values = evaluator.eval(Parser::new(src).parse().unwrap()).unwrap();
+    assert_eq!(
+        Some(&Some(new_string_symbol!("mocked\n".to_string()))),
+        values.last()
+    );
+    assert_eq!(
+        vec!["echo 'a b'", "sleep 10", "false", "ls", "pwd", "git status"],
+        *ran.borrow()
+    );
+    assert_eq!(Some(0), evaluator.last_status());
+
+    // pipelines through functions need commands streaming as they run
+    let src = "func f(line) {\nreturn line\n}\nls | f";
+    let error = evaluator
+        .eval(Parser::new(src).parse().unwrap())
+        .unwrap_err();
+    assert_eq!(RuntimeErrorKind::Command, error.kind);
+}
+
+#[test]
+fn output_writers() {
+    let stdout = OutputBuffer::new();
+    let stderr = OutputBuffer::new();
+    let mut evaluator = ASTEvaluator::new(vec![]);
+    evaluator.set_stdout(stdout.clone());
+    evaluator.set_stderr(stderr.clone());
+    evaluator.set_trace(true);
+
+    let src = "x = 1\necho hello\nls /missing-dir";
+    evaluator.eval(Parser::new(src).parse().unwrap()).unwrap();
+    assert_eq!(b"hello\n".to_vec(), stdout.contents());
+    let stderr = String::from_utf8(stderr.contents()).unwrap();
+    assert!(stderr.starts_with("+ x = 1\n+ echo hello\n+ ls /missing-dir\n"));
+    assert!(stderr.contains("missing-dir"));
+
+    // what a dry run prints goes to the same place
+    let stdout = OutputBuffer::new();
+    let mut evaluator = ASTEvaluator::new(vec![]);
+    evaluator.set_stdout(stdout.clone());
+    evaluator.set_dry_run(true);
+    let src = "name = \"a b\"\nrm -rf $name";
+    evaluator.eval(Parser::new(src).parse().unwrap()).unwrap();
+    assert_eq!(b"rm -rf 'a b'\n".to_vec(), stdout.contents());
+}
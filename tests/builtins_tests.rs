@@ -0,0 +1,508 @@
+use common::utils::{assert_expr, eval_expr};
+use sod::ast::evaluator::ASTEvaluator;
+use sod::new_string_symbol;
+use sod::parser::Parser;
+use sod::symbol::symbol::{List, StringSymbol, Symbol};
+mod common;
+
+#[test]
+fn parse_num() {
+    assert_expr("parse_num('42')", Symbol::Number(42.0));
+    assert_expr("parse_num(' -1.5\n')", Symbol::Number(-1.5));
+}
+
+#[test]
+#[should_panic]
+fn parse_num_invalid_panic() {
+    eval_expr("parse_num('abc')");
+}
+
+#[test]
+#[cfg(feature = "json")]
+fn json() {
+    assert_expr(
+        r#"x = json_parse('{"a": [1, true, null, "b"]}')
+y = x.a
+y[0] + y.len()"#,
+        Symbol::Number(5.0),
+    );
+    assert_expr(
+        r#"json_stringify([1, 1.5, "a", none, {b: false}])"#,
+        new_string_symbol!(r#"[1,1.5,"a",null,{"b":false}]"#.to_string()),
+    );
+}
+
+#[test]
+#[cfg(feature = "json")]
+fn json_key_order() {
+    assert_expr(
+        "x = json_parse('{\"b\": 1, \"a\": 2}')\njson_stringify(x)",
+        new_string_symbol!("{\"b\":1,\"a\":2}".to_string()),
+    );
+}
+
+#[test]
+#[cfg(feature = "json")]
+#[should_panic]
+fn json_parse_invalid_panic() {
+    eval_expr("json_parse('{')");
+}
+
+#[test]
+#[cfg(feature = "json")]
+fn json_values() {
+    use serde_json::json;
+    use sod::symbol::symbol::{Object, Range};
+
+    let value = json!({"name": "sod", "tags": ["a", "b"], "port": 80, "ratio": 0.5, "extra": null});
+    let symbol: Symbol = serde_json::from_value(value.clone()).unwrap();
+    assert_eq!(
+        Symbol::Object(Object::from(vec![
+            ("name", new_string_symbol!("sod".to_string())),
+            (
+                "tags",
+                Symbol::List(List::from(vec![
+                    new_string_symbol!("a".to_string()),
+                    new_string_symbol!("b".to_string()),
+                ]))
+            ),
+            ("port", Symbol::Number(80.0)),
+            ("ratio", Symbol::Number(0.5)),
+            ("extra", Symbol::None),
+        ])),
+        symbol
+    );
+    assert_eq!(value, serde_json::to_value(&symbol).unwrap());
+
+    assert_eq!(
+        json!([0, 2, 4]),
+        serde_json::to_value(Symbol::Range(Range::new(0, 5, Some(2)))).unwrap()
+    );
+    assert!(serde_json::to_value(Symbol::Number(f64::NAN)).is_err());
+    assert!(serde_json::to_value(Symbol::Bytes(vec![1])).is_err());
+}
+
+#[test]
+fn secrets() {
+    assert_expr(
+        "x = secret('hunter2')\nx",
+        Symbol::Secret(StringSymbol::new("hunter2".to_string())),
+    );
+    assert_expr(
+        "x = secret('hunter2')\n\"token=$x\"",
+        Symbol::Secret(StringSymbol::new("token=hunter2".to_string())),
+    );
+    assert_expr(
+        "x = secret('hunter2')\necho $x",
+        new_string_symbol!("hunter2\n".to_string()),
+    );
+
+    let evaluation = eval_expr("secret('hunter2')");
+    let symbol = evaluation.last().unwrap().as_ref().unwrap();
+    assert_eq!("*****", symbol.to_string());
+    assert_eq!("Secret(*****)", format!("{:?}", symbol));
+
+    let evaluation = eval_expr("[secret('hunter2'), {key: secret('hunter2')}]");
+    let symbol = evaluation.last().unwrap().as_ref().unwrap();
+    assert!(!format!("{:?}", symbol).contains("hunter2"));
+    assert!(!symbol.to_string().contains("hunter2"));
+}
+
+#[test]
+fn interpreter_info() {
+    assert_expr(
+        "sod.version",
+        new_string_symbol!(env!("CARGO_PKG_VERSION").to_string()),
+    );
+    assert_expr("sod.has('not_a_feature')", Symbol::Boolean(false));
+    assert_expr(
+        "sod.features.len()",
+        Symbol::Number(sod::builtins::interpreter::features().len() as f64),
+    );
+    assert_expr("sod.has('json')", Symbol::Boolean(cfg!(feature = "json")));
+}
+
+#[test]
+fn hashing() {
+    assert_expr(
+        "sha256('abc')",
+        new_string_symbol!(
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad".to_string()
+        ),
+    );
+    assert_expr(
+        "md5('abc')",
+        new_string_symbol!("900150983cd24fb0d6963f7d28e17f72".to_string()),
+    );
+    assert_expr("crc32('abc')", new_string_symbol!("352441c2".to_string()));
+
+    let path = std::env::temp_dir().join("sod_hash_file_test");
+    std::fs::write(&path, "abc").unwrap();
+    assert_expr(
+        &format!("hash_file('{}', 'md5')", path.display()),
+        new_string_symbol!("900150983cd24fb0d6963f7d28e17f72".to_string()),
+    );
+    std::fs::remove_file(path).unwrap();
+}
+
+#[test]
+#[should_panic]
+fn hash_file_unknown_algorithm_panic() {
+    eval_expr("hash_file('Cargo.toml', 'sha1')");
+}
+
+/// Serves a single canned response and returns the raw request it received.
+#[cfg(feature = "http")]
+fn serve_once(response: &'static str) -> (String, std::thread::JoinHandle<String>) {
+    use std::io::{Read, Write};
+
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    let handle = std::thread::spawn(move || {
+        let (mut stream, _) = listener.accept().unwrap();
+        let mut request = vec![0; 4096];
+        let read = stream.read(&mut request).unwrap();
+        stream.write_all(response.as_bytes()).unwrap();
+        String::from_utf8_lossy(&request[..read]).to_string()
+    });
+    (url, handle)
+}
+
+#[test]
+#[cfg(feature = "http")]
+fn http() {
+    let (url, server) =
+        serve_once("HTTP/1.1 200 OK\r\nServer: test\r\nContent-Length: 2\r\n\r\nok");
+    common::utils::assert_exprs(
+        vec![
+            &format!(
+                "r = http_get('{}/health', {{'X-Token': 'abc'}})\nr.body",
+                url
+            ),
+            "r.status",
+            "r.headers.server",
+        ],
+        vec![
+            new_string_symbol!("ok".to_string()),
+            Symbol::Number(200.0),
+            new_string_symbol!("test".to_string()),
+        ],
+    );
+    let request = server.join().unwrap();
+    assert!(request.starts_with("GET /health"));
+    assert!(request.to_lowercase().contains("x-token: abc"));
+
+    let (url, server) = serve_once("HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\n\r\n");
+    assert_expr(
+        &format!("r = http_post('{}/hook', 'payload')\nr.status", url),
+        Symbol::Number(404.0),
+    );
+    let request = server.join().unwrap();
+    assert!(request.starts_with("POST /hook"));
+}
+
+#[test]
+#[cfg(feature = "csv")]
+fn csv() {
+    assert_expr(
+        "rows = csv_parse('a,b\n1,\"x, y\"\n')\nrows[1][1]",
+        new_string_symbol!("x, y".to_string()),
+    );
+    assert_expr(
+        "rows = csv_parse('name,size\na.txt,10\nb.txt,20', true)\nrows[1].size",
+        new_string_symbol!("20".to_string()),
+    );
+    assert_expr(
+        "csv_stringify([['a', 1], ['b, c', none]])",
+        new_string_symbol!("a,1\n\"b, c\",\n".to_string()),
+    );
+    assert_expr(
+        "csv_stringify([{name: 'a.txt', size: 10}])",
+        new_string_symbol!("name,size\na.txt,10\n".to_string()),
+    );
+}
+
+#[test]
+#[cfg(feature = "yaml")]
+fn yaml() {
+    assert_expr(
+        "doc = yaml_parse('spec:\n  replicas: 3\n  ports: [80, 443]\n')\ndoc.spec.ports.len()",
+        Symbol::Number(2.0),
+    );
+    assert_expr(
+        "docs = yaml_parse('kind: Service\n---\nkind: Deployment\n')\ndocs[1].kind",
+        new_string_symbol!("Deployment".to_string()),
+    );
+    // keys that aren't strings and tagged values are read as plain values
+    assert_expr(
+        "json_stringify(yaml_parse('1: one\nref: !Ref bucket\n'))",
+        new_string_symbol!(r#"{"1":"one","ref":"bucket"}"#.to_string()),
+    );
+}
+
+#[test]
+#[cfg(feature = "toml")]
+fn toml() {
+    assert_expr(
+        "doc = toml_parse('[package]\nname = \"sod\"\nversion = \"0.1.0\"\n')\ndoc.package.name",
+        new_string_symbol!("sod".to_string()),
+    );
+}
+
+#[test]
+#[cfg(feature = "toml")]
+#[should_panic]
+fn toml_parse_invalid_panic() {
+    eval_expr("toml_parse('[package')");
+}
+
+#[test]
+fn args_parse() {
+    let spec = "{name: {default: 'world'}, count: {default: 1, short: 'c'}, dry_run: {flag: true}, env: {required: true}}";
+    let parse = |argv: &str, property: &str| {
+        let evaluation = eval_expr(&format!(
+            "args = args_parse({}, {})\nargs.{}",
+            spec, argv, property
+        ));
+        evaluation.last().unwrap().clone().unwrap()
+    };
+
+    let argv = "['--env', 'prod', 'a.txt', '-c', '3', '--dry-run', '--', '--name']";
+    assert_eq!(new_string_symbol!("prod".to_string()), parse(argv, "env"));
+    assert_eq!(Symbol::Number(3.0), parse(argv, "count"));
+    assert_eq!(Symbol::Boolean(true), parse(argv, "dry_run"));
+    assert_eq!(new_string_symbol!("world".to_string()), parse(argv, "name"));
+    assert_eq!(
+        Symbol::List(List::from(vec![
+            new_string_symbol!("a.txt".to_string()),
+            new_string_symbol!("--name".to_string()),
+        ])),
+        parse(argv, "positionals")
+    );
+
+    let argv = "['--env=dev', '--name', 'sod']";
+    assert_eq!(new_string_symbol!("dev".to_string()), parse(argv, "env"));
+    assert_eq!(new_string_symbol!("sod".to_string()), parse(argv, "name"));
+    assert_eq!(Symbol::Boolean(false), parse(argv, "dry_run"));
+}
+
+#[test]
+fn args_parse_script_args() {
+    let program =
+        Parser::new("args = args_parse({verbose: {flag: true, short: 'v'}})\nargs.verbose")
+            .parse()
+            .unwrap();
+    let mut evaluator = ASTEvaluator::new(vec!["script.sod".to_string(), "-v".to_string()]);
+    let evaluation = evaluator.eval(program).unwrap();
+    assert_eq!(&Some(Symbol::Boolean(true)), evaluation.last().unwrap());
+}
+
+#[test]
+#[should_panic]
+fn args_parse_missing_required_panic() {
+    eval_expr("args_parse({env: {required: true}}, [])");
+}
+
+#[test]
+#[should_panic]
+fn args_parse_unknown_option_panic() {
+    eval_expr("args_parse({}, ['--env'])");
+}
+
+#[test]
+fn which() {
+    let expected = String::from_utf8(
+        std::process::Command::new("sh")
+            .args(["-c", "command -v sh"])
+            .output()
+            .unwrap()
+            .stdout,
+    )
+    .unwrap();
+    assert_expr(
+        "which('sh')",
+        new_string_symbol!(expected.trim().to_string()),
+    );
+    assert_expr("which('sod_not_a_command')", Symbol::None);
+}
+
+#[test]
+fn exec() {
+    assert_expr(
+        "r = exec('echo', ['a b', '$HOME;', 1])\nr.stdout",
+        new_string_symbol!("a b $HOME; 1\n".to_string()),
+    );
+    assert_expr(
+        "r = exec('echo', ['a b', \"it's\"])\nr.command",
+        new_string_symbol!("echo 'a b' 'it'\\''s'".to_string()),
+    );
+    assert_expr(
+        "r = exec('sh', ['-c', 'exit 4'])\nr.status",
+        Symbol::Number(4.0),
+    );
+    assert_expr("r = exec('true')\nr.success", Symbol::Boolean(true));
+}
+
+#[test]
+#[should_panic]
+fn exec_missing_program_panic() {
+    eval_expr("exec('sod_not_a_command')");
+}
+
+#[test]
+fn parallel() {
+    assert_expr(
+        "r = parallel(['sleep 0.3; echo a', 'echo b', 'exit 3'])\n[r[0].stdout, r[1].stdout, r[2].status]",
+        Symbol::List(List::from(vec![
+            new_string_symbol!("a\n".to_string()),
+            new_string_symbol!("b\n".to_string()),
+            Symbol::Number(3.0),
+        ])),
+    );
+    assert_expr(
+        "func cmd(host) {\nreturn \"echo $host\"\n}\nr = parallel(['web1', 'web2'], cmd, 1)\nr[1].stdout",
+        new_string_symbol!("web2\n".to_string()),
+    );
+    assert_expr("parallel([]).len()", Symbol::Number(0.0));
+}
+
+#[test]
+fn parallel_limit() {
+    let cmds = "cmds = ['sleep 0.4', 'sleep 0.4', 'sleep 0.4']";
+    assert_expr(
+        &format!(
+            "{}\nfunc all() {{\nparallel(cmds)\n}}\nmeasure(all) < 1",
+            cmds
+        ),
+        Symbol::Boolean(true),
+    );
+    assert_expr(
+        &format!(
+            "{}\nfunc one() {{\nparallel(cmds, 1)\n}}\nmeasure(one) >= 1.2",
+            cmds
+        ),
+        Symbol::Boolean(true),
+    );
+}
+
+#[test]
+#[should_panic]
+fn parallel_command_type_panic() {
+    eval_expr("parallel(['echo a', 1])");
+}
+
+#[test]
+#[should_panic]
+fn parallel_limit_panic() {
+    eval_expr("parallel(['echo a'], 0)");
+}
+
+#[test]
+fn system_info() {
+    let run = |cmd: &str| {
+        let output = std::process::Command::new(cmd).output().unwrap().stdout;
+        String::from_utf8(output).unwrap().trim().to_string()
+    };
+
+    assert_expr("hostname()", new_string_symbol!(run("hostname")));
+    assert_expr("whoami()", new_string_symbol!(run("whoami")));
+    assert_expr("os()", new_string_symbol!(std::env::consts::OS.to_string()));
+    assert_expr("cpus() > 0", Symbol::Boolean(true));
+}
+
+#[test]
+fn prompts() {
+    use sod::builtins::prompt::{read_confirm, read_select};
+
+    let confirm = |answers: &str| {
+        let mut output = vec![];
+        read_confirm(&mut answers.as_bytes(), &mut output, "Deploy?").unwrap()
+    };
+    assert!(confirm("y\n"));
+    assert!(confirm("maybe\nYes\n"));
+    assert!(!confirm("\n"));
+    assert!(!confirm(""));
+
+    let options = vec![
+        new_string_symbol!("dev".to_string()),
+        new_string_symbol!("prod".to_string()),
+    ];
+    let mut output = vec![];
+    let choice = read_select(
+        &mut "3\nprod\n2\n".as_bytes(),
+        &mut output,
+        "env:",
+        &options,
+    );
+    assert_eq!(Ok(1), choice);
+    assert!(String::from_utf8(output)
+        .unwrap()
+        .starts_with("1) dev\n2) prod\n"));
+}
+
+#[test]
+#[should_panic]
+fn select_empty_panic() {
+    eval_expr("select('env:', [])");
+}
+
+#[test]
+fn tables() {
+    assert_expr(
+        "table([{name: 'a.txt', size: 10}, {name: 'long.txt', size: 200}])",
+        new_string_symbol!("name      size\na.txt     10\nlong.txt  200\n".to_string()),
+    );
+    assert_expr(
+        "table([{name: 'a.txt', size: 10}], ['size', 'name', 'owner'])",
+        new_string_symbol!("size  name   owner\n10    a.txt\n".to_string()),
+    );
+    assert_expr(
+        "table([['a', 1], ['bb', none, 3]])",
+        new_string_symbol!("a   1\nbb     3\n".to_string()),
+    );
+}
+
+#[test]
+fn bytes() {
+    assert_expr("b = bytes('abc')\nb.len()", Symbol::Number(3.0));
+    assert_expr("b = bytes('abc')\nb[1]", Symbol::Number(98.0));
+    assert_expr(
+        "b = bytes([104, 105, 255])\nc = b.slice(0, 2)\nc.to_string()",
+        new_string_symbol!("hi".to_string()),
+    );
+    assert_expr(
+        "b = bytes([104, 255])\nb.to_string('hex')",
+        new_string_symbol!("68ff".to_string()),
+    );
+    assert_expr(
+        "b = bytes([104, 255])\nb.to_string('latin1')",
+        new_string_symbol!("hÿ".to_string()),
+    );
+    assert_expr(
+        "b = bytes([104, 255])\nb.to_string('lossy')",
+        new_string_symbol!("h\u{fffd}".to_string()),
+    );
+}
+
+#[test]
+#[should_panic]
+fn bytes_invalid_utf8_panic() {
+    eval_expr("b = bytes([255])\nb.to_string()");
+}
+
+#[test]
+fn read_file() {
+    let path = std::env::temp_dir().join("sod_read_file_test");
+    std::fs::write(&path, [0x73, 0x6f, 0x64]).unwrap();
+    assert_expr(
+        &format!("read_file('{}')", path.display()),
+        new_string_symbol!("sod".to_string()),
+    );
+
+    std::fs::write(&path, [0xff, 0x00]).unwrap();
+    assert_expr(
+        &format!("read_file('{}')", path.display()),
+        Symbol::Bytes(vec![0xff, 0x00]),
+    );
+    std::fs::remove_file(path).unwrap();
+}
@@ -0,0 +1,301 @@
+use sod::error::{Error, RuntimeErrorKind};
+#[cfg(feature = "async")]
+use sod::executor::TokioExecutor;
+use sod::limits::Limits;
+use sod::symbol::symbol::{Object, Range, Symbol};
+use sod::Engine;
+use std::cell::Cell;
+use std::rc::Rc;
+#[cfg(feature = "async")]
+use std::sync::atomic::{AtomicUsize, Ordering};
+#[cfg(feature = "async")]
+use std::sync::Arc;
+#[cfg(feature = "async")]
+use std::time::{Duration, Instant};
+
+#[test]
+fn eval_str() {
+    let mut engine = Engine::new();
+    assert_eq!(
+        Some(Symbol::Number(3.0)),
+        engine.eval_str("x = 1\nx + 2").unwrap()
+    );
+    assert_eq!(None, engine.eval_str("y = 2").unwrap());
+    // what one evaluation defines is kept for the next
+    assert_eq!(Some(Symbol::Number(3.0)), engine.eval_str("x + y").unwrap());
+
+    assert!(matches!(engine.eval_str("x = (1"), Err(Error::Parse(_))));
+    assert!(matches!(
+        engine.eval_str("missing + 1"),
+        Err(Error::Runtime(_))
+    ));
+}
+
+#[test]
+fn variables() {
+    let mut engine = Engine::new();
+    engine.set("name", "sod".into());
+    assert_eq!(
+        Some(Symbol::from("hello sod")),
+        engine.eval_str("\"hello $name\"").unwrap()
+    );
+
+    engine.eval_str("count = 41\ncount = count + 1").unwrap();
+    assert_eq!(Some(&Symbol::Number(42.0)), engine.get("count"));
+    assert_eq!(None, engine.get("missing"));
+}
+
+#[test]
+fn calls() {
+    let mut engine = Engine::new();
+    engine
+        .eval_str("base = 10\nfunc add(x) {\nreturn base + x\n}")
+        .unwrap();
+    assert_eq!(
+        Symbol::Number(15.0),
+        engine.call("add", vec![5.into()]).unwrap()
+    );
+    assert_eq!(
+        Symbol::Number(12.0),
+        engine.call("parse_num", vec!["12".into()]).unwrap()
+    );
+    // functions declared in one evaluation are called, rather than run as
+    // commands, in the next
+    engine.eval_str("func hostname() {\nreturn 42\n}").unwrap();
+    assert_eq!(
+        Some(Symbol::Number(42.0)),
+        engine.eval_str("hostname").unwrap()
+    );
+
+    match engine.call("base", vec![]) {
+        Err(Error::Runtime(e)) => {
+            assert_eq!(RuntimeErrorKind::Type, e.kind);
+            assert_eq!("'base' is a number, not a function", e.message);
+        }
+        result => panic!("expected an error, found {:?}", result),
+    }
+    match engine.call("missing", vec![]) {
+        Err(Error::Runtime(e)) => assert_eq!(RuntimeErrorKind::Undefined, e.kind),
+        result => panic!("expected an error, found {:?}", result),
+    }
+}
+
+#[test]
+fn registered_functions() {
+    let mut engine = Engine::new();
+    engine.register_fn("fetch_config", |mut args| {
+        let key = String::try_from(args.pop().unwrap_or(Symbol::None))?;
+        match key.as_str() {
+            "retries" => Ok(3.into()),
+            _ => Err(format!("no config named {}", key)),
+        }
+    });
+    assert_eq!(
+        Some(Symbol::Number(4.0)),
+        engine.eval_str("fetch_config(\"retries\") + 1").unwrap()
+    );
+    match engine.eval_str("fetch_config(\"port\")") {
+        Err(Error::Runtime(e)) => assert_eq!("no config named port", e.message),
+        result => panic!("expected an error, found {:?}", result),
+    }
+
+    // state is kept between calls, and names may have a namespace
+    let calls = Rc::new(Cell::new(0));
+    let counter = calls.clone();
+    engine.register_fn("app.tick", move |_| {
+        counter.set(counter.get() + 1);
+        Ok(counter.get().into())
+    });
+    engine.eval_str("for i in 0..3 {\napp.tick()\n}").unwrap();
+    assert_eq!(3, calls.get());
+    assert_eq!(
+        Symbol::Number(4.0),
+        engine.call("app.tick", vec![]).unwrap()
+    );
+
+    // sod functions are called before registered ones, which are called
+    // before builtins
+    engine.register_fn("parse_num", |_| Ok(Symbol::Number(0.0)));
+    assert_eq!(
+        Some(Symbol::Number(0.0)),
+        engine.eval_str("parse_num(\"12\")").unwrap()
+    );
+    engine
+        .eval_str("func fetch_config(key) {\nreturn 10\n}")
+        .unwrap();
+    assert_eq!(
+        Some(Symbol::Number(10.0)),
+        engine.eval_str("fetch_config(\"retries\")").unwrap()
+    );
+}
+
+#[test]
+fn limits() {
+    let mut engine = Engine::new().with_limits(Limits::new().loop_iterations(10));
+    engine
+        .eval_str("func spin(n) {\nfor i in 0..n {\nx = i\n}\nreturn n\n}")
+        .unwrap();
+    assert_eq!(
+        Symbol::Number(5.0),
+        engine.call("spin", vec![5.into()]).unwrap()
+    );
+    match engine.call("spin", vec![100.into()]) {
+        Err(Error::Runtime(e)) => assert_eq!(RuntimeErrorKind::Limit, e.kind),
+        result => panic!("expected an error, found {:?}", result),
+    }
+}
+
+#[test]
+fn value_size() {
+    let limits = Limits::new().value_size(100);
+    for program in [
+        "xs = []\nfor i in 0..1000 {\nxs.push(i)\n}",
+        "xs = [1]\nfor i in 0..1000 {\nxs.extend(xs)\n}",
+        "s = \"\"\nfor i in 0..1000 {\ns = s + \"ab\"\n}",
+    ] {
+        let mut engine = Engine::new().with_limits(limits.clone());
+        match engine.eval_str(program) {
+            Err(Error::Runtime(e)) => assert_eq!(RuntimeErrorKind::Limit, e.kind),
+            result => panic!("expected an error from {:?}, found {:?}", program, result),
+        }
+    }
+
+    let mut engine = Engine::new().with_limits(limits);
+    assert_eq!(
+        Some(Symbol::Number(100.0)),
+        engine
+            .eval_str("xs = []\nfor i in 0..100 {\nxs.push(i)\n}\nxs.len()")
+            .unwrap()
+    );
+}
+
+#[test]
+fn deep_recursion() {
+    // a thread's default stack, not the large one the sod binary runs on
+    let depth = std::thread::Builder::new()
+        .stack_size(2 * 1024 * 1024)
+        .spawn(|| {
+            let mut engine = Engine::new();
+            engine
+                .eval_str("func down(n) {\nif n == 0 {\nreturn 0\n}\nreturn 1 + down(n - 1)\n}")
+                .unwrap();
+            // values aren't Send, so only their text leaves the thread
+            engine.eval_str("down(999)").unwrap().map(|s| s.to_string())
+        })
+        .unwrap()
+        .join()
+        .unwrap();
+    assert_eq!(Some("999".to_string()), depth);
+}
+
+#[test]
+fn conversions() {
+    assert_eq!(Symbol::Number(2.0), Symbol::from(2));
+    assert_eq!(Symbol::Number(2.5), Symbol::from(2.5));
+    assert_eq!(Symbol::Boolean(true), Symbol::from(true));
+    assert_eq!(Symbol::None, Symbol::from(None::<i64>));
+    assert_eq!(
+        Symbol::from(vec![Symbol::from(1), Symbol::from("a")]),
+        [Symbol::from(1), "a".into()].into_iter().collect()
+    );
+
+    let list = Symbol::from(vec!["a", "b"]);
+    let items = Vec::<Symbol>::try_from(list).unwrap();
+    let items: Vec<String> = items
+        .into_iter()
+        .map(String::try_from)
+        .collect::<Result<_, _>>()
+        .unwrap();
+    assert_eq!(vec!["a", "b"], items);
+    assert_eq!(
+        vec![Symbol::from(0), 1.into(), 2.into()],
+        Vec::<Symbol>::try_from(Symbol::from(Range::new(0, 3, None))).unwrap()
+    );
+
+    assert_eq!(Ok(3), i64::try_from(Symbol::from(3)));
+    assert_eq!(
+        Err("expected a whole number, found 3.5".to_string()),
+        i64::try_from(Symbol::from(3.5))
+    );
+    assert_eq!(Ok(true), bool::try_from(Symbol::from(true)));
+    assert_eq!(
+        Err("expected a number, found string".to_string()),
+        f64::try_from(Symbol::from("3"))
+    );
+    let object = Object::from(vec![("a", 1.into())]);
+    assert_eq!(Ok(object.clone()), Object::try_from(Symbol::from(object)));
+}
+
+#[cfg(feature = "async")]
+#[test]
+fn tokio_executor() {
+    let mut engine = Engine::new();
+    engine.set_command_executor(TokioExecutor::new().unwrap());
+    assert_eq!(
+        Some(Symbol::from("hi\n")),
+        engine.eval_str("`echo hi`").unwrap()
+    );
+
+    // jobs and parallel commands run at the same time
+    let started = Instant::now();
+    let src = "a = spawn sleep 1\nb = spawn sleep 1\na.wait()\nb.wait()
+r = parallel(['sleep 1; echo c', 'sleep 1; echo d'])
+r[0].stdout + r[1].stdout";
+    assert_eq!(Some(Symbol::from("c\nd\n")), engine.eval_str(src).unwrap());
+    assert!(started.elapsed() < Duration::from_millis(2800));
+
+    // a killed job has no status
+    assert_eq!(
+        Some(Symbol::None),
+        engine
+            .eval_str("j = spawn sleep 1\nj.kill()\nj.status()")
+            .unwrap()
+    );
+}
+
+#[cfg(feature = "async")]
+#[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+async fn tokio_executor_other_tasks() {
+    let ticks = Arc::new(AtomicUsize::new(0));
+    let ticker = tokio::spawn({
+        let ticks = ticks.clone();
+        async move {
+            loop {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+                ticks.fetch_add(1, Ordering::SeqCst);
+            }
+        }
+    });
+
+    // the worker runs the ticker while the program waits for its command
+    let mut engine = Engine::new();
+    engine.set_command_executor(TokioExecutor::new().unwrap());
+    let before = ticks.load(Ordering::SeqCst);
+    engine.eval_str("sleep 0.3").unwrap();
+    assert!(ticks.load(Ordering::SeqCst) > before + 5);
+    ticker.abort();
+}
+
+#[cfg(feature = "async")]
+#[test]
+fn tokio_executor_timeout() {
+    let mut engine = Engine::new();
+    engine.set_command_executor(
+        TokioExecutor::new()
+            .unwrap()
+            .timeout(Duration::from_millis(500)),
+    );
+    assert_eq!(
+        Some(Symbol::from("hi\n")),
+        engine
+            .eval_str("j = spawn echo hi\nr = j.wait()\nr.stdout")
+            .unwrap()
+    );
+
+    let started = Instant::now();
+    match engine.eval_str("`sleep 5`") {
+        Err(Error::Runtime(e)) => assert_eq!("command timed out: sleep 5", e.message),
+        result => panic!("expected an error, found {:?}", result),
+    }
+    assert!(started.elapsed() < Duration::from_secs(2));
+}
@@ -0,0 +1,85 @@
+use sod::ast::bash;
+use sod::parser::Parser;
+
+fn export(src: &str) -> Result<String, String> {
+    let program = Parser::new(src).parse().unwrap();
+    bash::export(&program)
+}
+
+fn assert_export(src: &str, expected: &str) {
+    let script = export(src).unwrap();
+    assert_eq!(format!("#!/usr/bin/env bash\n{}", expected), script);
+}
+
+#[test]
+fn variables() {
+    assert_export("x = 'a b'", "x='a b'\n");
+    assert_export("x = \"it's $\"", "x=\"it's \\$\"\n");
+    assert_export("x = 1\ny = \"n $x\"", "x=1\ny=\"n ${x}\"\n");
+    assert_export("x = [1, 'a']\nx[0] = 2", "x=(1 'a')\nx[0]=2\n");
+    assert_export("x = 1\ny = x * 2 + 1", "x=1\ny=$(( ((x * 2) + 1) ))\n");
+}
+
+#[test]
+fn control_flow() {
+    assert_export(
+        "x = 1\nif x >= 1 {\necho 'a'\n} else {\necho 'b'\n}",
+        "x=1\nif (( x >= 1 )); then\n    echo 'a'\nelse\n    echo 'b'\nfi\n",
+    );
+    assert_export(
+        "for i in 0..3 {\necho $i\n}",
+        "for (( i = 0; i < 3; i += 1 )); do\n    echo \"${i}\"\ndone\n",
+    );
+    assert_export(
+        "func id(a) {\necho $a\n}\nx = id(1)",
+        "id() {\n    local a=\"$1\"\n    echo \"${a}\"\n}\nx=\"$(id 1)\"\n",
+    );
+    assert_export(
+        "for a in process.argv {\necho $a\n}",
+        "for a in \"$@\"; do\n    echo \"${a}\"\ndone\n",
+    );
+    assert_export(
+        "func id(a) {\necho $a\n}\nx = ls -la |> id",
+        "id() {\n    local a=\"$1\"\n    echo \"${a}\"\n}\nx=\"$(id \"$(ls -la)\")\"\n",
+    );
+    assert_export(
+        "func stop(a) {\nif a == 'x' {\nreturn none\n}\necho $a\n}",
+        "stop() {\n    local a=\"$1\"\n    if [[ \"${a}\" == 'x' ]]; then\n        return\n    fi\n    echo \"${a}\"\n}\n",
+    );
+    assert_export(
+        "x = 'a'\nif x + 'b' {\necho $x\n}",
+        "x='a'\nif [[ -n \"${x}\"'b' ]]; then\n    echo \"${x}\"\nfi\n",
+    );
+    assert_export("spawn sleep 1", "sleep 1 &\n");
+    assert_export("FOO=bar ls", "FOO='bar' ls\n");
+    assert_export("f = 'a b'\nls > $f", "f='a b'\nls > \"${f}\"\n");
+    assert_export(
+        "func f(a) {\necho $a\n}\nseq 3 | f | sort > out.txt",
+        "f() {\n    local a=\"$1\"\n    echo \"${a}\"\n}\nseq 3 | while IFS= read -r line; do f \"$line\"; done | sort > 'out.txt'\n",
+    );
+    assert_export("x = 'a b'\nls $x $x!", "x='a b'\nls \"${x}\" ${x}\n");
+    assert_export("alias l = ls -la\nl /tmp", "ls -la /tmp\n");
+    assert_export(
+        "trap \"rm -f lock\" INT EXIT",
+        "_sod_trap_1() {\n    rm -f lock\n}\ntrap '_sod_trap_1; exit 130' INT\ntrap _sod_trap_1 EXIT\n",
+    );
+}
+
+#[test]
+fn unsupported() {
+    assert!(export("x = {a: 1}").is_err());
+    assert!(export("x = [1]\nx.push(2)").is_err());
+    assert!(export("with_env({A: 1}) {\necho 'a'\n}").is_err());
+}
+
+#[test]
+fn unsupported_semantics() {
+    // false, 0 and none are not empty strings to bash
+    assert!(export("x = 0\nif x {\necho 'a'\n}").is_err());
+    assert!(export("x = 1\nif x - 1 {\necho 'a'\n}").is_err());
+    // bash arithmetic drops the remainder
+    assert!(export("x = 7 / 2").is_err());
+    assert!(export("x = 1\ny = x * 1.5").is_err());
+    // a value echoed by a function mixes with its commands' output
+    assert!(export("func f() {\nreturn 1\n}").is_err());
+}
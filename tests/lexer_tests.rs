@@ -1,5 +1,6 @@
 use sod::lexer::lexer::Lexer;
 use sod::lexer::token::TokenType;
+use std::borrow::Cow;
 
 fn assert_tokens(mut l: Lexer, expected: Vec<TokenType>, cmd: bool) {
     let mut next = || {
@@ -49,7 +50,7 @@ fn assignment() {
     assert_tokens(
         Lexer::new("foo = 1"),
         vec![
-            TokenType::Identifier("foo".to_string()),
+            TokenType::Identifier("foo".into()),
             TokenType::Equals,
             TokenType::Integer(1),
         ],
@@ -62,9 +63,9 @@ fn strings() {
     assert_tokens(
         Lexer::new(r#"x="foo""#),
         vec![
-            TokenType::Identifier("x".to_string()),
+            TokenType::Identifier("x".into()),
             TokenType::Equals,
-            TokenType::TemplateString("foo".to_string()),
+            TokenType::TemplateString("foo".into()),
         ],
         false,
     );
@@ -75,17 +76,17 @@ fn bash() {
     assert_tokens(
         Lexer::new("ls -la >> foo.txt"),
         vec![
-            TokenType::Identifier("ls".to_string()),
+            TokenType::Identifier("ls".into()),
             TokenType::Whitespace,
             TokenType::Minus,
-            TokenType::Identifier("la".to_string()),
+            TokenType::Identifier("la".into()),
             TokenType::Whitespace,
             TokenType::GreaterThan,
             TokenType::GreaterThan,
             TokenType::Whitespace,
-            TokenType::Identifier("foo".to_string()),
+            TokenType::Identifier("foo".into()),
             TokenType::Dot,
-            TokenType::Identifier("txt".to_string()),
+            TokenType::Identifier("txt".into()),
         ],
         true,
     );
@@ -99,3 +100,77 @@ fn line_comment() {
         false,
     );
 }
+
+#[test]
+fn multibyte_characters() {
+    assert_tokens(
+        Lexer::new("1 € 2"),
+        vec![
+            TokenType::Integer(1),
+            TokenType::CatchAll("€".into()),
+            TokenType::Integer(2),
+        ],
+        false,
+    );
+}
+
+#[test]
+fn lexer_errors() {
+    let mut l = Lexer::new("99999999999999999999999");
+    assert!(matches!(l.next_token(), TokenType::Error(e) if e.starts_with("invalid number")));
+}
+
+#[test]
+fn unterminated_strings() {
+    let mut l = Lexer::new("x = 1\ny = 'abc\n");
+    let mut token = l.next_token();
+    while !matches!(token, TokenType::Error(_) | TokenType::EOF) {
+        token = l.next_token();
+    }
+    assert_eq!(
+        TokenType::Error("unterminated string starting at line 2".to_string()),
+        token
+    );
+}
+
+#[test]
+fn interned_identifiers() {
+    let mut l = Lexer::new("total = total + count");
+    let mut names = vec![];
+    loop {
+        match l.next_token() {
+            TokenType::Identifier(name) => names.push(name),
+            TokenType::EOF => break,
+            _ => (),
+        }
+    }
+
+    assert_eq!(3, names.len());
+    assert!(std::rc::Rc::ptr_eq(&names[0], &names[1]));
+    assert!(!std::rc::Rc::ptr_eq(&names[0], &names[2]));
+    assert!(std::rc::Rc::ptr_eq(&names[2], &l.intern("count")));
+}
+
+#[test]
+fn borrowed_text() {
+    let src = String::from("x = 'héllo' $name € `ls`");
+    let mut l = Lexer::new(&src);
+    let mut texts = vec![];
+    loop {
+        match l.next_token() {
+            TokenType::String(Cow::Borrowed(s))
+            | TokenType::EscapedIdentifier(Cow::Borrowed(s))
+            | TokenType::CatchAll(Cow::Borrowed(s))
+            | TokenType::CommandString(Cow::Borrowed(s)) => {
+                // the text is the source itself rather than a copy of it
+                assert!(src.as_bytes().as_ptr_range().contains(&s.as_ptr()));
+                texts.push(s);
+                assert!(l.text(l.span()).contains(s));
+            }
+            TokenType::EOF => break,
+            _ => (),
+        }
+    }
+
+    assert_eq!(vec!["héllo", "name", "€", "ls"], texts);
+}
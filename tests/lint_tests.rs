@@ -0,0 +1,86 @@
+use sod::ast::lint::{self, WarningKind};
+use sod::parser::Parser;
+
+fn lint(src: &str) -> Vec<String> {
+    let program = Parser::new(src).parse().unwrap();
+    lint::lint(&program)
+        .into_iter()
+        .map(|warning| warning.to_string())
+        .collect()
+}
+
+#[test]
+fn unused_variables() {
+    assert_eq!(vec!["'x' is assigned but never read"], lint("x = 1"));
+    assert!(lint("x = 1\necho $x").is_empty());
+    assert!(lint("x = 1\ny = \"$x.name\"\necho $y").is_empty());
+    assert!(lint("x = [1]\nx[0] = 2").is_empty());
+    assert!(lint("for i in 0..3 {\necho hi\n}").is_empty());
+    assert_eq!(
+        vec!["'y' is assigned but never read in function f"],
+        lint("func f(a) {\ny = a\n}\nf(1)")
+    );
+    // globals are read when a function is called, so may be set after it
+    assert!(lint("func f() {\nreturn x\n}\nx = 1\nf()").is_empty());
+}
+
+#[test]
+fn unreachable_code() {
+    let program = Parser::new("func f() {\nreturn 1\necho a\n}\nf()")
+        .parse()
+        .unwrap();
+    let warnings = lint::lint(&program);
+    assert_eq!(1, warnings.len());
+    assert_eq!(WarningKind::Unreachable, warnings[0].kind);
+    assert_eq!(
+        "code after return is never run in function f",
+        warnings[0].message
+    );
+}
+
+#[test]
+fn read_after_block() {
+    assert_eq!(
+        vec!["'y' is read after the if that defines it, where it isn't defined"],
+        lint("x = 1\nif x > 0 {\ny = 1\n} else {\ny = 2\n}\necho $y")
+    );
+    assert_eq!(
+        vec!["'y' is read after the for loop that defines it, where it isn't defined"],
+        lint("for i in 0..3 {\ny = i\n}\nz = y + 1\necho $z")
+    );
+    assert!(lint("y = 0\nif true {\ny = 1\n}\necho $y").is_empty());
+}
+
+#[test]
+fn lint_command() {
+    let sod = |src: &str| {
+        let script = std::env::temp_dir().join("sod_lint_command.sod");
+        std::fs::write(&script, src).unwrap();
+        std::process::Command::new(env!("CARGO_BIN_EXE_sod"))
+            .arg("lint")
+            .arg(&script)
+            .output()
+            .unwrap()
+    };
+
+    let output = sod("x = 1\n");
+    assert_eq!(Some(1), output.status.code());
+    assert!(output.stdout.is_empty());
+    assert!(
+        String::from_utf8_lossy(&output.stderr).ends_with(":1: 'x' is assigned but never read\n")
+    );
+
+    // warnings are in the order of the lines they are about
+    let output = sod("y = 1\nfunc f() {\nreturn 1\necho a\n}\nf()\n");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    let lines: Vec<&str> = stderr.lines().collect();
+    assert_eq!(2, lines.len());
+    assert!(lines[0].ends_with(".sod:1: 'y' is assigned but never read"));
+    assert!(lines[1].ends_with(".sod:4: code after return is never run in function f"));
+
+    // nothing is run
+    let output = sod("x = 1\necho $x\nprocess.exit(3)\n");
+    assert_eq!(Some(0), output.status.code());
+    assert!(output.stdout.is_empty());
+    assert!(output.stderr.is_empty());
+}
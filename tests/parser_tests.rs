@@ -1,6 +1,6 @@
 use common::utils::{assert_expr, assert_exprs, eval_expr};
 use sod::new_string_symbol;
-use sod::symbol::symbol::Symbol;
+use sod::symbol::symbol::{List, Object, Symbol};
 mod common;
 
 #[test]
@@ -17,6 +17,25 @@ fn math_expressions() {
     assert_expr("-2 ^ 2", Symbol::Number(-4.0));
 }
 
+#[test]
+fn number_methods() {
+    assert_expr(
+        "x = 0.1 + 0.2\nx.to_fixed(2)",
+        new_string_symbol!("0.30".to_string()),
+    );
+    assert_expr("x = 2.5\nx.to_fixed()", new_string_symbol!("3".to_string()));
+    assert_expr(
+        "x = 1.005\nx.to_fixed(1)",
+        new_string_symbol!("1.0".to_string()),
+    );
+    assert_expr(
+        "x = -0.4\nx.to_fixed()",
+        new_string_symbol!("0".to_string()),
+    );
+    assert_expr("x = 2.71828\nx.round(2)", Symbol::Number(2.72));
+    assert_expr("x = 2.6\nx.round()", Symbol::Number(3.0));
+}
+
 #[should_panic]
 #[test]
 fn invalid_number() {
@@ -53,6 +72,93 @@ fn functions() {
     )
 }
 
+#[test]
+fn function_arguments() {
+    // an argument hides a global of the same name rather than setting it
+    assert_expr(
+        "x = 1\nfunc foo(x) {\nx = x + 1\nreturn x\n}\nfoo(5)\nx",
+        Symbol::Number(1.0),
+    );
+    assert_expr(
+        "x = 1\nfunc foo(x) {\nreturn x * 2\n}\nfoo(5) + foo(7) + x",
+        Symbol::Number(25.0),
+    );
+    assert_expr(
+        "x = 1\nfunc foo(x) {\nreturn x\n}\nfoo(5) + x",
+        Symbol::Number(6.0),
+    );
+}
+
+#[test]
+fn function_bodies() {
+    // each call starts from the body as it was defined
+    assert_expr(
+        "func next(n) {\ny = n\ny = y + 1\nreturn y\n}\nnext(1) + next(10) + next(100)",
+        Symbol::Number(114.0),
+    );
+}
+
+#[test]
+fn early_return() {
+    assert_expr(
+        "func sign(n) {\nif n < 0 {\nreturn -1\n}\nreturn 1\n}\nsign(-5)",
+        Symbol::Number(-1.0),
+    );
+    assert_expr(
+        "func find(xs, v) {\nfor x in xs {\nif x == v {\nreturn x * 10\n}\n}\nreturn none\n}\nfind([4, 5, 6], 5)",
+        Symbol::Number(50.0),
+    );
+    // outside a function a return still only ends its block
+    assert_expr(
+        "x = 0\nif true {\nreturn 1\nx = 2\n}\nx = x + 3\nx",
+        Symbol::Number(3.0),
+    );
+}
+
+#[test]
+fn tail_calls() {
+    assert_expr(
+        "func count(n, acc) {\nif n == 0 {\nreturn acc\n}\nreturn count(n - 1, acc + 1)\n}\ncount(20000, 0)",
+        Symbol::Number(20000.0),
+    );
+    assert_expr(
+        "func even(n) {\nif n == 0 {\nreturn true\n}\nreturn odd(n - 1)\n}\nfunc odd(n) {\nif n == 0 {\nreturn false\n}\nreturn even(n - 1)\n}\neven(20001)",
+        Symbol::Boolean(false),
+    );
+    assert_expr(
+        "func fib(n) {\nif n < 2 {\nreturn n\n}\nreturn fib(n - 1) + fib(n - 2)\n}\nfib(10)",
+        Symbol::Number(55.0),
+    );
+}
+
+#[test]
+fn higher_order_functions() {
+    assert_expr(
+        "func double(x) {\nreturn x * 2\n}\nx = [1, 2, 3]\ny = x.map(double)\ny[2]",
+        Symbol::Number(6.0),
+    );
+    assert_expr(
+        "func odd(x) {\nreturn x != 2\n}\nx = [1, 2, 3]\ny = x.filter(odd)\ny.len()",
+        Symbol::Number(2.0),
+    );
+    assert_expr(
+        "func add(a, b) {\nreturn a + b\n}\nx = [1, 2, 3]\nx.reduce(add)",
+        Symbol::Number(6.0),
+    );
+    assert_expr(
+        "func add(a, b) {\nreturn a + b\n}\nx = [1, 2, 3]\nx.reduce(add, 10)",
+        Symbol::Number(16.0),
+    );
+    assert_expr(
+        "func big(x) {\nreturn x > 1\n}\nx = [1, 2, 3]\nx.find(big)",
+        Symbol::Number(2.0),
+    );
+    assert_expr(
+        "func big(x) {\nreturn x > 5\n}\nx = [1, 2, 3]\nx.find(big)",
+        Symbol::None,
+    );
+}
+
 #[test]
 fn conditionals() {
     assert_expr("x = 10\nif 2 > 1 {\n x = 20\n}\nx", Symbol::Number(20.0));
@@ -166,11 +272,34 @@ x + "bar"
         new_string_symbol!("foo bar".to_string()),
     );
     assert_expr(
-        "x = 'foo'\n'$x bar",
+        "x = 'foo'\n'$x bar'",
         new_string_symbol!("$x bar".to_string()),
     );
 }
 
+#[test]
+fn unicode_strings() {
+    assert_expr(
+        "x = 'wörld'\n\"héllo $x ü $ $x\"",
+        new_string_symbol!("héllo wörld ü $ wörld".to_string()),
+    );
+    assert_expr(
+        "s = ''\nfor c in 'aé€😀' {\ns = c + s\n}\ns",
+        new_string_symbol!("😀€éa".to_string()),
+    );
+}
+
+#[test]
+fn string_extract() {
+    assert_expr(
+        r#"line = '10.0.0.1 - GET /index.html 404'
+m = line.extract('(?P<ip>\S+) .* (?P<code>\d{3})')
+m.code"#,
+        new_string_symbol!("404".to_string()),
+    );
+    assert_expr("x = 'abc'\nx.extract('(?P<n>\\d+)')", Symbol::None);
+}
+
 #[test]
 fn lists() {
     assert_expr("x = [1, 2]\nx[1]", Symbol::Number(2.0));
@@ -183,6 +312,106 @@ fn lists() {
         "t = 0\nx = [5,2]\nfor v in x {\nt = t + v\n}\nt",
         Symbol::Number(7.0),
     );
+    assert_expr("x = [3, 10, 2]\nx.sort()\nx[2]", Symbol::Number(10.0));
+    assert_expr(
+        "x = ['b', 'c', 'a']\nx.sort()\nx[0]",
+        new_string_symbol!("a".to_string()),
+    );
+    assert_expr("x = [1, 2, 3]\nx.reverse()\nx[0]", Symbol::Number(3.0));
+    assert_expr("x = ['a', 'b']\nx.index_of('b')", Symbol::Number(1.0));
+    assert_expr("x = ['a', 'b']\nx.index_of('c')", Symbol::None);
+    assert_expr("x = [1]\nx.extend([2, 3])\nx[2]", Symbol::Number(3.0));
+    assert_expr("x = [1, 2]\nx.clear()\nx.len()", Symbol::Number(0.0));
+    assert_expr(
+        "x = [1, 2, 1, 3, 2]\ny = x.unique()\ny.len()",
+        Symbol::Number(3.0),
+    );
+    assert_expr(
+        "x = [1, [2, [3]], 4]\ny = x.flatten()\ny[2]",
+        Symbol::List(List::from(vec![Symbol::Number(3.0)])),
+    );
+}
+
+#[test]
+fn list_aggregates() {
+    assert_expr("x = [1, 2, 3.5]\nx.sum()", Symbol::Number(6.5));
+    assert_expr("x = [4, -1, 3]\nx.min()", Symbol::Number(-1.0));
+    assert_expr("x = [4, -1, 3]\nx.max()", Symbol::Number(4.0));
+    assert_expr("x = []\nx.max()", Symbol::None);
+}
+
+#[test]
+#[should_panic]
+fn sum_non_numbers_panic() {
+    eval_expr("x = [1, 'a']\nx.sum()");
+}
+
+#[test]
+#[should_panic]
+fn sort_mixed_list_panic() {
+    eval_expr("x = [1, 'a']\nx.sort()");
+}
+
+#[test]
+fn objects() {
+    assert_expr("x = {a: 1, \"b\": 2}\nx.b", Symbol::Number(2.0));
+    assert_expr("x = {}\nx", Symbol::Object(Object::from(vec![])));
+    assert_expr(
+        "x = {a: 1, b: 2}\ny = x.keys()\ny.len()",
+        Symbol::Number(2.0),
+    );
+    assert_expr(
+        "x = {a: 1, b: 2}\ny = x.values()\ny.sum()",
+        Symbol::Number(3.0),
+    );
+    assert_expr("x = {a: 1}\nx.has('a')", Symbol::Boolean(true));
+    assert_expr(
+        "x = {a: 1}\nx.delete('a')\nx.has('a')",
+        Symbol::Boolean(false),
+    );
+    assert_expr("x = {a: 1}\nx.delete('b')", Symbol::None);
+    assert_expr(
+        "t = 0\nx = {a: 1, b: 2}\nfor item in x.items() {\nt = t + item[1]\n}\nt",
+        Symbol::Number(3.0),
+    );
+    assert_expr(
+        "x = {b: 1, a: 2, c: 3}\nx.delete('a')\nx.keys()",
+        Symbol::List(List::from(vec![
+            new_string_symbol!("b".to_string()),
+            new_string_symbol!("c".to_string()),
+        ])),
+    );
+    assert_expr("x = {a: {b: 1}}\nx.a.b", Symbol::Number(1.0));
+    assert_expr("x = {a: {b: [1, 2]}}\nx.a.b.len()", Symbol::Number(2.0));
+    assert_expr(
+        "x = {a: {b: 'c'}}\n\"$x.a.b\"",
+        new_string_symbol!("c".to_string()),
+    );
+    // properties are set, and added when missing
+    assert_expr("x = {a: 1}\nx.a = 2\nx.a", Symbol::Number(2.0));
+    assert_expr("x = {a: 1}\nx.b = 2\nx.keys().len()", Symbol::Number(2.0));
+    assert_expr("x = {a: {b: 1}}\nx.a.b = 3\nx.a.b", Symbol::Number(3.0));
+    assert_expr("x = [{a: 1}]\nx[0].a = 4\nx[0].a", Symbol::Number(4.0));
+    assert_expr("x = {a: 1}\ny = x\ny.a = 2\nx.a", Symbol::Number(1.0));
+}
+
+#[test]
+fn copies() {
+    // values are shared until one copy changes, which leaves the others be
+    assert_expr("a = [1]\nb = a\nb.push(2)\na.len()", Symbol::Number(1.0));
+    assert_expr("a = [1]\nb = a\nb[0] = 2\na[0]", Symbol::Number(1.0));
+    assert_expr(
+        "a = 'ab'\nb = a\nb.push('c')\na",
+        new_string_symbol!("ab".to_string()),
+    );
+    assert_expr(
+        "a = {x: 1}\nb = a\nb.delete('x')\na.has('x')",
+        Symbol::Boolean(true),
+    );
+    assert_expr(
+        "a = [1]\nfunc change(l) {\nl.push(2)\nreturn l\n}\nb = change(a)\na.len()",
+        Symbol::Number(1.0),
+    );
 }
 
 #[test]
@@ -201,7 +430,63 @@ fn ranges() {
     );
 }
 
+#[test]
+fn for_destructuring() {
+    assert_expr(
+        "t = 0\nrows = [['a', 1], ['b', 2]]\nfor (name, n) in rows {\nt = t + n\n}\nt",
+        Symbol::Number(3.0),
+    );
+    assert_expr(
+        "t = ''\nx = {a: 1}\nfor (k, v) in x.items() {\nt = k\n}\nt",
+        new_string_symbol!("a".to_string()),
+    );
+}
+
+#[test]
+#[should_panic]
+fn for_destructuring_panic() {
+    eval_expr("rows = [[1]]\nfor (a, b) in rows {\nx = a\n}");
+}
+
+#[test]
+fn range_methods() {
+    assert_expr("r = 0..5..2\nr.len()", Symbol::Number(3.0));
+    assert_expr("r = 3..3\nr.len()", Symbol::Number(0.0));
+    assert_expr("r = 0..5..2\nl = r.to_list()\nl[2]", Symbol::Number(4.0));
+    assert_expr("r = 0..5..2\nr.contains(4)", Symbol::Boolean(true));
+    assert_expr("r = 0..5..2\nr.contains(3)", Symbol::Boolean(false));
+    assert_expr("r = 0..5..2\nr.contains(6)", Symbol::Boolean(false));
+    assert_expr("r = 4..1..-1\nr.contains(2)", Symbol::Boolean(true));
+}
+
 #[test]
 fn global_vars() {
     assert_expr("process.argv.len()", Symbol::Number(0.0));
 }
+
+#[test]
+fn timing() {
+    assert_expr("d = time {\nx = 1\n}\nd >= 0", Symbol::Boolean(true));
+    assert_expr(
+        "t = 0\nd = time {\nfor i in 0..3 {\nt = t + i\n}\n}\nt",
+        Symbol::Number(3.0),
+    );
+    assert_expr(
+        "func f() {\nreturn 1\n}\nd = measure(f)\nd >= 0",
+        Symbol::Boolean(true),
+    );
+}
+
+#[test]
+#[should_panic]
+fn measure_non_function_panic() {
+    eval_expr("measure(1)");
+}
+
+#[test]
+fn pipe_forward() {
+    assert_expr(
+        "func add(a, b) {\nreturn a + b\n}\n1 |> add(2) |> add(3)",
+        Symbol::Number(6.0),
+    );
+}
@@ -0,0 +1,92 @@
+use sod::new_string_symbol;
+use sod::symbol::scope::ScopeKind;
+use sod::symbol::symbol::Symbol;
+use sod::symbol::table::SymbolTable;
+mod common;
+
+#[test]
+fn scope_views() {
+    let mut table = SymbolTable::from(vec![("b", Symbol::Number(1.0)), ("a", Symbol::None)]);
+    table.push_scope(ScopeKind::ForBlock);
+    table.set("s", new_string_symbol!("abc".to_string()));
+
+    let scopes = table.scopes();
+    assert_eq!(2, scopes.len());
+    assert_eq!(ScopeKind::ForBlock, scopes[0].kind);
+    assert_eq!(ScopeKind::Global, scopes[1].kind);
+
+    let bindings = scopes[0].bindings();
+    assert_eq!(1, bindings.len());
+    assert_eq!("s", bindings[0].name);
+    assert_eq!("string", bindings[0].kind());
+    assert!(bindings[0].size() >= 3);
+
+    let names: Vec<&str> = scopes[1].bindings().iter().map(|b| b.name).collect();
+    assert_eq!(vec!["a", "b"], names);
+    assert_eq!(Some(&Symbol::Number(1.0)), scopes[1].get("b"));
+}
+
+#[test]
+fn function_scopes_hide_callers() {
+    let mut table = SymbolTable::from(vec![]);
+    table.push_scope(ScopeKind::ConditionalBlock);
+    table.push_scope(ScopeKind::FunctionBlock);
+
+    let kinds: Vec<ScopeKind> = table.scopes().iter().map(|s| s.kind).collect();
+    assert_eq!(vec![ScopeKind::FunctionBlock, ScopeKind::Global], kinds);
+}
+
+#[test]
+fn nested_scopes() {
+    let mut table = SymbolTable::from(vec![("g", Symbol::Number(1.0))]);
+    table.push_scope(ScopeKind::ForBlock);
+    table.set("x", Symbol::Number(2.0));
+
+    // a function sees globals but not the variables of its caller
+    table.push_scope(ScopeKind::FunctionBlock);
+    assert_eq!(Some(&Symbol::Number(1.0)), table.get("g"));
+    assert_eq!(None, table.get("x"));
+    table.set("x", Symbol::Number(3.0));
+    table.push_scope(ScopeKind::ConditionalBlock);
+    table.set("g", Symbol::Number(4.0));
+    table.set("y", Symbol::Number(5.0));
+    assert_eq!(Some(&Symbol::Number(3.0)), table.get("x"));
+    table.pop_scope();
+    assert_eq!(None, table.get("y"));
+    table.pop_scope();
+
+    assert_eq!(Some(&Symbol::Number(2.0)), table.get("x"));
+    assert_eq!(Some(&Symbol::Number(4.0)), table.get("g"));
+    table.pop_scope();
+    assert_eq!(None, table.get("x"));
+}
+
+#[test]
+fn pretty_printing() {
+    use common::utils::eval_expr;
+    use sod::symbol::pretty::pretty;
+
+    let render = |src: &str, color: bool| {
+        let values = eval_expr(src);
+        pretty(values.last().unwrap().as_ref().unwrap(), color)
+    };
+
+    assert_eq!("[]", render("[]", false));
+    assert_eq!(
+        "{ name: 'it\\'s', 'a b': [ 1, none ] }",
+        render("{name: \"it's\", 'a b': [1, none]}", false)
+    );
+    assert_eq!(
+        "[ \x1b[33m1\x1b[0m, \x1b[32m'a'\x1b[0m, \x1b[2mnone\x1b[0m ]",
+        render("[1, 'a', none]", true)
+    );
+
+    let long = "'xxxxxxxxxxxxxxxxxxxxxxxxxxxxxx'";
+    assert_eq!(
+        format!(
+            "{{\n  a: [\n    {0},\n    {0},\n    {0}\n  ],\n  b: true\n}}",
+            long
+        ),
+        render(&format!("{{a: [{0}, {0}, {0}], b: true}}", long), false)
+    );
+}
@@ -0,0 +1,142 @@
+use sod::ast::ast::ASTNode;
+use sod::ast::bytecode::{self, Instruction};
+use sod::ast::evaluator::ASTEvaluator;
+use sod::error::RuntimeErrorKind;
+use sod::limits::Limits;
+use sod::new_string_symbol;
+use sod::parser::Parser;
+use sod::symbol::symbol::Symbol;
+
+/// Runs a program with loops walked and with them compiled, checking both
+/// give the same values.
+fn assert_same(src: &str, expected: Symbol) {
+    let mut results = vec![];
+    for vm in [false, true] {
+        let program = Parser::new(src).parse().unwrap();
+        let mut evaluator = ASTEvaluator::new(vec![]);
+        evaluator.set_vm(vm);
+        let values = evaluator.eval(program).unwrap();
+        results.push(values.last().cloned().flatten());
+    }
+    assert_eq!(Some(expected), results[1]);
+    assert_eq!(results[0], results[1]);
+}
+
+#[test]
+fn loops() {
+    assert_same(
+        "total = 0\nfor i in 0..10..2 {\ntotal = total + i * 2\n}\ntotal",
+        Symbol::Number(40.0),
+    );
+    assert_same(
+        "xs = []\nfor (a, b) in [[1, 2], [3, 4]] {\nxs.push(a + b)\n}\nxs.len()",
+        Symbol::Number(2.0),
+    );
+    assert_same(
+        "n = 0\nfor c in 'a,b,c' {\nif c == ',' {\nn = n + 1\n} else if c == 'z' {\nn = 100\n}\n}\nn",
+        Symbol::Number(2.0),
+    );
+    assert_same(
+        "n = 0\nfor i in 1..4 {\nfor j in 1..4 {\nn = n + i * j\n}\n}\nn",
+        Symbol::Number(36.0),
+    );
+}
+
+#[test]
+fn fallback() {
+    // functions, higher order functions and templates are walked
+    assert_same(
+        "func double(x) {\nreturn x * 2\n}\nout = []\nfor i in [1, 2] {\nxs = [i]\nys = xs.map(double)\nout.push(ys[0])\nout.push(\"$i\")\n}\nout[3]",
+        new_string_symbol!("2".to_string()),
+    );
+    assert_same(
+        "seen = 0\nfor i in 1..3 {\nx = i > 1 && i\nseen = seen || x\n}\nseen",
+        Symbol::Number(2.0),
+    );
+}
+
+#[test]
+fn returns() {
+    assert_same(
+        "func first_over(xs, n) {\nfor x in xs {\nfor y in [x] {\nif y > n {\nreturn y\n}\n}\n}\nreturn none\n}\nfirst_over([1, 5, 9], 4)",
+        Symbol::Number(5.0),
+    );
+    assert_same(
+        "n = 0\nfor i in 0..3 {\nif i == 1 {\nreturn 1\nn = 100\n}\nn = n + 1\n}\nn",
+        Symbol::Number(3.0),
+    );
+}
+
+#[test]
+fn functions() {
+    assert_same(
+        "func fib(n) {\nif n < 2 {\nreturn n\n}\nreturn fib(n - 1) + fib(n - 2)\n}\nfib(15)",
+        Symbol::Number(610.0),
+    );
+    assert_same(
+        "func count(n, total) {\nif n == 0 {\nreturn total\n}\nreturn count(n - 1, total + n)\n}\ncount(5000, 0)",
+        Symbol::Number(12502500.0),
+    );
+    assert_same(
+        "func sum(xs) {\nn = 0\nfor x in xs {\nn = n + x\n}\nreturn n\n}\nsum([1, 2]) + sum([3])",
+        Symbol::Number(6.0),
+    );
+
+    // compiled once, when declared
+    for vm in [false, true] {
+        let program = Parser::new("func f(x) {\nreturn x\n}").parse().unwrap();
+        let mut evaluator = ASTEvaluator::new(vec![]);
+        evaluator.set_vm(vm);
+        evaluator.eval(program).unwrap();
+        match evaluator.symbol_table().get("f") {
+            Some(Symbol::Function(f)) => assert_eq!(vm, f.compiled.get().is_some()),
+            symbol => panic!("expected a function, found {:?}", symbol),
+        }
+    }
+}
+
+#[test]
+fn errors() {
+    for vm in [false, true] {
+        let program = Parser::new("for i in 1..3 {\ny = x\n}").parse().unwrap();
+        let mut evaluator = ASTEvaluator::new(vec![]);
+        evaluator.set_vm(vm);
+        let error = evaluator.eval(program).unwrap_err();
+        assert_eq!("'x' is not defined", error.message);
+    }
+}
+
+#[test]
+fn limits() {
+    for vm in [false, true] {
+        for src in [
+            "xs = []\nfor i in 0..100 {\nxs.push(i)\n}",
+            "s = \"\"\nfor i in 0..100 {\ns = s + \"ab\"\n}",
+        ] {
+            let program = Parser::new(src).parse().unwrap();
+            let mut evaluator = ASTEvaluator::new(vec![]);
+            evaluator.set_vm(vm);
+            evaluator.set_limits(Limits::new().value_size(10));
+            let error = evaluator.eval(program).unwrap_err();
+            assert_eq!(RuntimeErrorKind::Limit, error.kind);
+        }
+    }
+}
+
+#[test]
+fn compile() {
+    let program = Parser::new("for i in 0..3 {\nn = i[0]\n}").parse().unwrap();
+    let statement = match program {
+        ASTNode::Program(program) => program.body[0].clone(),
+        _ => unreachable!(),
+    };
+    let chunk = bytecode::compile(&statement);
+    assert!(chunk
+        .instructions
+        .iter()
+        .all(|instruction| !matches!(instruction, Instruction::Eval(_))));
+    assert!(chunk
+        .instructions
+        .iter()
+        .any(|instruction| matches!(instruction, Instruction::LoadIndex(_))));
+}